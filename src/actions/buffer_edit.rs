@@ -0,0 +1,340 @@
+//! Replace part of a buffer's data, keeping the same length.
+//!
+//! Optionally, any checksums in the buffer's entries that cover the edited
+//! bytes are fixed up too, so the data stays consistent. Undoing the edit
+//! restores the checksums as well.
+
+use redo::Command;
+use serde::{Serialize, Deserialize};
+use simple_error::{SimpleResult, SimpleError, bail};
+use std::ops::Range;
+
+use crate::project::H2Project;
+use crate::actions::Action;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Forward {
+    buffer: String,
+    data: Vec<u8>,
+    offset: usize,
+    fix_checksums: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Backward {
+    buffer: String,
+    original_data: Vec<u8>,
+    offset: usize,
+    fix_checksums: bool,
+
+    /// The offset and original value of each checksum that was fixed, in the
+    /// order they were fixed.
+    original_checksums: Vec<(usize, Vec<u8>)>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum State {
+    Forward(Forward),
+    Backward(Backward),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ActionBufferEdit(State);
+
+impl ActionBufferEdit {
+    /// Replace the bytes at `offset` with `data`.
+    pub fn new(buffer: &str, data: &[u8], offset: usize) -> Action {
+        Self::new_with_options(buffer, data, offset, false)
+    }
+
+    /// Replace the bytes at `offset` with `data`, then rewrite any checksums
+    /// that cover them (see [`crate::project::H2Buffer::checksums_fix`]).
+    pub fn new_fix_checksums(buffer: &str, data: &[u8], offset: usize) -> Action {
+        Self::new_with_options(buffer, data, offset, true)
+    }
+
+    fn new_with_options(buffer: &str, data: &[u8], offset: usize, fix_checksums: bool) -> Action {
+        Action::BufferEdit(
+            ActionBufferEdit(
+                State::Forward(Forward {
+                    buffer: buffer.to_string(),
+                    data: data.to_vec(),
+                    offset,
+                    fix_checksums,
+                })
+            )
+        )
+    }
+}
+
+impl Command for ActionBufferEdit {
+    type Target = H2Project;
+    type Error = SimpleError;
+
+    fn apply(&mut self, project: &mut H2Project) -> SimpleResult<()> {
+        // Get the forward struct
+        let forward = match &self.0 {
+            State::Forward(f) => f,
+            _                 => bail!("Failed to apply: action ended up in a broken undo/redo state"),
+        };
+
+        let buffer = match project.buffer_get_mut(&forward.buffer) {
+            Some(b) => b,
+            None => bail!("Couldn't find buffer {} to edit", forward.buffer),
+        };
+
+        // Do the edit, then the checksums
+        let original_data = buffer.edit(forward.data.clone(), forward.offset)?;
+        let original_checksums = match forward.fix_checksums {
+            true  => match buffer.checksums_fix(forward.offset..(forward.offset + forward.data.len())) {
+                Ok(c) => c,
+                Err(e) => {
+                    // Don't leave the edit half-done
+                    buffer.edit(original_data, forward.offset)?;
+                    return Err(e);
+                },
+            },
+            false => vec![],
+        };
+
+        // Save the backward struct
+        self.0 = State::Backward(Backward {
+            buffer: forward.buffer.clone(),
+            original_data,
+            offset: forward.offset,
+            fix_checksums: forward.fix_checksums,
+            original_checksums,
+        });
+
+        Ok(())
+    }
+
+    fn undo(&mut self, project: &mut H2Project) -> SimpleResult<()> {
+        // Get the backward struct
+        let backward = match &self.0 {
+            State::Backward(b) => b,
+            _                  => bail!("Failed to undo: action ended up in a broken undo/redo state"),
+        };
+
+        let buffer = match project.buffer_get_mut(&backward.buffer) {
+            Some(b) => b,
+            None => bail!("Couldn't find buffer {} to undo the edit", backward.buffer),
+        };
+
+        // Put the checksums back in reverse order, then undo the edit (the
+        // checksums will be recalculated when this is re-applied)
+        for (offset, data) in backward.original_checksums.iter().rev() {
+            buffer.edit(data.clone(), *offset)?;
+        }
+        let data = buffer.edit(backward.original_data.clone(), backward.offset)?;
+
+        // If the entries were refreshed, refresh them again
+        if backward.fix_checksums {
+            let changed: Vec<Range<usize>> = std::iter::once(backward.offset..(backward.offset + data.len()))
+                .chain(backward.original_checksums.iter().map(|(offset, data)| *offset..(*offset + data.len())))
+                .collect();
+
+            buffer.entries_refresh(&changed)?;
+        }
+
+        // Save the forward struct
+        self.0 = State::Forward(Forward {
+            buffer: backward.buffer.clone(),
+            data,
+            offset: backward.offset,
+            fix_checksums: backward.fix_checksums,
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use redo::Record;
+    use pretty_assertions::assert_eq;
+
+    use crate::actions::{ActionBufferCreateFromBytes, ActionLayerCreate, ActionEntryCreateFromType};
+    use crate::checksum::{ChecksumType, Crc};
+    use crate::datatype::{Expression, H2Type};
+    use crate::datatype::simple::{H2Number, H2Checksum, ChecksumStorage, ChecksumBound};
+    use crate::datatype::composite::{ArrayLength, H2Array, H2Struct};
+    use crate::generic_number::{GenericReader, Endian, DefaultFormatter};
+
+    fn display(record: &Record<Action>, offset: usize) -> String {
+        record.target().entry_get("buffer", "default", offset).unwrap().resolved().display.clone()
+    }
+
+    #[test]
+    fn test_action() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &b"AAAAAAAAAA".to_vec(), 0x80000000))?;
+
+        // Change the middle, then the ends
+        record.apply(ActionBufferEdit::new("buffer", b"BBBBBB", 2))?;
+        assert_eq!(b"AABBBBBBAA".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        record.apply(ActionBufferEdit::new("buffer", b"CC", 8))?;
+        assert_eq!(b"AABBBBBBCC".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        record.apply(ActionBufferEdit::new("buffer", b"D", 0))?;
+        assert_eq!(b"DABBBBBBCC".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        // Undo + redo
+        record.undo()?;
+        assert_eq!(b"AABBBBBBCC".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+        record.undo()?;
+        assert_eq!(b"AABBBBBBAA".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+        record.undo()?;
+        assert_eq!(b"AAAAAAAAAA".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        record.redo()?;
+        record.redo()?;
+        record.redo()?;
+        assert_eq!(b"DABBBBBBCC".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        // Bad edits
+        assert!(record.apply(ActionBufferEdit::new("buffer", b"EE", 9)).is_err());
+        assert!(record.apply(ActionBufferEdit::new("buffer", b"", 0)).is_err());
+        assert!(record.apply(ActionBufferEdit::new("nobuffer", b"E", 0)).is_err());
+
+        Ok(())
+    }
+
+    /// A four-character tag, followed by a CRC-32 of the tag.
+    fn tagged_crc() -> SimpleResult<H2Type> {
+        H2Struct::new(vec![
+            ("tag".to_string(), H2Array::new(4, H2Number::new_ascii())?),
+            ("crc".to_string(), H2Checksum::new(
+                ChecksumType::CRC(Crc::CRC32),
+                ChecksumStorage::Number(GenericReader::U32(Endian::Big)),
+                ChecksumBound::ParentStart(0),
+                ChecksumBound::Field(0),
+            )?),
+        ])
+    }
+
+    #[test]
+    fn test_fix_checksums() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // Two tags, each with a valid CRC
+        let data = b"IEND\xae\x42\x60\x82IEND\xae\x42\x60\x82".to_vec();
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &data, 0))?;
+        record.apply(ActionLayerCreate::new("buffer", "default"))?;
+        record.apply(ActionEntryCreateFromType::new("buffer", "default", tagged_crc()?, 0))?;
+        record.apply(ActionEntryCreateFromType::new("buffer", "default", tagged_crc()?, 8))?;
+        assert_eq!("{ tag: [ 'I', 'E', 'N', 'D' ], crc: 0xae426082 (CRC-32, valid) }", display(&record, 0));
+
+        // Without fixing, only the data changes - the entries aren't touched
+        record.apply(ActionBufferEdit::new("buffer", b"ABCD", 0))?;
+        assert_eq!(b"ABCD\xae\x42\x60\x82IEND\xae\x42\x60\x82".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+        assert_eq!("{ tag: [ 'I', 'E', 'N', 'D' ], crc: 0xae426082 (CRC-32, valid) }", display(&record, 0));
+        record.undo()?;
+
+        // With fixing, only the first tag's CRC changes
+        record.apply(ActionBufferEdit::new_fix_checksums("buffer", b"ABCD", 0))?;
+        assert_eq!(b"ABCD\xdb\x17\x20\xa5IEND\xae\x42\x60\x82".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+        assert_eq!("{ tag: [ 'A', 'B', 'C', 'D' ], crc: 0xdb1720a5 (CRC-32, valid) }", display(&record, 0));
+        assert_eq!("{ tag: [ 'I', 'E', 'N', 'D' ], crc: 0xae426082 (CRC-32, valid) }", display(&record, 8));
+
+        // Undo puts everything back, together
+        record.undo()?;
+        assert_eq!(data, record.target().buffer_get("buffer").unwrap().data);
+        assert_eq!("{ tag: [ 'I', 'E', 'N', 'D' ], crc: 0xae426082 (CRC-32, valid) }", display(&record, 0));
+
+        record.redo()?;
+        assert_eq!(b"ABCD\xdb\x17\x20\xa5IEND\xae\x42\x60\x82".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fix_nested_checksums() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // An 8-bit sum of the whole thing (including the inner checksum),
+        // then a tag with its own checksum
+        let t = H2Struct::new(vec![
+            ("sum".to_string(),   H2Checksum::new(
+                ChecksumType::Sum8,
+                ChecksumStorage::Bytes,
+                ChecksumBound::Field(1),
+                ChecksumBound::ParentEnd(0),
+            )?),
+            ("tag".to_string(), tagged_crc()?),
+        ])?;
+
+        let data = b"\x00IEND\xae\x42\x60\x82".to_vec();
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &data, 0))?;
+        record.apply(ActionLayerCreate::new("buffer", "default"))?;
+        record.apply(ActionEntryCreateFromType::new("buffer", "default", t, 0))?;
+
+        // Both are fixed, inner then outer
+        record.apply(ActionBufferEdit::new_fix_checksums("buffer", b"ABCD", 1))?;
+
+        let sum = b"ABCD\xdb\x17\x20\xa5".iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+        let mut expected = vec![sum];
+        expected.extend_from_slice(b"ABCD\xdb\x17\x20\xa5");
+        assert_eq!(expected, record.target().buffer_get("buffer").unwrap().data);
+
+        record.undo()?;
+        assert_eq!(data, record.target().buffer_get("buffer").unwrap().data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fix_checksums_entry_changes_size() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // A length, that many bytes, then a checksum of the bytes
+        let t = H2Struct::new(vec![
+            ("length".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("data".to_string(),   H2Array::new_dynamic(
+                ArrayLength::Count(Expression::parse("length")?),
+                H2Number::new_ascii(),
+            )?),
+            ("sum".to_string(),    H2Checksum::new(
+                ChecksumType::Sum8,
+                ChecksumStorage::Bytes,
+                ChecksumBound::ParentStart(1),
+                ChecksumBound::Field(0),
+            )?),
+        ])?;
+
+        let data = b"\x02AB\x83\x00".to_vec();
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &data, 0))?;
+        record.apply(ActionLayerCreate::new("buffer", "default"))?;
+        record.apply(ActionEntryCreateFromType::new("buffer", "default", t, 0))?;
+
+        // Changing the data is fine
+        record.apply(ActionBufferEdit::new_fix_checksums("buffer", b"AC", 1))?;
+        assert_eq!(b"\x02AC\x84\x00".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+        assert_eq!("{ length: 2, data: [ 'A', 'C' ], sum: 84 (Sum-8, valid) }", display(&record, 0));
+
+        // Changing the length would change the entry's size, so nothing
+        // changes
+        assert!(record.apply(ActionBufferEdit::new_fix_checksums("buffer", b"\x01", 0)).is_err());
+        assert_eq!(b"\x02AC\x84\x00".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+        assert_eq!("{ length: 2, data: [ 'A', 'C' ], sum: 84 (Sum-8, valid) }", display(&record, 0));
+
+        // Undo still refreshes
+        record.undo()?;
+        assert_eq!(data, record.target().buffer_get("buffer").unwrap().data);
+        assert_eq!("{ length: 2, data: [ 'A', 'B' ], sum: 83 (Sum-8, valid) }", display(&record, 0));
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -4,12 +4,20 @@ use simple_error::{SimpleResult, SimpleError, bail};
 
 use crate::project::H2Project;
 use crate::actions::Action;
-use crate::transformation::Transformation;
+use crate::transformation::{Transformation, CipherTemplate};
+
+/// The transformation to apply - either ready to go, or a template that still
+/// needs to be resolved against the project.
+#[derive(Serialize, Deserialize, Debug)]
+enum ToApply {
+    Transformation(Transformation),
+    Template(CipherTemplate),
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Forward {
     name: String,
-    transformation: Transformation,
+    transformation: ToApply,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -33,11 +41,35 @@ impl ActionBufferTransform {
             ActionBufferTransform(
                 State::Forward(Forward {
                     name: String::from(name),
-                    transformation: transformation,
+                    transformation: ToApply::Transformation(transformation),
+                })
+            )
+        )
+    }
+
+    /// Transform a buffer with a cipher whose key and / or IV come from the
+    /// project (or the buffer itself).
+    ///
+    /// The key and IV are read when the action is applied. The resulting
+    /// transformation remembers where they came from, and the buffers they
+    /// came from can't be removed while it's in place.
+    pub fn new_from_template(name: &str, template: CipherTemplate) -> Action {
+        Action::BufferTransform(
+            ActionBufferTransform(
+                State::Forward(Forward {
+                    name: String::from(name),
+                    transformation: ToApply::Template(template),
                 })
             )
         )
     }
+
+    /// Create one action per transformation in a chain - such as the ones
+    /// returned by [`Transformation::detect_recursive`] - to be applied in
+    /// order.
+    pub fn new_chain(name: &str, chain: &[Transformation]) -> Vec<Action> {
+        chain.iter().map(|t| Self::new(name, t.clone())).collect()
+    }
 }
 
 impl Command for ActionBufferTransform {
@@ -51,6 +83,12 @@ impl Command for ActionBufferTransform {
             _                 => bail!("Failed to apply: action ended up in a broken undo/redo state"),
         };
 
+        // Read the key and such, if needed
+        let transformation = match &forward.transformation {
+            ToApply::Transformation(t) => t.clone(),
+            ToApply::Template(t)       => project.cipher_template_resolve(&forward.name, t)?,
+        };
+
         // Get a handle to the buffer
         let buffer = match project.buffer_get_mut(&forward.name) {
             Some(b) => b,
@@ -58,7 +96,7 @@ impl Command for ActionBufferTransform {
         };
 
         // Transform the buffer, and get the original data (for undo)
-        let original_data = buffer.transform(forward.transformation)?;
+        let original_data = buffer.transform(transformation)?;
 
         // Save the backward struct
         self.0 = State::Backward(Backward {
@@ -85,10 +123,11 @@ impl Command for ActionBufferTransform {
         // Do the undo and save the transformation
         let transformation = buffer.transform_undo(backward.original_data.clone())?;
 
-        // Save the forward struct
+        // Save the forward struct - the key and IV have already been read,
+        // so keep the resolved transformation
         self.0 = State::Forward(Forward {
             name: backward.name.clone(),
-            transformation: transformation,
+            transformation: ToApply::Transformation(transformation),
         });
 
         Ok(())
@@ -101,9 +140,14 @@ mod tests {
 
     use redo::Record;
     use pretty_assertions::assert_eq;
+    use std::time::Duration;
 
-    use crate::transformation::{TransformHex, TransformBase64};
-    use crate::actions::{Action, ActionBufferCreateFromBytes};
+    use crate::transformation::{TransformHex, TransformBase64, DetectionLimits};
+    use crate::transformation::{BlockCipherType, BlockCipherMode, BlockCipherPadding, KeySource, StreamCipherType};
+    use crate::actions::{Action, ActionBufferCreateFromBytes, ActionLayerCreate, ActionEntryCreateFromType};
+    use crate::datatype::composite::H2Array;
+    use crate::datatype::simple::H2Number;
+    use crate::generic_number::{GenericReader, DefaultFormatter};
 
     #[test]
     fn test_action() -> SimpleResult<()> {
@@ -164,6 +208,198 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_action_chain() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // "Hello, world" as hex, then base64
+        let action = ActionBufferCreateFromBytes::new("buffer", &b"NDg2NTZjNmM2ZjJjMjA3NzZmNzI2YzY0".to_vec(), 0x80000000);
+        record.apply(action)?;
+
+        // Detect the layers, and apply the best chain (with plenty of time, so
+        // a slow machine finds the same thing)
+        let limits = DetectionLimits::new(2, Duration::from_secs(3600), 100);
+        let results = Transformation::detect_recursive(&record.target().buffer_get("buffer").unwrap().data, &limits);
+        assert_eq!(vec![TransformBase64::standard(), TransformHex::new()], results[0].chain);
+
+        for action in ActionBufferTransform::new_chain("buffer", &results[0].chain) {
+            record.apply(action)?;
+        }
+        assert_eq!(b"Hello, world".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        // Each layer undoes separately
+        record.undo()?;
+        assert_eq!(b"48656c6c6f2c20776f726c64".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        record.undo()?;
+        assert_eq!(b"NDg2NTZjNmM2ZjJjMjA3NzZmNzI2YzY0".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_iv_prefix() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // The IV is the first 16 bytes, and the rest is ciphertext
+        let data = b"BBBBBBBBBBBBBBBB\x9c\xf2\x65\x82\xa2\xa7\x8b\x65\xcb\x41\xbc\x2d\x02\x1a\xe4\x18\xaf\xf4\xbc\x9e\xf4\x0c\x8a\x26\xc4\x97\x22\x26\x3e\xc2\x34\x95".to_vec();
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &data, 0x80000000))?;
+
+        let template = CipherTemplate::block(
+            BlockCipherType::AES,
+            BlockCipherMode::CBC,
+            BlockCipherPadding::Pkcs7,
+            KeySource::Literal(b"AAAAAAAAAAAAAAAA".to_vec()),
+            Some(KeySource::Prefix(16)),
+        );
+        record.apply(ActionBufferTransform::new_from_template("buffer", template))?;
+        assert_eq!(b"Hello example section!".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        record.undo()?;
+        assert_eq!(data, record.target().buffer_get("buffer").unwrap().data);
+
+        record.redo()?;
+        assert_eq!(b"Hello example section!".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_key_from_project() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // The key is in one buffer, and the IV is an entry in another
+        record.apply(ActionBufferCreateFromBytes::new("keys", &b"KEY:AAAAAAAAAAAAAAAA".to_vec(), 0))?;
+        record.apply(ActionBufferCreateFromBytes::new("ivs", &b"IV:BBBBBBBBBBBBBBBB".to_vec(), 0))?;
+        record.apply(ActionLayerCreate::new("ivs", "default"))?;
+        record.apply(ActionEntryCreateFromType::new("ivs", "default", H2Array::new(16, H2Number::new(GenericReader::U8, DefaultFormatter::new()))?, 3))?;
+
+        let ciphertext = b"\x9c\xf2\x65\x82\xa2\xa7\x8b\x65\xcb\x41\xbc\x2d\x02\x1a\xe4\x18\xaf\xf4\xbc\x9e\xf4\x0c\x8a\x26\xc4\x97\x22\x26\x3e\xc2\x34\x95".to_vec();
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &ciphertext, 0))?;
+        assert!(record.target().buffer_can_be_removed("keys")?);
+
+        let template = CipherTemplate::block(
+            BlockCipherType::AES,
+            BlockCipherMode::CBC,
+            BlockCipherPadding::Pkcs7,
+            KeySource::Buffer { buffer: "keys".to_string(), offset: 4, length: 16 },
+            Some(KeySource::Entry { buffer: "ivs".to_string(), layer: "default".to_string(), offset: 10 }),
+        );
+        record.apply(ActionBufferTransform::new_from_template("buffer", template))?;
+        assert_eq!(b"Hello example section!".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        // The key buffer is in use now
+        assert!(!record.target().buffer_can_be_removed("keys")?);
+
+        // That survives serialization
+        let serialized = ron::ser::to_string(record.target()).unwrap();
+        let project: H2Project = ron::de::from_str(&serialized).unwrap();
+        assert!(!project.buffer_can_be_removed("keys")?);
+        assert!(project.buffer_get("buffer").unwrap().references_buffer("ivs"));
+
+        // Undo and redo
+        record.undo()?;
+        assert_eq!(ciphertext, record.target().buffer_get("buffer").unwrap().data);
+        assert!(record.target().buffer_can_be_removed("keys")?);
+
+        record.redo()?;
+        assert_eq!(b"Hello example section!".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_substitution_from_project() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // The S-box lives after a small header in another buffer
+        let sbox: Vec<u8> = (0..=255u8).map(|b| b.wrapping_mul(5).wrapping_add(1)).collect();
+        record.apply(ActionBufferCreateFromBytes::new("sboxes", &[b"SBOX".to_vec(), sbox.clone()].concat(), 0))?;
+
+        let data: Vec<u8> = b"Hello".iter().map(|b| sbox.iter().position(|s| s == b).unwrap() as u8).collect();
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &data, 0))?;
+
+        let template = CipherTemplate::substitution(KeySource::Buffer { buffer: "sboxes".to_string(), offset: 4, length: 256 });
+        record.apply(ActionBufferTransform::new_from_template("buffer", template))?;
+        assert_eq!(b"Hello".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+        assert!(!record.target().buffer_can_be_removed("sboxes")?);
+
+        record.undo()?;
+        assert_eq!(data, record.target().buffer_get("buffer").unwrap().data);
+        assert!(record.target().buffer_can_be_removed("sboxes")?);
+
+        record.redo()?;
+        assert_eq!(b"Hello".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_keystream_from_project() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // The keystream is another buffer, and the data starts partway in
+        let keystream: Vec<u8> = (0..64u8).map(|b| b.wrapping_mul(37)).collect();
+        record.apply(ActionBufferCreateFromBytes::new("keystream", &keystream, 0))?;
+
+        let data: Vec<u8> = b"Hello".iter().zip(keystream[10..].iter()).map(|(b, k)| b ^ k).collect();
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &data, 0))?;
+
+        let template = CipherTemplate::stream_with_offset(
+            StreamCipherType::Keystream,
+            KeySource::Buffer { buffer: "keystream".to_string(), offset: 0, length: 64 },
+            None,
+            10,
+        );
+        record.apply(ActionBufferTransform::new_from_template("buffer", template))?;
+        assert_eq!(b"Hello".to_vec(), record.target().buffer_get("buffer").unwrap().data);
+        assert!(!record.target().buffer_can_be_removed("keystream")?);
+
+        record.undo()?;
+        assert_eq!(data, record.target().buffer_get("buffer").unwrap().data);
+        assert!(record.target().buffer_can_be_removed("keystream")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_key_source_missing() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        let ciphertext = b"\x9c\xf2\x65\x82\xa2\xa7\x8b\x65\xcb\x41\xbc\x2d\x02\x1a\xe4\x18\xaf\xf4\xbc\x9e\xf4\x0c\x8a\x26\xc4\x97\x22\x26\x3e\xc2\x34\x95".to_vec();
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &ciphertext, 0))?;
+        record.apply(ActionBufferCreateFromBytes::new("keys", &b"KEY:AAAAAAAAAAAAAAAA".to_vec(), 0))?;
+
+        // No such buffer
+        let template = CipherTemplate::block(BlockCipherType::AES, BlockCipherMode::ECB, BlockCipherPadding::Pkcs7, KeySource::Buffer { buffer: "nokeys".to_string(), offset: 0, length: 16 }, None);
+        assert!(record.apply(ActionBufferTransform::new_from_template("buffer", template)).is_err());
+
+        // Off the end of the buffer
+        let template = CipherTemplate::block(BlockCipherType::AES, BlockCipherMode::ECB, BlockCipherPadding::Pkcs7, KeySource::Buffer { buffer: "keys".to_string(), offset: 8, length: 16 }, None);
+        assert!(record.apply(ActionBufferTransform::new_from_template("buffer", template)).is_err());
+
+        // No such entry
+        let template = CipherTemplate::block(BlockCipherType::AES, BlockCipherMode::ECB, BlockCipherPadding::Pkcs7, KeySource::Entry { buffer: "keys".to_string(), layer: "default".to_string(), offset: 0 }, None);
+        assert!(record.apply(ActionBufferTransform::new_from_template("buffer", template)).is_err());
+
+        // Nothing changed
+        assert_eq!(ciphertext, record.target().buffer_get("buffer").unwrap().data);
+
+        Ok(())
+    }
+
     // #[test]
     // fn test_action_fails_when_buffer_is_populated() -> SimpleResult<()> {
     //     Ok(())
@@ -0,0 +1,232 @@
+//! Transform part of a buffer into a new buffer.
+//!
+//! The original buffer isn't changed (and can have layers and entries); the
+//! new buffer remembers the parent, the range, and the transformations.
+
+use redo::Command;
+use serde::{Serialize, Deserialize};
+use simple_error::{SimpleResult, SimpleError, bail};
+use std::ops::Range;
+
+use crate::project::H2Project;
+use crate::actions::Action;
+use crate::transformation::Transformation;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Forward {
+    parent: String,
+    name: String,
+    range: Range<usize>,
+    transformations: Vec<Transformation>,
+    base_address: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Backward {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum State {
+    Forward(Forward),
+    Backward(Backward),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ActionBufferTransformPartial(State);
+
+impl ActionBufferTransformPartial {
+    /// Transform `range` of the buffer `parent` with each of the
+    /// `transformations` in turn, and put the result in a new buffer called
+    /// `name`.
+    pub fn new(parent: &str, name: &str, range: Range<usize>, transformations: &[Transformation], base_address: usize) -> Action {
+        Action::BufferTransformPartial(
+            ActionBufferTransformPartial(
+                State::Forward(Forward {
+                    parent: String::from(parent),
+                    name: String::from(name),
+                    range,
+                    transformations: transformations.to_vec(),
+                    base_address,
+                })
+            )
+        )
+    }
+}
+
+impl Command for ActionBufferTransformPartial {
+    type Target = H2Project;
+    type Error = SimpleError;
+
+    fn apply(&mut self, project: &mut H2Project) -> SimpleResult<()> {
+        // Get the forward struct
+        let forward = match &self.0 {
+            State::Forward(f) => f,
+            _                 => bail!("Failed to apply: action ended up in a broken undo/redo state"),
+        };
+
+        // Make sure we'll be able to insert before doing the work
+        if project.buffer_exists(&forward.name) {
+            bail!("Buffer already exists: {}", forward.name);
+        }
+
+        let parent = match project.buffer_get(&forward.parent) {
+            Some(b) => b,
+            None => bail!("Could not find buffer {} to transform", &forward.parent),
+        };
+
+        // Create the new buffer (this doesn't change the parent)
+        let buffer = parent.transform_partial(&forward.name, forward.range.clone(), &forward.transformations, forward.base_address)?;
+        project.buffer_insert(&forward.name, buffer)?;
+
+        // Save the backward struct
+        self.0 = State::Backward(Backward {
+            name: forward.name.clone(),
+        });
+
+        Ok(())
+    }
+
+    fn undo(&mut self, project: &mut H2Project) -> SimpleResult<()> {
+        // Get the backward struct
+        let backward = match &self.0 {
+            State::Backward(b) => b,
+            _                  => bail!("Failed to undo: action ended up in a broken undo/redo state"),
+        };
+
+        // Remove the buffer - everything we need to re-create it is stored in
+        // the buffer
+        let buffer = project.buffer_remove(&backward.name)?;
+
+        let origin = match buffer.origin() {
+            Some(o) => o.clone(),
+            None => bail!("Buffer {} doesn't know where it came from", &backward.name),
+        };
+
+        // Save the forward struct
+        self.0 = State::Forward(Forward {
+            parent: origin.parent,
+            name: backward.name.clone(),
+            range: origin.range,
+            transformations: buffer.transformations().clone(),
+            base_address: buffer.base_address,
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use redo::Record;
+    use pretty_assertions::assert_eq;
+
+    use crate::actions::{ActionBufferCreateFromBytes, ActionBufferTransform, ActionLayerCreate, ActionEntryCreateFromType};
+    use crate::datatype::simple::H2Number;
+    use crate::project::H2BufferOrigin;
+    use crate::transformation::{TransformBase64, TransformHex};
+
+    #[test]
+    fn test_action() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // "Hello, world" as hex, then base64, in the middle of some JSON
+        let data = b"{\"blob\": \"NDg2NTZjNmM2ZjJjMjA3NzZmNzI2YzY0\"}".to_vec();
+        record.apply(ActionBufferCreateFromBytes::new("json", &data, 0x1000))?;
+
+        // Give the parent a layer and an entry, to make sure it's left alone
+        record.apply(ActionLayerCreate::new("json", "default"))?;
+        record.apply(ActionEntryCreateFromType::new("json", "default", H2Number::new_ascii(), 0))?;
+
+        let chain = vec![TransformBase64::new(false, false, false), TransformHex::new()];
+        record.apply(ActionBufferTransformPartial::new("json", "blob", 10..42, &chain, 0))?;
+
+        let child = record.target().buffer_get("blob").unwrap();
+        assert_eq!(b"Hello, world".to_vec(), child.data);
+        assert_eq!(0, child.base_address);
+        assert_eq!(&chain, child.transformations());
+        assert_eq!(Some(&H2BufferOrigin { parent: "json".to_string(), range: 10..42 }), child.origin());
+
+        // The parent is untouched
+        let parent = record.target().buffer_get("json").unwrap();
+        assert_eq!(data, parent.data);
+        assert!(parent.layer_get("default").unwrap().entry_get(0).is_some());
+
+        // The parent can't be removed while the child is there
+        assert!(!record.target().buffer_can_be_removed("json")?);
+
+        // Undo
+        record.undo()?;
+        assert!(!record.target().buffer_exists("blob"));
+        assert_eq!(data, record.target().buffer_get("json").unwrap().data);
+
+        // Redo
+        record.redo()?;
+        assert_eq!(b"Hello, world".to_vec(), record.target().buffer_get("blob").unwrap().data);
+        assert_eq!(&chain, record.target().buffer_get("blob").unwrap().transformations());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_then_transform() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        // A cleartext header, then a base64 section that's hex
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &b"HEADER:NGE0QjRjNEQ0ZQ==".to_vec(), 0))?;
+
+        // Transform just the base64, then hex-decode the child separately
+        record.apply(ActionBufferTransformPartial::new("buffer", "child", 7..23, &[TransformBase64::new(false, false, false)], 0))?;
+        assert_eq!(b"4a4B4c4D4e".to_vec(), record.target().buffer_get("child").unwrap().data);
+
+        record.apply(ActionBufferTransform::new("child", TransformHex::new()))?;
+        assert_eq!(b"JKLMN".to_vec(), record.target().buffer_get("child").unwrap().data);
+        assert_eq!(2, record.target().buffer_get("child").unwrap().transformations().len());
+
+        // Undo both
+        record.undo()?;
+        record.undo()?;
+        assert!(!record.target().buffer_exists("child"));
+
+        // Redo both
+        record.redo()?;
+        record.redo()?;
+        assert_eq!(b"JKLMN".to_vec(), record.target().buffer_get("child").unwrap().data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_errors() -> SimpleResult<()> {
+        let mut record: Record<Action> = Record::new(
+            H2Project::new("name", "1.0")
+        );
+
+        record.apply(ActionBufferCreateFromBytes::new("buffer", &b"HEADER:NGE0QjRjNEQ0ZQ==".to_vec(), 0))?;
+        record.apply(ActionBufferCreateFromBytes::new("existing", &b"A".to_vec(), 0))?;
+
+        // No such buffer
+        assert!(record.apply(ActionBufferTransformPartial::new("nobuffer", "child", 7..23, &[TransformBase64::new(false, false, false)], 0)).is_err());
+
+        // The new buffer already exists
+        assert!(record.apply(ActionBufferTransformPartial::new("buffer", "existing", 7..23, &[TransformBase64::new(false, false, false)], 0)).is_err());
+
+        // Off the end, or empty
+        assert!(record.apply(ActionBufferTransformPartial::new("buffer", "child", 7..24, &[TransformBase64::new(false, false, false)], 0)).is_err());
+        assert!(record.apply(ActionBufferTransformPartial::new("buffer", "child", 7..7, &[TransformBase64::new(false, false, false)], 0)).is_err());
+
+        // Not base64
+        assert!(record.apply(ActionBufferTransformPartial::new("buffer", "child", 0..7, &[TransformBase64::new(false, false, false)], 0)).is_err());
+
+        // Nothing was created
+        assert!(!record.target().buffer_exists("child"));
+
+        Ok(())
+    }
+}
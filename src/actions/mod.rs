@@ -37,6 +37,12 @@ pub use buffer_create_from_bytes::ActionBufferCreateFromBytes;
 mod buffer_transform;
 pub use buffer_transform::ActionBufferTransform;
 
+mod buffer_transform_partial;
+pub use buffer_transform_partial::ActionBufferTransformPartial;
+
+mod buffer_edit;
+pub use buffer_edit::ActionBufferEdit;
+
 mod null;
 pub use null::NullAction;
 
@@ -56,6 +62,8 @@ pub enum Action {
     BufferCreateEmpty(ActionBufferCreateEmpty),
     BufferCreateFromBytes(ActionBufferCreateFromBytes),
     BufferTransform(ActionBufferTransform),
+    BufferTransformPartial(ActionBufferTransformPartial),
+    BufferEdit(ActionBufferEdit),
     LayerCreate(ActionLayerCreate),
     EntryCreateFromType(ActionEntryCreateFromType),
     EntrySetComment(ActionEntrySetComment),
@@ -71,6 +79,8 @@ impl Command for Action {
             Action::BufferCreateEmpty(a)     => a.apply(project),
             Action::BufferCreateFromBytes(a) => a.apply(project),
             Action::BufferTransform(a)       => a.apply(project),
+            Action::BufferTransformPartial(a) => a.apply(project),
+            Action::BufferEdit(a)            => a.apply(project),
             Action::LayerCreate(a)           => a.apply(project),
             Action::EntryCreateFromType(a)   => a.apply(project),
             Action::EntrySetComment(a)       => a.apply(project),
@@ -83,6 +93,8 @@ impl Command for Action {
             Action::BufferCreateEmpty(a)     => a.undo(project),
             Action::BufferCreateFromBytes(a) => a.undo(project),
             Action::BufferTransform(a)       => a.undo(project),
+            Action::BufferTransformPartial(a) => a.undo(project),
+            Action::BufferEdit(a)            => a.undo(project),
             Action::LayerCreate(a)           => a.undo(project),
             Action::EntryCreateFromType(a)   => a.undo(project),
             Action::EntrySetComment(a)       => a.undo(project),
@@ -0,0 +1,332 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use md5::{Md5, Digest};
+use sha1::Sha1;
+use sha2::{Sha224, Sha256, Sha384, Sha512};
+use sha3::{Sha3_224, Sha3_256, Sha3_384, Sha3_512};
+use blake2::{Blake2b512, Blake2s256};
+
+use crate::checksum::Crc;
+
+/// An algorithm for calculating a checksum or hash.
+///
+/// These range from simple sums, which are common in older or embedded
+/// formats, to CRCs, to cryptographic hashes.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub enum ChecksumType {
+    /// Any CRC - see [`Crc`] for the common ones.
+    CRC(Crc),
+
+    /// Adler-32, as used by zlib.
+    Adler32,
+
+    /// Fletcher's checksum over bytes.
+    Fletcher16,
+
+    /// Fletcher's checksum over 16-bit little-endian words (an odd byte at
+    /// the end is padded with zero).
+    Fletcher32,
+
+    /// Fletcher's checksum over 32-bit little-endian words (the end is
+    /// padded with zeroes).
+    Fletcher64,
+
+    /// The sum of the bytes, truncated to 8 bits.
+    Sum8,
+
+    /// The sum of the bytes, truncated to 16 bits.
+    Sum16,
+
+    /// The sum of the bytes, truncated to 32 bits.
+    Sum32,
+
+    /// The bytes XORed together.
+    XOR8,
+
+    MD5,
+    SHA1,
+    SHA224,
+    SHA256,
+    SHA384,
+    SHA512,
+    SHA3_224,
+    SHA3_256,
+    SHA3_384,
+    SHA3_512,
+
+    /// BLAKE2b, with a 512-bit output.
+    BLAKE2b,
+
+    /// BLAKE2s, with a 256-bit output.
+    BLAKE2s,
+
+    /// BLAKE3, with a 256-bit output.
+    BLAKE3,
+}
+
+impl fmt::Display for ChecksumType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Fletcher's checksum, over `word_size`-byte little-endian words. The
+/// result is twice as wide as the words.
+fn fletcher(data: &[u8], word_size: usize) -> u64 {
+    let bits = word_size * 8;
+    let modulus = (1u64 << bits) - 1;
+
+    let mut sum1: u64 = 0;
+    let mut sum2: u64 = 0;
+
+    for chunk in data.chunks(word_size) {
+        let word = chunk.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+
+        sum1 = (sum1 + word) % modulus;
+        sum2 = (sum2 + sum1) % modulus;
+    }
+
+    (sum2 << bits) | sum1
+}
+
+fn adler32(data: &[u8]) -> u64 {
+    let mut a: u64 = 1;
+    let mut b: u64 = 0;
+
+    for byte in data {
+        a = (a + *byte as u64) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+impl ChecksumType {
+    /// Get a short, human-readable name for the algorithm.
+    pub fn name(&self) -> String {
+        match self {
+            Self::CRC(crc)    => crc.name(),
+            Self::Adler32     => "Adler-32".to_string(),
+            Self::Fletcher16  => "Fletcher-16".to_string(),
+            Self::Fletcher32  => "Fletcher-32".to_string(),
+            Self::Fletcher64  => "Fletcher-64".to_string(),
+            Self::Sum8        => "Sum-8".to_string(),
+            Self::Sum16       => "Sum-16".to_string(),
+            Self::Sum32       => "Sum-32".to_string(),
+            Self::XOR8        => "XOR-8".to_string(),
+            Self::MD5         => "MD5".to_string(),
+            Self::SHA1        => "SHA-1".to_string(),
+            Self::SHA224      => "SHA-224".to_string(),
+            Self::SHA256      => "SHA-256".to_string(),
+            Self::SHA384      => "SHA-384".to_string(),
+            Self::SHA512      => "SHA-512".to_string(),
+            Self::SHA3_224    => "SHA3-224".to_string(),
+            Self::SHA3_256    => "SHA3-256".to_string(),
+            Self::SHA3_384    => "SHA3-384".to_string(),
+            Self::SHA3_512    => "SHA3-512".to_string(),
+            Self::BLAKE2b     => "BLAKE2b".to_string(),
+            Self::BLAKE2s     => "BLAKE2s".to_string(),
+            Self::BLAKE3      => "BLAKE3".to_string(),
+        }
+    }
+
+    /// The size of the checksum, in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::CRC(crc)   => crc.size(),
+            Self::Adler32    => 4,
+            Self::Fletcher16 => 2,
+            Self::Fletcher32 => 4,
+            Self::Fletcher64 => 8,
+            Self::Sum8       => 1,
+            Self::Sum16      => 2,
+            Self::Sum32      => 4,
+            Self::XOR8       => 1,
+            Self::MD5        => 16,
+            Self::SHA1       => 20,
+            Self::SHA224     => 28,
+            Self::SHA256     => 32,
+            Self::SHA384     => 48,
+            Self::SHA512     => 64,
+            Self::SHA3_224   => 28,
+            Self::SHA3_256   => 32,
+            Self::SHA3_384   => 48,
+            Self::SHA3_512   => 64,
+            Self::BLAKE2b    => 64,
+            Self::BLAKE2s    => 32,
+            Self::BLAKE3     => 32,
+        }
+    }
+
+    /// Is the result a number (as opposed to a hash, which is just a string
+    /// of bytes)?
+    ///
+    /// Numeric checksums are at most 64 bits, and are often stored in either
+    /// byte order.
+    pub fn is_numeric(&self) -> bool {
+        self.numeric(b"").is_some()
+    }
+
+    /// Calculate numeric checksums, or `None` for hashes.
+    fn numeric(&self, data: &[u8]) -> Option<u64> {
+        let sum = || data.iter().fold(0u64, |acc, b| acc.wrapping_add(*b as u64));
+
+        Some(match self {
+            Self::CRC(crc)   => crc.compute(data),
+            Self::Adler32    => adler32(data),
+            Self::Fletcher16 => fletcher(data, 1),
+            Self::Fletcher32 => fletcher(data, 2),
+            Self::Fletcher64 => fletcher(data, 4),
+            Self::Sum8       => sum() & 0xff,
+            Self::Sum16      => sum() & 0xffff,
+            Self::Sum32      => sum() & 0xffffffff,
+            Self::XOR8       => data.iter().fold(0u64, |acc, b| acc ^ *b as u64),
+            _                => return None,
+        })
+    }
+
+    /// Calculate the checksum of some data.
+    pub fn compute(&self, data: &[u8]) -> Checksum {
+        let value = match self.numeric(data) {
+            // Numbers are stored big endian, and only as wide as they need
+            Some(n) => n.to_be_bytes()[(8 - self.size())..].to_vec(),
+
+            None => match self {
+                Self::MD5      => Md5::digest(data).to_vec(),
+                Self::SHA1     => Sha1::digest(data).to_vec(),
+                Self::SHA224   => Sha224::digest(data).to_vec(),
+                Self::SHA256   => Sha256::digest(data).to_vec(),
+                Self::SHA384   => Sha384::digest(data).to_vec(),
+                Self::SHA512   => Sha512::digest(data).to_vec(),
+                Self::SHA3_224 => Sha3_224::digest(data).to_vec(),
+                Self::SHA3_256 => Sha3_256::digest(data).to_vec(),
+                Self::SHA3_384 => Sha3_384::digest(data).to_vec(),
+                Self::SHA3_512 => Sha3_512::digest(data).to_vec(),
+                Self::BLAKE2b  => Blake2b512::digest(data).to_vec(),
+                Self::BLAKE2s  => Blake2s256::digest(data).to_vec(),
+                Self::BLAKE3   => blake3::hash(data).as_bytes().to_vec(),
+                _              => unreachable!(),
+            },
+        };
+
+        Checksum {
+            checksum_type: *self,
+            value,
+        }
+    }
+}
+
+/// The result of calculating a checksum.
+///
+/// This displays as the algorithm and the value, such as
+/// `CRC-32: 0xcbf43926`, which makes it suitable for a comment.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Checksum {
+    checksum_type: ChecksumType,
+    value: Vec<u8>,
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.checksum_type.is_numeric() {
+            true  => write!(f, "{}: 0x{}", self.checksum_type, hex::encode(&self.value)),
+            false => write!(f, "{}: {}", self.checksum_type, hex::encode(&self.value)),
+        }
+    }
+}
+
+impl Checksum {
+    /// Get the algorithm that was used.
+    pub fn checksum_type(&self) -> ChecksumType {
+        self.checksum_type
+    }
+
+    /// Get the raw value. Numeric checksums are big endian.
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// Get the value as a number, if it's a numeric checksum.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.checksum_type.is_numeric() {
+            true  => Some(self.value.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64)),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_numeric() {
+        let tests = vec![
+            // Test vectors for Fletcher are from Wikipedia
+            (ChecksumType::Fletcher16, b"abcde".to_vec(),     0xc8f0),
+            (ChecksumType::Fletcher16, b"abcdef".to_vec(),    0x2057),
+            (ChecksumType::Fletcher16, b"abcdefgh".to_vec(),  0x0627),
+            (ChecksumType::Fletcher32, b"abcde".to_vec(),     0xf04fc729),
+            (ChecksumType::Fletcher32, b"abcdef".to_vec(),    0x56502d2a),
+            (ChecksumType::Fletcher32, b"abcdefgh".to_vec(),  0xebe19591),
+            (ChecksumType::Fletcher64, b"abcde".to_vec(),     0xc8c6c527646362c6),
+            (ChecksumType::Fletcher64, b"abcdef".to_vec(),    0xc8c72b276463c8c6),
+            (ChecksumType::Fletcher64, b"abcdefgh".to_vec(),  0x312e2b28cccac8c6),
+
+            (ChecksumType::Adler32,    b"Wikipedia".to_vec(), 0x11e60398),
+            (ChecksumType::Adler32,    b"".to_vec(),          0x00000001),
+
+            (ChecksumType::Sum8,       b"\xff\xff\x03".to_vec(), 0x01),
+            (ChecksumType::Sum16,      b"\xff\xff\x03".to_vec(), 0x0201),
+            (ChecksumType::Sum32,      b"\xff\xff\x03".to_vec(), 0x00000201),
+            (ChecksumType::XOR8,       b"\xff\x0f\x03".to_vec(), 0xf3),
+
+            (ChecksumType::CRC(Crc::CRC32), b"123456789".to_vec(), 0xcbf43926),
+        ];
+
+        for (checksum_type, data, expected) in tests {
+            let checksum = checksum_type.compute(&data);
+
+            assert_eq!(Some(expected), checksum.as_u64(), "{}", checksum_type);
+            assert_eq!(checksum_type.size(), checksum.value().len());
+        }
+    }
+
+    #[test]
+    fn test_hashes() {
+        let tests = vec![
+            (ChecksumType::MD5,      "900150983cd24fb0d6963f7d28e17f72"),
+            (ChecksumType::SHA1,     "a9993e364706816aba3e25717850c26c9cd0d89d"),
+            (ChecksumType::SHA224,   "23097d223405d8228642a477bda255b32aadbce4bda0b3f7e36c9da7"),
+            (ChecksumType::SHA256,   "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (ChecksumType::SHA384,   "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7"),
+            (ChecksumType::SHA512,   "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"),
+            (ChecksumType::SHA3_224, "e642824c3f8cf24ad09234ee7d3c766fc9a3a5168d0c94ad73b46fdf"),
+            (ChecksumType::SHA3_256, "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"),
+            (ChecksumType::SHA3_384, "ec01498288516fc926459f58e2c6ad8df9b473cb0fc08c2596da7cf0e49be4b298d88cea927ac7f539f1edf228376d25"),
+            (ChecksumType::SHA3_512, "b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0"),
+            (ChecksumType::BLAKE2b,  "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"),
+            (ChecksumType::BLAKE2s,  "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982"),
+            (ChecksumType::BLAKE3,   "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"),
+        ];
+
+        for (checksum_type, expected) in tests {
+            let checksum = checksum_type.compute(b"abc");
+
+            assert_eq!(expected, hex::encode(checksum.value()), "{}", checksum_type);
+            assert_eq!(checksum_type.size(), checksum.value().len());
+            assert_eq!(None, checksum.as_u64());
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("CRC-32: 0xcbf43926", ChecksumType::CRC(Crc::CRC32).compute(b"123456789").to_string());
+        assert_eq!("CRC-16/MODBUS: 0x4b37", ChecksumType::CRC(Crc::CRC16_MODBUS).compute(b"123456789").to_string());
+        assert_eq!("Sum-8: 0x06", ChecksumType::Sum8.compute(b"\x01\x02\x03").to_string());
+        assert_eq!("MD5: 900150983cd24fb0d6963f7d28e17f72", ChecksumType::MD5.compute(b"abc").to_string());
+    }
+}
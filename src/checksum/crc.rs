@@ -0,0 +1,303 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::convert::TryFrom;
+
+/// A CRC algorithm, described by its parameters.
+///
+/// The parameters are the same ones that the "Rocksoft model" (and the
+/// catalogue of CRC algorithms at <https://reveng.sourceforge.io/crc-catalogue/>)
+/// use, which means that just about any CRC can be described. The common
+/// ones are available as constants, such as [`Crc::CRC32`].
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::checksum::*;
+///
+/// assert_eq!(0xcbf43926, Crc::CRC32.compute(b"123456789"));
+///
+/// // CRC-16/XMODEM, from its parameters
+/// let crc = Crc::new(16, 0x1021, 0x0000, false, false, 0x0000).unwrap();
+/// assert_eq!(0x31c3, crc.compute(b"123456789"));
+/// ```
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+#[serde(try_from = "CrcParameters")]
+pub struct Crc {
+    width: u8,
+    poly: u64,
+    init: u64,
+    refin: bool,
+    refout: bool,
+    xorout: u64,
+}
+
+/// The serialized form of a [`Crc`], which is validated by [`Crc::new`]
+/// when it's loaded.
+#[derive(Deserialize)]
+struct CrcParameters {
+    width: u8,
+    poly: u64,
+    init: u64,
+    refin: bool,
+    refout: bool,
+    xorout: u64,
+}
+
+impl TryFrom<CrcParameters> for Crc {
+    type Error = simple_error::SimpleError;
+
+    fn try_from(p: CrcParameters) -> SimpleResult<Self> {
+        Self::new(p.width, p.poly, p.init, p.refin, p.refout, p.xorout)
+    }
+}
+
+/// The well-known CRCs, and their names from the catalogue.
+const PRESETS: [(&str, Crc); 13] = [
+    ("CRC-8",           Crc::CRC8),
+    ("CRC-8/MAXIM",     Crc::CRC8_MAXIM),
+    ("CRC-16/ARC",      Crc::CRC16_ARC),
+    ("CRC-16/CCITT",    Crc::CRC16_CCITT_FALSE),
+    ("CRC-16/XMODEM",   Crc::CRC16_XMODEM),
+    ("CRC-16/MODBUS",   Crc::CRC16_MODBUS),
+    ("CRC-16/KERMIT",   Crc::CRC16_KERMIT),
+    ("CRC-32",          Crc::CRC32),
+    ("CRC-32C",         Crc::CRC32C),
+    ("CRC-32/BZIP2",    Crc::CRC32_BZIP2),
+    ("CRC-32/MPEG-2",   Crc::CRC32_MPEG2),
+    ("CRC-64/XZ",       Crc::CRC64_XZ),
+    ("CRC-64/ECMA-182", Crc::CRC64_ECMA),
+];
+
+impl Crc {
+    /// CRC-8 (also called CRC-8/SMBUS).
+    pub const CRC8: Crc = Crc { width: 8, poly: 0x07, init: 0x00, refin: false, refout: false, xorout: 0x00 };
+
+    /// CRC-8/MAXIM, used by 1-Wire devices.
+    pub const CRC8_MAXIM: Crc = Crc { width: 8, poly: 0x31, init: 0x00, refin: true, refout: true, xorout: 0x00 };
+
+    /// CRC-16/ARC (also called CRC-16 or CRC-16/IBM).
+    pub const CRC16_ARC: Crc = Crc { width: 16, poly: 0x8005, init: 0x0000, refin: true, refout: true, xorout: 0x0000 };
+
+    /// CRC-16/CCITT-FALSE (also called CRC-16/IBM-3740).
+    pub const CRC16_CCITT_FALSE: Crc = Crc { width: 16, poly: 0x1021, init: 0xffff, refin: false, refout: false, xorout: 0x0000 };
+
+    /// CRC-16/XMODEM.
+    pub const CRC16_XMODEM: Crc = Crc { width: 16, poly: 0x1021, init: 0x0000, refin: false, refout: false, xorout: 0x0000 };
+
+    /// CRC-16/MODBUS.
+    pub const CRC16_MODBUS: Crc = Crc { width: 16, poly: 0x8005, init: 0xffff, refin: true, refout: true, xorout: 0x0000 };
+
+    /// CRC-16/KERMIT.
+    pub const CRC16_KERMIT: Crc = Crc { width: 16, poly: 0x1021, init: 0x0000, refin: true, refout: true, xorout: 0x0000 };
+
+    /// CRC-32, as used by zlib, PNG, Zip, Ethernet, and many others.
+    pub const CRC32: Crc = Crc { width: 32, poly: 0x04c11db7, init: 0xffffffff, refin: true, refout: true, xorout: 0xffffffff };
+
+    /// CRC-32C (Castagnoli), as used by iSCSI and ext4.
+    pub const CRC32C: Crc = Crc { width: 32, poly: 0x1edc6f41, init: 0xffffffff, refin: true, refout: true, xorout: 0xffffffff };
+
+    /// CRC-32/BZIP2.
+    pub const CRC32_BZIP2: Crc = Crc { width: 32, poly: 0x04c11db7, init: 0xffffffff, refin: false, refout: false, xorout: 0xffffffff };
+
+    /// CRC-32/MPEG-2.
+    pub const CRC32_MPEG2: Crc = Crc { width: 32, poly: 0x04c11db7, init: 0xffffffff, refin: false, refout: false, xorout: 0x00000000 };
+
+    /// CRC-64/XZ.
+    pub const CRC64_XZ: Crc = Crc { width: 64, poly: 0x42f0e1eba9ea3693, init: 0xffffffffffffffff, refin: true, refout: true, xorout: 0xffffffffffffffff };
+
+    /// CRC-64/ECMA-182.
+    pub const CRC64_ECMA: Crc = Crc { width: 64, poly: 0x42f0e1eba9ea3693, init: 0x0000000000000000, refin: false, refout: false, xorout: 0x0000000000000000 };
+
+    /// Create a CRC from its parameters.
+    ///
+    /// # Errors
+    ///
+    /// * The width must be between 1 and 64 bits
+    /// * The polynomial, init, and xorout values must fit in the width
+    pub fn new(width: u8, poly: u64, init: u64, refin: bool, refout: bool, xorout: u64) -> SimpleResult<Self> {
+        if width == 0 || width > 64 {
+            bail!("CRC width must be between 1 and 64 bits");
+        }
+
+        let crc = Self {
+            width,
+            poly,
+            init,
+            refin,
+            refout,
+            xorout,
+        };
+
+        if (poly | init | xorout) & !crc.mask() != 0 {
+            bail!("CRC parameters don't fit in {} bits", width);
+        }
+
+        Ok(crc)
+    }
+
+    /// The width of the CRC, in bits (1 - 64).
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    /// The polynomial, not reflected, without the top bit.
+    pub fn poly(&self) -> u64 {
+        self.poly
+    }
+
+    /// The value the register starts with.
+    pub fn init(&self) -> u64 {
+        self.init
+    }
+
+    /// Whether each input byte is reflected (processed least significant
+    /// bit first).
+    pub fn refin(&self) -> bool {
+        self.refin
+    }
+
+    /// Whether the final register is reflected.
+    pub fn refout(&self) -> bool {
+        self.refout
+    }
+
+    /// The value XORed with the final register.
+    pub fn xorout(&self) -> u64 {
+        self.xorout
+    }
+
+    /// Look up one of the well-known CRCs by name, such as `CRC-32` or
+    /// `CRC-16/MODBUS` (case insensitive).
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, crc)| *crc)
+    }
+
+    /// Get the name of the CRC - the catalogue name if it's a well-known
+    /// one, otherwise just its width.
+    pub fn name(&self) -> String {
+        match PRESETS.iter().find(|(_, crc)| crc == self) {
+            Some((name, _)) => name.to_string(),
+            None            => format!("CRC-{}", self.width),
+        }
+    }
+
+    /// The number of bytes needed to store the CRC.
+    pub fn size(&self) -> usize {
+        (self.width as usize).div_ceil(8)
+    }
+
+    fn mask(&self) -> u64 {
+        match self.width {
+            64 => !0,
+            w  => (1 << w) - 1,
+        }
+    }
+
+    /// Calculate the CRC of some data.
+    pub fn compute(&self, data: &[u8]) -> u64 {
+        let mask = self.mask();
+        let top = 1 << (self.width - 1);
+
+        // This works a bit at a time, which is slow but works for any width
+        let mut register = self.init;
+        for b in data {
+            let b = match self.refin {
+                true  => b.reverse_bits(),
+                false => *b,
+            };
+
+            for i in (0..8).rev() {
+                let bit = (b >> i) & 1 == 1;
+                let carry = register & top != 0;
+
+                register = (register << 1) & mask;
+                if bit != carry {
+                    register ^= self.poly;
+                }
+            }
+        }
+
+        if self.refout {
+            register = register.reverse_bits() >> (64 - self.width);
+        }
+
+        (register ^ self.xorout) & mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_presets() {
+        // The check values from the catalogue
+        let tests = vec![
+            ("CRC-8",           0xf4),
+            ("CRC-8/MAXIM",     0xa1),
+            ("CRC-16/ARC",      0xbb3d),
+            ("CRC-16/CCITT",    0x29b1),
+            ("CRC-16/XMODEM",   0x31c3),
+            ("CRC-16/MODBUS",   0x4b37),
+            ("CRC-16/KERMIT",   0x2189),
+            ("CRC-32",          0xcbf43926),
+            ("CRC-32C",         0xe3069283),
+            ("CRC-32/BZIP2",    0xfc891918),
+            ("CRC-32/MPEG-2",   0x0376e6e7),
+            ("CRC-64/XZ",       0x995dc9bbdf1939fa),
+            ("CRC-64/ECMA-182", 0x6c40df5f0b497347),
+        ];
+
+        for (name, expected) in tests {
+            let crc = Crc::preset(name).unwrap();
+            assert_eq!(expected, crc.compute(b"123456789"), "{}", name);
+            assert_eq!(name, crc.name());
+        }
+
+        // Presets are case insensitive
+        assert_eq!(Some(Crc::CRC32C), Crc::preset("crc-32c"));
+        assert_eq!(None, Crc::preset("CRC-99"));
+    }
+
+    #[test]
+    fn test_custom() {
+        // CRC-5/USB, which is an odd width
+        let crc = Crc::new(5, 0x05, 0x1f, true, true, 0x1f).unwrap();
+        assert_eq!(0x19, crc.compute(b"123456789"));
+        assert_eq!("CRC-5", crc.name());
+        assert_eq!(1, crc.size());
+
+        // CRC-24/OPENPGP
+        let crc = Crc::new(24, 0x864cfb, 0xb704ce, false, false, 0x000000).unwrap();
+        assert_eq!(0x21cf02, crc.compute(b"123456789"));
+        assert_eq!(3, crc.size());
+
+        // Matches crc32fast
+        let data = b"Hello, this is a longer string with some data in it".to_vec();
+        assert_eq!(crc32fast::hash(&data) as u64, Crc::CRC32.compute(&data));
+
+        // No data is just the init value, adjusted
+        assert_eq!(0, Crc::CRC32.compute(b""));
+    }
+
+    #[test]
+    fn test_bad_parameters() {
+        assert!(Crc::new(0,  0x07, 0, false, false, 0).is_err());
+        assert!(Crc::new(65, 0x07, 0, false, false, 0).is_err());
+        assert!(Crc::new(8,  0x107, 0, false, false, 0).is_err());
+        assert!(Crc::new(8,  0x07, 0x100, false, false, 0).is_err());
+        assert!(Crc::new(8,  0x07, 0, false, false, 0x100).is_err());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let serialized = serde_json::to_string(&Crc::CRC16_MODBUS).unwrap();
+        assert_eq!(Crc::CRC16_MODBUS, serde_json::from_str::<Crc>(&serialized).unwrap());
+
+        // Deserializing goes through the same checks as new()
+        assert!(serde_json::from_str::<Crc>(r#"{"width":0,"poly":7,"init":0,"refin":false,"refout":false,"xorout":0}"#).is_err());
+        assert!(serde_json::from_str::<Crc>(r#"{"width":65,"poly":7,"init":0,"refin":false,"refout":false,"xorout":0}"#).is_err());
+        assert!(serde_json::from_str::<Crc>(r#"{"width":8,"poly":263,"init":0,"refin":false,"refout":false,"xorout":0}"#).is_err());
+    }
+}
@@ -0,0 +1,34 @@
+//! Calculate checksums and hashes over data.
+//!
+//! Lots of formats store a checksum or hash of some of their data, so the
+//! consumer can tell whether it's intact. This calculates them, so they can
+//! be checked (or fixed).
+//!
+//! * [`ChecksumType`] - An algorithm, from simple sums to cryptographic hashes
+//! * [`Crc`] - The parameters for a CRC, with constants for the common ones
+//! * [`Checksum`] - A calculated value, which can be displayed as a comment
+//!
+//! All of them are serializable.
+//!
+//! # Usage
+//!
+//! ```
+//! use libh2gb::checksum::*;
+//!
+//! let checksum = ChecksumType::CRC(Crc::CRC32).compute(b"123456789");
+//! assert_eq!(Some(0xcbf43926), checksum.as_u64());
+//! assert_eq!("CRC-32: 0xcbf43926", checksum.to_string());
+//!
+//! let checksum = ChecksumType::SHA1.compute(b"abc");
+//! assert_eq!(b"\xa9\x99\x3e\x36\x47\x06\x81\x6a\xba\x3e\x25\x71\x78\x50\xc2\x6c\x9c\xd0\xd8\x9d", checksum.value());
+//! ```
+//!
+//! To calculate a checksum over part of a buffer, or over an entry, see
+//! [`crate::project::H2Buffer::checksum`] and
+//! [`crate::project::H2Project::entry_checksum`].
+
+mod crc;
+pub use crc::*;
+
+mod checksum_type;
+pub use checksum_type::*;
@@ -2,7 +2,67 @@ use serde::{Serialize, Deserialize};
 
 use simple_error::{bail, SimpleResult};
 
-use crate::datatype::{Alignment, H2Type, H2Types, H2TypeTrait, Offset};
+use crate::datatype::{Alignment, Expression, H2Type, H2Types, H2TypeTrait, Offset, Reference};
+
+/// The length of an [`H2Array`] (or [`crate::datatype::composite::string::H2String`]).
+///
+/// The counts and sizes are [`Expression`]s, which can refer to earlier
+/// fields in the [`crate::datatype::composite::H2Struct`] that contains the
+/// array (such as `header_len * 4 - 20`). They're calculated when the struct
+/// works out its children.
+///
+/// The rest read elements until something tells them to stop, which needs
+/// real data (an [`Offset::Dynamic`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ArrayLength {
+    /// A fixed number of elements.
+    Fixed(u64),
+
+    /// A calculated number of elements.
+    Count(Expression),
+
+    /// A calculated size, in bytes. Elements are added until the size is
+    /// filled exactly.
+    Size(Expression),
+
+    /// Elements are added until one matches the condition, which is included
+    /// as the last element.
+    ///
+    /// The condition refers to the element's own fields (see
+    /// [`H2Type::field_values`]) - for example, `type == 0` for a struct with
+    /// a `type` field, or `value == 0` for a number.
+    Sentinel(Expression),
+
+    /// Elements are added until these bytes are found where the next element
+    /// would start. The terminator counts towards the array's size, but isn't
+    /// an element.
+    Terminator(Vec<u8>),
+
+    /// Elements are added until the end of the data. In a struct, that's
+    /// the end of the struct, less any fields after the array (which must
+    /// have a fixed size); otherwise, it's the end of the buffer.
+    Remaining,
+}
+
+impl ArrayLength {
+    /// Get a copy with any known fields filled in.
+    pub(crate) fn bind(&self, fields: &[(Reference, i64)]) -> Self {
+        match self {
+            Self::Fixed(n)      => Self::Fixed(*n),
+            Self::Count(e)      => Self::Count(e.bind(fields)),
+            Self::Size(e)       => Self::Size(e.bind(fields)),
+
+            // The sentinel only uses the element's own fields
+            Self::Sentinel(e)   => Self::Sentinel(e.clone()),
+            Self::Terminator(t) => Self::Terminator(t.clone()),
+            Self::Remaining     => Self::Remaining,
+        }
+    }
+
+    fn is_fixed(&self) -> bool {
+        matches!(self, Self::Fixed(_))
+    }
+}
 
 /// Defines an array of values.
 ///
@@ -13,38 +73,213 @@ use crate::datatype::{Alignment, H2Type, H2Types, H2TypeTrait, Offset};
 /// Arrays can be nested, can contain
 /// [`crate::datatype::composite::H2Struct`]s/[`crate::datatype::composite::H2Union`]s, and can
 /// be as complex or simple as you need.
+///
+/// The length can also come from an earlier field in a struct (see
+/// [`ArrayLength`]); those arrays can be empty.
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::datatype::*;
+/// use libh2gb::datatype::simple::*;
+/// use libh2gb::datatype::composite::*;
+/// use libh2gb::generic_number::*;
+///
+/// let data = b"\x03ABCD".to_vec();
+/// let offset = Offset::Dynamic(Context::new(&data));
+///
+/// let t = H2Struct::new(vec![
+///     ("count".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+///     ("data".to_string(),  H2Array::new_dynamic(
+///         ArrayLength::Count(Expression::parse("count")?),
+///         H2Number::new_ascii(),
+///     )?),
+/// ])?;
+///
+/// assert_eq!("{ count: 3, data: [ 'A', 'B', 'C' ] }", t.to_display(offset)?);
+/// # Ok::<(), simple_error::SimpleError>(())
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct H2Array {
     field_type: Box<H2Type>,
-    length: u64,
+    length: ArrayLength,
+
+    /// Where the space that the containing struct leaves for the array ends;
+    /// this is filled in by the struct when it works out where its children
+    /// are.
+    parent_end: Option<u64>,
 }
 
 impl H2Array {
     pub fn new_aligned(alignment: Alignment, length: u64, field_type: H2Type) -> SimpleResult<H2Type> {
-        if length == 0 {
-            bail!("Arrays must be at least one element long");
+        Self::new_dynamic_aligned(alignment, ArrayLength::Fixed(length), field_type)
+    }
+
+    pub fn new(length: u64, field_type: H2Type) -> SimpleResult<H2Type> {
+        Self::new_aligned(Alignment::None, length, field_type)
+    }
+
+    pub fn new_dynamic_aligned(alignment: Alignment, length: ArrayLength, field_type: H2Type) -> SimpleResult<H2Type> {
+        match &length {
+            ArrayLength::Fixed(0)                      => bail!("Arrays must be at least one element long"),
+            ArrayLength::Terminator(t) if t.is_empty() => bail!("Array terminators must be at least one byte long"),
+            _                                          => (),
         }
 
         Ok(H2Type::new(alignment, H2Types::H2Array(Self {
             field_type: Box::new(field_type),
             length: length,
+            parent_end: None,
         })))
     }
 
-    pub fn new(length: u64, field_type: H2Type) -> SimpleResult<H2Type> {
-        Self::new_aligned(Alignment::None, length, field_type)
+    pub fn new_dynamic(length: ArrayLength, field_type: H2Type) -> SimpleResult<H2Type> {
+        Self::new_dynamic_aligned(Alignment::None, length, field_type)
+    }
+
+    /// Get a copy of this type with any known fields filled in.
+    pub(crate) fn with_fields(&self, fields: &[(Reference, i64)]) -> Self {
+        Self {
+            field_type: Box::new(self.field_type.with_fields(fields)),
+            length: self.length.bind(fields),
+            parent_end: self.parent_end,
+        }
+    }
+
+    /// Get a copy of this type that knows where the space its parent leaves
+    /// for it ends.
+    pub(crate) fn with_parent_end(&self, parent_end: u64) -> Self {
+        let mut array = self.clone();
+        array.parent_end = Some(parent_end);
+
+        array
+    }
+
+    /// Make sure a calculated count or size fits in what's left of the
+    /// buffer. Every element takes at least one byte, and a bad value from
+    /// the data could otherwise be enormous.
+    fn check_room(offset: Offset, n: u64, what: &str) -> SimpleResult<u64> {
+        if let Offset::Dynamic(context) = offset {
+            let left = (context.at(0).as_slice().len() as u64).saturating_sub(offset.position());
+
+            if n > left {
+                bail!("Array {} ({}) is more than the number of bytes left in the buffer ({})", what, n, left);
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Get the number of elements, without working out where they are if
+    /// the length doesn't depend on them.
+    fn count(&self, offset: Offset) -> SimpleResult<u64> {
+        match &self.length {
+            ArrayLength::Fixed(n) => Ok(*n),
+            ArrayLength::Count(e) => Self::check_room(offset, e.evaluate_u64()?, "count"),
+            _                     => Ok(self.analyze(offset)?.0),
+        }
+    }
+
+    /// Get the number of elements, and where the array ends (including any
+    /// terminator).
+    fn analyze(&self, offset: Offset) -> SimpleResult<(u64, u64)> {
+        let (count, size) = match &self.length {
+            ArrayLength::Fixed(n) => (Some(*n), None),
+            ArrayLength::Count(e) => (Some(Self::check_room(offset, e.evaluate_u64()?, "count")?), None),
+            ArrayLength::Size(e)  => (None, Some(Self::check_room(offset, e.evaluate_u64()?, "size")?)),
+            _                     => (None, None),
+        };
+
+        // The rest of the modes need to read the data
+        let data = match (count, size) {
+            (None, None) => offset.get_dynamic()?.at(0).as_slice(),
+            _            => &[],
+        };
+
+        // Where a Remaining array has to stop
+        let end = self.parent_end.unwrap_or(data.len() as u64);
+
+        let mut position = offset.position();
+        let mut elements = 0;
+
+        loop {
+            // Check if we're at the end before reading each element
+            let done = match (&self.length, count, size) {
+                (_, Some(count), _)                 => elements >= count,
+                (_, _, Some(size))                  => position >= offset.position() + size,
+                (ArrayLength::Terminator(t), _, _)  => match data.get(position as usize..) {
+                    Some(rest) => rest.starts_with(t),
+                    None       => bail!("Array ran off the end of the buffer without finding a terminator"),
+                },
+                (ArrayLength::Remaining, _, _)      => position >= end,
+                _                                   => false,
+            };
+
+            if done {
+                break;
+            }
+
+            let this_offset = offset.at(position);
+
+            // A sentinel needs the element's values as well as its range; get
+            // both from one analysis of the element
+            let (range, last) = match (&self.length, &self.field_type.field) {
+                (ArrayLength::Sentinel(condition), H2Types::H2Struct(s)) => {
+                    let (range, values) = s.range_and_values(this_offset)?;
+                    (self.field_type.alignment.align(range)?, condition.bind(&values).evaluate_bool()?)
+                },
+                (ArrayLength::Sentinel(condition), _) => (
+                    self.field_type.aligned_range(this_offset)?,
+                    condition.bind(&self.field_type.field_values(this_offset)?).evaluate_bool()?,
+                ),
+                _ => (self.field_type.aligned_range(this_offset)?, false),
+            };
+
+            if range.end <= position {
+                bail!("Array elements must take up space");
+            }
+
+            position = range.end;
+            elements += 1;
+
+            // Check if the element we just read was the last one
+            if last {
+                break;
+            }
+        }
+
+        match (&self.length, size) {
+            (_, Some(size)) if position != offset.position() + size => {
+                bail!("Array elements don't fill the size exactly (0x{:x} bytes instead of 0x{:x})", position - offset.position(), size);
+            },
+            (ArrayLength::Remaining, _) if position != end => {
+                bail!("Array elements don't end at the end of the data (0x{:x} instead of 0x{:x})", position, end);
+            },
+            (ArrayLength::Terminator(t), _) => Ok((elements, position + t.len() as u64)),
+            _ => Ok((elements, position)),
+        }
     }
 }
 
 impl H2TypeTrait for H2Array {
     fn is_static(&self) -> bool {
-        // Offload the is_static() question to the child field type
-        self.field_type.is_static()
+        // Offload the is_static() question to the child field type (unless
+        // the length depends on the data)
+        self.length.is_fixed() && self.field_type.is_static()
     }
 
-    fn children(&self, _offset: Offset) -> SimpleResult<Vec<(Option<String>, H2Type)>> {
+    fn actual_size(&self, offset: Offset) -> SimpleResult<u64> {
+        // Empty arrays and terminators are allowed, which the default
+        // implementation doesn't handle; analyzing the array already tells us
+        // where it ends, so there's no need to build the children again
+        let (_, end) = self.analyze(offset)?;
+
+        Ok(end - offset.position())
+    }
+
+    fn children(&self, offset: Offset) -> SimpleResult<Vec<(Option<String>, H2Type)>> {
         // Just clone the child type over and over
-        Ok((0..self.length).into_iter().map(|_index| {
+        Ok((0..self.count(offset)?).map(|_index| {
             (None, self.field_type.as_ref().clone())
         }).collect())
     }
@@ -56,7 +291,10 @@ impl H2TypeTrait for H2Array {
             child.to_display(offset.at(range.start))
         }).collect::<SimpleResult<Vec<String>>>()?;
 
-        Ok(format!("[ {} ]", strings.join(", ")))
+        match strings.len() {
+            0 => Ok("[]".to_string()),
+            _ => Ok(format!("[ {} ]", strings.join(", "))),
+        }
     }
 }
 
@@ -65,7 +303,9 @@ mod tests {
     use super::*;
     use simple_error::SimpleResult;
 
-    use crate::generic_number::{GenericReader, CharacterFormatter, Context};
+    use crate::generic_number::{GenericReader, CharacterFormatter, Context, Endian, HexFormatter, DefaultFormatter};
+    use crate::datatype::Expression;
+    use crate::datatype::composite::H2Struct;
     use crate::datatype::simple::H2Number;
 
     #[test]
@@ -324,4 +564,206 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_dynamic_length() -> SimpleResult<()> {
+        let data = b"ABCD".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        // Bound lengths work outside of a struct, too
+        let a = H2Array::new_dynamic(ArrayLength::Count(Expression::parse("n - 1")?), H2Number::new_ascii())?;
+        assert_eq!(false, a.is_static());
+        assert!(a.to_display(offset).is_err());
+
+        let a = a.with_fields(&[(Reference::Value("n".to_string()), 4)]);
+        assert_eq!(3, a.actual_size(offset)?);
+        assert_eq!("[ 'A', 'B', 'C' ]", a.to_display(offset)?);
+
+        // Empty
+        let a = H2Array::new_dynamic(ArrayLength::Count(Expression::parse("0")?), H2Number::new_ascii())?;
+        assert_eq!(0, a.actual_size(offset)?);
+        assert_eq!("[]", a.to_display(offset)?);
+        assert_eq!(0, a.resolve(offset, None)?.children.len());
+
+        // Sized in bytes
+        let a = H2Array::new_dynamic(ArrayLength::Size(Expression::parse("4")?), H2Number::new(GenericReader::U16(Endian::Big), HexFormatter::pretty()))?;
+        assert_eq!(2, a.children(offset)?.len());
+        assert_eq!("[ 0x4142, 0x4344 ]", a.to_display(offset)?);
+
+        let a = H2Array::new_dynamic(ArrayLength::Size(Expression::parse("3")?), H2Number::new(GenericReader::U16(Endian::Big), HexFormatter::pretty()))?;
+        assert!(a.to_display(offset).is_err());
+
+        // Fixed lengths still can't be empty
+        assert!(H2Array::new_dynamic(ArrayLength::Fixed(0), H2Number::new_ascii()).is_err());
+
+        // Lengths from the data can't be more than what's left (this would
+        // otherwise try to allocate an enormous number of elements)
+        let a = H2Array::new_dynamic(ArrayLength::Count(Expression::parse("0x7fffffff")?), H2Number::new_ascii())?;
+        assert!(a.actual_size(offset).is_err());
+        assert!(a.children(offset).is_err());
+        assert!(a.to_display(offset).is_err());
+
+        let a = H2Array::new_dynamic(ArrayLength::Size(Expression::parse("0x7fffffffffffffff")?), H2Number::new_ascii())?;
+        assert!(a.to_display(offset).is_err());
+
+        let a = H2Array::new_dynamic(ArrayLength::Count(Expression::parse("2")?), H2Number::new_ascii())?;
+        assert!(a.to_display(Offset::Dynamic(Context::new_at(&data, 3))).is_err());
+        assert_eq!("[ 'C', 'D' ]", a.to_display(Offset::Dynamic(Context::new_at(&data, 2)))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sentinel() -> SimpleResult<()> {
+        // TLV records, ending with a record whose type is 0
+        let record = H2Struct::new(vec![
+            ("type".to_string(),   H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("length".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("value".to_string(),  H2Array::new_dynamic(
+                ArrayLength::Count(Expression::parse("length")?),
+                H2Number::new_ascii(),
+            )?),
+        ])?;
+        let a = H2Array::new_dynamic(ArrayLength::Sentinel(Expression::parse("type == 0")?), record)?;
+        assert_eq!(false, a.is_static());
+
+        let data = b"\x01\x02hi\x02\x01!\x00\x00garbage".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(9, a.actual_size(offset)?);
+        assert_eq!("[ { type: 1, length: 2, value: [ 'h', 'i' ] }, { type: 2, length: 1, value: [ '!' ] }, { type: 0, length: 0, value: [] } ]", a.to_display(offset)?);
+
+        let r = a.resolve(offset, None)?;
+        assert_eq!(3, r.children.len());
+        assert_eq!(7..9, r.children[2].actual_range);
+
+        // Never finding the sentinel is an error
+        let data = b"\x01\x02hi".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert!(a.to_display(offset).is_err());
+
+        // Numbers are called "value"
+        let a = H2Array::new_dynamic(
+            ArrayLength::Sentinel(Expression::parse("value == 0")?),
+            H2Number::new(GenericReader::U16(Endian::Little), DefaultFormatter::new()),
+        )?;
+        let data = b"\x01\x00\x02\x00\x00\x00\x03\x00".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("[ 1, 2, 0 ]", a.to_display(offset)?);
+        assert_eq!(6, a.actual_size(offset)?);
+
+        // Sentinels need real data
+        assert!(a.actual_size(Offset::Static(0)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_terminator() -> SimpleResult<()> {
+        let a = H2Array::new_dynamic(
+            ArrayLength::Terminator(b"\xff\xff".to_vec()),
+            H2Number::new(GenericReader::U16(Endian::Big), HexFormatter::pretty()),
+        )?;
+
+        // The terminator is part of the size, but isn't an element
+        let data = b"\x00\x01\x00\xff\xff\xff\x00\x02".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(6, a.actual_size(offset)?);
+        assert_eq!(0..6, a.actual_range(offset)?);
+        assert_eq!("[ 0x0001, 0x00ff ]", a.to_display(offset)?);
+        assert_eq!(2, a.resolve(offset, None)?.children.len());
+
+        // Right away
+        let offset = Offset::Dynamic(Context::new_at(&data, 4));
+        assert_eq!(2, a.actual_size(offset)?);
+        assert_eq!("[]", a.to_display(offset)?);
+
+        // Missing
+        let data = b"\x00\x01\x00\x02".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert!(a.to_display(offset).is_err());
+
+        assert!(H2Array::new_dynamic(ArrayLength::Terminator(vec![]), H2Number::new_ascii()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remaining() -> SimpleResult<()> {
+        let a = H2Array::new_dynamic(
+            ArrayLength::Remaining,
+            H2Number::new(GenericReader::U16(Endian::Big), HexFormatter::pretty()),
+        )?;
+
+        let data = b"\x00\x01\x00\x02\x00\x03".to_vec();
+        assert_eq!("[ 0x0001, 0x0002, 0x0003 ]", a.to_display(Offset::Dynamic(Context::new(&data)))?);
+        assert_eq!("[ 0x0003 ]", a.to_display(Offset::Dynamic(Context::new_at(&data, 4)))?);
+        assert_eq!("[]", a.to_display(Offset::Dynamic(Context::new_at(&data, 6)))?);
+
+        // Not a multiple of the element size
+        assert!(a.to_display(Offset::Dynamic(Context::new_at(&data, 1))).is_err());
+
+        // At the end of a struct
+        let t = H2Struct::new(vec![
+            ("header".to_string(), H2Number::new(GenericReader::U16(Endian::Big), HexFormatter::pretty())),
+            ("rest".to_string(),   a.clone()),
+        ])?;
+        assert_eq!("{ header: 0x0001, rest: [ 0x0002, 0x0003 ] }", t.to_display(Offset::Dynamic(Context::new(&data)))?);
+
+        // With a field after it, it stops at the end of the struct instead
+        let t = H2Struct::new(vec![
+            ("header".to_string(),  H2Number::new(GenericReader::U8, HexFormatter::pretty())),
+            ("rest".to_string(),    a.clone()),
+            ("trailer".to_string(), H2Number::new(GenericReader::U8, HexFormatter::pretty())),
+        ])?;
+        let r = t.resolve(Offset::Dynamic(Context::new(&data)), None)?;
+        assert_eq!("{ header: 0x00, rest: [ 0x0100, 0x0200 ], trailer: 0x03 }", r.display);
+        assert_eq!(1..5, r.children[1].actual_range);
+        assert_eq!(5..6, r.children[2].actual_range);
+
+        // The end of a nested struct leaves room for the fields after it, too
+        let t = H2Struct::new(vec![
+            ("body".to_string(), H2Struct::new(vec![
+                ("rest".to_string(), a.clone()),
+                ("end".to_string(),  H2Number::new(GenericReader::U8, HexFormatter::pretty())),
+            ])?),
+            ("crc".to_string(),  H2Number::new(GenericReader::U8, HexFormatter::pretty())),
+        ])?;
+        assert_eq!("{ body: { rest: [ 0x0001, 0x0002 ], end: 0x00 }, crc: 0x03 }", t.to_display(Offset::Dynamic(Context::new(&data)))?);
+
+        // There's no room left
+        let t = H2Struct::new(vec![
+            ("rest".to_string(),    a.clone()),
+            ("trailer".to_string(), H2Array::new(7, H2Number::new(GenericReader::U8, HexFormatter::pretty()))?),
+        ])?;
+        assert!(t.to_display(Offset::Dynamic(Context::new(&data))).is_err());
+
+        // The fields after it need a fixed size
+        let t = H2Struct::new(vec![
+            ("rest".to_string(),    a),
+            ("trailer".to_string(), H2Array::new_dynamic(ArrayLength::Count(Expression::parse("1")?), H2Number::new_ascii())?),
+        ])?;
+        assert!(t.to_display(Offset::Dynamic(Context::new(&data))).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deeply_nested() -> SimpleResult<()> {
+        // Each level used to work out the one inside it several times, so
+        // this would take forever
+        let mut a = H2Array::new_dynamic(
+            ArrayLength::Sentinel(Expression::parse("value == 0")?),
+            H2Number::new(GenericReader::U8, DefaultFormatter::new()),
+        )?;
+        for _ in 0..40 {
+            a = H2Array::new_dynamic(ArrayLength::Remaining, a)?;
+        }
+
+        let data = b"\x01\x00".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(2, a.actual_size(offset)?);
+        assert!(a.to_display(offset)?.contains("[ [ 1, 0 ] ]"));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,237 @@
+use serde::{Serialize, Deserialize};
+
+use simple_error::SimpleResult;
+
+use crate::datatype::{Alignment, Expression, H2Type, H2Types, H2TypeTrait, Offset, Reference};
+
+/// Defines a field that's only there if a condition holds.
+///
+/// The condition is an [`Expression`], which normally refers to earlier
+/// fields in the [`crate::datatype::composite::H2Struct`] that contains it
+/// (like `version >= 2`); anything but 0 is true. When the condition fails,
+/// the struct leaves the field out entirely, and when it holds, the field is
+/// the wrapped type (so the conditional itself never shows up).
+///
+/// Outside of a struct, a conditional that fails takes up no space.
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::datatype::*;
+/// use libh2gb::datatype::simple::*;
+/// use libh2gb::datatype::composite::*;
+/// use libh2gb::generic_number::*;
+///
+/// let t = H2Struct::new(vec![
+///     ("version".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+///     ("extra".to_string(),   H2Conditional::new(
+///         Expression::parse("version >= 2")?,
+///         H2Number::new(GenericReader::U8, HexFormatter::pretty()),
+///     )),
+///     ("data".to_string(),    H2Number::new_ascii()),
+/// ])?;
+///
+/// let data = b"\x01A".to_vec();
+/// assert_eq!("{ version: 1, data: 'A' }", t.to_display(Offset::Dynamic(Context::new(&data)))?);
+///
+/// let data = b"\x02\xffA".to_vec();
+/// assert_eq!("{ version: 2, extra: 0xff, data: 'A' }", t.to_display(Offset::Dynamic(Context::new(&data)))?);
+/// # Ok::<(), simple_error::SimpleError>(())
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct H2Conditional {
+    condition: Expression,
+    field_type: Box<H2Type>,
+}
+
+impl H2Conditional {
+    pub fn new_aligned(alignment: Alignment, condition: Expression, field_type: H2Type) -> H2Type {
+        H2Type::new(alignment, H2Types::H2Conditional(Self {
+            condition,
+            field_type: Box::new(field_type),
+        }))
+    }
+
+    pub fn new(condition: Expression, field_type: H2Type) -> H2Type {
+        Self::new_aligned(Alignment::None, condition, field_type)
+    }
+
+    /// Get a copy of this type with any known fields filled in.
+    pub(crate) fn with_fields(&self, fields: &[(Reference, i64)]) -> Self {
+        Self {
+            condition: self.condition.bind(fields),
+            field_type: Box::new(self.field_type.with_fields(fields)),
+        }
+    }
+
+    /// Get the type, if the condition holds.
+    pub fn present(&self) -> SimpleResult<Option<H2Type>> {
+        match self.condition.evaluate_bool()? {
+            true  => Ok(Some(self.field_type.as_ref().clone())),
+            false => Ok(None),
+        }
+    }
+}
+
+impl H2TypeTrait for H2Conditional {
+    fn is_static(&self) -> bool {
+        // The size depends on the data
+        false
+    }
+
+    fn actual_size(&self, offset: Offset) -> SimpleResult<u64> {
+        match self.present()? {
+            Some(field_type) => field_type.aligned_size(offset),
+            None             => Ok(0),
+        }
+    }
+
+    fn children(&self, _offset: Offset) -> SimpleResult<Vec<(Option<String>, H2Type)>> {
+        match self.present()? {
+            Some(field_type) => Ok(vec![(None, field_type)]),
+            None             => Ok(vec![]),
+        }
+    }
+
+    fn to_display(&self, offset: Offset) -> SimpleResult<String> {
+        match self.present()? {
+            Some(field_type) => field_type.to_display(offset),
+            None             => Ok("(not present)".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use simple_error::SimpleResult;
+
+    use crate::generic_number::{Context, GenericReader, Endian, DefaultFormatter};
+    use crate::datatype::simple::H2Number;
+    use crate::datatype::composite::{ArrayLength, H2Array, H2Struct};
+
+    #[test]
+    fn test_conditional_field() -> SimpleResult<()> {
+        let t = H2Struct::new(vec![
+            ("flags".to_string(),  H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("length".to_string(), H2Conditional::new(
+                Expression::parse("flags % 2 == 1")?,
+                H2Number::new(GenericReader::U16(Endian::Big), DefaultFormatter::new()),
+            )),
+            ("data".to_string(),   H2Conditional::new(
+                Expression::parse("flags % 2 == 1 && length > 0")?,
+                H2Array::new_dynamic(ArrayLength::Count(Expression::parse("length")?), H2Number::new_ascii())?,
+            )),
+            ("end".to_string(),    H2Number::new_ascii()),
+        ])?;
+        assert_eq!(false, t.is_static());
+
+        // Both present
+        let data = b"\x01\x00\x02ab!".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(6, t.actual_size(offset)?);
+        assert_eq!("{ flags: 1, length: 2, data: [ 'a', 'b' ], end: '!' }", t.to_display(offset)?);
+
+        // The conditional fields are replaced by what they contain
+        let r = t.resolve(offset, None)?;
+        assert_eq!(4, r.children.len());
+        assert_eq!("length", r.children[1].field_name.as_ref().unwrap());
+        assert_eq!(1..3, r.children[1].actual_range);
+        assert_eq!(2, r.children[2].children.len());
+
+        // Only the first, since the length is 0
+        let data = b"\x03\x00\x00!".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ flags: 3, length: 0, end: '!' }", t.to_display(offset)?);
+
+        // Neither is present, so they're left out entirely (the second
+        // condition doesn't need the missing length, since && short-circuits)
+        let data = b"\x00!".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(2, t.actual_size(offset)?);
+        assert_eq!("{ flags: 0, end: '!' }", t.to_display(offset)?);
+
+        let r = t.resolve(offset, None)?;
+        assert_eq!(2, r.children.len());
+        assert_eq!("end", r.children[1].field_name.as_ref().unwrap());
+        assert_eq!(1..2, r.children[1].actual_range);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aligned_conditional() -> SimpleResult<()> {
+        let data = b"\x01A...B".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        // The padding is the same on its own and in a struct
+        let c = H2Conditional::new_aligned(Alignment::Loose(4), Expression::parse("1")?, H2Number::new_ascii());
+        assert_eq!(4, c.aligned_size(offset.at(1))?);
+
+        let t = H2Struct::new(vec![
+            ("flags".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("maybe".to_string(), H2Conditional::new_aligned(
+                Alignment::Loose(4),
+                Expression::parse("flags == 1")?,
+                H2Number::new_ascii(),
+            )),
+            ("end".to_string(),   H2Number::new_ascii()),
+        ])?;
+        assert_eq!(6, t.actual_size(offset)?);
+        assert_eq!("{ flags: 1, maybe: 'A', end: 'B' }", t.to_display(offset)?);
+
+        let r = t.resolve(offset, None)?;
+        assert_eq!(1..5, r.children[1].aligned_range);
+        assert_eq!(5..6, r.children[2].actual_range);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nothing_present() -> SimpleResult<()> {
+        let data = b"\x00A".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        // A nested struct whose fields are all missing is empty
+        let t = H2Struct::new(vec![
+            ("flags".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("extra".to_string(), H2Struct::new(vec![
+                ("a".to_string(), H2Conditional::new(Expression::parse("parent.flags == 1")?, H2Number::new_ascii())),
+                ("b".to_string(), H2Conditional::new(Expression::parse("parent.flags == 2")?, H2Number::new_ascii())),
+            ])?),
+            ("end".to_string(),   H2Number::new_ascii()),
+        ])?;
+        assert_eq!(2, t.actual_size(offset)?);
+        assert_eq!("{ flags: 0, extra: {}, end: 'A' }", t.to_display(offset)?);
+
+        let r = t.resolve(offset, None)?;
+        assert_eq!(1..1, r.children[1].actual_range);
+        assert_eq!(0, r.children[1].children.len());
+        assert_eq!(1..2, r.children[2].actual_range);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_standalone() -> SimpleResult<()> {
+        let data = b"A".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        let t = H2Conditional::new(Expression::parse("1")?, H2Number::new_ascii());
+        assert_eq!(1, t.actual_size(offset)?);
+        assert_eq!("'A'", t.to_display(offset)?);
+        assert_eq!(1, t.resolve(offset, None)?.children.len());
+
+        let t = H2Conditional::new(Expression::parse("0")?, H2Number::new_ascii());
+        assert_eq!(0, t.actual_size(offset)?);
+        assert_eq!("(not present)", t.to_display(offset)?);
+        assert_eq!(0, t.resolve(offset, None)?.children.len());
+
+        // Unbound
+        let t = H2Conditional::new(Expression::parse("version > 1")?, H2Number::new_ascii());
+        assert!(t.to_display(offset).is_err());
+
+        Ok(())
+    }
+}
@@ -1,8 +1,15 @@
 use serde::{Serialize, Deserialize};
 
 use simple_error::{bail, SimpleResult};
+use std::ops::Range;
 
-use crate::datatype::{Alignment, H2Type, H2Types, H2TypeTrait, Offset};
+use crate::datatype::{Alignment, H2Type, H2Types, H2TypeTrait, Offset, Reference, ResolvedType};
+
+/// Each child's range, name, and type, as returned by [`H2TypeTrait::children_with_range`].
+type ChildrenWithRange = Vec<(Range<u64>, Option<String>, H2Type)>;
+
+/// The values that an [`crate::datatype::Expression`] can refer to, as returned by [`H2Struct::field_values`].
+type FieldValues = Vec<(Reference, i64)>;
 
 /// Defines a struct.
 ///
@@ -11,6 +18,11 @@ use crate::datatype::{Alignment, H2Type, H2Types, H2TypeTrait, Offset};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct H2Struct {
     fields: Vec<(String, H2Type)>,
+
+    /// Where the space that the containing struct leaves for this one ends;
+    /// this is filled in by that struct when it works out where its children
+    /// are.
+    parent_end: Option<u64>,
 }
 
 impl H2Struct {
@@ -20,13 +32,159 @@ impl H2Struct {
         }
 
         Ok(H2Type::new(alignment, H2Types::H2Struct(Self {
-            fields: fields
+            fields,
+            parent_end: None,
         })))
     }
 
     pub fn new(fields: Vec<(String, H2Type)>) -> SimpleResult<H2Type> {
         Self::new_aligned(Alignment::None, fields)
     }
+
+    /// Get a copy of this type with any known fields filled in.
+    ///
+    /// The fields belong to the struct that contains this one, so they're
+    /// known to this struct's fields as `parent.<name>`.
+    pub(crate) fn with_fields(&self, fields: &[(Reference, i64)]) -> Self {
+        let fields: Vec<(Reference, i64)> = fields.iter().map(|(reference, value)| {
+            (reference.with_prefix("parent"), *value)
+        }).collect();
+
+        Self {
+            fields: self.fields.iter().map(|(name, field_type)| {
+                (name.clone(), field_type.with_fields(&fields))
+            }).collect(),
+            parent_end: self.parent_end,
+        }
+    }
+
+    /// Get a copy of this type that knows where the space its parent leaves
+    /// for it ends.
+    pub(crate) fn with_parent_end(&self, parent_end: u64) -> Self {
+        let mut s = self.clone();
+        s.parent_end = Some(parent_end);
+
+        s
+    }
+
+    /// Get everything about the fields that an [`crate::datatype::Expression`] can refer to:
+    /// the value of each number, the size and offset of each field, and the
+    /// values inside each field that's a struct (as `<name>.<field>`).
+    pub fn field_values(&self, offset: Offset) -> SimpleResult<FieldValues> {
+        Ok(self.analyze(offset)?.1)
+    }
+
+    /// Get the struct's (unaligned) range along with its [`Self::field_values`],
+    /// from a single analysis.
+    pub(crate) fn range_and_values(&self, offset: Offset) -> SimpleResult<(Range<u64>, FieldValues)> {
+        let (children, values) = self.analyze(offset)?;
+
+        Ok((Self::range_of(offset, &children), values))
+    }
+
+    /// Get the range that the children cover. If every field is a
+    /// conditional that isn't there, the struct is empty.
+    fn range_of(offset: Offset, children: &ChildrenWithRange) -> Range<u64> {
+        match (children.first(), children.last()) {
+            (Some((first, _, _)), Some((last, _, _))) => first.start..last.end,
+            _                                         => offset.position()..offset.position(),
+        }
+    }
+
+    /// Display the children, which have already been converted to strings.
+    fn display_fields(strings: Vec<String>) -> String {
+        match strings.len() {
+            0 => "{}".to_string(),
+            _ => format!("{{ {} }}", strings.join(", ")),
+        }
+    }
+
+    /// The size of the fields after the given one, if they all have a fixed
+    /// size.
+    fn static_size_after(&self, index: usize) -> Option<u64> {
+        self.fields[(index + 1)..].iter().try_fold(0, |size, (_, field_type)| {
+            match field_type.is_static() {
+                true  => field_type.aligned_size(Offset::Static(0)).ok().map(|s| size + s),
+                false => None,
+            }
+        })
+    }
+
+    /// Work out the children, in order, along with the values that later
+    /// fields can refer to (see [`Self::field_values`]).
+    fn analyze(&self, offset: Offset) -> SimpleResult<(ChildrenWithRange, FieldValues)> {
+        let mut child_offset = offset;
+        let mut values: FieldValues = vec![];
+        let mut children = vec![];
+
+        // Fields that run until the end of the data stop at the end of this
+        // struct instead, leaving room for the fields after them - if those
+        // have a fixed size
+        let end = match (self.parent_end, offset) {
+            (Some(end), _)                   => Some(end),
+            (None, Offset::Dynamic(context)) => Some(context.at(0).as_slice().len() as u64),
+            (None, Offset::Static(_))        => None,
+        };
+
+        for (index, (name, field_type)) in self.fields.iter().enumerate() {
+            let field_type = field_type.with_fields(&values);
+
+            // Conditional fields are either replaced by what they contain, or
+            // left out entirely; the conditional's own alignment still
+            // applies on top of whatever the contents have
+            let (field_type, outer_alignment) = match &field_type.field {
+                H2Types::H2Conditional(c) => match c.present()? {
+                    Some(mut t) => {
+                        if matches!(t.alignment, Alignment::None) {
+                            t.alignment = field_type.alignment;
+                        }
+
+                        (t, field_type.alignment)
+                    },
+                    None => continue,
+                },
+                _ => (field_type, Alignment::None),
+            };
+
+            // Only fields without a fixed size care where the struct ends
+            let field_type = match end {
+                Some(end) if !field_type.is_static() => match self.static_size_after(index) {
+                    Some(size_after) => field_type.with_parent_end(end.saturating_sub(size_after)),
+                    None             => field_type,
+                },
+                _ => field_type,
+            };
+
+            // A nested struct is analyzed once, for both its range and its
+            // values (analyzing it again for each would double the work at
+            // every level)
+            let range = match &field_type.field {
+                H2Types::H2Struct(s) => {
+                    let (range, child_values) = s.range_and_values(child_offset)?;
+                    for (reference, value) in child_values {
+                        values.push((reference.with_prefix(name), value));
+                    }
+
+                    field_type.alignment.align(range)?
+                },
+                _ => field_type.aligned_range(child_offset)?,
+            };
+            let range = outer_alignment.align(range)?;
+
+            if let Some(value) = field_type.to_i64(offset.at(range.start)) {
+                values.push((Reference::Value(name.clone()), value));
+            }
+
+            values.push((Reference::SizeOf(name.clone()), (range.end - range.start) as i64));
+            values.push((Reference::OffsetOf(name.clone()), (range.start - offset.position()) as i64));
+
+            child_offset = offset.at(range.end);
+
+            children.push((range, Some(name.clone()), field_type));
+        }
+
+        Ok((children, values))
+    }
 }
 
 impl H2TypeTrait for H2Struct {
@@ -35,13 +193,33 @@ impl H2TypeTrait for H2Struct {
         // Loop over each field - return an object as soon as is_static() is
         // false
         self.fields.iter().find(|(_, t)| {
-            t.is_static() == false
+            !t.is_static()
         }).is_none()
     }
 
-    fn children(&self, _offset: Offset) -> SimpleResult<Vec<(Option<String>, H2Type)>> {
-        Ok(self.fields.iter().map(|(name, field_type)| {
-            (Some(name.clone()), field_type.clone())
+    fn actual_size(&self, offset: Offset) -> SimpleResult<u64> {
+        // Unlike the default, this allows a struct with no fields present
+        let range = self.range_and_values(offset)?.0;
+
+        Ok(range.end - range.start)
+    }
+
+    fn children(&self, offset: Offset) -> SimpleResult<Vec<(Option<String>, H2Type)>> {
+        // The fields depend on each other, so work them all out
+        Ok(self.children_with_range(offset)?.into_iter().map(|(_range, name, field_type)| {
+            (name, field_type)
+        }).collect())
+    }
+
+    fn children_with_range(&self, offset: Offset) -> SimpleResult<Vec<(Range<u64>, Option<String>, H2Type)>> {
+        let (children, _) = self.analyze(offset)?;
+
+        // Once we know the full range, let the children know (checksums, for
+        // example, can cover part of their parent)
+        let parent = Self::range_of(offset, &children);
+
+        Ok(children.into_iter().map(|(range, name, child)| {
+            (range, name, child.with_parent(parent.clone()))
         }).collect())
     }
 
@@ -52,7 +230,36 @@ impl H2TypeTrait for H2Struct {
             Ok(format!("{}: {}", name.clone().unwrap_or("<name unknown>".to_string()), child.to_display(offset.at(range.start))?))
         }).collect::<SimpleResult<Vec<String>>>()?;
 
-        Ok(format!("{{ {} }}", strings.join(", ")))
+        Ok(Self::display_fields(strings))
+    }
+
+    fn resolve(&self, offset: Offset, alignment: Alignment, field_name: Option<String>) -> SimpleResult<ResolvedType> {
+        // Working out the children means analyzing the whole struct, so do it
+        // once and build everything else from them
+        let children = self.children_with_range(offset)?;
+        let actual_range = Self::range_of(offset, &children);
+
+        let children = children.into_iter().map(|(range, name, child)| {
+            child.resolve(offset.at(range.start), name)
+        }).collect::<SimpleResult<Vec<ResolvedType>>>()?;
+
+        let strings: Vec<String> = children.iter().map(|child| {
+            format!("{}: {}", child.field_name.clone().unwrap_or("<name unknown>".to_string()), child.display)
+        }).collect();
+
+        Ok(ResolvedType {
+            aligned_range: alignment.align(actual_range.clone())?,
+            actual_range,
+
+            field_name,
+            display: Self::display_fields(strings),
+
+            children,
+            related: vec![],
+
+            as_string: None,
+            as_number: None,
+        })
     }
 }
 
@@ -63,7 +270,9 @@ mod tests {
     use crate::generic_number::{Context, GenericReader, Endian, HexFormatter, OctalFormatter, DefaultFormatter};
     use crate::datatype::simple::H2Number;
     use crate::datatype::simple::network::IPv4;
-    use crate::datatype::composite::H2Array;
+    use crate::datatype::Expression;
+    use crate::datatype::composite::{ArrayLength, H2Array};
+    use crate::datatype::composite::string::H2String;
 
     #[test]
     fn test_struct() -> SimpleResult<()> {
@@ -246,4 +455,247 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_length_from_field() -> SimpleResult<()> {
+        // A count, that many u16s, then a header length that gives the number
+        // of option bytes (4 * 6 - 20 = 4)
+        let data = b"\x03\x00\x01\x00\x02\x00\x03\x06ABCDXX".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        let t = H2Struct::new(vec![
+            ("count".to_string(),      H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("data".to_string(),       H2Array::new_dynamic(
+                ArrayLength::Count(Expression::parse("count")?),
+                H2Number::new(GenericReader::U16(Endian::Big), DefaultFormatter::new()),
+            )?),
+            ("header_len".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("options".to_string(),    H2String::new_dynamic(
+                ArrayLength::Count(Expression::parse("header_len * 4 - 20")?),
+                H2Number::new_ascii(),
+            )?),
+        ])?;
+
+        assert_eq!(false, t.is_static());
+        assert_eq!(12, t.actual_size(offset)?);
+        assert_eq!("{ count: 3, data: [ 1, 2, 3 ], header_len: 6, options: \"ABCD\" }", t.to_display(offset)?);
+
+        // The resolved children have their lengths
+        let r = t.resolve(offset, None)?;
+        assert_eq!(0..12, r.actual_range);
+        assert_eq!(3, r.children[1].children.len());
+        assert_eq!(1..7, r.children[1].actual_range);
+        assert_eq!(8..12, r.children[3].actual_range);
+        assert_eq!("ABCD", r.children[3].as_string.as_ref().unwrap());
+
+        // A count of zero is fine
+        let data = b"\x00\x05A".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(2, t.actual_size(offset)?);
+        assert_eq!("{ count: 0, data: [], header_len: 5, options: \"\" }", t.to_display(offset)?);
+
+        // A negative length isn't
+        let data = b"\x00\x04A".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert!(t.to_display(offset).is_err());
+
+        // Neither is running off the end
+        let data = b"\x04\x00\x01".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert!(t.to_display(offset).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_from_field() -> SimpleResult<()> {
+        // A size in bytes, which includes the size field itself
+        let t = H2Struct::new(vec![
+            ("size".to_string(), H2Number::new(GenericReader::I16(Endian::Little), DefaultFormatter::new())),
+            ("data".to_string(), H2Array::new_dynamic(
+                ArrayLength::Size(Expression::parse("size - 2")?),
+                H2Number::new(GenericReader::U16(Endian::Little), HexFormatter::pretty()),
+            )?),
+            ("name".to_string(), H2String::new_dynamic(
+                ArrayLength::Size(Expression::parse("(size - 2) / 2")?),
+                H2Number::new_utf8(),
+            )?),
+        ])?;
+
+        let data = b"\x06\x00\x01\x00\x02\x00\xc3\xb7".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ size: 6, data: [ 0x0001, 0x0002 ], name: \"\u{f7}\" }", t.to_display(offset)?);
+        assert_eq!(8, t.actual_size(offset)?);
+
+        // Elements that don't fit the size exactly
+        let data = b"\x05\x00\x01\x00\x02\x00\xc3\xb7".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert!(t.to_display(offset).is_err());
+
+        // A name that isn't known
+        let t = H2Struct::new(vec![
+            ("data".to_string(), H2Array::new_dynamic(
+                ArrayLength::Count(Expression::parse("size")?),
+                H2Number::new_ascii(),
+            )?),
+            ("size".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+        ])?;
+        assert!(t.to_display(offset).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_paths() -> SimpleResult<()> {
+        // A length inside a nested header, and a count that nested types get
+        // from their parent
+        let t = H2Struct::new(vec![
+            ("header".to_string(), H2Struct::new(vec![
+                ("count".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+                ("size".to_string(),  H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ])?),
+            ("name".to_string(), H2String::new_dynamic(
+                ArrayLength::Count(Expression::parse("header.size")?),
+                H2Number::new_ascii(),
+            )?),
+            ("body".to_string(), H2Struct::new(vec![
+                ("values".to_string(), H2Array::new_dynamic(
+                    ArrayLength::Count(Expression::parse("parent.header.count")?),
+                    H2Number::new(GenericReader::U8, DefaultFormatter::new()),
+                )?),
+            ])?),
+            ("pairs".to_string(), H2Array::new_dynamic(
+                ArrayLength::Count(Expression::parse("header.count - 1")?),
+                H2Struct::new(vec![
+                    ("a".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+                    ("b".to_string(), H2Array::new_dynamic(
+                        ArrayLength::Count(Expression::parse("parent.header.size - a")?),
+                        H2Number::new_ascii(),
+                    )?),
+                ])?,
+            )?),
+        ])?;
+
+        let data = b"abcz".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ header: { count: 2, size: 3 }, name: \"abc\", body: { values: [ 5, 6 ] }, pairs: [ { a: 2, b: [ 'z' ] } ] }", t.to_display(offset)?);
+        assert_eq!(9, t.actual_size(offset)?);
+
+        // The values of nested structs are available, with their path
+        let values = as_struct(&t).field_values(offset)?;
+        assert!(values.contains(&(Reference::Value("header.size".to_string()), 3)));
+        assert!(values.contains(&(Reference::SizeOf("header".to_string()), 2)));
+        assert!(values.contains(&(Reference::OffsetOf("pairs".to_string()), 7)));
+        assert!(values.contains(&(Reference::SizeOf("body.values".to_string()), 2)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_unsigned_field() -> SimpleResult<()> {
+        // A u64 with the top bit set is still bound, with the same bits
+        let t = H2Struct::new(vec![
+            ("flags".to_string(), H2Number::new(GenericReader::U64(Endian::Big), HexFormatter::pretty())),
+            ("data".to_string(),  H2Array::new_dynamic(
+                ArrayLength::Count(Expression::parse("(flags & 0x8000000000000000) != 0")?),
+                H2Number::new_ascii(),
+            )?),
+        ])?;
+
+        let data = b"\x80\x00\x00\x00\x00\x00\x00\x01A".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(9, t.actual_size(offset)?);
+        assert!(as_struct(&t).field_values(offset)?.contains(&(Reference::Value("flags".to_string()), i64::MIN | 1)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sizeof_and_offsetof() -> SimpleResult<()> {
+        // A header, padded out to a multiple of 4 bytes, followed by data
+        // that takes up the rest of a total length
+        let t = H2Struct::new(vec![
+            ("total".to_string(),   H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("name".to_string(),    H2String::new_dynamic(
+                ArrayLength::Terminator(b"\x00".to_vec()),
+                H2Number::new_ascii(),
+            )?),
+            ("padding".to_string(), H2Array::new_dynamic(
+                ArrayLength::Count(Expression::parse("padding(offsetof(name) + sizeof(name), 4)")?),
+                H2Number::new(GenericReader::U8, HexFormatter::pretty()),
+            )?),
+            ("data".to_string(),    H2Array::new_dynamic(
+                ArrayLength::Size(Expression::parse("total - offsetof(padding) - sizeof(padding)")?),
+                H2Number::new_ascii(),
+            )?),
+        ])?;
+
+        let data = b"\x07a\x00\x00xyz".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ total: 7, name: \"a\", padding: [ 0x00 ], data: [ 'x', 'y', 'z' ] }", t.to_display(offset)?);
+
+        // Already aligned
+        let data = b"\x06ab\x00xy".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ total: 6, name: \"ab\", padding: [], data: [ 'x', 'y' ] }", t.to_display(offset)?);
+
+        // Offsets are relative to the start of the struct
+        let data = b"!!\x06ab\x00xy".to_vec();
+        let offset = Offset::Dynamic(Context::new_at(&data, 2));
+        assert_eq!("{ total: 6, name: \"ab\", padding: [], data: [ 'x', 'y' ] }", t.to_display(offset)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deeply_nested() -> SimpleResult<()> {
+        // Each level used to analyze the one inside it twice, so this would
+        // take forever
+        let mut t = H2Struct::new(vec![
+            ("n".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+        ])?;
+        for _ in 0..40 {
+            t = H2Struct::new(vec![
+                ("n".to_string(),     H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+                ("inner".to_string(), t),
+            ])?;
+        }
+
+        let data: Vec<u8> = (0..41).collect();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        let r = t.resolve(offset, None)?;
+        assert_eq!(0..41, r.actual_range);
+        assert_eq!(t.to_display(offset)?, r.display);
+        assert!(r.display.starts_with("{ n: 0, inner: { n: 1, inner: { n: 2, "));
+
+        let values = as_struct(&t).field_values(offset)?;
+        assert!(values.contains(&(Reference::Value("inner.inner.n".to_string()), 2)));
+        assert!(values.contains(&(Reference::OffsetOf("inner.inner".to_string()), 1)));
+
+        // The same, but ending with something that runs to the end of the data
+        let mut t = H2Array::new_dynamic(ArrayLength::Remaining, H2Number::new(GenericReader::U8, DefaultFormatter::new()))?;
+        for _ in 0..40 {
+            t = H2Struct::new(vec![
+                ("n".to_string(),     H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+                ("inner".to_string(), t),
+            ])?;
+        }
+
+        let data: Vec<u8> = (0..42).collect();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        let r = t.resolve(offset, None)?;
+        assert_eq!(0..42, r.actual_range);
+        assert!(r.display.contains("{ n: 39, inner: [ 40, 41 ] }"));
+
+        Ok(())
+    }
+
+    fn as_struct(t: &H2Type) -> &H2Struct {
+        match &t.field {
+            H2Types::H2Struct(s) => s,
+            _ => panic!("Not a struct"),
+        }
+    }
 }
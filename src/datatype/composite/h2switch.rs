@@ -0,0 +1,244 @@
+use serde::{Serialize, Deserialize};
+
+use simple_error::{bail, SimpleResult};
+
+use crate::datatype::{Alignment, Expression, H2Type, H2Types, H2TypeTrait, Offset, Reference};
+
+/// Defines a tagged union - one of several types, chosen by a value.
+///
+/// Unlike [`crate::datatype::composite::H2Union`], which shows every variant
+/// overlapping, only one variant is ever used. The variant is chosen by the
+/// value of an [`Expression`], which normally refers to an earlier field in
+/// the [`crate::datatype::composite::H2Struct`] that contains the switch
+/// (something like `switch (type) { 1 => ChunkA, 2 => ChunkB, _ => Raw }`).
+///
+/// The switch has one child - the chosen variant, with its name - and
+/// displays as that variant.
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::datatype::*;
+/// use libh2gb::datatype::simple::*;
+/// use libh2gb::datatype::composite::*;
+/// use libh2gb::generic_number::*;
+///
+/// let t = H2Struct::new(vec![
+///     ("type".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+///     ("body".to_string(), H2Switch::new(
+///         Expression::parse("type")?,
+///         vec![
+///             (1, "u16".to_string(), H2Number::new(GenericReader::U16(Endian::Big), HexFormatter::pretty())),
+///             (2, "u32".to_string(), H2Number::new(GenericReader::U32(Endian::Big), HexFormatter::pretty())),
+///         ],
+///         Some(("raw".to_string(), H2Number::new(GenericReader::U8, HexFormatter::pretty()))),
+///     )?),
+/// ])?;
+///
+/// let data = b"\x01\x12\x34".to_vec();
+/// assert_eq!("{ type: 1, body: 0x1234 }", t.to_display(Offset::Dynamic(Context::new(&data)))?);
+///
+/// let data = b"\x09\x12\x34".to_vec();
+/// assert_eq!("{ type: 9, body: 0x12 }", t.to_display(Offset::Dynamic(Context::new(&data)))?);
+/// # Ok::<(), simple_error::SimpleError>(())
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct H2Switch {
+    selector: Expression,
+
+    /// The value that chooses each variant, and the variant's name and type
+    cases: Vec<(i64, String, H2Type)>,
+
+    /// The variant to use if none of the cases match
+    default: Option<(String, Box<H2Type>)>,
+}
+
+impl H2Switch {
+    pub fn new_aligned(alignment: Alignment, selector: Expression, cases: Vec<(i64, String, H2Type)>, default: Option<(String, H2Type)>) -> SimpleResult<H2Type> {
+        if cases.is_empty() && default.is_none() {
+            bail!("Switches must have at least one case");
+        }
+
+        for (index, (value, name, _)) in cases.iter().enumerate() {
+            if cases[..index].iter().any(|(v, _, _)| v == value) {
+                bail!("Switch case {} ({}) is a duplicate", value, name);
+            }
+        }
+
+        Ok(H2Type::new(alignment, H2Types::H2Switch(Self {
+            selector,
+            cases,
+            default: default.map(|(name, field_type)| (name, Box::new(field_type))),
+        })))
+    }
+
+    pub fn new(selector: Expression, cases: Vec<(i64, String, H2Type)>, default: Option<(String, H2Type)>) -> SimpleResult<H2Type> {
+        Self::new_aligned(Alignment::None, selector, cases, default)
+    }
+
+    /// Get a copy of this type with any known fields filled in.
+    pub(crate) fn with_fields(&self, fields: &[(Reference, i64)]) -> Self {
+        Self {
+            selector: self.selector.bind(fields),
+            cases: self.cases.iter().map(|(value, name, field_type)| {
+                (*value, name.clone(), field_type.with_fields(fields))
+            }).collect(),
+            default: self.default.as_ref().map(|(name, field_type)| {
+                (name.clone(), Box::new(field_type.with_fields(fields)))
+            }),
+        }
+    }
+
+    /// Get a copy of this type that knows where the space its parent leaves
+    /// for it ends.
+    pub(crate) fn with_parent_end(&self, parent_end: u64) -> Self {
+        Self {
+            selector: self.selector.clone(),
+            cases: self.cases.iter().map(|(value, name, field_type)| {
+                (*value, name.clone(), field_type.with_parent_end(parent_end))
+            }).collect(),
+            default: self.default.as_ref().map(|(name, field_type)| {
+                (name.clone(), Box::new(field_type.with_parent_end(parent_end)))
+            }),
+        }
+    }
+
+    /// Get the name and type of the chosen variant.
+    pub fn chosen(&self) -> SimpleResult<(String, H2Type)> {
+        let value = self.selector.evaluate()?;
+
+        if let Some((_, name, field_type)) = self.cases.iter().find(|(v, _, _)| *v == value) {
+            return Ok((name.clone(), field_type.clone()));
+        }
+
+        match &self.default {
+            Some((name, field_type)) => Ok((name.clone(), field_type.as_ref().clone())),
+            None => bail!("No switch case matches {} (value {})", self.selector, value),
+        }
+    }
+}
+
+impl H2TypeTrait for H2Switch {
+    fn is_static(&self) -> bool {
+        // The size depends on the data
+        false
+    }
+
+    fn children(&self, _offset: Offset) -> SimpleResult<Vec<(Option<String>, H2Type)>> {
+        let (name, field_type) = self.chosen()?;
+
+        Ok(vec![(Some(name), field_type)])
+    }
+
+    fn to_display(&self, offset: Offset) -> SimpleResult<String> {
+        self.chosen()?.1.to_display(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use simple_error::SimpleResult;
+
+    use crate::generic_number::{Context, GenericReader, Endian, DefaultFormatter, HexFormatter};
+    use crate::datatype::simple::H2Number;
+    use crate::datatype::composite::{ArrayLength, H2Array, H2Struct};
+
+    fn chunk() -> SimpleResult<H2Type> {
+        H2Struct::new(vec![
+            ("type".to_string(),   H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("length".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("body".to_string(),   H2Switch::new(
+                Expression::parse("type")?,
+                vec![
+                    (1, "point".to_string(), H2Struct::new(vec![
+                        ("x".to_string(), H2Number::new(GenericReader::U16(Endian::Big), DefaultFormatter::new())),
+                        ("y".to_string(), H2Number::new(GenericReader::U16(Endian::Big), DefaultFormatter::new())),
+                    ])?),
+                    (2, "name".to_string(), H2Array::new_dynamic(
+                        ArrayLength::Count(Expression::parse("length")?),
+                        H2Number::new_ascii(),
+                    )?),
+                ],
+                Some(("raw".to_string(), H2Array::new_dynamic(
+                    ArrayLength::Size(Expression::parse("length")?),
+                    H2Number::new(GenericReader::U8, HexFormatter::pretty()),
+                )?)),
+            )?),
+        ])
+    }
+
+    #[test]
+    fn test_switch() -> SimpleResult<()> {
+        let t = chunk()?;
+        assert_eq!(false, t.is_static());
+
+        // Case 1
+        let data = b"\x01\x04\x00\x01\x00\x02".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(6, t.actual_size(offset)?);
+        assert_eq!("{ type: 1, length: 4, body: { x: 1, y: 2 } }", t.to_display(offset)?);
+
+        // Only the chosen branch is resolved
+        let r = t.resolve(offset, None)?;
+        assert_eq!(1, r.children[2].children.len());
+        assert_eq!("point", r.children[2].children[0].field_name.as_ref().unwrap());
+        assert_eq!(2..6, r.children[2].children[0].actual_range);
+        assert_eq!(2, r.children[2].children[0].children.len());
+
+        // Case 2, which uses the length
+        let data = b"\x02\x03abc".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(5, t.actual_size(offset)?);
+        assert_eq!("{ type: 2, length: 3, body: [ 'a', 'b', 'c' ] }", t.to_display(offset)?);
+
+        let r = t.resolve(offset, None)?;
+        assert_eq!("name", r.children[2].children[0].field_name.as_ref().unwrap());
+
+        // The default
+        let data = b"\x07\x02\xff\xfe".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ type: 7, length: 2, body: [ 0xff, 0xfe ] }", t.to_display(offset)?);
+
+        let r = t.resolve(offset, None)?;
+        assert_eq!("raw", r.children[2].children[0].field_name.as_ref().unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_match() -> SimpleResult<()> {
+        let t = H2Struct::new(vec![
+            ("type".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+            ("body".to_string(), H2Switch::new(
+                Expression::parse("type * 2")?,
+                vec![
+                    (2, "a".to_string(), H2Number::new(GenericReader::U8, DefaultFormatter::new())),
+                ],
+                None,
+            )?),
+        ])?;
+
+        let data = b"\x01\x05".to_vec();
+        assert_eq!("{ type: 1, body: 5 }", t.to_display(Offset::Dynamic(Context::new(&data)))?);
+
+        let data = b"\x02\x05".to_vec();
+        assert!(t.to_display(Offset::Dynamic(Context::new(&data))).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_switch() -> SimpleResult<()> {
+        assert!(H2Switch::new(Expression::parse("1")?, vec![], None).is_err());
+        assert!(H2Switch::new(Expression::parse("1")?, vec![], Some(("a".to_string(), H2Number::new_ascii()))).is_ok());
+
+        assert!(H2Switch::new(Expression::parse("1")?, vec![
+            (1, "a".to_string(), H2Number::new_ascii()),
+            (1, "b".to_string(), H2Number::new_ascii()),
+        ], None).is_err());
+
+        Ok(())
+    }
+}
@@ -12,4 +12,10 @@ pub use h2union::*;
 mod h2struct;
 pub use h2struct::*;
 
+mod h2switch;
+pub use h2switch::*;
+
+mod h2conditional;
+pub use h2conditional::*;
+
 pub mod string;
@@ -3,8 +3,8 @@ use serde::{Serialize, Deserialize};
 use simple_error::{bail, SimpleResult};
 use std::iter::FromIterator;
 
-use crate::datatype::{H2Type, H2Types, H2TypeTrait, Offset, Alignment};
-use crate::datatype::composite::H2Array;
+use crate::datatype::{H2Type, H2Types, H2TypeTrait, Offset, Alignment, Reference};
+use crate::datatype::composite::{ArrayLength, H2Array};
 
 /// Defines a string with a configured length.
 ///
@@ -12,10 +12,18 @@ use crate::datatype::composite::H2Array;
 /// bytes may be longer if the character type is non-ASCII, however. See
 /// [`crate::generic_number::GenericReader`] for a list of possible character
 /// types.
+///
+/// The length can also come from an earlier field in a struct, either as a
+/// number of characters or a number of bytes, or the string can run until a
+/// terminator (see [`ArrayLength`]).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct H2String {
-    length: u64,
+    length: ArrayLength,
     character: Box<H2Type>,
+
+    /// Where the space that the containing struct leaves for the string
+    /// ends (see [`H2Array`]).
+    parent_end: Option<u64>,
 }
 
 impl H2String {
@@ -24,41 +32,88 @@ impl H2String {
             bail!("Length must be at least 1 character long");
         }
 
+        Self::new_dynamic_aligned(alignment, ArrayLength::Fixed(length_in_characters), character)
+    }
+
+    pub fn new(length_in_characters: u64, character: H2Type) -> SimpleResult<H2Type> {
+        Self::new_aligned(Alignment::None, length_in_characters, character)
+    }
+
+    pub fn new_dynamic_aligned(alignment: Alignment, length: ArrayLength, character: H2Type) -> SimpleResult<H2Type> {
+        if let ArrayLength::Fixed(0) = length {
+            bail!("Length must be at least 1 character long");
+        }
+
         if !character.can_be_char() {
             bail!("Character type can't become a character");
         }
 
         Ok(H2Type::new(alignment, H2Types::H2String(Self {
-            length: length_in_characters,
+            length,
             character: Box::new(character),
+            parent_end: None,
         })))
     }
 
-    pub fn new(length_in_characters: u64, character: H2Type) -> SimpleResult<H2Type> {
-        Self::new_aligned(Alignment::None, length_in_characters, character)
+    pub fn new_dynamic(length: ArrayLength, character: H2Type) -> SimpleResult<H2Type> {
+        Self::new_dynamic_aligned(Alignment::None, length, character)
+    }
+
+    /// Get a copy of this type with any known fields filled in.
+    pub(crate) fn with_fields(&self, fields: &[(Reference, i64)]) -> Self {
+        Self {
+            length: self.length.bind(fields),
+            character: self.character.clone(),
+            parent_end: self.parent_end,
+        }
     }
 
+    /// Get a copy of this type that knows where the space its parent leaves
+    /// for it ends.
+    pub(crate) fn with_parent_end(&self, parent_end: u64) -> Self {
+        let mut string = self.clone();
+        string.parent_end = Some(parent_end);
+
+        string
+    }
+
+    fn array(&self) -> SimpleResult<H2Type> {
+        let array = H2Array::new_dynamic(self.length.clone(), self.character.as_ref().clone())?;
+
+        match self.parent_end {
+            Some(parent_end) => Ok(array.with_parent_end(parent_end)),
+            None             => Ok(array),
+        }
+    }
 
     fn analyze(&self, offset: Offset) -> SimpleResult<(u64, Vec<char>)> {
         let mut position = offset.position();
         let mut result = Vec::new();
 
-        for _ in 0..self.length {
+        for (_, character) in self.array()?.children(offset)? {
             let this_offset = offset.at(position);
-            let this_size = self.character.actual_size(this_offset)?;
-            let this_character = self.character.to_char(this_offset)?;
+            let this_size = character.actual_size(this_offset)?;
+            let this_character = character.to_char(this_offset)?;
 
             result.push(this_character);
             position = position + this_size;
         }
 
+        // The terminator is part of the string, but not a character
+        if let ArrayLength::Terminator(terminator) = &self.length {
+            position += terminator.len() as u64;
+        }
+
         Ok((position - offset.position(), result))
     }
 }
 
 impl H2TypeTrait for H2String {
     fn is_static(&self) -> bool {
-        self.character.is_static()
+        match self.length {
+            ArrayLength::Fixed(_) => self.character.is_static(),
+            _                     => false,
+        }
     }
 
     fn actual_size(&self, offset: Offset) -> SimpleResult<u64> {
@@ -83,7 +138,7 @@ impl H2TypeTrait for H2String {
 
     fn children(&self, _offset: Offset) -> SimpleResult<Vec<(Option<String>, H2Type)>> {
         Ok(vec![
-            ( None, H2Array::new(self.length, self.character.as_ref().clone())? ),
+            ( None, self.array()? ),
         ])
     }
 }
@@ -92,9 +147,11 @@ impl H2TypeTrait for H2String {
 mod tests {
     use super::*;
     use simple_error::SimpleResult;
-    use crate::generic_number::{Context, Endian};
+    use crate::generic_number::{Context, Endian, GenericReader, DefaultFormatter};
     use crate::datatype::simple::H2Number;
     use crate::datatype::simple::network::IPv4;
+    use crate::datatype::Expression;
+    use crate::datatype::composite::H2Struct;
 
     #[test]
     fn test_utf8_lstring() -> SimpleResult<()> {
@@ -165,4 +222,44 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_terminated() -> SimpleResult<()> {
+        let data = b"GET / HTTP/1.0\r\nHost: x\r\n".to_vec();
+
+        let t = H2String::new_dynamic(ArrayLength::Terminator(b"\r\n".to_vec()), H2Number::new_ascii())?;
+        assert_eq!(false, t.is_static());
+
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("\"GET / HTTP/1.0\"", t.to_display(offset)?);
+        assert_eq!(16, t.actual_size(offset)?);
+
+        let offset = Offset::Dynamic(Context::new_at(&data, 16));
+        assert_eq!("\"Host: x\"", t.to_display(offset)?);
+        assert_eq!(9, t.actual_size(offset)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_huge_length() -> SimpleResult<()> {
+        // A length from the data that's far bigger than the data
+        let data = b"\xff\xff\xff\x7fABCD".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        let t = H2Struct::new(vec![
+            ("length".to_string(), H2Number::new(GenericReader::U32(Endian::Little), DefaultFormatter::new())),
+            ("name".to_string(),   H2String::new_dynamic(
+                ArrayLength::Count(Expression::parse("length")?),
+                H2Number::new_ascii(),
+            )?),
+        ])?;
+        assert!(t.actual_size(offset).is_err());
+        assert!(t.to_display(offset).is_err());
+
+        let t = H2String::new_dynamic(ArrayLength::Size(Expression::parse("0x7fffffff")?), H2Number::new_utf8())?;
+        assert!(t.to_display(offset).is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,787 @@
+use serde::{Serialize, Deserialize};
+use simple_error::{bail, SimpleResult};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// An expression, such as `header_len * 4 - 20` or `version >= 2`.
+///
+/// Expressions are how types calculate things from the data - array lengths
+/// (see [`crate::datatype::composite::ArrayLength`]), which variant of a
+/// [`crate::datatype::composite::H2Switch`] to use, whether a
+/// [`crate::datatype::composite::H2Conditional`] is there, and so on. They're
+/// stored in the type, so they're serialized along with it.
+///
+/// # Syntax
+///
+/// * Integers, in decimal or hex (`0x`)
+/// * References to fields (see [`Reference`]): `length`, `header.size`,
+///   `parent.count`, `sizeof(header)`, and `offsetof(data)`
+/// * The operators in [`UnaryOperator`] and [`BinaryOperator`], with the same
+///   precedence as C, and parentheses
+/// * The functions in [`Function`], such as `align(length, 4)`
+///
+/// Field values are read as [`crate::generic_number::GenericNumber`]s, and
+/// everything is calculated as a signed 64-bit number. Overflows are errors.
+/// Unsigned values (and hex numbers) above [`i64::MAX`] keep their bits and
+/// wrap around to negative numbers, so bitwise operators still work on them
+/// (such as `flags & 0x8000000000000000`).
+///
+/// # Fields
+///
+/// When an [`crate::datatype::composite::H2Struct`] works out where its
+/// children are, it fills in each field that has been read so far (see
+/// [`Expression::bind`]), so later fields can refer to earlier ones:
+///
+/// * Each field that's a number, by name (`length`)
+/// * The numeric fields of a field that's a struct, with a `.` (`header.size`)
+/// * The size and offset of each field (`sizeof(header)`, `offsetof(data)`),
+///   where the offset is relative to the start of the struct
+/// * The fields of the struct that contains this one, with `parent.` in
+///   front (`parent.count`, or even `parent.parent.count`)
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::datatype::{Expression, Reference};
+///
+/// let expression = Expression::parse("header_len * 4 - 20").unwrap();
+///
+/// // The field isn't known yet
+/// assert!(expression.evaluate().is_err());
+///
+/// // Once it's bound, it can be evaluated
+/// let bound = expression.bind(&[(Reference::Value("header_len".to_string()), 6)]);
+/// assert_eq!(4, bound.evaluate().unwrap());
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Expression {
+    Number(i64),
+    Reference(Reference),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+    Call(Function, Vec<Expression>),
+}
+
+/// Something about a field that an [`Expression`] can refer to.
+///
+/// Each one has a path to the field, separated by `.` (such as
+/// `parent.header.size`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Reference {
+    /// The field's value, if it's a number
+    Value(String),
+
+    /// `sizeof(field)` - the field's size, in bytes, including alignment
+    SizeOf(String),
+
+    /// `offsetof(field)` - where the field starts, relative to the start of
+    /// the struct
+    OffsetOf(String),
+}
+
+/// An operator with one operand, in an [`Expression`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UnaryOperator {
+    /// `-`
+    Negate,
+
+    /// `!` - 1 if the operand is 0, otherwise 0
+    Not,
+
+    /// `~` - flip every bit
+    BitwiseNot,
+}
+
+/// An operator with two operands, in an [`Expression`].
+///
+/// Comparisons and logical operators are 1 if true and 0 if false, and treat
+/// anything other than 0 as true.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+
+    ShiftLeft,
+    ShiftRight,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+
+    And,
+    Or,
+}
+
+/// A function that can be called in an [`Expression`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Function {
+    /// `align(x, n)` - round `x` up to a multiple of `n`
+    Align,
+
+    /// `align_down(x, n)` - round `x` down to a multiple of `n`
+    AlignDown,
+
+    /// `padding(x, n)` - how far `x` is from the next multiple of `n`
+    Padding,
+
+    /// `min(a, b)`
+    Min,
+
+    /// `max(a, b)`
+    Max,
+}
+
+impl Reference {
+    fn path(&self) -> &str {
+        match self {
+            Self::Value(path)    => path,
+            Self::SizeOf(path)   => path,
+            Self::OffsetOf(path) => path,
+        }
+    }
+
+    /// Get the same reference, but to a field inside `prefix`.
+    pub(crate) fn with_prefix(&self, prefix: &str) -> Self {
+        let path = format!("{}.{}", prefix, self.path());
+
+        match self {
+            Self::Value(_)    => Self::Value(path),
+            Self::SizeOf(_)   => Self::SizeOf(path),
+            Self::OffsetOf(_) => Self::OffsetOf(path),
+        }
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Value(path)    => write!(f, "{}", path),
+            Self::SizeOf(path)   => write!(f, "sizeof({})", path),
+            Self::OffsetOf(path) => write!(f, "offsetof({})", path),
+        }
+    }
+}
+
+impl UnaryOperator {
+    const ALL: [UnaryOperator; 3] = [Self::Negate, Self::Not, Self::BitwiseNot];
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Negate     => "-",
+            Self::Not        => "!",
+            Self::BitwiseNot => "~",
+        }
+    }
+
+    /// Returns `None` on overflow.
+    fn apply(self, a: i64) -> Option<i64> {
+        match self {
+            Self::Negate     => a.checked_neg(),
+            Self::Not        => Some((a == 0) as i64),
+            Self::BitwiseNot => Some(!a),
+        }
+    }
+}
+
+impl BinaryOperator {
+    /// Every operator, from the loosest precedence to the tightest.
+    const PRECEDENCE: [&'static [BinaryOperator]; 10] = [
+        &[Self::Or],
+        &[Self::And],
+        &[Self::BitwiseOr],
+        &[Self::BitwiseXor],
+        &[Self::BitwiseAnd],
+        &[Self::Equal, Self::NotEqual],
+        &[Self::LessThan, Self::LessOrEqual, Self::GreaterThan, Self::GreaterOrEqual],
+        &[Self::ShiftLeft, Self::ShiftRight],
+        &[Self::Add, Self::Subtract],
+        &[Self::Multiply, Self::Divide, Self::Remainder],
+    ];
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Add            => "+",
+            Self::Subtract       => "-",
+            Self::Multiply       => "*",
+            Self::Divide         => "/",
+            Self::Remainder      => "%",
+            Self::ShiftLeft      => "<<",
+            Self::ShiftRight     => ">>",
+            Self::BitwiseAnd     => "&",
+            Self::BitwiseOr      => "|",
+            Self::BitwiseXor     => "^",
+            Self::Equal          => "==",
+            Self::NotEqual       => "!=",
+            Self::LessThan       => "<",
+            Self::LessOrEqual    => "<=",
+            Self::GreaterThan    => ">",
+            Self::GreaterOrEqual => ">=",
+            Self::And            => "&&",
+            Self::Or             => "||",
+        }
+    }
+
+    /// Returns `None` on overflow, division by zero, or a bad shift.
+    fn apply(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Self::Add            => a.checked_add(b),
+            Self::Subtract       => a.checked_sub(b),
+            Self::Multiply       => a.checked_mul(b),
+            Self::Divide         => a.checked_div(b),
+            Self::Remainder      => a.checked_rem(b),
+            Self::ShiftLeft      => a.checked_shl(u32::try_from(b).ok()?),
+            Self::ShiftRight     => a.checked_shr(u32::try_from(b).ok()?),
+            Self::BitwiseAnd     => Some(a & b),
+            Self::BitwiseOr      => Some(a | b),
+            Self::BitwiseXor     => Some(a ^ b),
+            Self::Equal          => Some((a == b) as i64),
+            Self::NotEqual       => Some((a != b) as i64),
+            Self::LessThan       => Some((a < b) as i64),
+            Self::LessOrEqual    => Some((a <= b) as i64),
+            Self::GreaterThan    => Some((a > b) as i64),
+            Self::GreaterOrEqual => Some((a >= b) as i64),
+            Self::And            => Some((a != 0 && b != 0) as i64),
+            Self::Or             => Some((a != 0 || b != 0) as i64),
+        }
+    }
+}
+
+impl Function {
+    const ALL: [Function; 5] = [Self::Align, Self::AlignDown, Self::Padding, Self::Min, Self::Max];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Align     => "align",
+            Self::AlignDown => "align_down",
+            Self::Padding   => "padding",
+            Self::Min       => "min",
+            Self::Max       => "max",
+        }
+    }
+
+    fn argument_count(self) -> usize {
+        2
+    }
+
+    /// Returns `None` on overflow or a bad alignment.
+    fn apply(self, arguments: &[i64]) -> Option<i64> {
+        let (a, b) = match arguments {
+            [a, b] => (*a, *b),
+            _      => return None,
+        };
+
+        match self {
+            Self::Align     if b > 0 => a.checked_add(Self::Padding.apply(arguments)?),
+            Self::AlignDown if b > 0 => a.checked_sub(a.rem_euclid(b)),
+            Self::Padding   if b > 0 => Some((b - a.rem_euclid(b)) % b),
+            Self::Min                => Some(a.min(b)),
+            Self::Max                => Some(a.max(b)),
+            _                        => None,
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n)              => write!(f, "{}", n),
+            Self::Reference(reference)   => write!(f, "{}", reference),
+            Self::Unary(operator, e)     => write!(f, "{}({})", operator.symbol(), e),
+            Self::Binary(operator, a, b) => write!(f, "({} {} {})", a, operator.symbol(), b),
+            Self::Call(function, args)   => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", function.name(), args.join(", "))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+/// Everything that isn't a number or a name; the longer symbols are first,
+/// so `<=` isn't read as `<`.
+const SYMBOLS: [&str; 24] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>",
+    "+", "-", "*", "/", "%", "<", ">", "!", "~", "&", "|", "^", "(", ")", ",", ".",
+];
+
+fn tokenize(s: &str) -> SimpleResult<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = s.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            let word = &rest[..length];
+            rest = &rest[length..];
+
+            if !c.is_ascii_digit() {
+                tokens.push(Token::Name(word.to_string()));
+            } else {
+                let number = match word.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16).map(|n| n as i64),
+                    None      => word.parse::<i64>(),
+                };
+
+                match number {
+                    Ok(n) => tokens.push(Token::Number(n)),
+                    Err(e) => bail!("Couldn't parse number {} in expression: {}", word, e),
+                }
+            }
+        } else {
+            let symbol = match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+                Some(symbol) => symbol,
+                None => bail!("Unexpected character in expression: '{}'", c),
+            };
+
+            rest = &rest[symbol.len()..];
+            tokens.push(Token::Symbol(symbol));
+        }
+
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive-descent parser.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        token
+    }
+
+    /// If the next token is `symbol`, consume it.
+    fn take(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) if *s == symbol => {
+                self.position += 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> SimpleResult<()> {
+        match self.take(symbol) {
+            true  => Ok(()),
+            false => bail!("Missing '{}' in expression", symbol),
+        }
+    }
+
+    /// Parse the binary operators at a level of precedence (or tighter),
+    /// left to right.
+    fn binary(&mut self, level: usize) -> SimpleResult<Expression> {
+        let operators = match BinaryOperator::PRECEDENCE.get(level) {
+            Some(o) => *o,
+            None    => return self.unary(),
+        };
+
+        let mut left = self.binary(level + 1)?;
+
+        while let Some(operator) = operators.iter().find(|o| self.take(o.symbol())) {
+            let right = self.binary(level + 1)?;
+
+            left = Expression::Binary(*operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    /// Unary operators, numbers, references, calls, and parentheses
+    fn unary(&mut self) -> SimpleResult<Expression> {
+        if let Some(operator) = UnaryOperator::ALL.iter().find(|o| self.take(o.symbol())) {
+            return Ok(Expression::Unary(*operator, Box::new(self.unary()?)));
+        }
+
+        match self.next() {
+            Some(Token::Symbol("(")) => {
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+
+                Ok(inner)
+            },
+            Some(Token::Number(n)) => Ok(Expression::Number(n)),
+            Some(Token::Name(name)) => self.name(name),
+            Some(Token::Symbol(s)) => bail!("Unexpected '{}' in expression", s),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+
+    /// A reference or function call, starting with `name`
+    fn name(&mut self, name: String) -> SimpleResult<Expression> {
+        if !self.take("(") {
+            return Ok(Expression::Reference(Reference::Value(self.path(name)?)));
+        }
+
+        // sizeof() and offsetof() take a path, not an expression
+        let reference = match name.as_str() {
+            "sizeof"   => Some(Reference::SizeOf as fn(String) -> Reference),
+            "offsetof" => Some(Reference::OffsetOf as fn(String) -> Reference),
+            _          => None,
+        };
+
+        if let Some(reference) = reference {
+            let path = match self.next() {
+                Some(Token::Name(first)) => self.path(first)?,
+                _ => bail!("{}() needs a field name", name),
+            };
+            self.expect(")")?;
+
+            return Ok(Expression::Reference(reference(path)));
+        }
+
+        let function = match Function::ALL.iter().find(|f| f.name() == name) {
+            Some(f) => *f,
+            None    => bail!("Unknown function in expression: {}", name),
+        };
+
+        let mut arguments = vec![self.binary(0)?];
+        while self.take(",") {
+            arguments.push(self.binary(0)?);
+        }
+        self.expect(")")?;
+
+        if arguments.len() != function.argument_count() {
+            bail!("{}() takes {} arguments, not {}", name, function.argument_count(), arguments.len());
+        }
+
+        Ok(Expression::Call(function, arguments))
+    }
+
+    /// The rest of a path like `parent.header.size`, starting with `first`
+    fn path(&mut self, first: String) -> SimpleResult<String> {
+        let mut path = first;
+
+        while self.take(".") {
+            match self.next() {
+                Some(Token::Name(name)) => {
+                    path.push('.');
+                    path.push_str(&name);
+                },
+                _ => bail!("Expected a field name after '{}.'", path),
+            }
+        }
+
+        Ok(path)
+    }
+}
+
+impl Expression {
+    /// Parse an expression from a string.
+    pub fn parse(s: &str) -> SimpleResult<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+        };
+
+        let expression = parser.binary(0)?;
+
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected {:?} at the end of expression", token);
+        }
+
+        Ok(expression)
+    }
+
+    /// Replace any references that are known with their values.
+    ///
+    /// If a reference appears more than once, the last one wins.
+    pub fn bind(&self, fields: &[(Reference, i64)]) -> Self {
+        match self {
+            Self::Number(n)              => Self::Number(*n),
+            Self::Reference(reference)   => match fields.iter().rev().find(|(r, _)| r == reference) {
+                Some((_, value)) => Self::Number(*value),
+                None             => Self::Reference(reference.clone()),
+            },
+            Self::Unary(operator, e)     => Self::Unary(*operator, Box::new(e.bind(fields))),
+            Self::Binary(operator, a, b) => Self::Binary(*operator, Box::new(a.bind(fields)), Box::new(b.bind(fields))),
+            Self::Call(function, args)   => Self::Call(*function, args.iter().map(|a| a.bind(fields)).collect()),
+        }
+    }
+
+    /// Calculate the value. Every reference must be bound first.
+    pub fn evaluate(&self) -> SimpleResult<i64> {
+        let result = match self {
+            Self::Number(n)            => Some(*n),
+            Self::Reference(reference) => bail!("Unknown field in expression: {}", reference),
+            Self::Unary(operator, e)   => operator.apply(e.evaluate()?),
+
+            // These short-circuit, so the other side doesn't need to be valid
+            Self::Binary(BinaryOperator::And, a, _) if a.evaluate()? == 0 => Some(0),
+            Self::Binary(BinaryOperator::Or,  a, _) if a.evaluate()? != 0 => Some(1),
+
+            Self::Binary(operator, a, b) => operator.apply(a.evaluate()?, b.evaluate()?),
+            Self::Call(function, args)   => function.apply(&args.iter().map(|a| a.evaluate()).collect::<SimpleResult<Vec<i64>>>()?),
+        };
+
+        match result {
+            Some(n) => Ok(n),
+            None    => bail!("Couldn't calculate {} (overflow, division by zero, or a bad argument)", self),
+        }
+    }
+
+    /// Calculate the value, which must not be negative.
+    pub fn evaluate_u64(&self) -> SimpleResult<u64> {
+        let value = self.evaluate()?;
+
+        if value < 0 {
+            bail!("Expression {} is negative ({})", self, value);
+        }
+
+        Ok(value as u64)
+    }
+
+    /// Calculate the value as a condition (anything but 0 is true).
+    pub fn evaluate_bool(&self) -> SimpleResult<bool> {
+        Ok(self.evaluate()? != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use simple_error::SimpleResult;
+
+    fn values(fields: &[(&str, i64)]) -> Vec<(Reference, i64)> {
+        fields.iter().map(|(name, value)| (Reference::Value(name.to_string()), *value)).collect()
+    }
+
+    #[test]
+    fn test_arithmetic() -> SimpleResult<()> {
+        let tests = vec![
+            ("1",               1),
+            ("0x10",            16),
+            ("1 + 2 * 3",       7),
+            ("(1 + 2) * 3",     9),
+            ("10 - 4 - 3",      3),
+            ("100 / 10 / 5",    2),
+            ("17 % 5",          2),
+            ("-3 + 5",          2),
+            ("-(3 + 5)",        -8),
+            ("2 * -3",          -6),
+            ("  7*(2+(3-1)) ",  28),
+
+            ("1 == 1",          1),
+            ("1 != 1",          0),
+            ("2 < 3",           1),
+            ("3 <= 3",          1),
+            ("2 > 3",           0),
+            ("2 >= 3",          0),
+            ("1 + 1 == 2",      1),
+            ("1 < 2 == 1",      1),
+            ("2 == 1 < 2",      0),
+            ("!0",              1),
+            ("!5",              0),
+            ("1 && 2",          1),
+            ("1 && 0",          0),
+            ("0 || 3",          1),
+            ("0 || 1 && 0",     0),
+            ("1 || 0 && 0",     1),
+        ];
+
+        for (expression, expected) in tests {
+            assert_eq!(expected, Expression::parse(expression)?.evaluate()?, "{}", expression);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bitwise() -> SimpleResult<()> {
+        let tests = vec![
+            ("0xf0 & 0x3c",          0x30),
+            ("0xf0 | 0x0f",          0xff),
+            ("0xff ^ 0x0f",          0xf0),
+            ("~0",                   -1),
+            ("~0 & 0xff",            0xff),
+            ("1 << 4",               16),
+            ("0x100 >> 4",           16),
+            ("-16 >> 2",             -4),
+            ("1 << 2 + 1",           8),
+            ("1 | 2 == 2",           1),
+            ("6 & 3 ^ 1",            3),
+            ("1 | 6 & 3",            3),
+            ("flags & 0x10 != 0",    0),
+        ];
+
+        for (expression, expected) in tests {
+            let fields = values(&[("flags", 0x10)]);
+            assert_eq!(expected, Expression::parse(expression)?.bind(&fields).evaluate()?, "{}", expression);
+        }
+
+        // This is the C precedence, which is a classic trap
+        assert_eq!(1, Expression::parse("(flags & 0x10) != 0")?.bind(&values(&[("flags", 0x10)])).evaluate()?);
+
+        // Hex numbers can use all 64 bits
+        assert_eq!(i64::MIN, Expression::parse("0x8000000000000000")?.evaluate()?);
+        assert_eq!(-1, Expression::parse("0xffffffffffffffff")?.evaluate()?);
+        assert_eq!(1, Expression::parse("(flags & 0x8000000000000000) != 0")?.bind(&values(&[("flags", i64::MIN | 1)])).evaluate()?);
+        assert!(Expression::parse("0x10000000000000000").is_err());
+
+        assert!(Expression::parse("1 << 64")?.evaluate().is_err());
+        assert!(Expression::parse("1 << -1")?.evaluate().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_functions() -> SimpleResult<()> {
+        let tests = vec![
+            ("align(5, 4)",           8),
+            ("align(8, 4)",           8),
+            ("align(0, 4)",           0),
+            ("align_down(7, 4)",      4),
+            ("align_down(-1, 4)",     -4),
+            ("padding(5, 4)",         3),
+            ("padding(8, 4)",         0),
+            ("min(3, -2)",            -2),
+            ("max(3, 1 + 4)",         5),
+            ("align(min(9, 7), 2)",   8),
+            ("2 * padding(1, 4)",     6),
+        ];
+
+        for (expression, expected) in tests {
+            assert_eq!(expected, Expression::parse(expression)?.evaluate()?, "{}", expression);
+        }
+
+        assert!(Expression::parse("align(5, 0)")?.evaluate().is_err());
+        assert!(Expression::parse("align(5)").is_err());
+        assert!(Expression::parse("align(5, 4, 3)").is_err());
+        assert!(Expression::parse("nope(5)").is_err());
+        assert!(Expression::parse("min(1, 2").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fields() -> SimpleResult<()> {
+        let expression = Expression::parse("header_len * 4 - 20")?;
+        assert!(expression.evaluate().is_err());
+
+        let fields = values(&[("header_len", 5), ("other", 1)]);
+        assert_eq!(0, expression.bind(&fields).evaluate()?);
+
+        // Later fields with the same name win
+        let fields = values(&[("header_len", 5), ("header_len", 6)]);
+        assert_eq!(4, expression.bind(&fields).evaluate_u64()?);
+
+        // Binding part of it still leaves the rest unknown
+        let expression = Expression::parse("a + b")?.bind(&values(&[("a", 1)]));
+        assert!(expression.evaluate().is_err());
+        assert_eq!(3, expression.bind(&values(&[("b", 2)])).evaluate()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_references() -> SimpleResult<()> {
+        let fields = vec![
+            (Reference::Value("parent.header.size".to_string()), 10),
+            (Reference::Value("header.size".to_string()),        20),
+            (Reference::SizeOf("header".to_string()),            8),
+            (Reference::OffsetOf("data".to_string()),            12),
+            (Reference::SizeOf("parent.header".to_string()),     4),
+        ];
+
+        let tests = vec![
+            ("parent.header.size",                10),
+            ("header.size",                       20),
+            ("sizeof(header)",                    8),
+            ("sizeof( parent . header )",         4),
+            ("offsetof(data) - sizeof(header)",   4),
+            ("align(offsetof(data) + 1, 8)",      16),
+        ];
+
+        for (expression, expected) in tests {
+            assert_eq!(expected, Expression::parse(expression)?.bind(&fields).evaluate()?, "{}", expression);
+        }
+
+        // Values, sizes, and offsets are different things
+        assert!(Expression::parse("sizeof(data)")?.bind(&fields).evaluate().is_err());
+        assert!(Expression::parse("header")?.bind(&fields).evaluate().is_err());
+
+        // Prefixes
+        assert_eq!(Reference::SizeOf("parent.header".to_string()), Reference::SizeOf("header".to_string()).with_prefix("parent"));
+
+        assert!(Expression::parse("header.").is_err());
+        assert!(Expression::parse("sizeof(1)").is_err());
+        assert!(Expression::parse("sizeof(a + b)").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_display() -> SimpleResult<()> {
+        assert_eq!("((a.b + sizeof(c)) << 2)", Expression::parse("a.b + sizeof(c) << 2")?.to_string());
+        assert_eq!("align(offsetof(x), 4)", Expression::parse("align(offsetof(x), 4)")?.to_string());
+        assert_eq!("-(~(!(1)))", Expression::parse("-~!1")?.to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize() -> SimpleResult<()> {
+        let expression = Expression::parse("align(parent.size, 4) - sizeof(header)")?;
+
+        let serialized = serde_json::to_string(&expression).unwrap();
+        let expression: Expression = serde_json::from_str(&serialized).unwrap();
+
+        let fields = vec![
+            (Reference::Value("parent.size".to_string()), 10),
+            (Reference::SizeOf("header".to_string()),     4),
+        ];
+        assert_eq!(8, expression.bind(&fields).evaluate()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() -> SimpleResult<()> {
+        assert!(Expression::parse("").is_err());
+        assert!(Expression::parse("1 +").is_err());
+        assert!(Expression::parse("(1 + 2").is_err());
+        assert!(Expression::parse("1 2").is_err());
+        assert!(Expression::parse("1 $ 2").is_err());
+        assert!(Expression::parse("0xZZ").is_err());
+        assert!(Expression::parse("12abc").is_err());
+
+        assert!(Expression::parse("1 / 0")?.evaluate().is_err());
+        assert!(Expression::parse("9223372036854775807 + 1")?.evaluate().is_err());
+        assert!(Expression::parse("1 - 2")?.evaluate_u64().is_err());
+        assert!(Expression::parse("1 = 2").is_err());
+
+        // Logical operators short-circuit
+        assert_eq!(0, Expression::parse("0 && 1 / 0")?.evaluate()?);
+        assert_eq!(1, Expression::parse("1 || unknown")?.evaluate()?);
+        assert!(Expression::parse("1 && unknown")?.evaluate().is_err());
+
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@ use std::ops::Range;
 
 use crate::generic_number::GenericNumber;
 
-use crate::datatype::{H2TypeTrait, Offset, Alignment, ResolvedType};
+use crate::datatype::{H2TypeTrait, Offset, Alignment, ResolvedType, Reference};
 use crate::datatype::simple::*;
 use crate::datatype::simple::network::*;
 use crate::datatype::composite::*;
@@ -19,6 +19,9 @@ pub enum H2Types {
     // Simple
     H2Number(H2Number),
     H2Pointer(H2Pointer),
+    H2Checksum(H2Checksum),
+    H2Bitfield(H2Bitfield),
+    H2Flags(H2Flags),
 
     // Netework
     IPv4(IPv4),
@@ -30,6 +33,8 @@ pub enum H2Types {
     H2Array(H2Array),
     H2Union(H2Union),
     H2Struct(H2Struct),
+    H2Switch(H2Switch),
+    H2Conditional(H2Conditional),
 
     // Strings
     H2String(H2String),
@@ -72,6 +77,9 @@ impl H2Type {
             // Simple
             H2Types::H2Number(t)  => t,
             H2Types::H2Pointer(t) => t,
+            H2Types::H2Checksum(t) => t,
+            H2Types::H2Bitfield(t) => t,
+            H2Types::H2Flags(t)    => t,
 
             // Network
             H2Types::IPv4(t)        => t,
@@ -83,6 +91,8 @@ impl H2Type {
             H2Types::H2Array(t)   => t,
             H2Types::H2Union(t)   => t,
             H2Types::H2Struct(t)  => t,
+            H2Types::H2Switch(t)  => t,
+            H2Types::H2Conditional(t) => t,
 
             // Strings
             H2Types::H2String(t)   => t,
@@ -91,6 +101,92 @@ impl H2Type {
         }
     }
 
+    /// Tell the type the range that its parent covers.
+    ///
+    /// Most types don't care, but some (like [`H2Checksum`]) can refer to
+    /// their parent.
+    pub fn with_parent(&self, parent: Range<u64>) -> Self {
+        match &self.field {
+            H2Types::H2Checksum(t) => Self::new(self.alignment, H2Types::H2Checksum(t.with_parent(parent))),
+            _                      => self.clone(),
+        }
+    }
+
+    /// Tell the type where the space its parent leaves for it ends (the end
+    /// of the parent, less any fields after it).
+    ///
+    /// Types that run until the end of the data (like an [`H2Array`] with
+    /// [`ArrayLength::Remaining`]) stop there instead.
+    pub fn with_parent_end(&self, parent_end: u64) -> Self {
+        match &self.field {
+            H2Types::H2Struct(t) => Self::new(self.alignment, H2Types::H2Struct(t.with_parent_end(parent_end))),
+            H2Types::H2Array(t)  => Self::new(self.alignment, H2Types::H2Array(t.with_parent_end(parent_end))),
+            H2Types::H2String(t) => Self::new(self.alignment, H2Types::H2String(t.with_parent_end(parent_end))),
+            H2Types::H2Switch(t) => Self::new(self.alignment, H2Types::H2Switch(t.with_parent_end(parent_end))),
+            _                    => self.clone(),
+        }
+    }
+
+    /// Fill in the values of earlier fields in the parent struct.
+    ///
+    /// Types that depend on another field (like an [`H2Array`] with a
+    /// dynamic length, or an [`H2Switch`]) use these to calculate it. A
+    /// nested [`H2Struct`] passes them on to its own fields as `parent.*`.
+    pub fn with_fields(&self, fields: &[(Reference, i64)]) -> Self {
+        match &self.field {
+            H2Types::H2Struct(t) => Self::new(self.alignment, H2Types::H2Struct(t.with_fields(fields))),
+            H2Types::H2Array(t)  => Self::new(self.alignment, H2Types::H2Array(t.with_fields(fields))),
+            H2Types::H2String(t) => Self::new(self.alignment, H2Types::H2String(t.with_fields(fields))),
+            H2Types::H2Switch(t) => Self::new(self.alignment, H2Types::H2Switch(t.with_fields(fields))),
+            H2Types::H2Conditional(t) => Self::new(self.alignment, H2Types::H2Conditional(t.with_fields(fields))),
+            _                    => self.clone(),
+        }
+    }
+
+    /// Get the values that an [`crate::datatype::Expression`] can refer to.
+    ///
+    /// For an [`H2Struct`], that's everything in [`H2Struct::field_values`]
+    /// (such as `type` or `sizeof(header)`); for a type that's a number
+    /// itself, it's called `value`.
+    pub fn field_values(&self, offset: Offset) -> SimpleResult<Vec<(Reference, i64)>> {
+        if let H2Types::H2Struct(t) = &self.field {
+            return t.field_values(offset);
+        }
+
+        Ok(self.to_i64(offset).map(|value| (Reference::Value("value".to_string()), value)).into_iter().collect())
+    }
+
+    /// Read the type as an [`i64`], if it's a number.
+    ///
+    /// Unsigned values above [`i64::MAX`] keep their bits, and wrap around
+    /// to negative numbers (so bitwise operators still work on them).
+    pub(crate) fn to_i64(&self, offset: Offset) -> Option<i64> {
+        if !self.can_be_number() {
+            return None;
+        }
+
+        let number = self.to_number(offset).ok()?;
+        match number.as_u64() {
+            Ok(n)  => Some(n as i64),
+            Err(_) => number.as_i64().ok(),
+        }
+    }
+
+    /// Find the [`H2Checksum`]s in this type and its children, and the
+    /// position of each one.
+    pub fn checksums(&self, offset: Offset) -> SimpleResult<Vec<(u64, H2Checksum)>> {
+        if let H2Types::H2Checksum(t) = &self.field {
+            return Ok(vec![(offset.position(), t.clone())]);
+        }
+
+        let mut checksums = vec![];
+        for (range, _, child) in self.field_type().children_with_range(offset)? {
+            checksums.append(&mut child.checksums(offset.at(range.start))?);
+        }
+
+        Ok(checksums)
+    }
+
     /// Is the size known ahead of time?
     pub fn is_static(&self) -> bool {
         self.field_type().is_static()
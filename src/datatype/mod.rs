@@ -47,6 +47,13 @@
 //! recursive - an array can contain a struct which can contain an array and so
 //! on, for as long as you like.
 //!
+//! Struct fields can also depend on earlier fields, using an [`Expression`]: an
+//! array's length can come from a field, a [`composite::H2Switch`] picks one
+//! type based on a field's value, and a [`composite::H2Conditional`] field is
+//! only there when a condition holds. Expressions can refer to fields in nested
+//! structs (`header.size`) and the struct around this one (`parent.count`), as
+//! well as their sizes and offsets (`sizeof(header)`, `offsetof(data)`).
+//!
 //! ### String types
 //!
 //! A string type, which are defined in [`composite::string`], are a special
@@ -227,5 +234,8 @@ pub use h2typetrait::H2TypeTrait;
 mod h2type;
 pub use h2type::{H2Types, H2Type};
 
+mod expression;
+pub use expression::{Expression, Reference, UnaryOperator, BinaryOperator, Function};
+
 pub mod simple;
 pub mod composite;
@@ -0,0 +1,196 @@
+use serde::{Serialize, Deserialize};
+use simple_error::{bail, SimpleResult};
+use std::ops::Range;
+
+use crate::generic_number::{GenericNumber, GenericReader, GenericFormatter};
+use crate::datatype::{Alignment, H2Type, H2Types, H2TypeTrait, Offset};
+
+/// Get the number of bits an unsigned [`GenericReader`] reads, or an error
+/// if it's not an unsigned integer of at most 64 bits.
+pub(crate) fn unsigned_bits(definition: GenericReader) -> SimpleResult<u8> {
+    match definition {
+        GenericReader::U8     => Ok(8),
+        GenericReader::U16(_) => Ok(16),
+        GenericReader::U32(_) => Ok(32),
+        GenericReader::U64(_) => Ok(64),
+        _ => bail!("Bits can only be read from an unsigned integer of at most 64 bits"),
+    }
+}
+
+/// Defines a number that's split into named groups of bits.
+///
+/// The number is read with a [`GenericReader`] (which must be unsigned),
+/// then each field takes a range of bits - numbered from the least
+/// significant bit, so `0..4` is the bottom nibble - and renders them with
+/// its own [`GenericFormatter`]. Each field is rendered as the smallest
+/// unsigned number that can hold it.
+///
+/// Since the fields aren't whole bytes, they aren't children; the display
+/// looks like a struct, though.
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::datatype::*;
+/// use libh2gb::datatype::simple::*;
+/// use libh2gb::generic_number::*;
+///
+/// // The data offset and flags from a TCP header
+/// let data = b"\x50\x18".to_vec();
+/// let offset = Offset::Dynamic(Context::new(&data));
+///
+/// let t = H2Bitfield::new(GenericReader::U16(Endian::Big), vec![
+///     ("data_offset".to_string(), 12..16, DefaultFormatter::new()),
+///     ("reserved".to_string(),    9..12,  BinaryFormatter::pretty()),
+///     ("flags".to_string(),       0..9,   HexFormatter::pretty()),
+/// ]).unwrap();
+///
+/// assert_eq!("{ data_offset: 5, reserved: 0b00000000, flags: 0x0018 }", t.to_display(offset).unwrap());
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct H2Bitfield {
+    definition: GenericReader,
+    fields: Vec<(String, Range<u8>, GenericFormatter)>,
+}
+
+impl H2Bitfield {
+    pub fn new_aligned(alignment: Alignment, definition: GenericReader, fields: Vec<(String, Range<u8>, GenericFormatter)>) -> SimpleResult<H2Type> {
+        let bits = unsigned_bits(definition)?;
+
+        if fields.is_empty() {
+            bail!("Bitfields must contain at least one field");
+        }
+
+        for (name, range, _) in &fields {
+            if range.start >= range.end || range.end > bits {
+                bail!("Bitfield field {} has an invalid range of bits ({}..{}) for a {}-bit number", name, range.start, range.end, bits);
+            }
+        }
+
+        Ok(H2Type::new(alignment, H2Types::H2Bitfield(Self {
+            definition,
+            fields,
+        })))
+    }
+
+    pub fn new(definition: GenericReader, fields: Vec<(String, Range<u8>, GenericFormatter)>) -> SimpleResult<H2Type> {
+        Self::new_aligned(Alignment::None, definition, fields)
+    }
+
+    /// Get the value of each field, by name.
+    pub fn field_values(&self, offset: Offset) -> SimpleResult<Vec<(String, GenericNumber)>> {
+        let number = self.definition.read(offset.get_dynamic()?)?.as_u64()?;
+
+        Ok(self.fields.iter().map(|(name, range, _)| {
+            let width = range.end - range.start;
+            let value = match width {
+                64 => number,
+                _  => (number >> range.start) & ((1 << width) - 1),
+            };
+
+            let value = match width {
+                0..=8   => GenericNumber::from(value as u8),
+                9..=16  => GenericNumber::from(value as u16),
+                17..=32 => GenericNumber::from(value as u32),
+                _       => GenericNumber::from(value),
+            };
+
+            (name.clone(), value)
+        }).collect())
+    }
+}
+
+impl H2TypeTrait for H2Bitfield {
+    fn is_static(&self) -> bool {
+        true
+    }
+
+    fn actual_size(&self, _offset: Offset) -> SimpleResult<u64> {
+        // The constructor makes sure this is a fixed-size number
+        Ok(self.definition.size().unwrap_or(0) as u64)
+    }
+
+    fn to_display(&self, offset: Offset) -> SimpleResult<String> {
+        if let Offset::Static(_) = offset {
+            return Ok("Bitfield".to_string());
+        }
+
+        let strings = self.field_values(offset)?.into_iter().zip(self.fields.iter()).map(|((name, value), (_, _, display))| {
+            Ok(format!("{}: {}", name, display.render(value)?))
+        }).collect::<SimpleResult<Vec<String>>>()?;
+
+        Ok(format!("{{ {} }}", strings.join(", ")))
+    }
+
+    fn can_be_number(&self) -> bool {
+        true
+    }
+
+    /// The number is the whole underlying value.
+    fn to_number(&self, offset: Offset) -> SimpleResult<GenericNumber> {
+        self.definition.read(offset.get_dynamic()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use simple_error::SimpleResult;
+
+    use crate::generic_number::{Context, Endian, DefaultFormatter, HexFormatter, BinaryFormatter, EnumFormatter, EnumType};
+
+    #[test]
+    fn test_bitfield() -> SimpleResult<()> {
+        let t = H2Bitfield::new(GenericReader::U16(Endian::Little), vec![
+            ("low".to_string(),  0..4,   HexFormatter::pretty()),
+            ("mode".to_string(), 4..5,   EnumFormatter::new(EnumType::TestEnum)),
+            ("high".to_string(), 8..16,  DefaultFormatter::new()),
+            ("all".to_string(),  0..16,  HexFormatter::new(false, true, false)),
+            ("mid".to_string(),  6..14,  BinaryFormatter::new(true, false)),
+        ])?;
+
+        //           -low-mode- high
+        let data = b"\x1a\xff".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+
+        assert_eq!(2, t.actual_size(offset)?);
+        assert_eq!("{ low: 0x0a, mode: TestEnum::One (One), high: 255, all: 0xff1a, mid: 0b11111100 }", t.to_display(offset)?);
+        assert_eq!(0xff1a, t.to_number(offset)?.as_u64()?);
+
+        let r = t.resolve(offset, None)?;
+        assert_eq!(0, r.children.len());
+        assert_eq!(0..2, r.actual_range);
+
+        assert_eq!("Bitfield", t.to_display(Offset::Static(0))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_width() -> SimpleResult<()> {
+        let t = H2Bitfield::new(GenericReader::U64(Endian::Big), vec![
+            ("all".to_string(), 0..64,  HexFormatter::pretty()),
+            ("top".to_string(), 63..64, DefaultFormatter::new()),
+        ])?;
+
+        let data = b"\x80\x00\x00\x00\x00\x00\x00\x01".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ all: 0x8000000000000001, top: 1 }", t.to_display(offset)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_bitfield() {
+        // Not unsigned
+        assert!(H2Bitfield::new(GenericReader::I8, vec![("a".to_string(), 0..1, DefaultFormatter::new())]).is_err());
+
+        // No fields
+        assert!(H2Bitfield::new(GenericReader::U8, vec![]).is_err());
+
+        // Bad ranges
+        assert!(H2Bitfield::new(GenericReader::U8, vec![("a".to_string(), 0..9, DefaultFormatter::new())]).is_err());
+        assert!(H2Bitfield::new(GenericReader::U8, vec![("a".to_string(), 3..3, DefaultFormatter::new())]).is_err());
+    }
+}
@@ -0,0 +1,414 @@
+use serde::{Serialize, Deserialize};
+use simple_error::{bail, SimpleResult};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use crate::checksum::{Checksum, ChecksumType};
+use crate::generic_number::{Context, Endian, GenericNumber, GenericReader};
+use crate::datatype::{Alignment, H2Type, H2Types, H2TypeTrait, Offset};
+
+/// How a checksum is stored.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ChecksumStorage {
+    /// A number (one of the unsigned [`GenericReader`]s, in either byte
+    /// order), which must be big enough to hold the checksum.
+    Number(GenericReader),
+
+    /// A string of bytes, exactly as long as the checksum, in the same order
+    /// as [`Checksum::value`] (which is how hashes are normally stored).
+    Bytes,
+}
+
+/// One end of the range of bytes that a checksum covers.
+///
+/// Each is relative to something, and can be negative.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ChecksumBound {
+    /// Relative to the start of the checksum field.
+    Field(i64),
+
+    /// Relative to the start of the struct that contains the checksum field.
+    ParentStart(i64),
+
+    /// Relative to the end of the struct that contains the checksum field.
+    ParentEnd(i64),
+}
+
+/// Defines a stored checksum, which is checked against the data it covers.
+///
+/// The checksum is read either as a number or as raw bytes (see
+/// [`ChecksumStorage`]), then it's calculated over the range between `start`
+/// and `end` (see [`ChecksumBound`]). The display shows whether they match,
+/// and what was expected if they don't.
+///
+/// The range can be relative to the checksum field, or to the
+/// [`crate::datatype::composite::H2Struct`] that contains it - a PNG chunk's
+/// CRC, for example, covers from 4 bytes into the chunk up to the CRC field.
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::checksum::*;
+/// use libh2gb::datatype::*;
+/// use libh2gb::datatype::simple::*;
+/// use libh2gb::datatype::composite::*;
+/// use libh2gb::generic_number::*;
+///
+/// // A PNG IEND chunk
+/// let data = b"\x00\x00\x00\x00IEND\xae\x42\x60\x82".to_vec();
+/// let offset = Offset::Dynamic(Context::new(&data));
+///
+/// let t = H2Struct::new(vec![
+///     ("length".to_string(), H2Number::new(GenericReader::U32(Endian::Big), DefaultFormatter::new())),
+///     ("type".to_string(),   H2Array::new(4, H2Number::new_ascii())?),
+///     ("crc".to_string(),    H2Checksum::new(
+///         ChecksumType::CRC(Crc::CRC32),
+///         ChecksumStorage::Number(GenericReader::U32(Endian::Big)),
+///         ChecksumBound::ParentStart(4),
+///         ChecksumBound::Field(0),
+///     )?),
+/// ])?;
+///
+/// assert_eq!("{ length: 0, type: [ 'I', 'E', 'N', 'D' ], crc: 0xae426082 (CRC-32, valid) }", t.to_display(offset)?);
+/// # Ok::<(), simple_error::SimpleError>(())
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct H2Checksum {
+    checksum_type: ChecksumType,
+    storage: ChecksumStorage,
+
+    start: ChecksumBound,
+    end: ChecksumBound,
+
+    /// The range covered by the containing struct; this is filled in by the
+    /// struct when it works out where its children are.
+    parent: Option<Range<u64>>,
+}
+
+impl H2Checksum {
+    pub fn new_aligned(alignment: Alignment, checksum_type: ChecksumType, storage: ChecksumStorage, start: ChecksumBound, end: ChecksumBound) -> SimpleResult<H2Type> {
+        if let ChecksumStorage::Number(reader) = storage {
+            if !checksum_type.is_numeric() {
+                bail!("{} can't be stored as a number", checksum_type);
+            }
+
+            let size = match reader {
+                GenericReader::U8     => 1,
+                GenericReader::U16(_) => 2,
+                GenericReader::U32(_) => 4,
+                GenericReader::U64(_) => 8,
+                _ => bail!("Checksums must be stored as an unsigned number of at most 64 bits"),
+            };
+
+            if size < checksum_type.size() {
+                bail!("{} doesn't fit in a {}-byte number", checksum_type, size);
+            }
+        }
+
+        Ok(H2Type::new(alignment, H2Types::H2Checksum(Self {
+            checksum_type,
+            storage,
+            start,
+            end,
+            parent: None,
+        })))
+    }
+
+    pub fn new(checksum_type: ChecksumType, storage: ChecksumStorage, start: ChecksumBound, end: ChecksumBound) -> SimpleResult<H2Type> {
+        Self::new_aligned(Alignment::None, checksum_type, storage, start, end)
+    }
+
+    /// Get a copy of this type that knows the range of its parent.
+    pub(crate) fn with_parent(&self, parent: Range<u64>) -> Self {
+        let mut checksum = self.clone();
+        checksum.parent = Some(parent);
+
+        checksum
+    }
+
+    /// Get the algorithm.
+    pub fn checksum_type(&self) -> ChecksumType {
+        self.checksum_type
+    }
+
+    /// The number of bytes the stored checksum takes up.
+    fn size(&self) -> usize {
+        match self.storage {
+            ChecksumStorage::Number(reader) => reader.size().unwrap_or(0),
+            ChecksumStorage::Bytes          => self.checksum_type.size(),
+        }
+    }
+
+    fn bound(&self, bound: ChecksumBound, offset: Offset) -> SimpleResult<u64> {
+        let (base, delta) = match (bound, &self.parent) {
+            (ChecksumBound::Field(d),       _)            => (offset.position(), d),
+            (ChecksumBound::ParentStart(d), Some(parent)) => (parent.start, d),
+            (ChecksumBound::ParentEnd(d),   Some(parent)) => (parent.end, d),
+            _ => bail!("Checksum range is relative to a parent, but the checksum isn't in a struct"),
+        };
+
+        let bound = i64::try_from(base).ok().and_then(|base| base.checked_add(delta));
+
+        match bound.and_then(|b| u64::try_from(b).ok()) {
+            Some(b) => Ok(b),
+            None => bail!("Checksum range starts before the start of the buffer"),
+        }
+    }
+
+    /// Get the range of bytes that the checksum covers.
+    pub fn covered_range(&self, offset: Offset) -> SimpleResult<Range<u64>> {
+        let start = self.bound(self.start, offset)?;
+        let end = self.bound(self.end, offset)?;
+
+        if end < start {
+            bail!("Checksum range ends (0x{:x}) before it starts (0x{:x})", end, start);
+        }
+
+        Ok(start..end)
+    }
+
+    /// Calculate the checksum of the data it covers.
+    pub fn calculate(&self, offset: Offset) -> SimpleResult<Checksum> {
+        let context = offset.get_dynamic()?;
+        let range = self.covered_range(offset)?;
+
+        let data = context.at(range.start).read_bytes((range.end - range.start) as usize)?;
+
+        Ok(self.checksum_type.compute(&data))
+    }
+
+    /// Get the bytes that the field should contain, based on the data it
+    /// covers.
+    pub fn expected(&self, offset: Offset) -> SimpleResult<Vec<u8>> {
+        let checksum = self.calculate(offset)?;
+
+        let value = match (self.storage, checksum.as_u64()) {
+            (ChecksumStorage::Number(_), Some(n)) => n,
+            _                                     => return Ok(checksum.value().to_vec()),
+        };
+
+        let size = self.size();
+        Ok(match self.storage {
+            ChecksumStorage::Number(GenericReader::U16(Endian::Little)) |
+            ChecksumStorage::Number(GenericReader::U32(Endian::Little)) |
+            ChecksumStorage::Number(GenericReader::U64(Endian::Little)) => value.to_le_bytes()[..size].to_vec(),
+            _                                                           => value.to_be_bytes()[(8 - size)..].to_vec(),
+        })
+    }
+
+    /// Read the stored checksum, as bytes.
+    pub fn stored(&self, offset: Offset) -> SimpleResult<Vec<u8>> {
+        offset.get_dynamic()?.read_bytes(self.size())
+    }
+
+    /// Does the stored checksum match the data?
+    pub fn is_valid(&self, offset: Offset) -> SimpleResult<bool> {
+        Ok(self.stored(offset)? == self.expected(offset)?)
+    }
+
+    /// Render a stored or expected value - numbers in hex, as wide as they're
+    /// stored, and bytes as a hex string.
+    fn render(&self, value: &[u8]) -> SimpleResult<String> {
+        match self.storage {
+            ChecksumStorage::Number(reader) => {
+                let value = value.to_vec();
+                let number = reader.read(Context::new(&value))?.as_u64()?;
+
+                Ok(format!("0x{:0width$x}", number, width = self.size() * 2))
+            },
+            ChecksumStorage::Bytes => Ok(hex::encode(value)),
+        }
+    }
+}
+
+impl H2TypeTrait for H2Checksum {
+    fn is_static(&self) -> bool {
+        true
+    }
+
+    fn actual_size(&self, _offset: Offset) -> SimpleResult<u64> {
+        Ok(self.size() as u64)
+    }
+
+    fn to_display(&self, offset: Offset) -> SimpleResult<String> {
+        if let Offset::Static(_) = offset {
+            return Ok("Checksum".to_string());
+        }
+
+        let stored = self.stored(offset)?;
+        let expected = self.expected(offset)?;
+
+        match stored == expected {
+            true  => Ok(format!("{} ({}, valid)", self.render(&stored)?, self.checksum_type)),
+            false => Ok(format!("{} ({}, invalid - expected {})", self.render(&stored)?, self.checksum_type, self.render(&expected)?)),
+        }
+    }
+
+    fn can_be_number(&self) -> bool {
+        match self.storage {
+            ChecksumStorage::Number(_) => true,
+            ChecksumStorage::Bytes     => false,
+        }
+    }
+
+    fn to_number(&self, offset: Offset) -> SimpleResult<GenericNumber> {
+        match self.storage {
+            ChecksumStorage::Number(reader) => reader.read(offset.get_dynamic()?),
+            ChecksumStorage::Bytes          => bail!("This checksum is stored as bytes, not a number"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use simple_error::SimpleResult;
+
+    use crate::checksum::Crc;
+    use crate::datatype::composite::{H2Array, H2Struct};
+    use crate::datatype::simple::H2Number;
+    use crate::generic_number::DefaultFormatter;
+
+    fn as_checksum(t: &H2Type) -> &H2Checksum {
+        match &t.field {
+            H2Types::H2Checksum(c) => c,
+            _ => panic!("Not a checksum"),
+        }
+    }
+
+    fn png_chunk() -> SimpleResult<H2Type> {
+        H2Struct::new(vec![
+            ("length".to_string(), H2Number::new(GenericReader::U32(Endian::Big), DefaultFormatter::new())),
+            ("type".to_string(),   H2Array::new(4, H2Number::new_ascii())?),
+            ("crc".to_string(),    H2Checksum::new(
+                ChecksumType::CRC(Crc::CRC32),
+                ChecksumStorage::Number(GenericReader::U32(Endian::Big)),
+                ChecksumBound::ParentStart(4),
+                ChecksumBound::Field(0),
+            )?),
+        ])
+    }
+
+    #[test]
+    fn test_png_crc() -> SimpleResult<()> {
+        let t = png_chunk()?;
+
+        let data = b"\x00\x00\x00\x00IEND\xae\x42\x60\x82".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(12, t.actual_size(offset)?);
+        assert_eq!("{ length: 0, type: [ 'I', 'E', 'N', 'D' ], crc: 0xae426082 (CRC-32, valid) }", t.to_display(offset)?);
+
+        // Resolving passes the parent along too
+        let r = t.resolve(offset, None)?;
+        assert_eq!("0xae426082 (CRC-32, valid)", r.children[2].display);
+        assert_eq!(0xae426082, r.children[2].as_number.unwrap().as_u64()?);
+
+        // A bad CRC, not at the start of the buffer
+        let data = b"...\x00\x00\x00\x00IEND\x11\x22\x33\x44".to_vec();
+        let offset = Offset::Dynamic(Context::new_at(&data, 3));
+        assert_eq!("{ length: 0, type: [ 'I', 'E', 'N', 'D' ], crc: 0x11223344 (CRC-32, invalid - expected 0xae426082) }", t.to_display(offset)?);
+
+        // Static offsets can't be checked
+        assert_eq!("{ length: Number, type: [ Number, Number, Number, Number ], crc: Checksum }", t.to_display(Offset::Static(0))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_standalone() -> SimpleResult<()> {
+        // A little-endian sum, covering the 4 bytes after the field
+        let t = H2Checksum::new(
+            ChecksumType::Sum16,
+            ChecksumStorage::Number(GenericReader::U16(Endian::Little)),
+            ChecksumBound::Field(2),
+            ChecksumBound::Field(6),
+        )?;
+
+        let data = b"\x0a\x01\x01\x02\x03\x04".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!(2, t.actual_size(offset)?);
+        assert_eq!("0x010a (Sum-16, invalid - expected 0x000a)", t.to_display(offset)?);
+        assert_eq!(b"\x0a\x00".to_vec(), as_checksum(&t).expected(offset)?);
+        assert_eq!(2..6, as_checksum(&t).covered_range(offset)?);
+
+        // Relative to a parent that doesn't exist
+        let t = H2Checksum::new(
+            ChecksumType::Sum8,
+            ChecksumStorage::Bytes,
+            ChecksumBound::ParentStart(0),
+            ChecksumBound::Field(0),
+        )?;
+        assert!(t.to_display(offset).is_err());
+
+        // Off the end of the buffer
+        let t = H2Checksum::new(
+            ChecksumType::Sum8,
+            ChecksumStorage::Bytes,
+            ChecksumBound::Field(1),
+            ChecksumBound::Field(7),
+        )?;
+        assert!(t.to_display(offset).is_err());
+
+        // Backwards
+        let t = H2Checksum::new(
+            ChecksumType::Sum8,
+            ChecksumStorage::Bytes,
+            ChecksumBound::Field(2),
+            ChecksumBound::Field(1),
+        )?;
+        assert!(t.to_display(offset).is_err());
+
+        // Bounds that overflow
+        let t = H2Checksum::new(
+            ChecksumType::Sum8,
+            ChecksumStorage::Bytes,
+            ChecksumBound::Field(1),
+            ChecksumBound::Field(i64::MAX),
+        )?;
+        assert!(as_checksum(&t).covered_range(Offset::Static(2)).is_err());
+        assert!(as_checksum(&t).covered_range(Offset::Static(u64::MAX)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_to_end_of_struct() -> SimpleResult<()> {
+        // An MD5 of everything after it, like some game saves use
+        let t = H2Struct::new(vec![
+            ("md5".to_string(),  H2Checksum::new(
+                ChecksumType::MD5,
+                ChecksumStorage::Bytes,
+                ChecksumBound::Field(16),
+                ChecksumBound::ParentEnd(0),
+            )?),
+            ("data".to_string(), H2Array::new(3, H2Number::new_ascii())?),
+        ])?;
+
+        let mut data = b"\x90\x01\x50\x98\x3c\xd2\x4f\xb0\xd6\x96\x3f\x7d\x28\xe1\x7f\x72".to_vec();
+        data.extend_from_slice(b"abcXYZ");
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ md5: 900150983cd24fb0d6963f7d28e17f72 (MD5, valid), data: [ 'a', 'b', 'c' ] }", t.to_display(offset)?);
+
+        data[16] = b'x';
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!("{ md5: 900150983cd24fb0d6963f7d28e17f72 (MD5, invalid - expected c9efacca293ef80d861dd2796de1acb7), data: [ 'x', 'b', 'c' ] }", t.to_display(offset)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_storage() {
+        // Hashes aren't numbers
+        assert!(H2Checksum::new(ChecksumType::MD5, ChecksumStorage::Number(GenericReader::U64(Endian::Big)), ChecksumBound::Field(0), ChecksumBound::Field(0)).is_err());
+
+        // Too small
+        assert!(H2Checksum::new(ChecksumType::CRC(Crc::CRC32), ChecksumStorage::Number(GenericReader::U16(Endian::Big)), ChecksumBound::Field(0), ChecksumBound::Field(0)).is_err());
+
+        // Not unsigned
+        assert!(H2Checksum::new(ChecksumType::Sum8, ChecksumStorage::Number(GenericReader::I8), ChecksumBound::Field(0), ChecksumBound::Field(0)).is_err());
+
+        // Bigger is fine
+        assert!(H2Checksum::new(ChecksumType::Sum8, ChecksumStorage::Number(GenericReader::U32(Endian::Little)), ChecksumBound::Field(0), ChecksumBound::Field(0)).is_ok());
+    }
+}
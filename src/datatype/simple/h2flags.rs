@@ -0,0 +1,185 @@
+use serde::{Serialize, Deserialize};
+use simple_error::{bail, SimpleResult};
+
+use crate::generic_number::{GenericNumber, GenericReader};
+use crate::datatype::{Alignment, H2Type, H2Types, H2TypeTrait, Offset};
+use crate::datatype::simple::h2bitfield::unsigned_bits;
+
+/// Defines a number made up of named flags.
+///
+/// The number is read with a [`GenericReader`] (which must be unsigned), and
+/// each flag is a mask - usually a single bit, but it can be more. The
+/// display is the name of each flag that's set, separated by `|`, with any
+/// leftover bits at the end in hex (or `0` if nothing is set).
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::datatype::*;
+/// use libh2gb::datatype::simple::*;
+/// use libh2gb::generic_number::*;
+///
+/// let data = b"\x83".to_vec();
+/// let offset = Offset::Dynamic(Context::new(&data));
+///
+/// let t = H2Flags::new(GenericReader::U8, vec![
+///     ("READ".to_string(),    0x01),
+///     ("WRITE".to_string(),   0x02),
+///     ("EXECUTE".to_string(), 0x04),
+/// ]).unwrap();
+///
+/// assert_eq!("READ | WRITE | 0x80", t.to_display(offset).unwrap());
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct H2Flags {
+    definition: GenericReader,
+    flags: Vec<(String, u64)>,
+}
+
+impl H2Flags {
+    pub fn new_aligned(alignment: Alignment, definition: GenericReader, flags: Vec<(String, u64)>) -> SimpleResult<H2Type> {
+        let bits = unsigned_bits(definition)?;
+
+        for (name, mask) in &flags {
+            if *mask == 0 || (bits < 64 && mask >> bits != 0) {
+                bail!("Flag {} has an invalid mask (0x{:x}) for a {}-bit number", name, mask, bits);
+            }
+        }
+
+        Ok(H2Type::new(alignment, H2Types::H2Flags(Self {
+            definition,
+            flags,
+        })))
+    }
+
+    pub fn new(definition: GenericReader, flags: Vec<(String, u64)>) -> SimpleResult<H2Type> {
+        Self::new_aligned(Alignment::None, definition, flags)
+    }
+
+    /// Get the names of the flags that are set, and any bits that are set
+    /// but don't have a name.
+    pub fn flags_set(&self, offset: Offset) -> SimpleResult<(Vec<String>, u64)> {
+        let number = self.definition.read(offset.get_dynamic()?)?.as_u64()?;
+
+        let mut leftover = number;
+        let names = self.flags.iter().filter(|(_, mask)| number & mask == *mask).map(|(name, mask)| {
+            leftover &= !mask;
+
+            name.clone()
+        }).collect();
+
+        Ok((names, leftover))
+    }
+}
+
+impl H2TypeTrait for H2Flags {
+    fn is_static(&self) -> bool {
+        true
+    }
+
+    fn actual_size(&self, _offset: Offset) -> SimpleResult<u64> {
+        // The constructor makes sure this is a fixed-size number
+        Ok(self.definition.size().unwrap_or(0) as u64)
+    }
+
+    fn to_display(&self, offset: Offset) -> SimpleResult<String> {
+        if let Offset::Static(_) = offset {
+            return Ok("Flags".to_string());
+        }
+
+        let (mut names, leftover) = self.flags_set(offset)?;
+        if leftover != 0 {
+            names.push(format!("0x{:x}", leftover));
+        }
+
+        match names.len() {
+            0 => Ok("0".to_string()),
+            _ => Ok(names.join(" | ")),
+        }
+    }
+
+    fn can_be_number(&self) -> bool {
+        true
+    }
+
+    fn to_number(&self, offset: Offset) -> SimpleResult<GenericNumber> {
+        self.definition.read(offset.get_dynamic()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use simple_error::SimpleResult;
+
+    use crate::generic_number::{Context, Endian};
+
+    #[test]
+    fn test_flags() -> SimpleResult<()> {
+        // Some of the PE "characteristics" flags
+        let t = H2Flags::new(GenericReader::U16(Endian::Little), vec![
+            ("RELOCS_STRIPPED".to_string(),      0x0001),
+            ("EXECUTABLE_IMAGE".to_string(),     0x0002),
+            ("LARGE_ADDRESS_AWARE".to_string(),  0x0020),
+            ("32BIT_MACHINE".to_string(),        0x0100),
+            ("DLL".to_string(),                  0x2000),
+        ])?;
+
+        let tests: Vec<(&[u8], &str)> = vec![
+            (b"\x02\x01", "EXECUTABLE_IMAGE | 32BIT_MACHINE"),
+            (b"\x22\x20", "EXECUTABLE_IMAGE | LARGE_ADDRESS_AWARE | DLL"),
+            (b"\x00\x00", "0"),
+            (b"\x40\x00", "0x40"),
+            (b"\x03\x80", "RELOCS_STRIPPED | EXECUTABLE_IMAGE | 0x8000"),
+        ];
+
+        for (data, expected) in tests {
+            let data = data.to_vec();
+            let offset = Offset::Dynamic(Context::new(&data));
+
+            assert_eq!(2, t.actual_size(offset)?);
+            assert_eq!(expected, t.to_display(offset)?);
+        }
+
+        let data = b"\x03\x80".to_vec();
+        let offset = Offset::Dynamic(Context::new(&data));
+        assert_eq!((vec!["RELOCS_STRIPPED".to_string(), "EXECUTABLE_IMAGE".to_string()], 0x8000), as_flags(&t).flags_set(offset)?);
+        assert_eq!(0x8003, t.to_number(offset)?.as_u64()?);
+
+        assert_eq!("Flags", t.to_display(Offset::Static(0))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_bit_flags() -> SimpleResult<()> {
+        // A flag that's several bits is only set if they're all set
+        let t = H2Flags::new(GenericReader::U8, vec![
+            ("READ".to_string(),       0x01),
+            ("WRITE".to_string(),      0x02),
+            ("READ_WRITE".to_string(), 0x03),
+        ])?;
+
+        let data = b"\x03\x02".to_vec();
+        assert_eq!("READ | WRITE | READ_WRITE", t.to_display(Offset::Dynamic(Context::new(&data)))?);
+        assert_eq!("WRITE", t.to_display(Offset::Dynamic(Context::new_at(&data, 1)))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_flags() {
+        assert!(H2Flags::new(GenericReader::I16(Endian::Big), vec![("a".to_string(), 1)]).is_err());
+        assert!(H2Flags::new(GenericReader::U8, vec![("a".to_string(), 0)]).is_err());
+        assert!(H2Flags::new(GenericReader::U8, vec![("a".to_string(), 0x100)]).is_err());
+        assert!(H2Flags::new(GenericReader::U64(Endian::Big), vec![("a".to_string(), 0x8000000000000000)]).is_ok());
+    }
+
+    fn as_flags(t: &H2Type) -> &H2Flags {
+        match &t.field {
+            H2Types::H2Flags(f) => f,
+            _ => panic!("Not flags"),
+        }
+    }
+}
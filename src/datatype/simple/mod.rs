@@ -9,4 +9,13 @@ pub use h2number::*;
 mod h2pointer;
 pub use h2pointer::*;
 
+mod h2checksum;
+pub use h2checksum::*;
+
+mod h2bitfield;
+pub use h2bitfield::*;
+
+mod h2flags;
+pub use h2flags::*;
+
 pub mod network;
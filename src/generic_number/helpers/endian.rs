@@ -1,7 +1,7 @@
 use serde::{Serialize, Deserialize};
 
 /// Define the endianness for reading multi-byte integers
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Endian {
     /// Most significant byte is first (eg, `0x1234` -> `12 34`)
     Big,
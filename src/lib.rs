@@ -16,6 +16,7 @@
 
 pub mod analyzer;
 pub mod bumpy_vector;
+pub mod checksum;
 pub mod datatype;
 pub mod multi_vector;
 pub mod generic_number;
@@ -24,11 +24,24 @@ use std::collections::HashMap;
 use std::ops::Range;
 use std::fmt;
 
+use crate::checksum::{Checksum, ChecksumType};
 use crate::transformation::Transformation;
 use crate::project::{H2Layer, H2Entry};
 use crate::datatype::{Offset, H2Type};
+use crate::datatype::simple::H2Checksum;
 use crate::generic_number::Context;
 
+/// Where a buffer's data came from, if it was transformed out of part of
+/// another buffer (see [`H2Buffer::transform_partial`]).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct H2BufferOrigin {
+    /// The name of the buffer the data came from.
+    pub parent: String,
+
+    /// The range of bytes in the parent that were transformed.
+    pub range: Range<usize>,
+}
+
 // H2Buffer holds the actual data, as well as its layers
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct H2Buffer {
@@ -41,6 +54,10 @@ pub struct H2Buffer {
     // A list of transformations that this buffer has undergone
     transformations: Vec<Transformation>,
 
+    // If this buffer was created from part of another buffer, where it came
+    // from
+    origin: Option<H2BufferOrigin>,
+
     layers: HashMap<String, H2Layer>,
 
     display_empty_addresses: bool,
@@ -49,6 +66,9 @@ pub struct H2Buffer {
 impl fmt::Display for H2Buffer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Buffer: {} (base 0x{:x})", self.name, self.base_address)?;
+        if let Some(origin) = &self.origin {
+            writeln!(f, " Transformed from: {} [0x{:x}..0x{:x}]", origin.parent, origin.range.start, origin.range.end)?;
+        }
         writeln!(f, " Transformations:")?;
         for transformation in self.transformations.iter() {
             writeln!(f, " * {}", transformation)?;
@@ -155,6 +175,7 @@ impl H2Buffer {
             base_address: base_address,
             layers: HashMap::new(),
             transformations: Vec::new(),
+            origin: None,
 
             display_empty_addresses: false, // TODO: Figure out how to handle empty addresses
         })
@@ -170,6 +191,17 @@ impl H2Buffer {
         &self.name
     }
 
+    /// Get the list of transformations the buffer has undergone, oldest first.
+    pub fn transformations(&self) -> &Vec<Transformation> {
+        &self.transformations
+    }
+
+    /// Get where the buffer came from, if it was transformed from part of
+    /// another buffer.
+    pub fn origin(&self) -> Option<&H2BufferOrigin> {
+        self.origin.as_ref()
+    }
+
     /// Clone the buffer and data (but not the layers and entries).
     ///
     /// The base address can be preserved or changed as part of the copy. The
@@ -225,6 +257,42 @@ impl H2Buffer {
         Self::new(&self.name, self.data[range].into(), base_address)
     }
 
+    /// Transform a range of data within the buffer into a new buffer.
+    ///
+    /// The transformations are applied in order, and the new buffer remembers
+    /// this buffer's name, the range, and the transformations. This buffer
+    /// isn't changed, and doesn't need to be unpopulated.
+    ///
+    /// # Errors
+    ///
+    /// * The `range` must not go off the end of the buffer
+    /// * The `range` must not work out to zero bytes
+    /// * Any of the transformations can fail
+    pub fn transform_partial(&self, name: &str, range: Range<usize>, transformations: &[Transformation], base_address: usize) -> SimpleResult<Self> {
+        // Sanity check
+        if range.end > self.data.len() {
+            bail!("Range to transform goes off the end of the buffer");
+        }
+
+        if range.is_empty() {
+            bail!("Transform range cannot be empty");
+        }
+
+        let mut data = self.data[range.clone()].to_vec();
+        for transformation in transformations {
+            data = transformation.transform(&data)?;
+        }
+
+        let mut buffer = Self::new(name, data, base_address)?;
+        buffer.transformations = transformations.to_vec();
+        buffer.origin = Some(H2BufferOrigin {
+            parent: self.name.clone(),
+            range,
+        });
+
+        Ok(buffer)
+    }
+
     /// Returns true if the buffer contains layers, entries, or any changes
     /// that could prevent it from being cleanly removed.
     ///
@@ -239,6 +307,12 @@ impl H2Buffer {
         return false;
     }
 
+    /// Returns true if any of the buffer's transformations read their key or
+    /// IV from the given buffer.
+    pub fn references_buffer(&self, buffer: &str) -> bool {
+        self.transformations.iter().any(|t| t.references_buffer(buffer))
+    }
+
     /// Transform the buffer using an `Transformation`.
     ///
     /// # Errors
@@ -355,6 +429,138 @@ impl H2Buffer {
         Ok(buffer_data.splice(offset..(offset+data.len()), data).collect())
     }
 
+    /// Re-resolve the entries that overlap any of `ranges` from the types
+    /// that created them, after the data changes (so checksums, for example,
+    /// show whether they're still valid).
+    ///
+    /// If any of those entries can't be resolved anymore, or would change
+    /// size, nothing is changed and an error is returned.
+    pub fn entries_refresh(&mut self, ranges: &[Range<usize>]) -> SimpleResult<()> {
+        let context = Context::new(&self.data);
+        let mut refreshed = vec![];
+
+        for (layer_name, layer) in self.layers.iter() {
+            for e in layer.entries().into_iter() {
+                if !ranges.iter().any(|r| r.start < e.range.end && e.range.start < r.end) {
+                    continue;
+                }
+
+                let creator = match e.entry.creator() {
+                    Some(c) => c,
+                    None => continue,
+                };
+
+                let offset = Offset::Dynamic(context.at(e.entry.resolved().actual_range.start));
+                let resolved = match creator.resolve(offset, None) {
+                    Ok(r) => r,
+                    Err(err) => bail!("Entry at 0x{:x} in layer {} no longer fits the data: {}", e.range.start, layer_name, err),
+                };
+
+                if resolved.aligned_range != e.entry.resolved().aligned_range {
+                    bail!("Entry at 0x{:x} in layer {} would change size (0x{:x} bytes instead of 0x{:x})", e.range.start, layer_name, resolved.aligned_size(), e.entry.resolved().aligned_size());
+                }
+
+                refreshed.push((layer_name.clone(), e.range.start, H2Entry::new(resolved, Some(creator))));
+            }
+        }
+
+        for (layer_name, start, entry) in refreshed {
+            if let Some(e) = self.layers.get_mut(&layer_name).and_then(|layer| layer.entry_get_mut(start)) {
+                *e = entry;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find every checksum in every entry, and where each one is stored.
+    fn checksums_get(&self) -> Vec<(usize, H2Checksum)> {
+        let context = Context::new(&self.data);
+
+        self.layers.values().flat_map(|layer| layer.entries().into_iter()).filter_map(|e| {
+            let offset = Offset::Dynamic(context.at(e.entry.resolved().actual_range.start));
+
+            e.entry.creator()?.checksums(offset).ok()
+        }).flatten().map(|(position, checksum)| (position as usize, checksum)).collect()
+    }
+
+    /// Rewrite any stored checksums that cover part of `range` and no longer
+    /// match their data.
+    ///
+    /// Fixing one checksum can break another one that covers it, so this
+    /// keeps going until there's nothing left to fix. In case that never
+    /// happens (say, a checksum that covers itself), each checksum is fixed
+    /// at most as many times as there are checksums. Checksums that can't be
+    /// calculated are skipped.
+    ///
+    /// Afterwards, the entries that overlap `range` or a fixed checksum are
+    /// refreshed (see [`Self::entries_refresh`]); if that fails, the
+    /// checksums are put back and the error is returned.
+    ///
+    /// Returns the offset and original data of each checksum that changed,
+    /// in the order they were changed - to undo, [`Self::edit`] them back in
+    /// reverse order.
+    pub fn checksums_fix(&mut self, range: Range<usize>) -> SimpleResult<Vec<(usize, Vec<u8>)>> {
+        let checksums = self.checksums_get();
+        let mut fix_counts = vec![0; checksums.len()];
+        let mut changed: Vec<Range<usize>> = vec![range];
+        let mut fixes: Vec<(usize, Vec<u8>)> = vec![];
+
+        loop {
+            let context = Context::new(&self.data);
+
+            // Find the next checksum that covers something that changed, and
+            // is wrong
+            let next = checksums.iter().enumerate().find_map(|(i, (position, checksum))| {
+                if fix_counts[i] >= checksums.len() {
+                    return None;
+                }
+
+                let offset = Offset::Dynamic(context.at(*position as u64));
+
+                let covered = checksum.covered_range(offset).ok()?;
+                let covered = (covered.start as usize)..(covered.end as usize);
+                if !changed.iter().any(|r| r.start < covered.end && covered.start < r.end) {
+                    return None;
+                }
+
+                match checksum.is_valid(offset).ok()? {
+                    true  => None,
+                    false => Some((i, checksum.expected(offset).ok()?)),
+                }
+            });
+
+            let (i, expected) = match next {
+                Some(n) => n,
+                None    => break,
+            };
+
+            fix_counts[i] += 1;
+            let range = checksums[i].0..(checksums[i].0 + expected.len());
+
+            fixes.push((range.start, self.data.splice(range.clone(), expected).collect()));
+            changed.push(range);
+        }
+
+        if let Err(e) = self.entries_refresh(&changed) {
+            for (offset, original) in fixes.iter().rev() {
+                self.data.splice(*offset..(*offset + original.len()), original.iter().cloned());
+            }
+
+            return Err(e);
+        }
+
+        Ok(fixes)
+    }
+
+    /// Calculate a checksum (or hash) over part of the buffer.
+    pub fn checksum(&self, range: Range<usize>, checksum_type: ChecksumType) -> SimpleResult<Checksum> {
+        match self.data.get(range.clone()) {
+            Some(data) => Ok(checksum_type.compute(data)),
+            None => bail!("Checksum range 0x{:x}..0x{:x} goes off the end of buffer {}", range.start, range.end, self.name()),
+        }
+    }
+
     pub fn rebase(&mut self, new_base_address: usize) -> SimpleResult<usize> {
         let old_base_address = self.base_address;
         self.base_address = new_base_address;
@@ -446,6 +652,8 @@ mod tests {
     use super::*;
     use simple_error::SimpleResult;
     use crate::transformation::TransformHex;
+    use crate::checksum::Crc;
+    use crate::datatype::simple::{ChecksumStorage, ChecksumBound};
 
     #[test]
     fn test_new() -> SimpleResult<()> {
@@ -655,4 +863,43 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_checksum() -> SimpleResult<()> {
+        let buffer = H2Buffer::new("name", b"AAAA123456789BBBB".to_vec(), 0x4000)?;
+
+        assert_eq!(Some(0xcbf43926), buffer.checksum(4..13, ChecksumType::CRC(Crc::CRC32))?.as_u64());
+        assert_eq!(Some(0x04),       buffer.checksum(0..4,  ChecksumType::Sum8)?.as_u64());
+
+        // The whole buffer is fine, but off the end isn't
+        assert!(buffer.checksum(0..17, ChecksumType::MD5).is_ok());
+        assert!(buffer.checksum(0..18, ChecksumType::MD5).is_err());
+        assert!(buffer.checksum(17..20, ChecksumType::MD5).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksums_fix_covers_itself() -> SimpleResult<()> {
+        let mut buffer = H2Buffer::new("name", b"\x00ABC".to_vec(), 0x4000)?;
+        buffer.layer_add("default")?;
+
+        // A sum that includes itself can never be right, but it shouldn't
+        // loop forever either
+        buffer.entry_insert_from_type("default", H2Checksum::new(ChecksumType::Sum8, ChecksumStorage::Bytes, ChecksumBound::Field(0), ChecksumBound::Field(4))?, 0)?;
+
+        let fixes = buffer.checksums_fix(1..2)?;
+        assert_eq!(1, fixes.len());
+        assert_eq!((0, vec![0x00]), fixes[0]);
+
+        // Edits that don't touch the checksum don't fix it
+        let mut buffer = H2Buffer::new("name", b"\x00ABCD".to_vec(), 0x4000)?;
+        buffer.layer_add("default")?;
+        buffer.entry_insert_from_type("default", H2Checksum::new(ChecksumType::Sum8, ChecksumStorage::Bytes, ChecksumBound::Field(1), ChecksumBound::Field(4))?, 0)?;
+        assert_eq!(0, buffer.checksums_fix(4..5)?.len());
+        assert_eq!(1, buffer.checksums_fix(3..5)?.len());
+        assert_eq!(b"\xc6ABCD".to_vec(), buffer.data);
+
+        Ok(())
+    }
 }
@@ -7,9 +7,12 @@ use serde::{Serialize, Deserialize};
 use simple_error::{bail, SimpleResult};
 use std::collections::HashMap;
 use std::fmt;
+use std::ops::Range;
 
+use crate::checksum::{Checksum, ChecksumType};
 use crate::datatype::H2Type;
 use crate::project::{H2Buffer, H2Layer, H2Entry};
+use crate::transformation::{Transformation, KeySource, CipherTemplate};
 
 // H2Project is the very core, and the root of undo. All actions will be taken
 // via this object.
@@ -88,13 +91,23 @@ impl H2Project {
     //     Ok(())
     // }
 
-    // Note: In the future, we should check for references to this buffer to
-    // ensure we aren't breaking anything else
+    // Note: In the future, we should check for other references to this
+    // buffer to ensure we aren't breaking anything else
     pub fn buffer_can_be_removed(&self, buffer: &str) -> SimpleResult<bool> {
-        match self.buffer_get(buffer) {
-            Some(buffer) => Ok(!buffer.is_populated()),
+        let b = match self.buffer_get(buffer) {
+            Some(b) => b,
             None => bail!("No such buffer: {}", buffer),
+        };
+
+        if b.is_populated() {
+            return Ok(false);
         }
+
+        // Don't remove a buffer that another buffer's key or IV came from, or
+        // that another buffer was transformed out of
+        Ok(!self.buffers.iter().any(|(name, other)| {
+            name != buffer && (other.references_buffer(buffer) || other.origin().is_some_and(|o| o.parent == buffer))
+        }))
     }
 
     pub fn buffer_remove(&mut self, buffer: &str) -> SimpleResult<H2Buffer> {
@@ -196,6 +209,76 @@ impl H2Project {
         self.buffer_get_mut(buffer)?.entry_remove(layer, offset)
     }
 
+    /// Get the range of bytes that an entry covers.
+    fn entry_range(&self, buffer: &str, layer: &str, offset: usize) -> SimpleResult<Range<usize>> {
+        let entry = match self.entry_get(buffer, layer, offset) {
+            Some(e) => e,
+            None => bail!("Couldn't find an entry at {}:{}:0x{:x}", buffer, layer, offset),
+        };
+        let range = &entry.resolved().actual_range;
+
+        Ok((range.start as usize)..(range.end as usize))
+    }
+
+    /// Read the bytes that a [`KeySource`] refers to.
+    ///
+    /// Only [`KeySource::Buffer`] and [`KeySource::Entry`] refer to the
+    /// project; the others are handled by [`CipherTemplate::resolve`].
+    pub fn key_source_read(&self, source: &KeySource) -> SimpleResult<Vec<u8>> {
+        let (buffer, range) = match source {
+            KeySource::Buffer { buffer, offset, length } => {
+                match offset.checked_add(*length) {
+                    Some(end) => (buffer, *offset..end),
+                    None => bail!("Key source is too long: {}", source),
+                }
+            },
+            KeySource::Entry { buffer, layer, offset } => {
+                (buffer, self.entry_range(buffer, layer, *offset)?)
+            },
+            _ => bail!("Key source doesn't refer to the project: {}", source),
+        };
+
+        let buffer = match self.buffer_get(buffer) {
+            Some(b) => b,
+            None => bail!("Couldn't find buffer {} to read the key from", buffer),
+        };
+
+        match buffer.data.get(range) {
+            Some(data) => Ok(data.to_vec()),
+            None => bail!("Key source goes off the end of buffer {}", buffer.name()),
+        }
+    }
+
+    /// Create a transformation from a [`CipherTemplate`], for the given
+    /// buffer.
+    pub fn cipher_template_resolve(&self, buffer: &str, template: &CipherTemplate) -> SimpleResult<Transformation> {
+        let data = match self.buffer_get(buffer) {
+            Some(b) => &b.data,
+            None => bail!("Couldn't find buffer {}", buffer),
+        };
+
+        template.resolve(data, |source| self.key_source_read(source))
+    }
+
+    /// Calculate a checksum (or hash) over part of a buffer.
+    pub fn checksum(&self, buffer: &str, range: Range<usize>, checksum_type: ChecksumType) -> SimpleResult<Checksum> {
+        match self.buffer_get(buffer) {
+            Some(b) => b.checksum(range, checksum_type),
+            None => bail!("Couldn't find buffer {} to calculate a checksum", buffer),
+        }
+    }
+
+    /// Calculate a checksum (or hash) over the bytes an entry covers.
+    ///
+    /// The result displays as a short string, which can be attached to an
+    /// entry as a comment with
+    /// [`crate::actions::ActionEntrySetComment`].
+    pub fn entry_checksum(&self, buffer: &str, layer: &str, offset: usize, checksum_type: ChecksumType) -> SimpleResult<Checksum> {
+        let range = self.entry_range(buffer, layer, offset)?;
+
+        self.checksum(buffer, range, checksum_type)
+    }
+
     pub fn comment_set(&mut self, buffer: &str, layer: &str, offset: usize, comment: Option<String>) -> SimpleResult<Option<String>> {
         let buffer = match self.buffer_get_mut(buffer) {
             Some(l) => l,
@@ -252,6 +335,12 @@ mod tests {
 
     use simple_error::SimpleResult;
     use pretty_assertions::assert_eq;
+    use redo::Record;
+
+    use crate::actions::{Action, ActionEntrySetComment};
+    use crate::checksum::Crc;
+    use crate::datatype::simple::H2Number;
+    use crate::generic_number::{GenericReader, Endian, DefaultFormatter};
 
     #[test]
     fn test_buffer_insert() -> SimpleResult<()> {
@@ -394,4 +483,44 @@ mod tests {
 
     //     Ok(())
     // }
+
+    #[test]
+    fn test_entry_checksum() -> SimpleResult<()> {
+        let mut project = H2Project::new("name", "1.0");
+        project.buffer_insert("buffer", H2Buffer::new("buffer", b"\xcb\xf4\x39\x26123456789".to_vec(), 0x100)?)?;
+        project.layer_add("buffer", "default")?;
+
+        // Over a range
+        assert_eq!(Some(0xcbf43926), project.checksum("buffer", 4..13, ChecksumType::CRC(Crc::CRC32))?.as_u64());
+        assert!(project.checksum("buffer", 4..14, ChecksumType::CRC(Crc::CRC32)).is_err());
+        assert!(project.checksum("nobuffer", 4..13, ChecksumType::CRC(Crc::CRC32)).is_err());
+
+        // Over an entry (the stored CRC, in this case)
+        project.entry_create_from_type("buffer", "default", H2Number::new(GenericReader::U32(Endian::Big), DefaultFormatter::new()), 0)?;
+        let checksum = project.entry_checksum("buffer", "default", 2, ChecksumType::Sum8)?;
+        assert_eq!(Some(0xcb + 0xf4 + 0x39 + 0x26 - 0x200), checksum.as_u64());
+        assert!(project.entry_checksum("buffer", "default", 4, ChecksumType::Sum8).is_err());
+
+        // Attach it as a comment
+        let mut record: Record<Action> = Record::new(project);
+        record.apply(ActionEntrySetComment::new("buffer", "default", 0, Some(checksum.to_string())))?;
+        assert_eq!(Some(&"Sum-8: 0x1e".to_string()), record.target().layer_get("buffer", "default").unwrap().comment_get(0)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_source_read() -> SimpleResult<()> {
+        let mut project = H2Project::new("name", "1.0");
+        project.buffer_insert("buffer", H2Buffer::new("buffer", b"ABCDEFGH".to_vec(), 0x100)?)?;
+
+        assert_eq!(b"CDE".to_vec(), project.key_source_read(&KeySource::Buffer { buffer: "buffer".to_string(), offset: 2, length: 3 })?);
+        assert!(project.key_source_read(&KeySource::Buffer { buffer: "buffer".to_string(), offset: 6, length: 3 }).is_err());
+        assert!(project.key_source_read(&KeySource::Buffer { buffer: "nobuffer".to_string(), offset: 0, length: 1 }).is_err());
+
+        // Lengths that overflow
+        assert!(project.key_source_read(&KeySource::Buffer { buffer: "buffer".to_string(), offset: 2, length: usize::MAX }).is_err());
+
+        Ok(())
+    }
 }
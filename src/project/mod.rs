@@ -2,7 +2,7 @@ mod h2project;
 pub use h2project::H2Project;
 
 mod h2buffer;
-pub use h2buffer::H2Buffer;
+pub use h2buffer::{H2Buffer, H2BufferOrigin};
 
 mod h2layer;
 pub use h2layer::H2Layer;
@@ -0,0 +1,66 @@
+//! Adapters for block ciphers that are built on a newer version of the
+//! `cipher` traits.
+//!
+//! block-modes, aes-gcm, and ccm all use the older traits, so these wrap the
+//! newer ciphers to look like the old ones.
+
+use cipher::{BlockCipher, NewBlockCipher};
+use cipher::block::InvalidKeyLength;
+use cipher::consts::{U1, U8, U16, U24, U32};
+use cipher::generic_array::GenericArray;
+
+use twofish::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+
+/// Wrap a cipher from the newer traits, with the given block size, key size,
+/// and range of allowed key lengths (in bytes).
+macro_rules! adapt_cipher {
+    ($name:ident, $inner:ty, $block_size:ty, $key_size:ty, $min_key:expr, $max_key:expr) => {
+        #[derive(Clone)]
+        pub struct $name($inner);
+
+        impl NewBlockCipher for $name {
+            type KeySize = $key_size;
+
+            fn new(key: &GenericArray<u8, $key_size>) -> Self {
+                Self::new_varkey(key).unwrap()
+            }
+
+            fn new_varkey(key: &[u8]) -> Result<Self, InvalidKeyLength> {
+                if key.len() < $min_key || key.len() > $max_key {
+                    return Err(InvalidKeyLength);
+                }
+
+                match <$inner as KeyInit>::new_from_slice(key) {
+                    Ok(c) => Ok(Self(c)),
+                    Err(_) => Err(InvalidKeyLength),
+                }
+            }
+        }
+
+        impl BlockCipher for $name {
+            type BlockSize = $block_size;
+            type ParBlocks = U1;
+
+            fn encrypt_block(&self, block: &mut GenericArray<u8, $block_size>) {
+                BlockEncrypt::encrypt_block(&self.0, block);
+            }
+
+            fn decrypt_block(&self, block: &mut GenericArray<u8, $block_size>) {
+                BlockDecrypt::decrypt_block(&self.0, block);
+            }
+        }
+    };
+}
+
+// Twofish takes any key size, but splitting it up lets the AEAD modes (which
+// need a fixed key size) work
+adapt_cipher!(Twofish128, twofish::Twofish, U16, U16, 16, 16);
+adapt_cipher!(Twofish192, twofish::Twofish, U16, U24, 24, 24);
+adapt_cipher!(Twofish256, twofish::Twofish, U16, U32, 32, 32);
+
+adapt_cipher!(Camellia128, camellia::Camellia128, U16, U16, 16, 16);
+adapt_cipher!(Camellia192, camellia::Camellia192, U16, U24, 24, 24);
+adapt_cipher!(Camellia256, camellia::Camellia256, U16, U32, 32, 32);
+
+// RC2 takes 1 - 128 byte keys (the effective key length is the full key)
+adapt_cipher!(Rc2, rc2::Rc2, U8, U32, 1, 128);
@@ -0,0 +1,236 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The padding character used by the standard alphabets.
+const STANDARD_PADDING: u8 = b'=';
+
+/// A replacement alphabet for Base64 or Base32, along with the padding
+/// character (if any).
+///
+/// Decoding is done by translating each character into the standard alphabet
+/// then decoding as normal (and encoding does the reverse), so every
+/// character has to be ASCII, and they all have to be different.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CustomAlphabet {
+    alphabet: String,
+    padding: Option<char>,
+}
+
+impl fmt::Display for CustomAlphabet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl CustomAlphabet {
+    /// Create an alphabet, and make sure it has exactly `length` characters.
+    pub fn new(alphabet: &str, padding: Option<char>, length: usize) -> SimpleResult<Self> {
+        if !alphabet.is_ascii() {
+            bail!("Alphabet must be ASCII");
+        }
+
+        if alphabet.len() != length {
+            bail!("Alphabet must be {} characters long (is {})", length, alphabet.len());
+        }
+
+        if alphabet.bytes().collect::<BTreeSet<u8>>().len() != length {
+            bail!("Alphabet can't have the same character twice");
+        }
+
+        if let Some(p) = padding {
+            if !p.is_ascii() {
+                bail!("Padding character must be ASCII");
+            }
+
+            if alphabet.contains(p) {
+                bail!("Padding character can't be part of the alphabet");
+            }
+        }
+
+        Ok(Self {
+            alphabet: alphabet.to_string(),
+            padding,
+        })
+    }
+
+    pub fn alphabet(&self) -> &str {
+        &self.alphabet
+    }
+
+    pub fn padding(&self) -> Option<char> {
+        self.padding
+    }
+
+    /// Translate an encoded buffer into the `standard` alphabet (with '=' for
+    /// padding).
+    ///
+    /// Characters that aren't in the alphabet are an error, unless
+    /// `permissive` is set, in which case they're removed.
+    pub fn to_standard(&self, buffer: &[u8], standard: &[u8], permissive: bool) -> SimpleResult<Vec<u8>> {
+        let mut out: Vec<u8> = Vec::with_capacity(buffer.len());
+
+        for b in buffer {
+            if self.padding == Some(*b as char) {
+                out.push(STANDARD_PADDING);
+                continue;
+            }
+
+            match self.alphabet.bytes().position(|a| a == *b) {
+                Some(i) => out.push(standard[i]),
+                None => {
+                    if !permissive {
+                        bail!("Character isn't in the alphabet: 0x{:02x}", b);
+                    }
+                },
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Translate a buffer encoded with the `standard` alphabet into this one.
+    pub fn from_standard(&self, buffer: &[u8], standard: &[u8]) -> SimpleResult<Vec<u8>> {
+        let alphabet = self.alphabet.as_bytes();
+
+        buffer.iter().map(|b| {
+            if *b == STANDARD_PADDING {
+                return match self.padding {
+                    Some(p) => Ok(p as u8),
+                    None => bail!("Alphabet doesn't have a padding character"),
+                };
+            }
+
+            match standard.iter().position(|s| s == b) {
+                Some(i) => Ok(alphabet[i]),
+                None => bail!("Character isn't in the standard alphabet: 0x{:02x}", b),
+            }
+        }).collect()
+    }
+
+    /// Guess which alphabets could have been used to encode `buffer`.
+    ///
+    /// We can only see which characters are used, not what order they go in,
+    /// so this returns each of the `known` alphabets that has every character
+    /// in the buffer. If the buffer ends with a run of a character that isn't
+    /// used anywhere else, that's assumed to be the padding.
+    ///
+    /// Additionally, if `base` is given, and the buffer uses that plus some
+    /// other characters, the others are tried in each of the remaining
+    /// positions (this catches the many Base64 variants that only change the
+    /// last two characters).
+    pub fn infer(buffer: &[u8], known: &[&str], base: Option<&str>, length: usize) -> Vec<Self> {
+        // Find the padding - the final run of the same character, if it isn't
+        // used anywhere else
+        let padding = match buffer.last() {
+            Some(last) if !last.is_ascii_alphanumeric() => {
+                let body_length = buffer.iter().rposition(|b| b != last).map(|p| p + 1).unwrap_or(0);
+
+                match buffer[..body_length].contains(last) {
+                    true  => None,
+                    false => Some(*last as char),
+                }
+            },
+            _ => None,
+        };
+
+        let body: BTreeSet<u8> = buffer.iter().filter(|b| padding != Some(**b as char)).copied().collect();
+
+        let mut candidates: Vec<String> = known.iter().map(|k| k.to_string()).collect();
+
+        if let Some(base) = base {
+            let extra: Vec<u8> = body.iter().filter(|b| !base.as_bytes().contains(b)).copied().collect();
+
+            if extra.len() == length - base.len() {
+                // Try every order (there are only a couple)
+                let mut orders: Vec<Vec<u8>> = vec![vec![]];
+                for _ in 0..extra.len() {
+                    orders = orders.into_iter().flat_map(|order| {
+                        extra.iter().filter(|e| !order.contains(e)).map(|e| {
+                            let mut order = order.clone();
+                            order.push(*e);
+                            order
+                        }).collect::<Vec<_>>()
+                    }).collect();
+                }
+
+                for order in orders {
+                    candidates.push(format!("{}{}", base, String::from_utf8_lossy(&order)));
+                }
+            }
+        }
+
+        candidates.into_iter().filter(|candidate| {
+            body.iter().all(|b| candidate.as_bytes().contains(b))
+        }).filter_map(|candidate| {
+            Self::new(&candidate, padding, length).ok()
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_new() -> SimpleResult<()> {
+        assert!(CustomAlphabet::new("abcd", None, 4).is_ok());
+        assert!(CustomAlphabet::new("abcd", Some('='), 4).is_ok());
+
+        // Wrong length
+        assert!(CustomAlphabet::new("abc", None, 4).is_err());
+
+        // Duplicates
+        assert!(CustomAlphabet::new("abca", None, 4).is_err());
+        assert!(CustomAlphabet::new("abcd", Some('a'), 4).is_err());
+
+        // Not ASCII
+        assert!(CustomAlphabet::new("abcé", None, 5).is_err());
+        assert!(CustomAlphabet::new("abcd", Some('é'), 4).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_translate() -> SimpleResult<()> {
+        let alphabet = CustomAlphabet::new("dcba", Some('.'), 4)?;
+
+        assert_eq!(b"ABCD==".to_vec(), alphabet.to_standard(b"dcba..", b"ABCD", false)?);
+        assert_eq!(b"dcba..".to_vec(), alphabet.from_standard(b"ABCD==", b"ABCD")?);
+
+        assert!(alphabet.to_standard(b"dcbaX", b"ABCD", false).is_err());
+        assert_eq!(b"ABCD".to_vec(), alphabet.to_standard(b"d c\nbaX", b"ABCD", true)?);
+
+        assert!(alphabet.from_standard(b"ABCDE", b"ABCD").is_err());
+
+        let alphabet = CustomAlphabet::new("dcba", None, 4)?;
+        assert!(alphabet.from_standard(b"A=", b"ABCD").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_infer() -> SimpleResult<()> {
+        let known = ["abcdefgh", "hgfedcba", "abcdefgX"];
+
+        // The padding is found, and only the alphabets with all the right
+        // characters are returned
+        assert_eq!(vec![
+            CustomAlphabet::new("abcdefgh", Some('!'), 8)?,
+            CustomAlphabet::new("hgfedcba", Some('!'), 8)?,
+        ], CustomAlphabet::infer(b"abch!!", &known, None, 8));
+
+        // A padding character that's used elsewhere isn't padding
+        assert_eq!(Vec::<CustomAlphabet>::new(), CustomAlphabet::infer(b"ab!h!!", &known, None, 8));
+
+        // Extra characters are tried in every position
+        assert_eq!(vec![
+            CustomAlphabet::new("abcdef+-", None, 8)?,
+            CustomAlphabet::new("abcdef-+", None, 8)?,
+        ], CustomAlphabet::infer(b"a-b+c", &[], Some("abcdef"), 8));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::generic_number::Endian;
+
+/// Read a 1, 2, 4, or 8-byte element as an integer.
+pub(crate) fn read_element(chunk: &[u8], endian: Endian) -> u64 {
+    let mut bytes = chunk.to_vec();
+    if endian == Endian::Little {
+        bytes.reverse();
+    }
+
+    bytes.iter().fold(0u64, |acc, b| (acc << 8) | (*b as u64))
+}
+
+/// Write an integer back out as an element of `width` bytes. Any bits that
+/// don't fit are discarded.
+pub(crate) fn write_element(value: u64, width: usize, endian: Endian) -> Vec<u8> {
+    let mut bytes = value.to_be_bytes()[(8 - width)..].to_vec();
+    if endian == Endian::Little {
+        bytes.reverse();
+    }
+
+    bytes
+}
+
+/// A mask for the bits in an element of `width` bytes.
+pub(crate) fn element_mask(width: usize) -> u64 {
+    match width {
+        8 => u64::MAX,
+        _ => (1u64 << (width * 8)) - 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_elements() {
+        assert_eq!(0x12, read_element(&[0x12], Endian::Little));
+        assert_eq!(0x1234, read_element(&[0x12, 0x34], Endian::Big));
+        assert_eq!(0x3412, read_element(&[0x12, 0x34], Endian::Little));
+        assert_eq!(0x0102030405060708, read_element(&[1, 2, 3, 4, 5, 6, 7, 8], Endian::Big));
+
+        assert_eq!(vec![0x12, 0x34], write_element(0x1234, 2, Endian::Big));
+        assert_eq!(vec![0x34, 0x12], write_element(0x1234, 2, Endian::Little));
+        assert_eq!(vec![0x34], write_element(0x1234, 1, Endian::Big));
+        assert_eq!(vec![8, 7, 6, 5, 4, 3, 2, 1], write_element(0x0102030405060708, 8, Endian::Little));
+
+        assert_eq!(0xff, element_mask(1));
+        assert_eq!(0xffffffff, element_mask(4));
+        assert_eq!(u64::MAX, element_mask(8));
+    }
+}
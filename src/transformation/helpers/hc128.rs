@@ -0,0 +1,138 @@
+//! An implementation of the HC-128 stream cipher (from the eSTREAM
+//! portfolio).
+//!
+//! There's no HC-128 crate that works with the version of the `cipher`
+//! traits we use, and it's a simple cipher, so it lives here.
+
+use crate::transformation::helpers::keystream::Keystream;
+
+fn f1(x: u32) -> u32 {
+    x.rotate_right(7) ^ x.rotate_right(18) ^ (x >> 3)
+}
+
+fn f2(x: u32) -> u32 {
+    x.rotate_right(17) ^ x.rotate_right(19) ^ (x >> 10)
+}
+
+fn g1(x: u32, y: u32, z: u32) -> u32 {
+    (x.rotate_right(10) ^ z.rotate_right(23)).wrapping_add(y.rotate_right(8))
+}
+
+fn g2(x: u32, y: u32, z: u32) -> u32 {
+    (x.rotate_left(10) ^ z.rotate_left(23)).wrapping_add(y.rotate_left(8))
+}
+
+/// Look up bytes 0 and 2 of `x` in a table.
+fn h(table: &[u32; 512], x: u32) -> u32 {
+    table[(x & 0xff) as usize].wrapping_add(table[256 + ((x >> 16) & 0xff) as usize])
+}
+
+#[derive(Clone)]
+pub(crate) struct Hc128 {
+    p: [u32; 512],
+    q: [u32; 512],
+    counter: usize,
+
+    /// The current word of keystream, and how much of it has been used.
+    word: [u8; 4],
+    position: usize,
+}
+
+impl Hc128 {
+    pub(crate) fn new(key: &[u8; 16], iv: &[u8; 16]) -> Self {
+        let word = |b: &[u8], i: usize| u32::from_le_bytes([b[i * 4], b[i * 4 + 1], b[i * 4 + 2], b[i * 4 + 3]]);
+
+        // The key and IV are each repeated twice, then expanded
+        let mut w = [0u32; 1280];
+        for i in 0..4 {
+            w[i] = word(key, i);
+            w[i + 4] = w[i];
+            w[i + 8] = word(iv, i);
+            w[i + 12] = w[i + 8];
+        }
+
+        for i in 16..1280 {
+            w[i] = f2(w[i - 2])
+                .wrapping_add(w[i - 7])
+                .wrapping_add(f1(w[i - 15]))
+                .wrapping_add(w[i - 16])
+                .wrapping_add(i as u32);
+        }
+
+        let mut cipher = Self {
+            p: [0; 512],
+            q: [0; 512],
+            counter: 0,
+            word: [0; 4],
+            position: 4,
+        };
+        cipher.p.copy_from_slice(&w[256..768]);
+        cipher.q.copy_from_slice(&w[768..1280]);
+
+        // Run the cipher 1024 steps, feeding the output back into the tables
+        for _ in 0..1024 {
+            cipher.step(true);
+        }
+
+        cipher
+    }
+
+    /// Update one table entry and return the next word of keystream. While
+    /// initializing, the output replaces the table entry.
+    fn step(&mut self, initializing: bool) -> u32 {
+        let j = self.counter % 512;
+
+        // The two tables take turns, 512 steps each
+        let (table, other) = match self.counter < 512 {
+            true  => (&mut self.p, &self.q),
+            false => (&mut self.q, &self.p),
+        };
+        let g: fn(u32, u32, u32) -> u32 = match self.counter < 512 {
+            true  => g1,
+            false => g2,
+        };
+
+        // Indexes are all relative to j, mod 512
+        table[j] = table[j].wrapping_add(g(table[(j + 509) % 512], table[(j + 502) % 512], table[(j + 1) % 512]));
+        let s = h(other, table[(j + 500) % 512]) ^ table[j];
+
+        if initializing {
+            table[j] = s;
+        }
+
+        self.counter = (self.counter + 1) % 1024;
+
+        s
+    }
+}
+
+impl Keystream for Hc128 {
+    fn fill(&mut self, out: &mut [u8]) {
+        for b in out.iter_mut() {
+            if self.position == 4 {
+                self.word = self.step(false).to_le_bytes();
+                self.position = 0;
+            }
+
+            *b = self.word[self.position];
+            self.position += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_hc128() {
+        // The test vector from the specification (the words are
+        // little-endian)
+        let mut cipher = Hc128::new(&[0; 16], &[0; 16]);
+
+        let mut out = vec![0; 16];
+        cipher.fill(&mut out);
+        assert_eq!(b"\x82\x00\x15\x73\xa0\x03\xfd\x3b\x7f\xd7\x2f\xfb\x0e\xaf\x63\xaa".to_vec(), out);
+    }
+}
@@ -0,0 +1,315 @@
+use simple_error::{SimpleResult, bail};
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use hkdf::Hkdf;
+use md5::{Md5, Digest};
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+
+/// The magic string that starts an OpenSSL `enc` file.
+const OPENSSL_MAGIC: &[u8] = b"Salted__";
+
+/// The length of the salt that follows [`OPENSSL_MAGIC`].
+const OPENSSL_SALT_LENGTH: usize = 8;
+
+/// Run some code with `$d` set to the hash algorithm's type.
+macro_rules! with_digest {
+    ($hash:expr, $d:ident => $body:expr) => {
+        match $hash {
+            DigestType::MD5    => { type $d = Md5;    $body },
+            DigestType::SHA1   => { type $d = Sha1;   $body },
+            DigestType::SHA256 => { type $d = Sha256; $body },
+            DigestType::SHA384 => { type $d = Sha384; $body },
+            DigestType::SHA512 => { type $d = Sha512; $body },
+        }
+    };
+}
+
+/// Which hash algorithm a [`KeyDerivation`] uses.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub enum DigestType {
+    MD5,
+    SHA1,
+    SHA256,
+    SHA384,
+    SHA512,
+}
+
+impl DigestType {
+    /// Hash the data once.
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        with_digest!(self, D => D::digest(data).to_vec())
+    }
+}
+
+/// Derive a key (and possibly an IV) from a passphrase.
+///
+/// Encrypted data often isn't encrypted with a raw key; instead, the key is
+/// derived from a password. This stores the parameters for the derivation,
+/// which lets a cipher transformation be created from a passphrase instead
+/// of a key - see [`crate::transformation::TransformBlockCipher::new_derived`]
+/// and [`crate::transformation::TransformStreamCipher::new_derived`].
+///
+/// The derivation creates one long string of bytes - the key comes first,
+/// then the IV (if the cipher needs one and it isn't given).
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::transformation::*;
+///
+/// let derivation = KeyDerivation::PBKDF2 {
+///     hash: DigestType::SHA256,
+///     passphrase: b"password".to_vec(),
+///     salt: b"salt".to_vec(),
+///     iterations: 1,
+/// };
+///
+/// let (key, iv) = derivation.derive(16, 0).unwrap();
+/// assert_eq!(b"\x12\x0f\xb6\xcf\xfc\xf8\xb3\x2c\x43\xe7\x22\x52\x56\xc4\xf8\x37".to_vec(), key);
+/// assert_eq!(None, iv);
+/// ```
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum KeyDerivation {
+    /// Hash the passphrase once, and use the start of the hash.
+    ///
+    /// The hash needs to be long enough for the key (and IV).
+    Hash {
+        hash: DigestType,
+        passphrase: Vec<u8>,
+    },
+
+    /// PBKDF2 (RFC 8018), using HMAC with the given hash.
+    PBKDF2 {
+        hash: DigestType,
+        passphrase: Vec<u8>,
+        salt: Vec<u8>,
+        iterations: u32,
+    },
+
+    /// OpenSSL's `EVP_BytesToKey()` function.
+    ///
+    /// This is what `openssl enc` uses (without `-pbkdf2`) - MD5 with one
+    /// iteration in older versions, and SHA256 in 1.1.0 and later. The salt is
+    /// optional, and is normally 8 bytes.
+    BytesToKey {
+        hash: DigestType,
+        passphrase: Vec<u8>,
+        salt: Option<Vec<u8>>,
+        iterations: u32,
+    },
+
+    /// HKDF (RFC 5869) - extract then expand.
+    HKDF {
+        hash: DigestType,
+        secret: Vec<u8>,
+        salt: Option<Vec<u8>>,
+        info: Vec<u8>,
+    },
+
+    /// An OpenSSL `enc` file, which starts with `Salted__` and an 8-byte salt.
+    ///
+    /// The key and IV are derived with `EVP_BytesToKey` if `pbkdf2_iterations`
+    /// isn't set, or PBKDF2 (as `openssl enc -pbkdf2` does) if it is. The
+    /// header is removed when decrypting and added back when encrypting; use
+    /// [`KeyDerivation::from_openssl_header`] to read the salt from a buffer.
+    OpenSSL {
+        hash: DigestType,
+        passphrase: Vec<u8>,
+        salt: Vec<u8>,
+        pbkdf2_iterations: Option<u32>,
+    },
+}
+
+impl fmt::Display for KeyDerivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl KeyDerivation {
+    /// Create an [`KeyDerivation::OpenSSL`] derivation, using the salt from
+    /// the header of an encrypted buffer.
+    pub fn from_openssl_header(buffer: &[u8], hash: DigestType, passphrase: Vec<u8>, pbkdf2_iterations: Option<u32>) -> SimpleResult<Self> {
+        if buffer.len() < OPENSSL_MAGIC.len() + OPENSSL_SALT_LENGTH || !buffer.starts_with(OPENSSL_MAGIC) {
+            bail!("Buffer doesn't start with an OpenSSL \"Salted__\" header");
+        }
+
+        Ok(Self::OpenSSL {
+            hash,
+            passphrase,
+            salt: buffer[OPENSSL_MAGIC.len()..(OPENSSL_MAGIC.len() + OPENSSL_SALT_LENGTH)].to_vec(),
+            pbkdf2_iterations,
+        })
+    }
+
+    /// The header that the ciphertext starts with, if any.
+    pub fn header(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::OpenSSL { salt, .. } => Some([OPENSSL_MAGIC, salt].concat()),
+            _ => None,
+        }
+    }
+
+    /// Derive a key of `key_length` bytes and an IV of `iv_length` bytes
+    /// (`None` if `iv_length` is 0).
+    pub fn derive(&self, key_length: usize, iv_length: usize) -> SimpleResult<(Vec<u8>, Option<Vec<u8>>)> {
+        let length = key_length + iv_length;
+
+        let mut material = match self {
+            Self::Hash { hash, passphrase } => {
+                let digest = hash.digest(passphrase);
+                if digest.len() < length {
+                    bail!("{:?} is too short to derive {} bytes", hash, length);
+                }
+
+                digest
+            },
+            Self::PBKDF2 { hash, passphrase, salt, iterations } => {
+                Self::pbkdf2(*hash, passphrase, salt, *iterations, length)?
+            },
+            Self::BytesToKey { hash, passphrase, salt, iterations } => {
+                Self::bytes_to_key(*hash, passphrase, salt.as_deref(), *iterations, length)?
+            },
+            Self::HKDF { hash, secret, salt, info } => {
+                let mut out = vec![0; length];
+
+                let result = with_digest!(hash, D => Hkdf::<D>::new(salt.as_deref(), secret).expand(info, &mut out));
+                if result.is_err() {
+                    bail!("HKDF with {:?} can't derive {} bytes", hash, length);
+                }
+
+                out
+            },
+            Self::OpenSSL { hash, passphrase, salt, pbkdf2_iterations } => {
+                match pbkdf2_iterations {
+                    Some(iterations) => Self::pbkdf2(*hash, passphrase, salt, *iterations, length)?,
+                    None => Self::bytes_to_key(*hash, passphrase, Some(salt), 1, length)?,
+                }
+            },
+        };
+
+        material.truncate(length);
+        let iv = material.split_off(key_length);
+
+        Ok((material, if iv_length == 0 { None } else { Some(iv) }))
+    }
+
+    /// Run PBKDF2-HMAC with the given hash.
+    fn pbkdf2(hash: DigestType, passphrase: &[u8], salt: &[u8], iterations: u32, length: usize) -> SimpleResult<Vec<u8>> {
+        if iterations == 0 {
+            bail!("PBKDF2 needs at least one iteration");
+        }
+
+        let mut out = vec![0; length];
+        with_digest!(hash, D => pbkdf2::pbkdf2_hmac::<D>(passphrase, salt, iterations, &mut out));
+
+        Ok(out)
+    }
+
+    /// Implement OpenSSL's `EVP_BytesToKey()`.
+    ///
+    /// Each block is the hash of the previous block, the passphrase, and the
+    /// salt, re-hashed `iterations - 1` more times. Blocks are generated until
+    /// there's enough data.
+    fn bytes_to_key(hash: DigestType, passphrase: &[u8], salt: Option<&[u8]>, iterations: u32, length: usize) -> SimpleResult<Vec<u8>> {
+        if iterations == 0 {
+            bail!("EVP_BytesToKey needs at least one iteration");
+        }
+
+        let mut out: Vec<u8> = vec![];
+        let mut block: Vec<u8> = vec![];
+
+        while out.len() < length {
+            block = hash.digest(&[&block, passphrase, salt.unwrap_or(&[])].concat());
+            for _ in 1..iterations {
+                block = hash.digest(&block);
+            }
+
+            out.extend(&block);
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_derive() -> SimpleResult<()> {
+        let tests: Vec<(KeyDerivation, usize, usize, Vec<u8>, Option<Vec<u8>>)> = vec![
+            (
+                // RFC 6070
+                KeyDerivation::PBKDF2 { hash: DigestType::SHA1, passphrase: b"password".to_vec(), salt: b"salt".to_vec(), iterations: 4096 },
+                20, 0,
+                b"\x4b\x00\x79\x01\xb7\x65\x48\x9a\xbe\xad\x49\xd9\x26\xf7\x21\xd0\x65\xa4\x29\xc1".to_vec(),
+                None,
+            ),
+            (
+                // RFC 5869, test case 1
+                KeyDerivation::HKDF {
+                    hash: DigestType::SHA256,
+                    secret: vec![0x0b; 22],
+                    salt: Some((0x00..=0x0c).collect()),
+                    info: (0xf0..=0xf9).collect(),
+                },
+                26, 16,
+                b"\x3c\xb2\x5f\x25\xfa\xac\xd5\x7a\x90\x43\x4f\x64\xd0\x36\x2f\x2a\x2d\x2d\x0a\x90\xcf\x1a\x5a\x4c\x5d\xb0".to_vec(),
+                Some(b"\x2d\x56\xec\xc4\xc5\xbf\x34\x00\x72\x08\xd5\xb8\x87\x18\x58\x65".to_vec()),
+            ),
+            (
+                // openssl enc -aes-128-cbc -md md5 -S 0102030405060708 -pass pass:password -P
+                KeyDerivation::BytesToKey { hash: DigestType::MD5, passphrase: b"password".to_vec(), salt: Some(b"\x01\x02\x03\x04\x05\x06\x07\x08".to_vec()), iterations: 1 },
+                16, 16,
+                b"\xe7\xb0\x97\x1e\x52\xca\x5c\xc8\xd0\x53\x9f\xb3\x41\x2f\x63\x16".to_vec(),
+                Some(b"\xf7\xba\x2e\x6e\xe2\x93\xd9\xf3\x45\x7b\x99\x43\x6b\x51\xce\x02".to_vec()),
+            ),
+            (
+                KeyDerivation::Hash { hash: DigestType::MD5, passphrase: b"hunter2".to_vec() },
+                8, 8,
+                b"\x2a\xb9\x63\x90\xc7\xdb\xe3\x43".to_vec(),
+                Some(b"\x9d\xe7\x4d\x0c\x9b\x0b\x17\x67".to_vec()),
+            ),
+        ];
+
+        for (derivation, key_length, iv_length, expected_key, expected_iv) in tests {
+            let (key, iv) = derivation.derive(key_length, iv_length)?;
+            assert_eq!(expected_key, key, "{}", derivation);
+            assert_eq!(expected_iv, iv, "{}", derivation);
+        }
+
+        // MD5 isn't long enough for a 256-bit key and 128-bit IV
+        assert!(KeyDerivation::Hash { hash: DigestType::MD5, passphrase: b"hunter2".to_vec() }.derive(32, 16).is_err());
+
+        // HKDF can only go up to 255 blocks
+        assert!(KeyDerivation::HKDF { hash: DigestType::SHA1, secret: b"secret".to_vec(), salt: None, info: vec![] }.derive(255 * 20 + 1, 0).is_err());
+
+        // Zero iterations aren't allowed
+        assert!(KeyDerivation::PBKDF2 { hash: DigestType::SHA1, passphrase: b"password".to_vec(), salt: b"salt".to_vec(), iterations: 0 }.derive(16, 0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_openssl_header() -> SimpleResult<()> {
+        let buffer = b"Salted__\x01\x02\x03\x04\x05\x06\x07\x08ciphertext".to_vec();
+
+        let derivation = KeyDerivation::from_openssl_header(&buffer, DigestType::MD5, b"password".to_vec(), None)?;
+        assert_eq!(Some(b"Salted__\x01\x02\x03\x04\x05\x06\x07\x08".to_vec()), derivation.header());
+
+        // Same as BytesToKey with the salt
+        let (key, iv) = derivation.derive(16, 16)?;
+        assert_eq!(b"\xe7\xb0\x97\x1e\x52\xca\x5c\xc8\xd0\x53\x9f\xb3\x41\x2f\x63\x16".to_vec(), key);
+        assert_eq!(Some(b"\xf7\xba\x2e\x6e\xe2\x93\xd9\xf3\x45\x7b\x99\x43\x6b\x51\xce\x02".to_vec()), iv);
+
+        // No header at all
+        assert!(KeyDerivation::from_openssl_header(&b"ciphertext".to_vec(), DigestType::MD5, b"password".to_vec(), None).is_err());
+        assert!(KeyDerivation::from_openssl_header(&b"Salted__1234".to_vec(), DigestType::MD5, b"password".to_vec(), None).is_err());
+
+        Ok(())
+    }
+}
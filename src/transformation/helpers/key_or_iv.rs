@@ -80,5 +80,15 @@ impl KeyOrIV {
             _ => bail!("Invalid IV length"),
         }
     }
+
+    /// Get the value as bytes, whatever size it is.
+    pub fn to_vec(self) -> Vec<u8> {
+        match self {
+            KeyOrIV::Bits64(v)  => v.to_vec(),
+            KeyOrIV::Bits128(v) => v.to_vec(),
+            KeyOrIV::Bits192(v) => v.to_vec(),
+            KeyOrIV::Bits256(v) => v.to_vec(),
+        }
+    }
 }
 
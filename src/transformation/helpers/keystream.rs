@@ -0,0 +1,33 @@
+/// How much keystream to throw away at a time, when skipping to an offset.
+/// It's a multiple of every cipher's block size.
+pub(crate) const SKIP_CHUNK_SIZE: usize = 4096;
+
+/// A stream cipher that we implement ourselves, which just produces a
+/// keystream to XOR with the data.
+pub(crate) trait Keystream {
+    /// Fill `out` with the next bytes of the keystream.
+    fn fill(&mut self, out: &mut [u8]);
+
+    /// Throw away the next `count` bytes of the keystream.
+    fn skip(&mut self, count: usize) {
+        let mut discard = [0; SKIP_CHUNK_SIZE];
+        let mut count = count;
+
+        while count > 0 {
+            let length = count.min(SKIP_CHUNK_SIZE);
+            self.fill(&mut discard[..length]);
+            count -= length;
+        }
+    }
+
+    /// XOR the next bytes of the keystream into `buffer` (which encrypts and
+    /// decrypts).
+    fn apply(&mut self, buffer: &mut [u8]) {
+        let mut keystream = vec![0; buffer.len()];
+        self.fill(&mut keystream);
+
+        for (b, k) in buffer.iter_mut().zip(keystream) {
+            *b ^= k;
+        }
+    }
+}
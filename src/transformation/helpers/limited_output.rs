@@ -0,0 +1,81 @@
+use std::io::{self, Write};
+
+/// While detecting, compressed data that expands to more than this many times
+/// its own size is rejected, so a small decompression bomb can't use up all
+/// the memory.
+const DETECT_MAX_RATIO: usize = 1024;
+
+/// Small buffers can always expand to this size while detecting, since very
+/// repetitive data can legitimately compress better than [`DETECT_MAX_RATIO`].
+const DETECT_MIN_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Collects decompressed data, and fails the write that would take it past a
+/// limit.
+pub(crate) struct LimitedOutput {
+    data: Vec<u8>,
+    limit: usize,
+}
+
+impl LimitedOutput {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            data: Vec::new(),
+            limit,
+        }
+    }
+
+    /// No limit, for when the data is actually being transformed.
+    pub(crate) fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    /// The limit for checking whether `input_length` bytes of compressed data
+    /// are valid (see [`Self::detection_limit`]).
+    pub(crate) fn for_detection(input_length: usize) -> Self {
+        Self::new(Self::detection_limit(input_length))
+    }
+
+    /// The most that `input_length` bytes of compressed data can expand to
+    /// while detecting.
+    pub(crate) fn detection_limit(input_length: usize) -> usize {
+        input_length.saturating_mul(DETECT_MAX_RATIO).max(DETECT_MIN_LIMIT)
+    }
+
+    pub(crate) fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Write for LimitedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.data.len().saturating_add(buf.len()) > self.limit {
+            return Err(io::Error::other(format!("decompressed data is over the limit of {} bytes", self.limit)));
+        }
+
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_limited_output() {
+        let mut out = LimitedOutput::new(4);
+        assert!(out.write_all(b"AB").is_ok());
+        assert!(out.write_all(b"CD").is_ok());
+        assert!(out.write_all(b"E").is_err());
+        assert_eq!(b"ABCD".to_vec(), out.into_inner());
+
+        assert_eq!(16 * 1024 * 1024, LimitedOutput::detection_limit(4));
+        assert_eq!(1024 * 1024 * 1024, LimitedOutput::detection_limit(1024 * 1024));
+        assert_eq!(usize::MAX, LimitedOutput::detection_limit(usize::MAX));
+    }
+}
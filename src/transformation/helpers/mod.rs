@@ -1,2 +1,35 @@
 mod key_or_iv;
 pub use key_or_iv::*;
+
+mod key_derivation;
+pub use key_derivation::*;
+
+mod custom_alphabet;
+pub use custom_alphabet::*;
+
+mod elements;
+pub(crate) use elements::*;
+
+mod scoring;
+pub use scoring::*;
+
+mod sm4;
+pub(crate) use sm4::Sm4;
+
+mod cipher_compat;
+pub(crate) use cipher_compat::*;
+
+mod keystream;
+pub(crate) use keystream::{Keystream, SKIP_CHUNK_SIZE};
+
+mod hc128;
+pub(crate) use hc128::Hc128;
+
+mod rabbit;
+pub(crate) use rabbit::Rabbit;
+
+mod spritz;
+pub(crate) use spritz::Spritz;
+
+mod limited_output;
+pub(crate) use limited_output::LimitedOutput;
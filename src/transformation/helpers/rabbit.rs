@@ -0,0 +1,161 @@
+//! An implementation of the Rabbit stream cipher (RFC 4503).
+//!
+//! There's no Rabbit crate that works with the version of the `cipher`
+//! traits we use, and it's a simple cipher, so it lives here.
+
+use crate::transformation::helpers::keystream::Keystream;
+
+/// The constants added to the counters each round.
+const A: [u32; 8] = [
+    0x4d34d34d, 0xd34d34d3, 0x34d34d34, 0x4d34d34d,
+    0xd34d34d3, 0x34d34d34, 0x4d34d34d, 0xd34d34d3,
+];
+
+/// Square a number, and XOR the high and low words of the result together.
+fn g_function(u: u32, v: u32) -> u32 {
+    let square = (u.wrapping_add(v) as u64) * (u.wrapping_add(v) as u64);
+
+    (square ^ (square >> 32)) as u32
+}
+
+#[derive(Clone)]
+pub(crate) struct Rabbit {
+    x: [u32; 8],
+    c: [u32; 8],
+    carry: u32,
+
+    /// The current block of keystream, and how much of it has been used.
+    block: [u8; 16],
+    position: usize,
+}
+
+impl Rabbit {
+    pub(crate) fn new(key: &[u8; 16], iv: Option<&[u8; 8]>) -> Self {
+        let k: Vec<u32> = key.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+
+        let mut cipher = Self {
+            x: [
+                k[0], (k[3] << 16) | (k[2] >> 16),
+                k[1], (k[0] << 16) | (k[3] >> 16),
+                k[2], (k[1] << 16) | (k[0] >> 16),
+                k[3], (k[2] << 16) | (k[1] >> 16),
+            ],
+            c: [
+                k[2].rotate_left(16), (k[0] & 0xffff0000) | (k[1] & 0xffff),
+                k[3].rotate_left(16), (k[1] & 0xffff0000) | (k[2] & 0xffff),
+                k[0].rotate_left(16), (k[2] & 0xffff0000) | (k[3] & 0xffff),
+                k[1].rotate_left(16), (k[3] & 0xffff0000) | (k[0] & 0xffff),
+            ],
+            carry: 0,
+            block: [0; 16],
+            position: 16,
+        };
+
+        for _ in 0..4 {
+            cipher.next_state();
+        }
+
+        for i in 0..8 {
+            cipher.c[i] ^= cipher.x[(i + 4) % 8];
+        }
+
+        // The IV is mixed into the counters
+        if let Some(iv) = iv {
+            let i0 = u32::from_le_bytes([iv[0], iv[1], iv[2], iv[3]]);
+            let i2 = u32::from_le_bytes([iv[4], iv[5], iv[6], iv[7]]);
+            let i1 = (i0 >> 16) | (i2 & 0xffff0000);
+            let i3 = (i2 << 16) | (i0 & 0x0000ffff);
+
+            for (i, v) in [i0, i1, i2, i3, i0, i1, i2, i3].iter().enumerate() {
+                cipher.c[i] ^= v;
+            }
+
+            for _ in 0..4 {
+                cipher.next_state();
+            }
+        }
+
+        cipher
+    }
+
+    fn next_state(&mut self) {
+        // Update the counters, carrying from one to the next
+        for (c, a) in self.c.iter_mut().zip(A.iter()) {
+            let t = *c as u64 + *a as u64 + self.carry as u64;
+            *c = t as u32;
+            self.carry = (t >> 32) as u32;
+        }
+
+        let g: Vec<u32> = self.x.iter().zip(self.c.iter()).map(|(x, c)| g_function(*x, *c)).collect();
+
+        self.x = [
+            g[0].wrapping_add(g[7].rotate_left(16)).wrapping_add(g[6].rotate_left(16)),
+            g[1].wrapping_add(g[0].rotate_left(8)).wrapping_add(g[7]),
+            g[2].wrapping_add(g[1].rotate_left(16)).wrapping_add(g[0].rotate_left(16)),
+            g[3].wrapping_add(g[2].rotate_left(8)).wrapping_add(g[1]),
+            g[4].wrapping_add(g[3].rotate_left(16)).wrapping_add(g[2].rotate_left(16)),
+            g[5].wrapping_add(g[4].rotate_left(8)).wrapping_add(g[3]),
+            g[6].wrapping_add(g[5].rotate_left(16)).wrapping_add(g[4].rotate_left(16)),
+            g[7].wrapping_add(g[6].rotate_left(8)).wrapping_add(g[5]),
+        ];
+    }
+
+    /// Generate the next 16-byte block of keystream.
+    fn next_block(&mut self) -> [u8; 16] {
+        self.next_state();
+
+        let x = self.x;
+        let words = [
+            x[0] ^ (x[5] >> 16) ^ (x[3] << 16),
+            x[2] ^ (x[7] >> 16) ^ (x[5] << 16),
+            x[4] ^ (x[1] >> 16) ^ (x[7] << 16),
+            x[6] ^ (x[3] >> 16) ^ (x[1] << 16),
+        ];
+
+        let mut block = [0u8; 16];
+        for (i, word) in words.iter().enumerate() {
+            block[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+
+        block
+    }
+}
+
+impl Keystream for Rabbit {
+    fn fill(&mut self, out: &mut [u8]) {
+        for b in out.iter_mut() {
+            if self.position == 16 {
+                self.block = self.next_block();
+                self.position = 0;
+            }
+
+            *b = self.block[self.position];
+            self.position += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_rabbit() {
+        // Test vectors from RFC 4503, appendix A (the RFC writes each block as
+        // a 128-bit number, so the bytes are reversed compared to the output)
+        let mut cipher = Rabbit::new(&[0; 16], None);
+
+        let mut out = vec![0; 32];
+        cipher.fill(&mut out);
+        assert_eq!(b"\x02\xf7\x4a\x1c\x26\x45\x6b\xf5\xec\xd6\xa5\x36\xf0\x54\x57\xb1\
+                     \xa7\x8a\xc6\x89\x47\x6c\x69\x7b\x39\x0c\x9c\xc5\x15\xd8\xe8\x88".to_vec(), out);
+
+        // With an IV
+        let mut cipher = Rabbit::new(&[0; 16], Some(&[0; 8]));
+
+        let mut out = vec![0; 16];
+        cipher.fill(&mut out);
+        assert_eq!(b"\xed\xb7\x05\x67\x37\x5d\xcd\x7c\xd8\x95\x54\xf8\x5e\x27\xa7\xc6".to_vec(), out);
+    }
+}
@@ -0,0 +1,278 @@
+//! Heuristics for deciding how much a buffer looks like "real" data.
+//!
+//! These are used by the detection code to rank guesses (like XOR keys), so
+//! they're deliberately cheap and approximate.
+
+use crate::transformation::Transformation;
+
+/// Don't bother guessing how a buffer was encoded if it's shorter than this.
+pub(crate) const DETECT_MIN_LENGTH: usize = 32;
+
+/// Each byte of a key needs at least this many samples before we trust a
+/// guess (otherwise, it's too easy to fit a key to random data).
+pub(crate) const DETECT_MIN_SAMPLES: usize = 16;
+
+/// Only look at the start of the buffer when scoring guesses, to keep
+/// detection fast on big buffers (must be a multiple of 8).
+pub(crate) const DETECT_SAMPLE_SIZE: usize = 4096;
+
+/// How confident a guess needs to be before `detect()` suggests it.
+pub(crate) const DETECT_MIN_CONFIDENCE: f64 = 0.75;
+
+/// How much better a guess has to look than the original buffer before
+/// `detect()` suggests it (so we don't offer to decode plain text).
+pub(crate) const DETECT_MIN_IMPROVEMENT: f64 = 0.2;
+
+/// The most guesses that a single `detect()` will suggest.
+pub(crate) const DETECT_MAX_RESULTS: usize = 4;
+
+/// How much of a buffer has to be printable for it to count as text (see
+/// [`looks_like_text`]).
+pub(crate) const TEXT_MIN_PRINTABLE: f64 = 0.95;
+
+/// How much of a buffer has to be letters, digits, or whitespace for it to
+/// count as text; text that's been XORed into printable characters is mostly
+/// punctuation.
+pub(crate) const TEXT_MIN_ALPHANUMERIC: f64 = 0.8;
+
+/// File signatures that are worth recognizing, with a friendly name.
+///
+/// These are only checked at the very start of a buffer.
+pub const KNOWN_MAGIC: [(&str, &[u8]); 16] = [
+    ("Windows executable (MZ)",  b"MZ"),
+    ("ZIP archive",              b"PK\x03\x04"),
+    ("ELF executable",           b"\x7fELF"),
+    ("PDF document",             b"%PDF-"),
+    ("PNG image",                b"\x89PNG\r\n\x1a\n"),
+    ("GIF image",                b"GIF8"),
+    ("JPEG image",               b"\xff\xd8\xff"),
+    ("Java class",               b"\xca\xfe\xba\xbe"),
+    ("Mach-O executable (32)",   b"\xce\xfa\xed\xfe"),
+    ("Mach-O executable (64)",   b"\xcf\xfa\xed\xfe"),
+    ("Gzip data",                b"\x1f\x8b\x08"),
+    ("Bzip2 data",               b"BZh"),
+    ("XZ data",                  b"\xfd7zXZ\x00"),
+    ("7-Zip archive",            b"7z\xbc\xaf\x27\x1c"),
+    ("RAR archive",              b"Rar!\x1a\x07"),
+    ("XML document",             b"<?xml"),
+];
+
+/// Is the byte something you'd expect to see in a text file?
+pub fn is_printable(b: u8) -> bool {
+    (0x20..=0x7e).contains(&b) || b == b'\t' || b == b'\r' || b == b'\n'
+}
+
+/// The fraction (0.0 - 1.0) of the buffer that's printable text.
+pub fn printable_ratio(buffer: &[u8]) -> f64 {
+    if buffer.is_empty() {
+        return 0.0;
+    }
+
+    buffer.iter().filter(|b| is_printable(**b)).count() as f64 / buffer.len() as f64
+}
+
+/// Is the buffer (almost) entirely printable text, made up mostly of letters,
+/// digits, and whitespace?
+///
+/// Transformations that change each byte, like XOR, aren't worth suggesting
+/// for text - they'd only turn it into slightly different text.
+pub fn looks_like_text(buffer: &[u8]) -> bool {
+    if buffer.is_empty() || printable_ratio(buffer) < TEXT_MIN_PRINTABLE {
+        return false;
+    }
+
+    let alphanumeric = buffer.iter().filter(|b| b.is_ascii_alphanumeric() || b.is_ascii_whitespace()).count();
+    alphanumeric as f64 / buffer.len() as f64 >= TEXT_MIN_ALPHANUMERIC
+}
+
+/// The fraction (0.0 - 1.0) of the buffer that's NUL bytes.
+pub fn zero_ratio(buffer: &[u8]) -> f64 {
+    if buffer.is_empty() {
+        return 0.0;
+    }
+
+    buffer.iter().filter(|b| **b == 0).count() as f64 / buffer.len() as f64
+}
+
+/// If the buffer starts with a recognized file signature, return its name.
+pub fn known_magic(buffer: &[u8]) -> Option<&'static str> {
+    KNOWN_MAGIC.iter().find(|(_, magic)| buffer.starts_with(magic)).map(|(name, _)| *name)
+}
+
+/// The score we give a buffer that starts with a known file signature.
+pub const MAGIC_SCORE: f64 = 0.95;
+
+/// Score (0.0 - 1.0) how much a buffer looks like it's been successfully
+/// decoded, ignoring any file signature.
+///
+/// Text scores by how printable it is, and how much it's shaped like normal
+/// text (mostly letters, with regular spaces). Binary data scores by how many
+/// NUL bytes it has (since most binary formats are full of them).
+pub fn content_score(buffer: &[u8]) -> f64 {
+    if buffer.is_empty() {
+        return 0.0;
+    }
+
+    let ratio = |f: fn(&u8) -> bool| buffer.iter().filter(|b| f(b)).count() as f64 / buffer.len() as f64;
+
+    let printable = printable_ratio(buffer);
+    let letters   = ratio(|b| b.is_ascii_alphabetic());
+    let lowercase = ratio(|b| b.is_ascii_lowercase());
+    let spaces    = ratio(|b| *b == b' ');
+
+    // Normal text is mostly letters, with a space every handful of characters
+    let mut shape = (letters / 0.5).min(1.0) * (spaces / 0.03).min(1.0);
+    if spaces > 0.3 {
+        shape *= ((0.6 - spaces) / 0.3).max(0.0);
+    }
+
+    // Squaring the printable ratio punishes "mostly printable" data, which
+    // random bytes can get surprisingly close to; lowercase letters get a
+    // small bonus, since they're more common than uppercase
+    let text = printable * printable * (0.6 + 0.35 * shape + 0.05 * lowercase);
+
+    // Executables and the like are typically 20% - 50% NULs; this curve
+    // climbs quickly through that range, but never lets binary data outscore
+    // good text
+    let binary = 0.85 * (1.0 - (-8.0 * zero_ratio(buffer)).exp());
+
+    text.max(binary)
+}
+
+/// Score (0.0 - 1.0) how much a buffer looks like it's been successfully
+/// decoded.
+///
+/// Recognized file signatures score highly; otherwise, this is the same as
+/// [`content_score`].
+pub fn plaintext_score(buffer: &[u8]) -> f64 {
+    match known_magic(buffer) {
+        Some(_) => MAGIC_SCORE.max(content_score(buffer)),
+        None    => content_score(buffer),
+    }
+}
+
+/// The Shannon entropy of the buffer, in bits per byte (0.0 - 8.0).
+///
+/// Text is typically around 4 - 5, and compressed or encrypted data is close
+/// to 8.
+pub fn entropy(buffer: &[u8]) -> f64 {
+    if buffer.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0u64; 256];
+    for b in buffer {
+        counts[*b as usize] += 1;
+    }
+
+    let n = buffer.len() as f64;
+    counts.iter().filter(|c| **c > 0).map(|c| {
+        let p = *c as f64 / n;
+        -p * p.log2()
+    }).sum()
+}
+
+/// The index of coincidence - the probability that two bytes picked at random
+/// from the buffer are the same.
+///
+/// Random data scores about 1/256; text and most file formats score much
+/// higher.
+pub fn index_of_coincidence(buffer: &[u8]) -> f64 {
+    if buffer.len() < 2 {
+        return 0.0;
+    }
+
+    let mut counts = [0u64; 256];
+    for b in buffer {
+        counts[*b as usize] += 1;
+    }
+
+    let matches: u64 = counts.iter().map(|c| c * c.saturating_sub(1)).sum();
+    let n = buffer.len() as u64;
+
+    matches as f64 / (n * (n - 1)) as f64
+}
+
+/// The number of bits that differ between two buffers (only the overlapping
+/// part is compared).
+pub fn hamming_distance(a: &[u8], b: &[u8]) -> u64 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x ^ y).count_ones() as u64).sum()
+}
+
+/// Try each of the candidates on the start of the buffer, and return the one
+/// that makes it look the most like real data, along with its score.
+///
+/// This is for the simple transformations that change each byte (or element)
+/// on its own, where trying every possibility is cheap. Nothing is returned
+/// if the buffer is too short to guess from, or if the best candidate isn't
+/// confident enough or much of an improvement on the original.
+pub(crate) fn best_single_byte_candidate(buffer: &[u8], candidates: impl IntoIterator<Item = Transformation>) -> Option<(Transformation, f64)> {
+    if buffer.len() < DETECT_MIN_LENGTH {
+        return None;
+    }
+
+    let sample = buffer[..buffer.len().min(DETECT_SAMPLE_SIZE)].to_vec();
+    let baseline = content_score(&sample);
+
+    let mut best: Option<(Transformation, f64)> = None;
+    for t in candidates {
+        let score = match t.transform(&sample) {
+            Ok(result) => content_score(&result),
+            Err(_) => continue,
+        };
+
+        if best.as_ref().is_none_or(|(_, best_score)| score > *best_score) {
+            best = Some((t, score));
+        }
+    }
+
+    best.filter(|(_, score)| *score >= DETECT_MIN_CONFIDENCE && *score >= baseline + DETECT_MIN_IMPROVEMENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_scoring() {
+        assert_eq!(1.0, printable_ratio(b"Hello world!\n"));
+        assert_eq!(0.5, printable_ratio(b"AB\x00\xff"));
+        assert_eq!(0.0, printable_ratio(b""));
+
+        assert_eq!(0.25, zero_ratio(b"AB\x00\xff"));
+
+        assert!(looks_like_text(b"1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"));
+        assert!(looks_like_text(b"Hello, world! This is text."));
+        assert!(!looks_like_text(b"\x8b\xa6\xaf\xaf\xac"));
+        assert!(!looks_like_text(b".23)z3)z.?\".")); // "this is text" ^ 0x5a
+        assert!(!looks_like_text(b""));
+
+        assert_eq!(Some("Windows executable (MZ)"), known_magic(b"MZ\x90\x00"));
+        assert_eq!(Some("ELF executable"), known_magic(b"\x7fELF\x02\x01"));
+        assert_eq!(None, known_magic(b"ZM"));
+
+        assert_eq!(0.0, entropy(b"AAAA"));
+        assert_eq!(1.0, entropy(b"ABAB"));
+        assert_eq!(8.0, entropy(&(0..=255u8).collect::<Vec<u8>>()));
+
+        assert_eq!(1.0, index_of_coincidence(b"AAAA"));
+        assert_eq!(0.0, index_of_coincidence(b"ABCD"));
+        assert_eq!(1.0 / 3.0, index_of_coincidence(b"AABB"));
+
+        assert_eq!(0, hamming_distance(b"abc", b"abc"));
+        assert_eq!(37, hamming_distance(b"this is a test", b"wokka wokka!!!"));
+
+        // Text beats binary beats garbage
+        let text = plaintext_score(b"this is some text that looks normal");
+        let binary = plaintext_score(b"\x01\x00\x00\x00\x02\x00\x00\x00\x10\x00\x00\x00");
+        let garbage = plaintext_score(b"\x8e\xf1\x93\x02\xc4\xaa\x17\xd9\xfe\x80\x5c\x31");
+
+        assert!(text > binary);
+        assert!(binary > garbage);
+
+        // A file signature counts for a lot, unless we ignore it
+        assert!(plaintext_score(b"MZ\x90\x00\xff\xff\xff\xff") > binary);
+        assert!(content_score(b"MZ\x90\x00\xff\xff\xff\xff") < binary);
+    }
+}
@@ -0,0 +1,156 @@
+//! An implementation of the Spritz stream cipher (Rivest and Schuldt's
+//! RC4 redesign).
+//!
+//! There's no Spritz crate, and it's a simple cipher, so it lives here.
+
+use crate::transformation::helpers::keystream::Keystream;
+
+const N: usize = 256;
+
+#[derive(Clone)]
+pub(crate) struct Spritz {
+    i: u8,
+    j: u8,
+    k: u8,
+    z: u8,
+    a: u8,
+    w: u8,
+    s: [u8; N],
+}
+
+impl Spritz {
+    /// Set up the cipher with a key and (optional) IV, the same way as the
+    /// paper's `KeySetup` and `EncryptWithIV`.
+    pub(crate) fn new(key: &[u8], iv: Option<&[u8]>) -> Self {
+        let mut s = [0u8; N];
+        for (v, b) in s.iter_mut().enumerate() {
+            *b = v as u8;
+        }
+
+        let mut cipher = Self {
+            i: 0,
+            j: 0,
+            k: 0,
+            z: 0,
+            a: 0,
+            w: 1,
+            s,
+        };
+
+        cipher.absorb(key);
+
+        if let Some(iv) = iv {
+            cipher.absorb_stop();
+            cipher.absorb(iv);
+        }
+
+        cipher
+    }
+
+    fn s(&self, index: u8) -> u8 {
+        self.s[index as usize]
+    }
+
+    fn absorb(&mut self, data: &[u8]) {
+        for b in data {
+            self.absorb_nibble(b & 0x0f);
+            self.absorb_nibble(b >> 4);
+        }
+    }
+
+    fn absorb_nibble(&mut self, x: u8) {
+        if self.a as usize == N / 2 {
+            self.shuffle();
+        }
+
+        self.s.swap(self.a as usize, N / 2 + x as usize);
+        self.a = self.a.wrapping_add(1);
+    }
+
+    fn absorb_stop(&mut self) {
+        if self.a as usize == N / 2 {
+            self.shuffle();
+        }
+
+        self.a = self.a.wrapping_add(1);
+    }
+
+    fn shuffle(&mut self) {
+        self.whip(2 * N);
+        self.crush();
+        self.whip(2 * N);
+        self.crush();
+        self.whip(2 * N);
+        self.a = 0;
+    }
+
+    fn whip(&mut self, r: usize) {
+        for _ in 0..r {
+            self.update();
+        }
+
+        self.w = self.w.wrapping_add(2);
+    }
+
+    fn crush(&mut self) {
+        for v in 0..(N / 2) {
+            if self.s[v] > self.s[N - 1 - v] {
+                self.s.swap(v, N - 1 - v);
+            }
+        }
+    }
+
+    fn update(&mut self) {
+        self.i = self.i.wrapping_add(self.w);
+        self.j = self.k.wrapping_add(self.s(self.j.wrapping_add(self.s(self.i))));
+        self.k = self.i.wrapping_add(self.k).wrapping_add(self.s(self.j));
+        self.s.swap(self.i as usize, self.j as usize);
+    }
+
+    fn output(&mut self) -> u8 {
+        self.z = self.s(self.j.wrapping_add(self.s(self.i.wrapping_add(self.s(self.z.wrapping_add(self.k))))));
+        self.z
+    }
+
+    fn drip(&mut self) -> u8 {
+        if self.a > 0 {
+            self.shuffle();
+        }
+
+        self.update();
+        self.output()
+    }
+}
+
+impl Keystream for Spritz {
+    fn fill(&mut self, out: &mut [u8]) {
+        for b in out.iter_mut() {
+            *b = self.drip();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_spritz() {
+        // Test vectors from the paper (the first bytes of output after
+        // absorbing each string)
+        let tests: Vec<(&[u8], Vec<u8>)> = vec![
+            (b"ABC",     b"\x77\x9a\x8e\x01\xf9\xe9\xcb\xc0".to_vec()),
+            (b"spam",    b"\xf0\x60\x9a\x1d\xf1\x43\xce\xbf".to_vec()),
+            (b"arcfour", b"\x1a\xfa\x8b\x5e\xe3\x37\xdb\xc7".to_vec()),
+        ];
+
+        for (key, expected) in tests {
+            let mut cipher = Spritz::new(key, None);
+
+            let mut out = vec![0; 8];
+            cipher.fill(&mut out);
+            assert_eq!(expected, out);
+        }
+    }
+}
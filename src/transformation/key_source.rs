@@ -0,0 +1,346 @@
+use serde::{Serialize, Deserialize};
+use simple_error::{SimpleResult, bail};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformBlockCipher, TransformStreamCipher, TransformSubstitution};
+use crate::transformation::{BlockCipherType, BlockCipherMode, BlockCipherPadding, StreamCipherType};
+
+/// Where a cipher's key, IV, or nonce comes from.
+///
+/// Lots of formats store the IV (or even the key) alongside the data, so
+/// rather than copying the bytes out by hand, a [`CipherTemplate`] can point
+/// at them. The sources are saved in the resulting [`Transformation`], so it's
+/// always clear where the key came from.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum KeySource {
+    /// The bytes themselves.
+    Literal(Vec<u8>),
+
+    /// The first bytes of the data being transformed.
+    ///
+    /// Those bytes aren't part of the ciphertext - they're removed before
+    /// decrypting, and put back after encrypting. If both the key and the IV
+    /// are prefixes, the key comes first.
+    Prefix(usize),
+
+    /// A range of bytes from a buffer in the project.
+    Buffer {
+        buffer: String,
+        offset: usize,
+        length: usize,
+    },
+
+    /// The bytes that make up an entry in the project.
+    Entry {
+        buffer: String,
+        layer: String,
+        offset: usize,
+    },
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl KeySource {
+    /// Does this source read from the given buffer?
+    pub fn references_buffer(&self, name: &str) -> bool {
+        match self {
+            Self::Buffer { buffer, .. } => buffer == name,
+            Self::Entry { buffer, .. }  => buffer == name,
+            _ => false,
+        }
+    }
+}
+
+/// The sources for a cipher's key and IV.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CipherSources {
+    pub key: KeySource,
+    pub iv: Option<KeySource>,
+}
+
+impl CipherSources {
+    /// Does either source read from the given buffer?
+    pub fn references_buffer(&self, name: &str) -> bool {
+        self.key.references_buffer(name) || self.iv.as_ref().is_some_and(|iv| iv.references_buffer(name))
+    }
+
+    /// Get the bytes for the key and IV.
+    ///
+    /// `data` is the data being transformed (for [`KeySource::Prefix`]), and
+    /// `lookup` finds the bytes for sources that refer to the project.
+    pub fn resolve<F>(&self, data: &[u8], lookup: F) -> SimpleResult<(Vec<u8>, Option<Vec<u8>>)>
+    where F: Fn(&KeySource) -> SimpleResult<Vec<u8>>
+    {
+        // Prefixes are read one after the other
+        let mut position: usize = 0;
+
+        let mut read = |source: &KeySource| -> SimpleResult<Vec<u8>> {
+            match source {
+                KeySource::Literal(bytes) => Ok(bytes.clone()),
+                KeySource::Prefix(length) => {
+                    let end = match position.checked_add(*length) {
+                        Some(end) if end <= data.len() => end,
+                        _ => bail!("Buffer is too short to read a {}-byte prefix", length),
+                    };
+
+                    let prefix = data[position..end].to_vec();
+                    position = end;
+
+                    Ok(prefix)
+                },
+                _ => lookup(source),
+            }
+        };
+
+        let key = read(&self.key)?;
+        let iv = match &self.iv {
+            Some(iv) => Some(read(iv)?),
+            None => None,
+        };
+
+        Ok((key, iv))
+    }
+
+    /// The bytes that come before the ciphertext - the key and IV, if they're
+    /// [`KeySource::Prefix`] sources.
+    pub(crate) fn prefix(&self, key: &[u8], iv: Option<&[u8]>) -> Vec<u8> {
+        let mut prefix = vec![];
+
+        if let KeySource::Prefix(_) = self.key {
+            prefix.extend(key);
+        }
+
+        if let (Some(KeySource::Prefix(_)), Some(iv)) = (&self.iv, iv) {
+            prefix.extend(iv);
+        }
+
+        prefix
+    }
+}
+
+/// A cipher whose key and IV haven't been read yet.
+///
+/// This is the same as the cipher transformations, except that the key and IV
+/// are [`KeySource`]s instead of bytes. A substitution table (S-box) counts as
+/// a key, too. Pass it to
+/// [`crate::actions::ActionBufferTransform::new_from_template`], which reads
+/// them out of the project when it's applied.
+///
+/// # Example
+///
+/// ```
+/// use libh2gb::transformation::*;
+///
+/// // The IV is the first 16 bytes, and the ciphertext is the rest
+/// let template = CipherTemplate::block(
+///     BlockCipherType::AES,
+///     BlockCipherMode::CBC,
+///     BlockCipherPadding::Pkcs7,
+///     KeySource::Literal(b"AAAAAAAAAAAAAAAA".to_vec()),
+///     Some(KeySource::Prefix(16)),
+/// );
+///
+/// let data = b"BBBBBBBBBBBBBBBB\x9c\xf2\x65\x82\xa2\xa7\x8b\x65\xcb\x41\xbc\x2d\x02\x1a\xe4\x18\xaf\xf4\xbc\x9e\xf4\x0c\x8a\x26\xc4\x97\x22\x26\x3e\xc2\x34\x95".to_vec();
+///
+/// // Nothing refers to the project, so there's nothing to look up
+/// let transformation = template.resolve(&data, |_| panic!()).unwrap();
+/// assert_eq!(b"Hello example section!".to_vec(), transformation.transform(&data).unwrap());
+/// ```
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CipherTemplate {
+    Block {
+        cipher: BlockCipherType,
+        mode: BlockCipherMode,
+        padding: BlockCipherPadding,
+        sources: CipherSources,
+    },
+    Stream {
+        cipher: StreamCipherType,
+        sources: CipherSources,
+        offset: u64,
+    },
+    Substitution {
+        sources: CipherSources,
+    },
+}
+
+impl fmt::Display for CipherTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl CipherTemplate {
+    /// A template for a [`TransformBlockCipher`].
+    pub fn block(cipher: BlockCipherType, mode: BlockCipherMode, padding: BlockCipherPadding, key: KeySource, iv: Option<KeySource>) -> Self {
+        Self::Block {
+            cipher,
+            mode,
+            padding,
+            sources: CipherSources {
+                key,
+                iv,
+            },
+        }
+    }
+
+    /// A template for a [`TransformStreamCipher`].
+    ///
+    /// For [`StreamCipherType::Keystream`], the key is the keystream - this
+    /// is how to XOR one buffer with another.
+    pub fn stream(cipher: StreamCipherType, key: KeySource, iv: Option<KeySource>) -> Self {
+        Self::stream_with_offset(cipher, key, iv, 0)
+    }
+
+    /// A template for a [`TransformStreamCipher`], where the data starts
+    /// `offset` bytes into the keystream.
+    pub fn stream_with_offset(cipher: StreamCipherType, key: KeySource, iv: Option<KeySource>, offset: u64) -> Self {
+        Self::Stream {
+            cipher,
+            sources: CipherSources {
+                key,
+                iv,
+            },
+            offset,
+        }
+    }
+
+    /// A template for a [`TransformSubstitution`], where the 256-byte table
+    /// is the key.
+    pub fn substitution(table: KeySource) -> Self {
+        Self::Substitution {
+            sources: CipherSources {
+                key: table,
+                iv: None,
+            },
+        }
+    }
+
+    pub fn sources(&self) -> &CipherSources {
+        match self {
+            Self::Block { sources, .. }  => sources,
+            Self::Stream { sources, .. } => sources,
+            Self::Substitution { sources } => sources,
+        }
+    }
+
+    /// Read the key and IV, and create the [`Transformation`].
+    ///
+    /// `data` is the data that's going to be transformed, and `lookup` finds
+    /// the bytes for [`KeySource::Buffer`] and [`KeySource::Entry`] (see
+    /// [`crate::project::H2Project::key_source_read`]).
+    pub fn resolve<F>(&self, data: &[u8], lookup: F) -> SimpleResult<Transformation>
+    where F: Fn(&KeySource) -> SimpleResult<Vec<u8>>
+    {
+        let (key, iv) = self.sources().resolve(data, lookup)?;
+
+        match self {
+            Self::Block { cipher, mode, padding, sources } => {
+                TransformBlockCipher::new_with_sources(*cipher, *mode, *padding, key, iv, sources.clone())
+            },
+            Self::Stream { cipher, sources, offset } => {
+                TransformStreamCipher::new_with_sources(*cipher, key, iv, *offset, sources.clone())
+            },
+            Self::Substitution { sources } => {
+                TransformSubstitution::new_with_sources(key, sources.clone())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_resolve() -> SimpleResult<()> {
+        let data = b"KEYIVciphertext".to_vec();
+
+        let lookup = |source: &KeySource| -> SimpleResult<Vec<u8>> {
+            match source {
+                KeySource::Buffer { buffer, offset, length } => Ok(format!("{}:{}:{}", buffer, offset, length).into_bytes()),
+                _ => bail!("Unexpected source"),
+            }
+        };
+
+        // Prefixes are read in order
+        let sources = CipherSources { key: KeySource::Prefix(3), iv: Some(KeySource::Prefix(2)) };
+        assert_eq!((b"KEY".to_vec(), Some(b"IV".to_vec())), sources.resolve(&data, lookup)?);
+        assert_eq!(b"KEYIV".to_vec(), sources.prefix(b"KEY", Some(b"IV")));
+
+        // Mixed sources
+        let sources = CipherSources {
+            key: KeySource::Buffer { buffer: "other".to_string(), offset: 1, length: 2 },
+            iv: Some(KeySource::Prefix(3)),
+        };
+        assert_eq!((b"other:1:2".to_vec(), Some(b"KEY".to_vec())), sources.resolve(&data, lookup)?);
+        assert_eq!(b"KEY".to_vec(), sources.prefix(b"other:1:2", Some(b"KEY")));
+        assert!(sources.references_buffer("other"));
+        assert!(!sources.references_buffer("data"));
+
+        // Literals don't add a prefix
+        let sources = CipherSources { key: KeySource::Literal(b"key".to_vec()), iv: None };
+        assert_eq!((b"key".to_vec(), None), sources.resolve(&data, lookup)?);
+        assert_eq!(Vec::<u8>::new(), sources.prefix(b"key", None));
+
+        // Too long
+        let sources = CipherSources { key: KeySource::Prefix(10), iv: Some(KeySource::Prefix(10)) };
+        assert!(sources.resolve(&data, lookup).is_err());
+
+        // Way too long
+        let sources = CipherSources { key: KeySource::Prefix(3), iv: Some(KeySource::Prefix(usize::MAX)) };
+        assert!(sources.resolve(&data, lookup).is_err());
+
+        // Failed lookups fail
+        let sources = CipherSources { key: KeySource::Entry { buffer: "b".to_string(), layer: "l".to_string(), offset: 0 }, iv: None };
+        assert!(sources.resolve(&data, lookup).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_template() -> SimpleResult<()> {
+        // Salsa20 with the nonce in front
+        let template = CipherTemplate::stream(
+            StreamCipherType::Salsa20,
+            KeySource::Literal(b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec()),
+            Some(KeySource::Prefix(8)),
+        );
+
+        let data = b"BBBBBBBB\x96\xb9\x31\xa2\x3b\xff\x65\x11\xe4\xba\x52\x79\xb1\xaa\x57".to_vec();
+        let transformation = template.resolve(&data, |_| bail!("No lookups"))?;
+
+        let result = transformation.transform(&data)?;
+        assert_eq!(b"Testing Salsa20".to_vec(), result);
+        assert_eq!(data, transformation.untransform(&result)?);
+
+        // A different nonce doesn't match
+        assert!(transformation.transform(&b"CCCCCCCC\x96\xb9\x31\xa2".to_vec()).is_err());
+
+        // Invalid key lengths are still caught
+        let template = CipherTemplate::block(
+            BlockCipherType::AES,
+            BlockCipherMode::ECB,
+            BlockCipherPadding::Pkcs7,
+            KeySource::Prefix(4),
+            None,
+        );
+        assert!(template.resolve(&data, |_| bail!("No lookups")).is_err());
+
+        // A substitution table from another buffer
+        let template = CipherTemplate::substitution(KeySource::Buffer { buffer: "sbox".to_string(), offset: 0, length: 256 });
+        let transformation = template.resolve(&b"\x00\x01".to_vec(), |_| Ok((0..=255u8).rev().collect()))?;
+        assert_eq!(b"\xff\xfe".to_vec(), transformation.transform(&b"\x00\x01".to_vec())?);
+        assert!(transformation.references_buffer("sbox"));
+
+        // The table has to be the right size
+        assert!(template.resolve(&b"\x00\x01".to_vec(), |_| Ok(vec![0; 16])).is_err());
+
+        Ok(())
+    }
+}
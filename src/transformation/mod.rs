@@ -52,11 +52,20 @@ pub use transformer_trait::TransformerTrait;
 mod helpers;
 pub use helpers::*;
 
+mod ranked_transformation;
+pub use ranked_transformation::RankedTransformation;
+
+mod ranked_chain;
+pub use ranked_chain::{RankedChain, DetectionLimits};
+
+mod key_source;
+pub use key_source::{KeySource, CipherSources, CipherTemplate};
+
 /// Which transformation to perform.
 ///
 /// In general, don't create this enum directly - use the initializer methods
 /// from the different transformations, which returns this enum.
-#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Transformation {
     /// No transformation - simply returns the same value. Mostly here for
     /// testing.
@@ -82,6 +91,11 @@ pub enum Transformation {
     /// Xor each byte / word / dword / qword by a constant. Operates on eight,
     /// 16, 32, or 64-bit chunks.
     ///
+    /// [`Transformation::detect`] will try to recover the key, using frequency
+    /// analysis, known plaintext, and how readable the result is. Use
+    /// [`TransformXorByConstant::find_keys`] to get every guess along with its
+    /// confidence.
+    ///
     /// # Examples
     ///
     /// ## Eight bit
@@ -124,6 +138,165 @@ pub enum Transformation {
     /// ```
     XorByConstant(TransformXorByConstant),
 
+    /// Xor (or add / subtract) the buffer with a key of any length, which
+    /// repeats as needed. The key can also roll - incrementing after each
+    /// byte, or chaining with the previous byte - depending on the
+    /// [`XorKeyMode`].
+    ///
+    /// [`Transformation::detect`] will try to figure out the key length
+    /// (using the index of coincidence and Hamming distance) and then the key
+    /// itself. Use [`TransformXorByKey::find_keys`] to get every guess along
+    /// with its confidence.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformXorByKey;
+    ///
+    /// // Input: "Hello!" XORed with "key"
+    /// let i: Vec<u8> = b"\x23\x00\x15\x07\x0a\x58".to_vec();
+    ///
+    /// // Output: "Hello!"
+    /// let t = TransformXorByKey::repeating(b"key".to_vec()).unwrap();
+    /// let o = t.transform(&i).unwrap();
+    /// assert_eq!(b"Hello!".to_vec(), o);
+    ///
+    /// // And back
+    /// assert_eq!(i, t.untransform(&o).unwrap());
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// The key can't be empty.
+    XorByKey(TransformXorByKey),
+
+    /// Add or subtract a constant to each byte / word / dword / qword. Like
+    /// [`Transformation::XorByConstant`], the size comes from the
+    /// [`XorSettings`]; multi-byte elements also need an [`Endian`] so the
+    /// carry goes the right way.
+    ///
+    /// The constructors describe how the data was encoded - transforming
+    /// data that had a constant added will subtract it.
+    ///
+    /// [`Transformation::detect`] tries every single-byte constant, and
+    /// suggests the one that makes the buffer the most readable.
+    ///
+    /// [`Endian`]: crate::generic_number::Endian
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::{TransformAddConstant, XorSettings};
+    /// use libh2gb::generic_number::Endian;
+    ///
+    /// // Input: "Hello" with 1 added to each byte
+    /// let i: Vec<u8> = b"Ifmmp".to_vec();
+    ///
+    /// // Output: "Hello"
+    /// let o = TransformAddConstant::add(XorSettings::EightBit(1), Endian::Big).transform(&i);
+    /// assert_eq!(Ok(b"Hello".to_vec()), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// The size of the input buffer must be a multiple of the element size.
+    AddConstant(TransformAddConstant),
+
+    /// Rotate the bits in each byte / word / dword / qword. The
+    /// [`XorSettings`] gives the size of each element, and its value is the
+    /// number of bits to rotate by.
+    ///
+    /// The constructors describe how the data was encoded - transforming
+    /// data that was rotated left will rotate it right.
+    ///
+    /// [`Transformation::detect`] tries rotating each byte by every amount,
+    /// and suggests the one that makes the buffer the most readable.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::{TransformRotate, XorSettings};
+    /// use libh2gb::generic_number::Endian;
+    ///
+    /// // Input: "\x12\x34" rotated left by four bits, as a 16-bit word
+    /// let i: Vec<u8> = b"\x23\x41".to_vec();
+    ///
+    /// // Output: "\x12\x34"
+    /// let o = TransformRotate::left(XorSettings::SixteenBit(4), Endian::Big).unwrap().transform(&i);
+    /// assert_eq!(Ok(b"\x12\x34".to_vec()), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// The rotation must be less than the size of the element (in bits), and
+    /// the size of the input buffer must be a multiple of the element size.
+    Rotate(TransformRotate),
+
+    /// Simple bitwise operations - NOT, swapping nibbles, reversing the bits,
+    /// or swapping the byte order. See [`BitwiseOperation`] for the full
+    /// list. Each of them undoes itself.
+    ///
+    /// [`Transformation::detect`] tries the ones that change each byte, and
+    /// suggests any that make the buffer readable.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBitwise;
+    ///
+    /// // Input: "\x12\x34\x56\x78"
+    /// let i: Vec<u8> = b"\x12\x34\x56\x78".to_vec();
+    ///
+    /// // Output: "\x78\x56\x34\x12" (a 32-bit endian swap)
+    /// let o = TransformBitwise::byte_swap(4).unwrap().transform(&i);
+    /// assert_eq!(Ok(b"\x78\x56\x34\x12".to_vec()), o);
+    ///
+    /// // Swap the nibbles in each byte
+    /// let o = TransformBitwise::nibble_swap().transform(&i);
+    /// assert_eq!(Ok(b"\x21\x43\x65\x87".to_vec()), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// The size of the input buffer must be a multiple of the element size.
+    Bitwise(TransformBitwise),
+
+    /// Replace each byte using a 256-byte substitution table (an S-box) -
+    /// byte `b` becomes `table[b]`.
+    ///
+    /// If the table is a permutation (every value appears once), the inverse
+    /// table is worked out automatically and the transformation is two-way.
+    ///
+    /// The table is often stored somewhere else in the binary; use
+    /// [`CipherTemplate::substitution`] to read it from another buffer or an
+    /// entry when the transformation is applied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformSubstitution;
+    ///
+    /// // A table that reverses the byte values
+    /// let table: Vec<u8> = (0..=255u8).rev().collect();
+    /// let t = TransformSubstitution::new(table).unwrap();
+    ///
+    /// // Input: "\x00\x01\x02"
+    /// let i: Vec<u8> = b"\x00\x01\x02".to_vec();
+    ///
+    /// // Output: "\xff\xfe\xfd"
+    /// let o = t.transform(&i).unwrap();
+    /// assert_eq!(b"\xff\xfe\xfd".to_vec(), o);
+    ///
+    /// // And back
+    /// assert_eq!(i, t.untransform(&o).unwrap());
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// The table must be exactly 256 bytes. Tables that aren't permutations
+    /// can't be untransformed.
+    Substitution(TransformSubstitution),
+
     /// Base64 decode the buffer.
     ///
     /// [`TransformBase64`] has a number of constructors to configure the
@@ -260,21 +433,365 @@ pub enum Transformation {
     /// ```
     FromBase32(TransformBase32),
 
-    /// Convert from Zlib "Deflated" format with or without a header. Uses the
-    /// [inflate](https://github.com/image-rs/inflate) library.
+    /// Decode Base85, in one of its flavours (see [`Base85Format`]): Ascii85
+    /// (with or without Adobe's `<~` `~>` wrapper), ZeroMQ's Z85, or RFC 1924.
+    ///
+    /// Like Base32, the data must encode back to the same length, so things
+    /// like whitespace aren't allowed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBase85;
+    ///
+    /// // Input: "<~87cURD_*#TDfTZ)~>"
+    /// let i: Vec<u8> = b"<~87cURD_*#TDfTZ)~>".to_vec();
+    ///
+    /// // Output: "Hello, world"
+    /// let o = TransformBase85::ascii85_adobe().transform(&i).unwrap();
+    ///
+    /// assert_eq!(b"Hello, world".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Z85 must be a multiple of five characters, and can only encode a
+    /// multiple of four bytes.
+    FromBase85(TransformBase85),
+
+    /// Decode Base58, with Bitcoin's alphabet. Optionally, verify and remove
+    /// the four-byte Base58Check checksum.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBase58;
+    ///
+    /// // Input: "2NEpo7TZRRrLZSi2U"
+    /// let i: Vec<u8> = b"2NEpo7TZRRrLZSi2U".to_vec();
+    ///
+    /// // Output: "Hello World!"
+    /// let o = TransformBase58::standard().transform(&i).unwrap();
+    ///
+    /// assert_eq!(b"Hello World!".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// With [`TransformBase58::check`], the checksum must be correct.
+    FromBase58(TransformBase58),
+
+    /// Decode Base45 (RFC 9285), which is mostly seen in QR codes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBase45;
+    ///
+    /// // Input: "%69 VD92EX0"
+    /// let i: Vec<u8> = b"%69 VD92EX0".to_vec();
+    ///
+    /// // Output: "Hello!!"
+    /// let o = TransformBase45::new().transform(&i).unwrap();
+    ///
+    /// assert_eq!(b"Hello!!".to_vec(), o);
+    /// ```
+    FromBase45(TransformBase45),
+
+    /// Decode uuencoded data, with or without the `begin` / `end` lines.
+    ///
+    /// Untransforming produces canonical uuencode (45-byte lines and '`' for
+    /// zero), or the same layout as some existing data with
+    /// [`TransformUuencode::new_matching`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformUuencode;
+    ///
+    /// let i: Vec<u8> = b"begin 644 hello.txt\n,2&5L;&\\L('=O<FQD\n`\nend\n".to_vec();
+    ///
+    /// // Output: "Hello, world"
+    /// let o = TransformUuencode::new().transform(&i).unwrap();
+    ///
+    /// assert_eq!(b"Hello, world".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Each line's length must match the number of characters on it.
+    FromUuencode(TransformUuencode),
+
+    /// Decode yEnc data, including the `=ybegin` and `=yend` lines (and
+    /// `=ypart` for multipart data). The size and checksum are verified, if
+    /// they're there.
+    ///
+    /// This is a ONE-WAY transformation!
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformYenc;
+    ///
+    /// let i: Vec<u8> = b"=ybegin line=128 size=5 name=a.txt\r\nr\x8f\x96\x96\x99\r\n=yend size=5\r\n".to_vec();
+    ///
+    /// // Output: "Hello"
+    /// let o = TransformYenc::new().transform(&i).unwrap();
+    ///
+    /// assert_eq!(b"Hello".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Must start with a `=ybegin` line, and end with a `=yend` line.
+    FromYenc(TransformYenc),
+
+    /// Decode quoted-printable, as seen in email. Soft line breaks are
+    /// removed, and other line endings are left alone.
     ///
     /// This is a ONE-WAY transformation!
     ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformQuotedPrintable;
+    ///
+    /// // Input: "Caf=C3=A9 =3D=\n caf=C3=A9"
+    /// let i: Vec<u8> = b"Caf=C3=A9 =3D=\n caf=C3=A9".to_vec();
+    ///
+    /// // Output: "Café = café"
+    /// let o = TransformQuotedPrintable::new().transform(&i).unwrap();
+    ///
+    /// assert_eq!("Café = café".as_bytes().to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Every '=' must be followed by two hex digits or a line ending, and
+    /// everything else must be printable.
+    FromQuotedPrintable(TransformQuotedPrintable),
+
+    /// Decode URL percent-encoding. Optionally, decode '+' as a space (like
+    /// HTML forms do).
+    ///
+    /// This is a ONE-WAY transformation!
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformPercent;
+    ///
+    /// // Input: "a+b%2Bc%21"
+    /// let i: Vec<u8> = b"a+b%2Bc%21".to_vec();
+    ///
+    /// // Output: "a b+c!"
+    /// let o = TransformPercent::form().transform(&i).unwrap();
+    ///
+    /// assert_eq!(b"a b+c!".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Every '%' must be followed by two hex digits.
+    FromPercent(TransformPercent),
+
+    /// Convert from Zlib "Deflated" format with or without a header. Uses the
+    /// [inflate](https://github.com/image-rs/inflate) library to decompress,
+    /// and [flate2](https://github.com/rust-lang/flate2-rs) to re-compress.
+    ///
+    /// Untransforming re-compresses the data at the chosen compression level
+    /// (0 - 9). Compression can only reproduce the original bytes if the
+    /// level matches what the data was originally compressed with (and even
+    /// then, only if the original compressor behaves like ours), so this is
+    /// only considered two-way when a level is known.
+    /// [`TransformDeflate::new_two_way`] finds a level that reproduces the
+    /// input, if there is one ([`Transformation::detect`] doesn't, since
+    /// that's slow).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformDeflate;
+    ///
+    /// // Input: "ABCD" compressed with zlib at the default level
+    /// let i: Vec<u8> = b"\x78\x9c\x73\x74\x72\x76\x01\x00\x02\x98\x01\x0b".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let t = TransformDeflate::with_header_level(6).unwrap();
+    /// let o = t.transform(&i).unwrap();
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    ///
+    /// // Compress it again, and get the original back
+    /// assert_eq!(i, t.untransform(&o).unwrap());
+    /// ```
+    ///
     /// # Restrictions / errors
     ///
     /// Must be valid deflated data.
     FromDeflated(TransformDeflate),
 
-    /// Convert from a hex string. Case is ignored.
+    /// Decompress a gzip file (such as a .gz or .tar.gz). Multiple
+    /// concatenated members are decompressed together.
+    ///
+    /// The header fields (filename, comment, modification time, etc) can be
+    /// read with [`TransformGzip::header`].
+    ///
+    /// This is a ONE-WAY transformation!
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformGzip;
+    ///
+    /// // Input: "ABCD" compressed with `gzip -n`
+    /// let i: Vec<u8> = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let o = TransformGzip::new().transform(&i).unwrap();
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    ///
+    /// // The header shows the file was created on a Unix system
+    /// assert_eq!(3, TransformGzip::header(&i).unwrap().operating_system);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Must start with the gzip magic bytes (`1f 8b 08`) and be valid gzip
+    /// data, including the checksum.
+    FromGzip(TransformGzip),
+
+    /// Decompress a bzip2 file.
+    ///
+    /// This is a ONE-WAY transformation!
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBzip2;
+    ///
+    /// // Input: "ABCD" compressed with `bzip2`
+    /// let i: Vec<u8> = b"\x42\x5a\x68\x39\x31\x41\x59\x26\x53\x59\x54\x30\x65\x9c\x00\x00\x00\x04\x00\x3c\x00\x20\x00\x21\x9a\x68\x33\x4d\x13\x3c\x5d\xc9\x14\xe1\x42\x41\x50\xc1\x96\x70".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let o = TransformBzip2::new().transform(&i).unwrap();
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Must start with "BZh" and a block size, and be valid bzip2 data.
+    FromBzip2(TransformBzip2),
+
+    /// Decompress LZMA data, either in the legacy .lzma format or the .xz
+    /// container format (see [`LzmaFormat`]). Uses the
+    /// [lzma-rs](https://github.com/gendx/lzma-rs) library.
+    ///
+    /// This is a ONE-WAY transformation!
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformLzma;
+    ///
+    /// // Input: "ABCD" compressed with `xz --format=lzma`
+    /// let i: Vec<u8> = b"\x5d\x00\x00\x80\x00\xff\xff\xff\xff\xff\xff\xff\xff\x00\x20\x90\x84\x76\xc3\xf0\x11\xb2\xff\xfa\x1c\x80\x00".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let o = TransformLzma::lzma().transform(&i).unwrap();
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Must be valid LZMA data. The .xz format must start with its magic
+    /// bytes, and the .lzma format must have a sane header.
+    FromLzma(TransformLzma),
+
+    /// Decompress LZ4 data, either as a frame or a raw block (see
+    /// [`Lz4Format`]). Uses the
+    /// [lz4_flex](https://github.com/PSeitz/lz4_flex) library.
+    ///
+    /// This is a ONE-WAY transformation!
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformLz4;
+    ///
+    /// // Input: "ABCD" compressed with `lz4`
+    /// let i: Vec<u8> = b"\x04\x22\x4d\x18\x64\x40\xa7\x04\x00\x00\x80\x41\x42\x43\x44\x00\x00\x00\x00\xa6\x0c\x96\xaa".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let o = TransformLz4::frame().transform(&i).unwrap();
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Must be valid LZ4 data. Frames must start with the magic bytes, and
+    /// blocks must decompress to exactly the expected size.
+    FromLz4(TransformLz4),
+
+    /// Decompress Zstandard data. Uses the
+    /// [ruzstd](https://github.com/KillingSpark/zstd-rs) library.
+    ///
+    /// This is a ONE-WAY transformation!
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformZstd;
+    ///
+    /// // Input: "ABCD" compressed with `zstd`
+    /// let i: Vec<u8> = b"\x28\xb5\x2f\xfd\x24\x04\x21\x00\x00\x41\x42\x43\x44\x6e\x9c\x71\x3f".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let o = TransformZstd::new().transform(&i).unwrap();
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Must start with the Zstandard magic bytes and be a valid frame.
+    FromZstd(TransformZstd),
+
+    /// Decompress Snappy data, either raw or framed. Uses the
+    /// [snap](https://github.com/BurntSushi/rust-snappy) library.
+    ///
+    /// This is a ONE-WAY transformation!
     ///
     /// # Example
     ///
     /// ```
+    /// use libh2gb::transformation::TransformSnappy;
+    ///
+    /// // Input: "ABCD" as a raw Snappy literal
+    /// let i: Vec<u8> = b"\x04\x0cABCD".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let o = TransformSnappy::raw().transform(&i).unwrap();
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    /// ```
+    ///
+    /// # Restrictions / errors
+    ///
+    /// Must be valid Snappy data. Framed data must start with the stream
+    /// identifier chunk.
+    FromSnappy(TransformSnappy),
+
+    /// Convert from a hex string. Case is ignored.
+    ///
+    /// Besides a plain string of digits, the bytes can be separated by spaces
+    /// or colons, escaped (`\x41`), written as a C / Python array
+    /// (`0x41, 0x42`), or pasted from the output of `hexdump -C` or `xxd`.
+    /// See [`HexFormat`] for details. Untransforming produces the same
+    /// layout - including the case, and anything around the data such as a
+    /// C declaration - when the transformation comes from
+    /// [`TransformHex::new_matching`] (which is what detection uses).
+    ///
+    /// # Example 1 - Plain
+    ///
+    /// ```
     /// use libh2gb::transformation::TransformHex;
     ///
     /// // Input: "41424344"
@@ -286,18 +803,56 @@ pub enum Transformation {
     /// assert_eq!(b"ABCD".to_vec(), o);
     /// ```
     ///
+    /// # Example 2 - C array
+    ///
+    /// ```
+    /// use libh2gb::transformation::{TransformHex, HexFormat};
+    ///
+    /// // Input: "{ 0x41, 0x42, 0x43, 0x44 }"
+    /// let i: Vec<u8> = b"{ 0x41, 0x42, 0x43, 0x44 }".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let o = TransformHex::c_array().transform(&i).unwrap();
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    ///
+    /// // Back to an array
+    /// assert_eq!(b"0x41, 0x42, 0x43, 0x44".to_vec(), TransformHex::c_array().untransform(&o).unwrap());
+    ///
+    /// // Or back to the same array
+    /// let t = TransformHex::new_matching(HexFormat::CArray, &i);
+    /// assert_eq!(i, t.untransform(&o).unwrap());
+    /// ```
+    ///
+    /// # Example 3 - xxd
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformHex;
+    ///
+    /// // Input: the output of xxd
+    /// let i: Vec<u8> = b"00000000: 4142 4344                                ABCD\n".to_vec();
+    ///
+    /// // Output: "ABCD"
+    /// let o = TransformHex::xxd().transform(&i).unwrap();
+    ///
+    /// assert_eq!(b"ABCD".to_vec(), o);
+    /// ```
+    ///
     /// # Restrictions / errors
     ///
-    /// Must be a hex string with an even length, made up of the digits 0-9
-    /// and a-f.
+    /// Each byte must be two hex digits (except in C arrays, where `0x0` is
+    /// allowed). Hex dumps must have offsets that match the data.
     FromHex(TransformHex),
 
-    /// Convert from a block cipher such as AES and DES.
+    /// Convert from a block cipher such as AES, DES, or Blowfish.
     ///
     /// Block ciphers have a lot of knobs, such as the algorithm, the key,
     /// the IV, mode of operation, and so on. These options are all included
     /// in the [`TransformBlockCipher`] struct.
     ///
+    /// The authenticated modes (GCM and CCM) expect the 16-byte tag at the
+    /// end of the ciphertext. It's verified when decrypting, and calculated
+    /// and appended again when encrypting.
+    ///
     /// # Example
     ///
     /// This is AES-128-CBC with Pkcs7 padding and a key/IV (this is by far the
@@ -330,6 +885,12 @@ pub enum Transformation {
 
     /// Convert from a stream cipher such as Salsa20, ChaCha, or Arc4.
     ///
+    /// The extended-nonce variants (XSalsa20 and XChaCha20), RC4-drop,
+    /// HC-128, Rabbit, and Spritz are also supported, as well as XORing with
+    /// an external keystream (such as another buffer). Data that starts
+    /// partway through the keystream can be decrypted by setting an offset
+    /// with [`TransformStreamCipher::new_with_offset`].
+    ///
     /// Stream ciphers have even more knobs than block ciphers. I tried to
     /// implement some of the most common algorithms with common settings, but
     /// that meant skipping others. The most popular libraries are also somewhat
@@ -355,6 +916,27 @@ pub enum Transformation {
     /// assert_eq!(b"Salsa20 Demo".to_vec(), result);
     /// ```
     FromStreamCipher(TransformStreamCipher),
+
+    /// Decrypt RSA, given the private key in PEM or DER form.
+    ///
+    /// Supports PKCS#1 v1.5 and OAEP padding, as well as raw ("textbook")
+    /// RSA. Raw RSA that was encrypted with a tiny exponent (like 3) can
+    /// also be decrypted without the key, since the plaintext is just the
+    /// cube root of the ciphertext. Use [`TransformRsa::find_keys`] to look
+    /// for keys embedded in a buffer.
+    ///
+    /// Only raw RSA with a private key can be undone.
+    ///
+    /// ```
+    /// use libh2gb::transformation::*;
+    ///
+    /// // "Hello" encrypted with e=3 and no padding is just "Hello" cubed
+    /// let ciphertext = b"\x05\xca\x33\x72\x14\xfb\xf3\x32\xa9\x6f\x70\xda\x12\xa2\x4f".to_vec();
+    ///
+    /// let transformation = TransformRsa::small_exponent(3).unwrap();
+    /// assert_eq!(b"Hello".to_vec(), transformation.transform(&ciphertext).unwrap());
+    /// ```
+    FromRsa(TransformRsa),
 }
 
 impl fmt::Display for Transformation {
@@ -368,12 +950,31 @@ impl Transformation {
         match self { // TODO: I think I can simplify this by moving the *
             Self::Null(s)             => Box::new(*s),
             Self::XorByConstant(s)    => Box::new(*s),
-            Self::FromBase64(s)       => Box::new(*s),
-            Self::FromBase32(s)       => Box::new(*s),
+            Self::XorByKey(s)         => Box::new(s.clone()),
+            Self::AddConstant(s)      => Box::new(*s),
+            Self::Rotate(s)           => Box::new(*s),
+            Self::Bitwise(s)          => Box::new(*s),
+            Self::Substitution(s)     => Box::new(s.clone()),
+            Self::FromBase64(s)       => Box::new(s.clone()),
+            Self::FromBase32(s)       => Box::new(s.clone()),
+            Self::FromBase85(s)       => Box::new(*s),
+            Self::FromBase58(s)       => Box::new(*s),
+            Self::FromBase45(s)       => Box::new(*s),
+            Self::FromUuencode(s)     => Box::new(s.clone()),
+            Self::FromYenc(s)         => Box::new(*s),
+            Self::FromQuotedPrintable(s) => Box::new(*s),
+            Self::FromPercent(s)      => Box::new(*s),
             Self::FromDeflated(s)     => Box::new(*s),
-            Self::FromHex(s)          => Box::new(*s),
-            Self::FromBlockCipher(s)  => Box::new(*s),
-            Self::FromStreamCipher(s) => Box::new(*s),
+            Self::FromGzip(s)         => Box::new(*s),
+            Self::FromBzip2(s)        => Box::new(*s),
+            Self::FromLzma(s)         => Box::new(*s),
+            Self::FromLz4(s)          => Box::new(*s),
+            Self::FromZstd(s)         => Box::new(*s),
+            Self::FromSnappy(s)       => Box::new(*s),
+            Self::FromHex(s)          => Box::new(s.clone()),
+            Self::FromBlockCipher(s)  => Box::new(s.clone()),
+            Self::FromStreamCipher(s) => Box::new(s.clone()),
+            Self::FromRsa(s)          => Box::new(s.clone()),
         }
     }
 
@@ -421,6 +1022,17 @@ impl Transformation {
         self.get_transformer().is_two_way()
     }
 
+    /// Does this transformation read its key, IV, or table from the given
+    /// buffer (see [`CipherTemplate`])?
+    pub fn references_buffer(&self, buffer: &str) -> bool {
+        match self {
+            Self::FromBlockCipher(s)  => s.references_buffer(buffer),
+            Self::FromStreamCipher(s) => s.references_buffer(buffer),
+            Self::Substitution(s)     => s.references_buffer(buffer),
+            _ => false,
+        }
+    }
+
     /// Returns a list of possible transformations that will work on this
     /// buffer.
     ///
@@ -432,14 +1044,87 @@ impl Transformation {
         out.extend(TransformNull::detect(buffer));
         out.extend(TransformHex::detect(buffer));
         out.extend(TransformXorByConstant::detect(buffer));
+        out.extend(TransformXorByKey::detect(buffer));
+        out.extend(TransformAddConstant::detect(buffer));
+        out.extend(TransformRotate::detect(buffer));
+        out.extend(TransformBitwise::detect(buffer));
+        out.extend(TransformSubstitution::detect(buffer));
         out.extend(TransformBase64::detect(buffer));
         out.extend(TransformBase32::detect(buffer));
+        out.extend(TransformBase85::detect(buffer));
+        out.extend(TransformBase58::detect(buffer));
+        out.extend(TransformBase45::detect(buffer));
+        out.extend(TransformUuencode::detect(buffer));
+        out.extend(TransformYenc::detect(buffer));
+        out.extend(TransformQuotedPrintable::detect(buffer));
+        out.extend(TransformPercent::detect(buffer));
         out.extend(TransformDeflate::detect(buffer));
+        out.extend(TransformGzip::detect(buffer));
+        out.extend(TransformBzip2::detect(buffer));
+        out.extend(TransformLzma::detect(buffer));
+        out.extend(TransformLz4::detect(buffer));
+        out.extend(TransformZstd::detect(buffer));
+        out.extend(TransformSnappy::detect(buffer));
         out.extend(TransformBlockCipher::detect(buffer));
         out.extend(TransformStreamCipher::detect(buffer));
+        out.extend(TransformRsa::detect(buffer));
+
+        out
+    }
+
+    /// Score how likely this transformation is to be the right one for a
+    /// buffer, with a short explanation.
+    ///
+    /// See [`RankedTransformation::new`] for how the score is calculated.
+    pub fn score(&self, buffer: &Vec<u8>) -> SimpleResult<RankedTransformation> {
+        RankedTransformation::new(self.clone(), buffer)
+    }
+
+    /// Like [`Transformation::detect`], but each result comes with a score
+    /// and an explanation, and the results are sorted with the most likely
+    /// first.
+    ///
+    /// This is just as expensive as [`Transformation::detect`], plus a little
+    /// more.
+    ///
+    /// ```
+    /// use libh2gb::transformation::{Transformation, TransformHex};
+    ///
+    /// let results = Transformation::detect_ranked(&b"48656c6c6f2c20776f726c64".to_vec());
+    ///
+    /// // Hex-encoded text is the best guess
+    /// assert_eq!(TransformHex::new(), results[0].transformation);
+    /// assert!(results[0].explanation.contains("100% printable"));
+    /// ```
+    pub fn detect_ranked(buffer: &Vec<u8>) -> Vec<RankedTransformation> {
+        let mut out: Vec<RankedTransformation> = Self::detect(buffer).into_iter().filter_map(|t| t.score(buffer).ok()).collect();
+
+        RankedTransformation::sort(&mut out);
 
         out
     }
+
+    /// Look for layers of transformations - like Base64 of zlib of XORed
+    /// data - by running [`Transformation::detect_ranked`] against the output
+    /// of each candidate, up to the given [`DetectionLimits`].
+    ///
+    /// Each result is a chain of transformations to apply in order, scored by
+    /// the quality of the final output. The best is first.
+    ///
+    /// This is even more expensive than [`Transformation::detect`], which is
+    /// what the limits are for.
+    ///
+    /// ```
+    /// use libh2gb::transformation::{Transformation, DetectionLimits, TransformBase64, TransformHex};
+    ///
+    /// // "Hello, world" as hex, then Base64
+    /// let results = Transformation::detect_recursive(&b"NDg2NTZjNmM2ZjJjMjA3NzZmNzI2YzY0".to_vec(), &DetectionLimits::default());
+    ///
+    /// assert_eq!(vec![TransformBase64::standard(), TransformHex::new()], results[0].chain);
+    /// ```
+    pub fn detect_recursive(buffer: &[u8], limits: &DetectionLimits) -> Vec<RankedChain> {
+        RankedChain::detect(buffer, limits)
+    }
 }
 
 #[cfg(test)]
@@ -454,6 +1139,7 @@ mod tests {
                 "Testcase: 'A'",
                 b"A".to_vec(),
                 vec![
+                    TransformBase58::standard(),
                 ],
             ),
 
@@ -465,7 +1151,8 @@ mod tests {
                     TransformBase32::crockford(),
                     TransformBase64::no_padding(),
                     TransformBase64::url_no_padding(),
-                    TransformHex::new(),
+                    TransformBase58::standard(),
+                    TransformHex::new_matching(HexFormat::Plain, b"AA"),
                 ],
             ),
 
@@ -511,6 +1198,89 @@ mod tests {
                 ]
             ),
 
+            (
+                "Testcase: Gzip",
+                b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00".to_vec(),
+                vec![
+                    TransformGzip::new(),
+                ]
+            ),
+
+            (
+                "Testcase: Bzip2",
+                b"\x42\x5a\x68\x39\x31\x41\x59\x26\x53\x59\x54\x30\x65\x9c\x00\x00\x00\x04\x00\x3c\x00\x20\x00\x21\x9a\x68\x33\x4d\x13\x3c\x5d\xc9\x14\xe1\x42\x41\x50\xc1\x96\x70".to_vec(),
+                vec![
+                    TransformBzip2::new(),
+                ]
+            ),
+
+            (
+                "Testcase: LZMA",
+                b"\x5d\x00\x00\x80\x00\xff\xff\xff\xff\xff\xff\xff\xff\x00\x20\x90\x84\x76\xc3\xf0\x11\xb2\xff\xfa\x1c\x80\x00".to_vec(),
+                vec![
+                    TransformLzma::lzma(),
+                ]
+            ),
+
+            (
+                "Testcase: XZ",
+                b"\xfd\x37\x7a\x58\x5a\x00\x00\x04\xe6\xd6\xb4\x46\x04\xc0\x08\x04\x21\x01\x16\x00\x00\x00\x00\x00\x00\x00\x00\x00\x4c\x41\xbc\x27\x01\x00\x03\x41\x42\x43\x44\x00\xcf\xd8\x9a\xb7\xd5\xb2\x84\x27\x00\x01\x24\x04\x94\x90\x03\xd6\x1f\xb6\xf3\x7d\x01\x00\x00\x00\x00\x04\x59\x5a".to_vec(),
+                vec![
+                    TransformLzma::xz(),
+                ]
+            ),
+
+            (
+                "Testcase: LZ4 frame",
+                b"\x04\x22\x4d\x18\x64\x40\xa7\x04\x00\x00\x80\x41\x42\x43\x44\x00\x00\x00\x00\xa6\x0c\x96\xaa".to_vec(),
+                vec![
+                    TransformLz4::frame(),
+
+                    // The frame header happens to look like a stored block
+                    TransformDeflate::without_header(),
+                ]
+            ),
+
+            (
+                "Testcase: LZ4 block with size",
+                b"\x18\x00\x00\x00\x4b\x41\x42\x43\x44\x04\x00\x50\x44\x41\x42\x43\x44".to_vec(),
+                vec![
+                    TransformLz4::block_size_prepended(),
+                ]
+            ),
+
+            (
+                "Testcase: Zstandard",
+                b"\x28\xb5\x2f\xfd\x24\x04\x21\x00\x00\x41\x42\x43\x44\x6e\x9c\x71\x3f".to_vec(),
+                vec![
+                    TransformZstd::new(),
+                ]
+            ),
+
+            (
+                "Testcase: Snappy framed",
+                b"\xff\x06\x00\x00\x73\x4e\x61\x50\x70\x59\x00\x0a\x00\x00\x17\xe2\x68\xb3\x04\x0c\x41\x42\x43\x44".to_vec(),
+                vec![
+                    TransformSnappy::framed(),
+                ]
+            ),
+
+            (
+                "Testcase: Snappy raw",
+                b"\x18\x0cABCD\x4e\x04\x00".to_vec(),
+                vec![
+                    TransformSnappy::raw(),
+                ]
+            ),
+
+            (
+                "Testcase: Single-byte XOR",
+                b"\x8b\xa6\xaf\xaf\xac\xef\xe3\xb7\xab\xaa\xb0\xe3\xaa\xb0\xe3\xb0\xac\xae\xa6\xe3\xa5\xa2\xaa\xb1\xaf\xba\xe3\xad\xac\xb1\xae\xa2\xaf\xe3\xb7\xa6\xbb\xb7\xed".to_vec(),
+                vec![
+                    TransformXorByConstant::new(XorSettings::EightBit(0xc3)),
+                ]
+            ),
+
             (
                 "Testcase: Base32",
                 b"ORSXG5BRGIZSA2DFNRWG6===".to_vec(),
@@ -525,6 +1295,9 @@ mod tests {
                 vec![
                     TransformBase32::no_padding(),
                     TransformBase32::crockford(),
+
+                    // It's a multiple of 3 characters, all upper-case
+                    TransformBase45::new(),
                 ]
             ),
 
@@ -536,6 +1309,77 @@ mod tests {
                 ]
             ),
 
+            (
+                "Testcase: Ascii85 (Adobe)",
+                b"<~87cURD_*#TDfTZ)~>".to_vec(),
+                vec![
+                    TransformBase85::ascii85_adobe(),
+                    TransformBase85::rfc1924(),
+                ]
+            ),
+
+            (
+                "Testcase: Z85",
+                b"nm=QNz.92Pz/PV8".to_vec(),
+                vec![
+                    TransformBase85::z85(),
+                ]
+            ),
+
+            (
+                "Testcase: Base58Check",
+                b"1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa".to_vec(),
+                vec![
+                    TransformBase58::standard(),
+                    TransformBase58::check(),
+                ]
+            ),
+
+            (
+                "Testcase: Base45",
+                b"UJCLQE7W581".to_vec(),
+                vec![
+                    TransformBase45::new(),
+
+                    // Short strings tend to be valid as lots of things
+                    TransformBase58::standard(),
+                    TransformDeflate::without_header(),
+                ]
+            ),
+
+            (
+                "Testcase: Uuencode",
+                b"begin 644 hello.txt\n,2&5L;&\\L('=O<FQD\n`\nend\n".to_vec(),
+                vec![
+                    TransformUuencode::new_matching(b"begin 644 hello.txt\n,2&5L;&\\L('=O<FQD\n`\nend\n"),
+                ]
+            ),
+
+            (
+                "Testcase: yEnc",
+                b"=ybegin line=128 size=5 name=a.txt\r\nr\x8f\x96\x96\x99\r\n=yend size=5\r\n".to_vec(),
+                vec![
+                    TransformYenc::new(),
+                ]
+            ),
+
+            (
+                "Testcase: Quoted-printable",
+                b"Caf=C3=A9 =3D=\n caf=C3=A9".to_vec(),
+                vec![
+                    TransformQuotedPrintable::new(),
+                ]
+            ),
+
+            (
+                "Testcase: Percent-encoding",
+                b"a+b%2Bc%21".to_vec(),
+                vec![
+                    TransformPercent::url(),
+                    TransformPercent::form(),
+                ]
+            ),
+
             (
                 "Testcase: AES-128-CBC or ECB with a NULL key + IV",
                 b"\x1c\xdc\x23\xdb\xc0\xc5\x5a\x68\xd6\xed\x4e\x5b\x1d\x36\xdf\xf5".to_vec(),
@@ -586,4 +1430,27 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_detect_ranked() -> SimpleResult<()> {
+        // Every candidate is still there, but sorted by score
+        let results = Transformation::detect_ranked(&b"AA".to_vec());
+        assert_eq!(6, results.len());
+        assert!(results.windows(2).all(|w| w[0].score >= w[1].score));
+
+        // Ties are always broken the same way
+        assert_eq!(results, Transformation::detect_ranked(&b"AA".to_vec()));
+
+        // Hex-encoded text ranks above things that decode to garbage
+        let results = Transformation::detect_ranked(&b"5468697320697320612074657374".to_vec());
+        assert_eq!(TransformHex::new(), results[0].transformation);
+
+        // Something with a file signature ranks highly
+        // echo -ne 'ABCD' | gzip -n | base64
+        let results = Transformation::detect_ranked(&b"H4sIAAAAAAAAA3N0cnYBAKUgF9sEAAAA".to_vec());
+        assert_eq!(TransformBase64::standard(), results[0].transformation);
+        assert!(results[0].explanation.starts_with("output looks like: Gzip data"));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,240 @@
+use serde::{Serialize, Deserialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::transformation::Transformation;
+
+/// Limits on how much work [`Transformation::detect_recursive`] is allowed to
+/// do.
+///
+/// Every layer runs every detector against every candidate, so the search
+/// grows very quickly; these keep it from running away.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectionLimits {
+    /// The longest chain to look for.
+    pub max_depth: usize,
+
+    /// Stop looking for new layers after this long (a detector that's already
+    /// running is allowed to finish).
+    pub max_time: Duration,
+
+    /// Stop looking after this many chains have been found.
+    pub max_candidates: usize,
+}
+
+impl Default for DetectionLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_time: Duration::from_secs(5),
+            max_candidates: 100,
+        }
+    }
+}
+
+impl DetectionLimits {
+    pub fn new(max_depth: usize, max_time: Duration, max_candidates: usize) -> Self {
+        Self {
+            max_depth,
+            max_time,
+            max_candidates,
+        }
+    }
+}
+
+/// A chain of transformations that might apply to a buffer, one after the
+/// other, along with how likely it is to be the right one.
+///
+/// These are returned by [`Transformation::detect_recursive`], best first.
+/// Each transformation in the chain applies to the output of the previous
+/// one, so they can be used directly as a series of
+/// [`crate::actions::ActionBufferTransform`] actions (see
+/// [`crate::actions::ActionBufferTransform::new_chain`]).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RankedChain {
+    /// The transformations, in the order they're applied.
+    pub chain: Vec<Transformation>,
+
+    /// How likely this is to be correct, from 0.0 (not at all) to 1.0.
+    pub score: f64,
+
+    /// A short, human-readable explanation of the score (based on the final
+    /// output).
+    pub explanation: String,
+}
+
+impl fmt::Display for RankedChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chain: Vec<String> = self.chain.iter().map(|t| t.to_string()).collect();
+
+        write!(f, "{} (score {:.2}: {})", chain.join(" -> "), self.score, self.explanation)
+    }
+}
+
+/// A partially-explored chain.
+struct Pending {
+    chain: Vec<Transformation>,
+    data: Vec<u8>,
+    score: f64,
+
+    /// How much of the input each step consumed, multiplied together.
+    consumed: f64,
+}
+
+impl RankedChain {
+    /// Search for chains of transformations, best-first.
+    ///
+    /// The most promising chain found so far is always the next to be
+    /// extended. Outputs that have already been seen aren't explored again,
+    /// which stops transformations that undo each other (like XOR) from going
+    /// in circles.
+    pub fn detect(buffer: &[u8], limits: &DetectionLimits) -> Vec<Self> {
+        let start = Instant::now();
+
+        let mut results: Vec<Self> = Vec::new();
+        let mut seen: HashSet<Vec<u8>> = HashSet::new();
+        seen.insert(buffer.to_vec());
+
+        let mut pending: Vec<Pending> = vec![Pending {
+            chain: vec![],
+            data: buffer.to_vec(),
+            score: 1.0,
+            consumed: 1.0,
+        }];
+
+        'search: while !pending.is_empty() {
+            if start.elapsed() >= limits.max_time {
+                break;
+            }
+
+            // Take the best chain we haven't extended yet
+            let best = pending.iter().enumerate().max_by(|(_, a), (_, b)| {
+                a.score.partial_cmp(&b.score).unwrap_or(Ordering::Equal)
+            }).map(|(i, _)| i).unwrap();
+            let node = pending.swap_remove(best);
+
+            if node.chain.len() >= limits.max_depth {
+                continue;
+            }
+
+            for ranked in Transformation::detect_ranked(&node.data) {
+                if results.len() >= limits.max_candidates {
+                    break 'search;
+                }
+
+                let output = match ranked.transformation.transform(&node.data) {
+                    Ok(o) => o,
+                    Err(_) => continue,
+                };
+
+                if !seen.insert(output.clone()) {
+                    continue;
+                }
+
+                let mut chain = node.chain.clone();
+                chain.push(ranked.transformation);
+
+                // The score from the final step already accounts for what it
+                // consumed, so just account for the earlier steps
+                let score = ranked.score * node.consumed;
+
+                results.push(Self {
+                    chain: chain.clone(),
+                    score,
+                    explanation: ranked.explanation,
+                });
+
+                pending.push(Pending {
+                    chain,
+                    data: output,
+                    score,
+                    consumed: node.consumed * ranked.consumed,
+                });
+            }
+        }
+
+        Self::sort(&mut results);
+
+        results
+    }
+
+    /// Sort a list of results so the best is first; ties go to the shorter
+    /// chain, then the order of the transformations.
+    pub fn sort(results: &mut [Self]) {
+        results.sort_by(|a, b| {
+            b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal)
+                .then(a.chain.len().cmp(&b.chain.len()))
+                .then(a.chain.cmp(&b.chain))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use simple_error::SimpleResult;
+
+    use crate::transformation::{TransformBase64, TransformDeflate, TransformHex, TransformXorByConstant, XorSettings};
+
+    #[test]
+    fn test_detect_layers() -> SimpleResult<()> {
+        let expected = vec![
+            TransformBase64::standard(),
+            TransformDeflate::with_header(),
+            TransformXorByConstant::new(XorSettings::EightBit(0x5a)),
+        ];
+
+        // The original text, XORed by 0x5a, zlib compressed at level 6, then
+        // base64 encoded
+        let plaintext = b"Real payloads are often wrapped up in a few layers of encoding, to keep them away from prying eyes.".to_vec();
+        let mut buffer = plaintext.clone();
+        for t in expected.iter().rev() {
+            buffer = t.untransform(&buffer)?;
+        }
+
+        // Plenty of time, so a slow machine finds the same thing
+        let results = RankedChain::detect(&buffer, &DetectionLimits::new(4, Duration::from_secs(3600), 100));
+        assert_eq!(expected, results[0].chain);
+        assert!(results[0].explanation.contains("100% printable"));
+
+        // Applying the chain gives back the original
+        let mut data = buffer.clone();
+        for t in results[0].chain.iter() {
+            data = t.transform(&data)?;
+        }
+        assert_eq!(plaintext, data);
+
+        // Results are sorted
+        assert!(results.windows(2).all(|w| w[0].score >= w[1].score));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_limits() -> SimpleResult<()> {
+        // "Hey you" as hex, as hex
+        let buffer = b"3438363537393230373936663735".to_vec();
+
+        // One layer deep only finds hex
+        let results = RankedChain::detect(&buffer, &DetectionLimits::new(1, Duration::from_secs(60), 100));
+        assert!(results.iter().all(|r| r.chain.len() == 1));
+        assert_eq!(vec![TransformHex::new()], results[0].chain);
+
+        // Two layers deep finds both
+        let results = RankedChain::detect(&buffer, &DetectionLimits::new(2, Duration::from_secs(60), 100));
+        assert_eq!(vec![TransformHex::new(), TransformHex::new()], results[0].chain);
+
+        // Limiting the number of candidates
+        let results = RankedChain::detect(&buffer, &DetectionLimits::new(4, Duration::from_secs(60), 1));
+        assert_eq!(1, results.len());
+
+        // No time means no results
+        let results = RankedChain::detect(&buffer, &DetectionLimits::new(4, Duration::from_secs(0), 100));
+        assert_eq!(0, results.len());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::transformation::Transformation;
+use crate::transformation::{MAGIC_SCORE, content_score, entropy, known_magic, printable_ratio};
+
+/// Outputs shorter than this don't have enough data to be sure of anything,
+/// so their score is scaled down (otherwise, a single NUL byte looks great).
+const CONFIDENT_LENGTH: usize = 4;
+
+/// A transformation that might apply to a buffer, along with how likely it
+/// is to be the right one.
+///
+/// These are returned by [`Transformation::detect_ranked`], best first.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RankedTransformation {
+    /// The transformation itself.
+    pub transformation: Transformation,
+
+    /// How likely this is to be correct, from 0.0 (not at all) to 1.0.
+    pub score: f64,
+
+    /// How much of the input (0.0 - 1.0) the transformation used.
+    pub consumed: f64,
+
+    /// A short, human-readable explanation of the score.
+    pub explanation: String,
+}
+
+impl fmt::Display for RankedTransformation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (score {:.2}: {})", self.transformation, self.score, self.explanation)
+    }
+}
+
+impl RankedTransformation {
+    /// Score a transformation against a buffer.
+    ///
+    /// The score looks at the output - how printable it is (and how much it
+    /// looks like text, see [`content_score`]), how much structure it has
+    /// (measured by entropy), and whether it starts with a known file
+    /// signature - and how much of the input the transformation actually
+    /// consumed. Very short outputs are penalized, since there's not enough
+    /// there to tell.
+    ///
+    /// Consumption is measured by untransforming the output: two-way
+    /// transformations promise to give back the same length, so anything
+    /// missing was skipped over while decoding (permissive decoders do that).
+    /// One-way transformations are assumed to have used everything.
+    pub fn new(transformation: Transformation, buffer: &Vec<u8>) -> SimpleResult<Self> {
+        let output = transformation.transform(buffer)?;
+        if output.is_empty() {
+            bail!("Transformation produced no output");
+        }
+
+        let printable = printable_ratio(&output);
+        let entropy = entropy(&output);
+        let magic = known_magic(&output);
+
+        let consumed = match transformation.is_two_way() {
+            true => match transformation.untransform(&output) {
+                Ok(original) => (original.len().min(buffer.len()) as f64) / buffer.len() as f64,
+                Err(_) => 0.0,
+            },
+            false => 1.0,
+        };
+
+        // Printable (ideally text-shaped) output and low entropy both suggest
+        // we decoded something real; a file signature trumps both
+        let quality = 0.5 * content_score(&output) + 0.5 * (1.0 - entropy / 8.0);
+        let quality = match magic {
+            Some(_) => quality.max(MAGIC_SCORE),
+            None    => quality,
+        };
+        let quality = quality * (output.len().min(CONFIDENT_LENGTH) as f64 / CONFIDENT_LENGTH as f64);
+
+        let mut explanation: Vec<String> = Vec::new();
+        if let Some(name) = magic {
+            explanation.push(format!("output looks like: {}", name));
+        }
+        if output.len() < CONFIDENT_LENGTH {
+            explanation.push(format!("only {} byte(s) of output", output.len()));
+        }
+        explanation.push(format!("{:.0}% printable", printable * 100.0));
+        explanation.push(format!("entropy {:.2} bits/byte", entropy));
+        explanation.push(format!("consumed {:.0}% of the input", consumed * 100.0));
+
+        Ok(Self {
+            transformation,
+            score: quality * consumed,
+            consumed,
+            explanation: explanation.join(", "),
+        })
+    }
+
+    /// Sort a list of results so the best is first (ties are sorted by the
+    /// transformation, so the order is always the same).
+    pub fn sort(results: &mut [Self]) {
+        results.sort_by(|a, b| {
+            b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal).then(a.transformation.cmp(&b.transformation))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    use crate::transformation::{TransformBase32, TransformBase64, TransformDeflate, TransformGzip, TransformHex, TransformNull};
+
+    #[test]
+    fn test_ranked_transformation() -> SimpleResult<()> {
+        // Text beats random-looking data
+        let text = RankedTransformation::new(TransformHex::new(), &b"48656c6c6f2c20776f726c64".to_vec())?;
+        let random = RankedTransformation::new(TransformHex::new(), &b"8ef19302c4aa17d9fe805c31".to_vec())?;
+        assert!(text.score > random.score);
+        assert_eq!("100% printable, entropy 3.02 bits/byte, consumed 100% of the input", text.explanation);
+
+        // Decoders that stop early and ignore the rest of the input lose points
+        let deflate = TransformDeflate::without_header_level(6)?;
+        let exact = RankedTransformation::new(deflate.clone(), &b"\xf3\x48\xcd\xc9\xc9\xd7\x51\xf0\x40\xa1\xca\xf3\x8b\x72\x52\x00".to_vec())?;
+        let trailing = RankedTransformation::new(deflate, &b"\xf3\x48\xcd\xc9\xc9\xd7\x51\xf0\x40\xa1\xca\xf3\x8b\x72\x52\x00AAAAAAAA".to_vec())?;
+        assert!(exact.explanation.ends_with("consumed 100% of the input"));
+        assert!(trailing.explanation.ends_with("consumed 67% of the input"));
+        assert!(exact.score > trailing.score);
+
+        // A file signature is a strong hint, even if the data is otherwise binary
+        let gzip = RankedTransformation::new(TransformNull::new(), &b"\x1f\x8b\x08\x00\xd3\xa0\x01\x00\xff\xfe".to_vec())?;
+        assert_eq!(MAGIC_SCORE, gzip.score);
+        assert!(gzip.explanation.starts_with("output looks like: Gzip data, "));
+
+        // Transformations that don't work are errors
+        assert!(RankedTransformation::new(TransformGzip::new(), &b"abc".to_vec()).is_err());
+        assert!(RankedTransformation::new(TransformBase64::standard(), &b"abc".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort() -> SimpleResult<()> {
+        let mut results = vec![
+            RankedTransformation::new(TransformBase64::no_padding(), &b"AA".to_vec())?,
+            RankedTransformation::new(TransformHex::new(), &b"8ef19302c4aa17d9fe805c31".to_vec())?,
+            RankedTransformation::new(TransformHex::new(), &b"48656c6c6f2c20776f726c64".to_vec())?,
+            RankedTransformation::new(TransformBase32::no_padding(), &b"AA".to_vec())?,
+        ];
+
+        RankedTransformation::sort(&mut results);
+
+        // Best first
+        assert_eq!(TransformHex::new(), results[0].transformation);
+        assert_eq!(TransformHex::new(), results[1].transformation);
+        assert!(results[0].score > results[1].score);
+
+        // A single byte isn't much to go on, so those are last; ties are
+        // broken by the transformation
+        assert!(results[1].score > results[2].score);
+        assert_eq!(results[2].score, results[3].score);
+        assert_eq!(TransformBase64::no_padding(), results[2].transformation);
+        assert_eq!(TransformBase32::no_padding(), results[3].transformation);
+        assert!(results[3].explanation.starts_with("only 1 byte(s) of output, "));
+
+        Ok(())
+    }
+}
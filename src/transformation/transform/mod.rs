@@ -7,18 +7,75 @@ pub use transform_base64::TransformBase64;
 mod transform_base32;
 pub use transform_base32::TransformBase32;
 
+mod transform_base85;
+pub use transform_base85::{TransformBase85, Base85Format};
+
+mod transform_base58;
+pub use transform_base58::TransformBase58;
+
+mod transform_base45;
+pub use transform_base45::TransformBase45;
+
+mod transform_uuencode;
+pub use transform_uuencode::TransformUuencode;
+
+mod transform_yenc;
+pub use transform_yenc::TransformYenc;
+
+mod transform_quoted_printable;
+pub use transform_quoted_printable::TransformQuotedPrintable;
+
+mod transform_percent;
+pub use transform_percent::TransformPercent;
+
 mod transform_xor_by_constant;
 pub use transform_xor_by_constant::TransformXorByConstant;
 pub use transform_xor_by_constant::XorSettings;
 
+mod transform_xor_by_key;
+pub use transform_xor_by_key::{TransformXorByKey, XorKeyMode};
+
+mod transform_add_constant;
+pub use transform_add_constant::TransformAddConstant;
+
+mod transform_rotate;
+pub use transform_rotate::TransformRotate;
+
+mod transform_bitwise;
+pub use transform_bitwise::{TransformBitwise, BitwiseOperation};
+
+mod transform_substitution;
+pub use transform_substitution::TransformSubstitution;
+
 mod transform_deflate;
 pub use transform_deflate::TransformDeflate;
 
 mod transform_hex;
-pub use transform_hex::TransformHex;
+pub use transform_hex::{TransformHex, HexFormat};
 
 mod transform_block_cipher;
 pub use transform_block_cipher::{TransformBlockCipher, BlockCipherPadding, BlockCipherType, BlockCipherMode};
 
 mod transform_stream_cipher;
 pub use transform_stream_cipher::{TransformStreamCipher, StreamCipherType};
+
+mod transform_rsa;
+pub use transform_rsa::{TransformRsa, RsaPadding, RsaKey, RsaKeyType};
+
+mod transform_gzip;
+pub use transform_gzip::{TransformGzip, GzipHeader};
+
+mod transform_bzip2;
+pub use transform_bzip2::TransformBzip2;
+
+mod transform_lzma;
+pub use transform_lzma::{TransformLzma, LzmaFormat};
+
+mod transform_lz4;
+pub use transform_lz4::{TransformLz4, Lz4Format};
+
+mod transform_zstd;
+pub use transform_zstd::TransformZstd;
+
+mod transform_snappy;
+pub use transform_snappy::TransformSnappy;
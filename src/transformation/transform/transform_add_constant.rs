@@ -0,0 +1,166 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::generic_number::Endian;
+use crate::transformation::{Transformation, TransformerTrait, TransformXorByConstant, XorSettings};
+use crate::transformation::{best_single_byte_candidate, read_element, write_element, element_mask};
+
+/// Add or subtract a constant to each element of the buffer.
+///
+/// Like [`crate::transformation::XorKeyMode`], this describes how the data
+/// was *encoded* - if a constant was added, transforming the buffer subtracts
+/// it.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformAddConstant {
+    settings: XorSettings,
+    endian: Endian,
+    subtract: bool,
+}
+
+impl fmt::Display for TransformAddConstant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformAddConstant {
+    pub fn new(settings: XorSettings, endian: Endian, subtract: bool) -> Transformation {
+        Transformation::AddConstant(Self {
+            settings,
+            endian,
+            subtract,
+        })
+    }
+
+    /// The constant was added to each element.
+    pub fn add(settings: XorSettings, endian: Endian) -> Transformation {
+        Self::new(settings, endian, false)
+    }
+
+    /// The constant was subtracted from each element.
+    pub fn subtract(settings: XorSettings, endian: Endian) -> Transformation {
+        Self::new(settings, endian, true)
+    }
+
+    /// Add (or subtract) the constant to each element.
+    fn apply(&self, buffer: &[u8], subtract: bool) -> SimpleResult<Vec<u8>> {
+        let width = self.settings.width();
+        if !buffer.len().is_multiple_of(width) {
+            bail!("Add failed: the buffer isn't a multiple of the element size");
+        }
+
+        let constant = self.settings.value();
+
+        Ok(buffer.chunks(width).flat_map(|chunk| {
+            let value = read_element(chunk, self.endian);
+            let value = match subtract {
+                false => value.wrapping_add(constant),
+                true  => value.wrapping_sub(constant),
+            };
+
+            write_element(value & element_mask(width), width, self.endian)
+        }).collect())
+    }
+}
+
+impl TransformerTrait for TransformAddConstant {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        self.apply(buffer, !self.subtract)
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        self.apply(buffer, self.subtract)
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        buffer.len().is_multiple_of(self.settings.width())
+    }
+
+    fn is_two_way(&self) -> bool {
+        true
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        // Only try single bytes - subtracting is the same as adding the
+        // negative, so we don't need to try both
+        let candidates = (1..=255u8).map(|c| Self::add(XorSettings::EightBit(c), Endian::Big));
+
+        // If XOR explains the data at least as well, that's almost certainly
+        // the real answer
+        let simplest = match TransformXorByConstant::find_keys(buffer).first() {
+            Some((_, confidence)) => *confidence,
+            None => 0.0,
+        };
+
+        best_single_byte_candidate(buffer, candidates).into_iter()
+            .filter(|(_, score)| *score > simplest)
+            .map(|(t, _)| t)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_add8() -> SimpleResult<()> {
+        let t = TransformAddConstant::add(XorSettings::EightBit(1), Endian::Big);
+        assert!(t.is_two_way());
+
+        assert_eq!(vec![0x00, 0x01, 0xff], t.transform(&vec![0x01, 0x02, 0x00])?);
+        assert_eq!(vec![0x01, 0x02, 0x00], t.untransform(&vec![0x00, 0x01, 0xff])?);
+
+        let t = TransformAddConstant::subtract(XorSettings::EightBit(1), Endian::Big);
+        assert_eq!(vec![0x02, 0x03, 0x00], t.transform(&vec![0x01, 0x02, 0xff])?);
+        assert_eq!(vec![0x01, 0x02, 0xff], t.untransform(&vec![0x02, 0x03, 0x00])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_words() -> SimpleResult<()> {
+        // The carry goes into the more significant byte
+        let t = TransformAddConstant::add(XorSettings::SixteenBit(0x0001), Endian::Big);
+        assert_eq!(vec![0x11, 0xff, 0x00, 0x00], t.transform(&vec![0x12, 0x00, 0x00, 0x01])?);
+        assert_eq!(vec![0x12, 0x00, 0x00, 0x01], t.untransform(&vec![0x11, 0xff, 0x00, 0x00])?);
+
+        let t = TransformAddConstant::add(XorSettings::SixteenBit(0x0001), Endian::Little);
+        assert_eq!(vec![0xff, 0x11, 0x00, 0x00], t.transform(&vec![0x00, 0x12, 0x01, 0x00])?);
+
+        let t = TransformAddConstant::subtract(XorSettings::ThirtyTwoBit(0x01020304), Endian::Big);
+        assert_eq!(vec![0x01, 0x02, 0x03, 0x04], t.transform(&vec![0x00, 0x00, 0x00, 0x00])?);
+
+        // Wrapping works for 64 bits too
+        let t = TransformAddConstant::subtract(XorSettings::SixtyFourBit(1), Endian::Little);
+        assert_eq!(vec![0; 8], t.transform(&vec![0xff; 8])?);
+        assert_eq!(vec![0xff; 8], t.untransform(&vec![0; 8])?);
+
+        // Lengths have to be a multiple of the width
+        assert!(!t.can_transform(&vec![0; 7]));
+        assert!(t.transform(&vec![0; 7]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect() -> SimpleResult<()> {
+        let text = b"Here is some perfectly normal text, which has been obfuscated by adding a constant to each byte.".to_vec();
+        let t = TransformAddConstant::add(XorSettings::EightBit(0x20), Endian::Big);
+        let encoded = t.untransform(&text)?;
+
+        assert_eq!(vec![t], TransformAddConstant::detect(&encoded));
+
+        // Text doesn't need to be changed, and short buffers are skipped
+        assert_eq!(Vec::<Transformation>::new(), TransformAddConstant::detect(&text));
+        assert_eq!(Vec::<Transformation>::new(), TransformAddConstant::detect(&encoded[..16].to_vec()));
+
+        // Data that was XORed is left to the XOR detector
+        let encoded = TransformXorByConstant::new(XorSettings::EightBit(0xc3)).transform(&text)?;
+        assert_eq!(Vec::<Transformation>::new(), TransformAddConstant::detect(&encoded));
+
+        Ok(())
+    }
+}
@@ -3,13 +3,33 @@ use simple_error::{SimpleResult, bail};
 use serde::{Serialize, Deserialize};
 use std::fmt;
 
-use crate::transformation::{Transformation, TransformerTrait};
+use crate::transformation::{Transformation, TransformerTrait, CustomAlphabet};
 
-#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+/// The RFC 4648 alphabet - custom alphabets are translated to this.
+const STANDARD_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Alphabets that we know about, which are tried by
+/// [`TransformBase32::infer_alphabets`].
+const KNOWN_ALPHABETS: [&str; 4] = [
+    // Standard (in case the padding is unusual)
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567",
+
+    // "base32hex" from RFC 4648
+    "0123456789ABCDEFGHIJKLMNOPQRSTUV",
+
+    // z-base-32
+    "ybndrfg8ejkmcpqxot1uwisza345h769",
+
+    // Geohash
+    "0123456789bcdefghjkmnpqrstuvwxyz",
+];
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TransformBase32 {
     no_padding: bool,
     permissive: bool,
     crockford: bool,
+    custom: Option<CustomAlphabet>,
 }
 
 impl fmt::Display for TransformBase32 {
@@ -24,9 +44,92 @@ impl TransformBase32 {
             no_padding: no_padding,
             permissive: permissive,
             crockford: crockford,
+            custom: None,
         })
     }
 
+    /// Use a custom alphabet, which must be 32 different ASCII characters, in
+    /// order. The `padding` character can be `None` if there's no padding.
+    ///
+    /// Unlike the built-in alphabets, custom alphabets are case sensitive.
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBase32;
+    ///
+    /// // z-base-32
+    /// let t = TransformBase32::custom("ybndrfg8ejkmcpqxot1uwisza345h769", None).unwrap();
+    ///
+    /// assert_eq!(b"Hello".to_vec(), t.transform(&b"jb1sa5dx".to_vec()).unwrap());
+    /// assert_eq!(b"jb1sa5dx".to_vec(), t.untransform(&b"Hello".to_vec()).unwrap());
+    /// ```
+    pub fn custom(alphabet: &str, padding: Option<char>) -> SimpleResult<Transformation> {
+        Ok(Transformation::FromBase32(Self {
+            no_padding: padding.is_none(),
+            permissive: false,
+            crockford: false,
+            custom: Some(CustomAlphabet::new(alphabet, padding, 32)?),
+        }))
+    }
+
+    /// Like [`TransformBase32::custom`], except that characters that aren't
+    /// in the alphabet are ignored.
+    pub fn custom_permissive(alphabet: &str, padding: Option<char>) -> SimpleResult<Transformation> {
+        Ok(Transformation::FromBase32(Self {
+            no_padding: padding.is_none(),
+            permissive: true,
+            crockford: false,
+            custom: Some(CustomAlphabet::new(alphabet, padding, 32)?),
+        }))
+    }
+
+    /// Get the custom alphabet, if there is one.
+    pub fn alphabet(&self) -> Option<&CustomAlphabet> {
+        self.custom.as_ref()
+    }
+
+    /// Guess at custom alphabets that could decode the buffer, based on
+    /// which characters it uses.
+    ///
+    /// The order of the alphabet can't be seen in the data, so this tries a
+    /// list of well-known alphabets (base32hex, z-base-32, and Geohash). Only
+    /// the ones that decode are returned.
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBase32;
+    ///
+    /// let alphabets = TransformBase32::infer_alphabets(&b"jb1sa5dx".to_vec());
+    ///
+    /// assert_eq!(vec![TransformBase32::custom("ybndrfg8ejkmcpqxot1uwisza345h769", None).unwrap()], alphabets);
+    /// ```
+    pub fn infer_alphabets(buffer: &Vec<u8>) -> Vec<Transformation> {
+        CustomAlphabet::infer(buffer, &KNOWN_ALPHABETS, None, 32).into_iter().filter(|alphabet| {
+            // Don't bother with the one that's already built in
+            let built_in_padding = alphabet.padding().is_none() || alphabet.padding() == Some('=');
+
+            !(alphabet.alphabet() == KNOWN_ALPHABETS[0] && built_in_padding)
+        }).filter_map(|alphabet| {
+            Self::custom(alphabet.alphabet(), alphabet.padding()).ok()
+        }).filter(|t| t.can_transform(buffer)).collect()
+    }
+
+    /// Translate from the custom alphabet (if there is one) into the standard
+    /// alphabet.
+    fn decode_alphabet(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        match &self.custom {
+            Some(c) => c.to_standard(buffer, STANDARD_ALPHABET, self.permissive),
+            None    => Ok(buffer.to_vec()),
+        }
+    }
+
+    /// Translate from the standard alphabet into the custom alphabet (if
+    /// there is one).
+    fn encode_alphabet(&self, buffer: Vec<u8>) -> SimpleResult<Vec<u8>> {
+        match &self.custom {
+            Some(c) => c.from_standard(&buffer, STANDARD_ALPHABET),
+            None    => Ok(buffer),
+        }
+    }
+
     pub fn standard() -> Transformation {
         Self::new(false, false, false)
     }
@@ -59,10 +162,11 @@ impl TransformBase32 {
         }
     }
 
-    fn transform_standard(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+    fn transform_standard(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
         let original_length = buffer.len();
+        let buffer = self.decode_alphabet(buffer)?;
 
-        let s = match std::str::from_utf8(buffer) {
+        let s = match std::str::from_utf8(&buffer) {
             Ok(s) => s,
             Err(e) => bail!("Couldn't convert the buffer into a string: {}", e),
         };
@@ -81,18 +185,18 @@ impl TransformBase32 {
         Ok(out)
     }
 
-    fn untransform_standard(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        Ok(base32::encode(self.get_alphabet(), buffer).into_bytes())
+    fn untransform_standard(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        self.encode_alphabet(base32::encode(self.get_alphabet(), buffer).into_bytes())
     }
 
-    fn check_standard(self, buffer: &Vec<u8>) -> bool {
+    fn check_standard(&self, buffer: &Vec<u8>) -> bool {
         // The only reasonable way to check is by just doing it
         self.transform(buffer).is_ok()
     }
 
-    fn transform_permissive(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+    fn transform_permissive(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
         // Filter out any obviously impossible characters
-        let buffer: Vec<u8> = buffer.clone().into_iter().filter(|b| {
+        let buffer: Vec<u8> = self.decode_alphabet(buffer)?.into_iter().filter(|b| {
             (*b >= 0x30 && *b <= 0x39) || (*b >= 0x41 && *b <= 0x5a) || (*b >= 0x61 && *b <= 0x7a)
         }).collect();
 
@@ -108,11 +212,11 @@ impl TransformBase32 {
         }
     }
 
-    fn untransform_permissive(self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+    fn untransform_permissive(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
         bail!("Cannot untransform Base32 Permissive");
     }
 
-    fn check_permissive(self, buffer: &Vec<u8>) -> bool {
+    fn check_permissive(&self, buffer: &[u8]) -> bool {
         // The only reasonable way to check is by just doing it
         self.transform_permissive(buffer).is_ok()
     }
@@ -155,7 +259,21 @@ impl TransformerTrait for TransformBase32 {
         ];
 
         // Filter down to the ones that work
-        transformations.into_iter().filter(|t| t.can_transform(buffer)).collect()
+        let mut out: Vec<Transformation> = transformations.into_iter().filter(|t| t.can_transform(buffer)).collect();
+
+        // Crockford decoding ignores case, but encoders stick to one - mixed
+        // case is something else (like Base58)
+        let mixed_case = buffer.iter().any(|b| b.is_ascii_uppercase()) && buffer.iter().any(|b| b.is_ascii_lowercase());
+        if mixed_case {
+            out.retain(|t| *t != Self::crockford());
+        }
+
+        // If none of them work, it might be a custom alphabet
+        if out.is_empty() {
+            return Self::infer_alphabets(buffer);
+        }
+
+        out
     }
 }
 
@@ -373,4 +491,65 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_base32_custom() -> SimpleResult<()> {
+        // base32hex, with a different padding character
+        let t = TransformBase32::custom("0123456789ABCDEFGHIJKLMNOPQRSTUV", Some('*'))?;
+        assert_eq!(true, t.is_two_way());
+
+        assert_eq!(b"Hello, world!".to_vec(), t.transform(&b"91IMOR3F5GG7ERRIDHI22***".to_vec())?);
+        assert_eq!(b"91IMOR3F5GG7ERRIDHI22***".to_vec(), t.untransform(&b"Hello, world!".to_vec())?);
+
+        // Standard padding isn't accepted
+        assert!(t.transform(&b"91IMOR3F5GG7ERRIDHI22===".to_vec()).is_err());
+
+        // Custom alphabets are case sensitive
+        assert!(t.transform(&b"91imor3f5gg7erridhi22***".to_vec()).is_err());
+
+        // Bad alphabets
+        assert!(TransformBase32::custom("0123456789ABCDEFGHIJKLMNOPQRSTU", None).is_err());
+        assert!(TransformBase32::custom("0123456789ABCDEFGHIJKLMNOPQRSTUU", None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_base32_custom_permissive() -> SimpleResult<()> {
+        let t = TransformBase32::custom_permissive("0123456789ABCDEFGHIJKLMNOPQRSTUV", Some('*'))?;
+        assert_eq!(false, t.is_two_way());
+
+        assert_eq!(b"Hello, world!".to_vec(), t.transform(&b"91IMOR3F 5GG7ERRI\nDHI22*".to_vec())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_base32_infer() -> SimpleResult<()> {
+        // Standard data doesn't need a custom alphabet
+        assert!(TransformBase32::detect(&b"JBSWY3DP".to_vec()).iter().all(|t| {
+            match t {
+                Transformation::FromBase32(t) => t.alphabet().is_none(),
+                _ => false,
+            }
+        }));
+
+        // z-base-32 is the only known alphabet with these characters
+        assert_eq!(vec![
+            TransformBase32::custom("ybndrfg8ejkmcpqxot1uwisza345h769", None)?,
+        ], TransformBase32::infer_alphabets(&b"jb1sa5dx".to_vec()));
+
+        // The padding character is figured out
+        assert_eq!(vec![
+            TransformBase32::custom("ABCDEFGHIJKLMNOPQRSTUVWXYZ234567", Some('*'))?,
+        ], TransformBase32::detect(&b"JBSWY3DPFQQHO33SNRSCC***".to_vec()));
+
+        // Nothing works
+        assert_eq!(Vec::<Transformation>::new(), TransformBase32::detect(&b"!!!!!".to_vec()));
+
+        // Crockford ignores case, but mixed case is something else (Base58)
+        assert_eq!(Vec::<Transformation>::new(), TransformBase32::detect(&b"1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa".to_vec()));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,82 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait};
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformBase45 {
+}
+
+impl TransformBase45 {
+    pub fn new() -> Transformation {
+        Transformation::FromBase45(TransformBase45 {})
+    }
+}
+
+impl fmt::Display for TransformBase45 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformerTrait for TransformBase45 {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let out = match base45::decode(buffer) {
+            Ok(r) => r,
+            Err(e) => bail!("Couldn't decode base45: {}", e),
+        };
+
+        // Out-of-range values can slip through, so make sure it encodes back
+        // to the same thing
+        if base45::encode(&out).as_bytes() != &buffer[..] {
+            bail!("Base45 didn't decode correctly");
+        }
+
+        Ok(out)
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        Ok(base45::encode(buffer).into_bytes())
+    }
+
+    fn is_two_way(&self) -> bool {
+        true
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        let s = Self::new();
+        match s.can_transform(buffer) {
+            true => vec![s],
+            false => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_base45() -> SimpleResult<()> {
+        let t = TransformBase45::new();
+        assert!(t.is_two_way());
+
+        // The examples from RFC 9285
+        assert_eq!(b"AB".to_vec(), t.transform(&b"BB8".to_vec())?);
+        assert_eq!(b"Hello!!".to_vec(), t.transform(&b"%69 VD92EX0".to_vec())?);
+        assert_eq!(b"base-45".to_vec(), t.transform(&b"UJCLQE7W581".to_vec())?);
+        assert_eq!(b"ietf!".to_vec(), t.transform(&b"QED8WEX0".to_vec())?);
+
+        assert_eq!(b"%69 VD92EX0".to_vec(), t.untransform(&b"Hello!!".to_vec())?);
+
+        // Invalid characters, lengths, or values
+        assert!(t.transform(&b"bb8".to_vec()).is_err());
+        assert!(t.transform(&b"B".to_vec()).is_err());
+        assert!(t.transform(&b"GGW".to_vec()).is_err());
+        assert!(t.transform(&b"ZZ".to_vec()).is_err());
+
+        Ok(())
+    }
+}
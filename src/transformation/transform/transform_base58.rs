@@ -0,0 +1,121 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait};
+
+/// Decode Base58, using Bitcoin's alphabet.
+///
+/// With `checksum` set, this is Base58Check: the last four bytes of the
+/// decoded data are the start of a double SHA-256 of the rest. The checksum
+/// is verified and removed when decoding, and added back when encoding.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformBase58 {
+    checksum: bool,
+}
+
+impl fmt::Display for TransformBase58 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformBase58 {
+    pub fn new(checksum: bool) -> Transformation {
+        Transformation::FromBase58(TransformBase58 {
+            checksum,
+        })
+    }
+
+    pub fn standard() -> Transformation {
+        Self::new(false)
+    }
+
+    pub fn check() -> Transformation {
+        Self::new(true)
+    }
+}
+
+impl TransformerTrait for TransformBase58 {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let decoder = bs58::decode(buffer);
+
+        let result = match self.checksum {
+            false => decoder.into_vec(),
+            true  => decoder.with_check(None).into_vec(),
+        };
+
+        match result {
+            Ok(r) => Ok(r),
+            Err(e) => bail!("Couldn't decode base58: {}", e),
+        }
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let encoder = bs58::encode(buffer);
+
+        Ok(match self.checksum {
+            false => encoder.into_vec(),
+            true  => encoder.with_check().into_vec(),
+        })
+    }
+
+    fn is_two_way(&self) -> bool {
+        true
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        vec![Self::standard(), Self::check()].into_iter().filter(|t| t.can_transform(buffer)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_base58() -> SimpleResult<()> {
+        let t = TransformBase58::standard();
+        assert!(t.is_two_way());
+
+        assert_eq!(b"Hello World!".to_vec(), t.transform(&b"2NEpo7TZRRrLZSi2U".to_vec())?);
+        assert_eq!(b"2NEpo7TZRRrLZSi2U".to_vec(), t.untransform(&b"Hello World!".to_vec())?);
+
+        // Leading '1's are leading zeroes
+        assert_eq!(b"\x00\x00\x01".to_vec(), t.transform(&b"112".to_vec())?);
+        assert_eq!(b"112".to_vec(), t.untransform(&b"\x00\x00\x01".to_vec())?);
+
+        // Characters that aren't in the alphabet
+        assert!(t.transform(&b"0OIl".to_vec()).is_err());
+        assert!(t.transform(&b"2NEpo7TZRRrLZSi2U=".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_base58_check() -> SimpleResult<()> {
+        let t = TransformBase58::check();
+        assert!(t.is_two_way());
+
+        // The Bitcoin genesis block address - a version byte, then a hash
+        let address = b"1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa".to_vec();
+        let decoded = t.transform(&address)?;
+        assert_eq!(b"\x00\x62\xe9\x07\xb1\x5c\xbf\x27\xd5\x42\x53\x99\xeb\xf6\xf0\xfb\x50\xeb\xb8\x8f\x18".to_vec(), decoded);
+        assert_eq!(address, t.untransform(&decoded)?);
+
+        // A bad checksum
+        assert!(t.transform(&b"1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb".to_vec()).is_err());
+
+        // Too short to have a checksum
+        assert!(t.transform(&b"2NE".to_vec()).is_err());
+
+        // Only the valid one is detected
+        let mut detected = TransformBase58::detect(&address);
+        detected.sort();
+        assert_eq!(vec![TransformBase58::standard(), TransformBase58::check()], detected);
+        assert_eq!(vec![TransformBase58::standard()], TransformBase58::detect(&b"2NEpo7TZRRrLZSi2U".to_vec()));
+
+        Ok(())
+    }
+}
@@ -3,13 +3,42 @@ use simple_error::{SimpleResult, bail};
 use serde::{Serialize, Deserialize};
 use std::fmt;
 
-use crate::transformation::{Transformation, TransformerTrait};
+use crate::transformation::{Transformation, TransformerTrait, CustomAlphabet};
 
-#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+/// The standard Base64 alphabet - custom alphabets are translated to this.
+const STANDARD_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Alphabets that we know about, which are tried by
+/// [`TransformBase64::infer_alphabets`].
+const KNOWN_ALPHABETS: [&str; 7] = [
+    // Standard and URL (in case the padding is unusual)
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+
+    // Unix crypt() and bcrypt
+    "./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz",
+    "./ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+
+    // xxencode
+    "+-0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz",
+
+    // IMAP mailbox names (RFC 3501)
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+,",
+
+    // Standard, with the case swapped
+    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789+/",
+];
+
+/// Most custom alphabets are the standard one with the last two characters
+/// changed.
+const ALPHABET_BASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TransformBase64 {
     no_padding: bool,
     permissive: bool,
     url: bool,
+    custom: Option<CustomAlphabet>,
 }
 
 impl fmt::Display for TransformBase64 {
@@ -24,9 +53,96 @@ impl TransformBase64 {
             no_padding: no_padding,
             permissive: permissive,
             url: url,
+            custom: None,
         })
     }
 
+    /// Use a custom alphabet, which must be 64 different ASCII characters, in
+    /// order. The `padding` character can be `None` if there's no padding.
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBase64;
+    ///
+    /// // The standard alphabet, but backwards
+    /// let t = TransformBase64::custom("/+9876543210zyxwvutsrqponmlkjihgfedcbaZYXWVUTSRQPONMLKJIHGFEDCBA", Some('*')).unwrap();
+    ///
+    /// assert_eq!(b"Hello".to_vec(), t.transform(&b"t5qTk5D*".to_vec()).unwrap());
+    /// assert_eq!(b"t5qTk5D*".to_vec(), t.untransform(&b"Hello".to_vec()).unwrap());
+    /// ```
+    pub fn custom(alphabet: &str, padding: Option<char>) -> SimpleResult<Transformation> {
+        Ok(Transformation::FromBase64(Self {
+            no_padding: padding.is_none(),
+            permissive: false,
+            url: false,
+            custom: Some(CustomAlphabet::new(alphabet, padding, 64)?),
+        }))
+    }
+
+    /// Like [`TransformBase64::custom`], except that characters that aren't
+    /// in the alphabet are ignored.
+    pub fn custom_permissive(alphabet: &str, padding: Option<char>) -> SimpleResult<Transformation> {
+        Ok(Transformation::FromBase64(Self {
+            no_padding: padding.is_none(),
+            permissive: true,
+            url: false,
+            custom: Some(CustomAlphabet::new(alphabet, padding, 64)?),
+        }))
+    }
+
+    /// Get the custom alphabet, if there is one.
+    pub fn alphabet(&self) -> Option<&CustomAlphabet> {
+        self.custom.as_ref()
+    }
+
+    /// Guess at custom alphabets that could decode the buffer, based on
+    /// which characters it uses.
+    ///
+    /// The order of the alphabet can't be seen in the data, so this tries a
+    /// list of well-known alphabets (like crypt(), bcrypt, and xxencode), as
+    /// well as the standard alphabet with the last two characters replaced by
+    /// whatever's in the buffer. Only the ones that decode are returned (and
+    /// it's worth ranking them with [`Transformation::detect_ranked`] or
+    /// [`Transformation::score`], since many may decode).
+    ///
+    /// ```
+    /// use libh2gb::transformation::TransformBase64;
+    ///
+    /// // "<<??>>Hi" with '!' and '*' instead of '+' and '/', and '.' for
+    /// // padding
+    /// let alphabets = TransformBase64::infer_alphabets(&b"PDw*Pz4!SGk.".to_vec());
+    ///
+    /// assert!(alphabets.contains(&TransformBase64::custom("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!*", Some('.')).unwrap()));
+    /// ```
+    pub fn infer_alphabets(buffer: &Vec<u8>) -> Vec<Transformation> {
+        CustomAlphabet::infer(buffer, &KNOWN_ALPHABETS, Some(ALPHABET_BASE), 64).into_iter().filter(|alphabet| {
+            // Don't bother with the ones that are already built in
+            let built_in = alphabet.alphabet() == KNOWN_ALPHABETS[0] || alphabet.alphabet() == KNOWN_ALPHABETS[1];
+            let built_in_padding = alphabet.padding().is_none() || alphabet.padding() == Some('=');
+
+            !(built_in && built_in_padding)
+        }).filter_map(|alphabet| {
+            Self::custom(alphabet.alphabet(), alphabet.padding()).ok()
+        }).filter(|t| t.can_transform(buffer)).collect()
+    }
+
+    /// Translate from the custom alphabet (if there is one) into the standard
+    /// alphabet.
+    fn decode_alphabet(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        match &self.custom {
+            Some(c) => c.to_standard(buffer, STANDARD_ALPHABET, self.permissive),
+            None    => Ok(buffer.to_vec()),
+        }
+    }
+
+    /// Translate from the standard alphabet into the custom alphabet (if
+    /// there is one).
+    fn encode_alphabet(&self, buffer: Vec<u8>) -> SimpleResult<Vec<u8>> {
+        match &self.custom {
+            Some(c) => c.from_standard(&buffer, STANDARD_ALPHABET),
+            None    => Ok(buffer),
+        }
+    }
+
     pub fn standard() -> Transformation {
         Self::new(false, false, false)
     }
@@ -65,6 +181,7 @@ impl TransformBase64 {
     // TODO: Permit newlines / whitespace in base64 (seems fairly common)
     fn transform_standard(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
         let original_length = buffer.len();
+        let buffer = self.decode_alphabet(buffer)?;
 
         // Decode
         let out = match base64::decode_config(buffer, self.get_config()) {
@@ -81,7 +198,7 @@ impl TransformBase64 {
     }
 
     fn untransform_standard(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        Ok(base64::encode_config(buffer, self.get_config()).into_bytes())
+        self.encode_alphabet(base64::encode_config(buffer, self.get_config()).into_bytes())
     }
 
     fn check_standard(&self, buffer: &Vec<u8>) -> bool {
@@ -92,7 +209,7 @@ impl TransformBase64 {
 
     fn transform_permissive(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
         // Filter out any control characters and spaces
-        let buffer: Vec<u8> = buffer.clone().into_iter().filter(|b| {
+        let buffer: Vec<u8> = self.decode_alphabet(buffer)?.into_iter().filter(|b| {
             *b > 0x20 && *b < 0x80
         }).collect();
 
@@ -153,7 +270,15 @@ impl TransformerTrait for TransformBase64 {
         ];
 
         // Filter down to the ones that work
-        transformations.into_iter().filter(|t| t.can_transform(buffer)).collect()
+        let out: Vec<Transformation> = transformations.into_iter().filter(|t| t.can_transform(buffer)).collect();
+
+        // If none of them work, and there are unexpected characters, it might
+        // be a custom alphabet
+        if out.is_empty() && buffer.iter().any(|b| !(b.is_ascii_alphanumeric() || b"+/=-_".contains(b))) {
+            return Self::infer_alphabets(buffer);
+        }
+
+        out
     }
 }
 
@@ -348,4 +473,70 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_base64_custom() -> SimpleResult<()> {
+        // The crypt() alphabet
+        let t = TransformBase64::custom("./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz", Some('='))?;
+        assert_eq!(true, t.is_two_way());
+
+        assert_eq!(b"Hello, world!".to_vec(), t.transform(&b"G4JgP4wg65RjQalY6E==".to_vec())?);
+        assert_eq!(b"G4JgP4wg65RjQalY6E==".to_vec(), t.untransform(&b"Hello, world!".to_vec())?);
+
+        // Characters from the standard alphabet that aren't in this one
+        assert!(t.transform(&b"SGVsbG8+".to_vec()).is_err());
+
+        // Custom padding, and no padding
+        let t = TransformBase64::custom(&format!("{}-.", ALPHABET_BASE), Some('~'))?;
+        assert_eq!(b"\xfb\xff\xfe".to_vec(), t.transform(&b"-..-".to_vec())?);
+        assert_eq!(b"SGVsbG8sIHdvcmxkIQ~~".to_vec(), t.untransform(&b"Hello, world!".to_vec())?);
+
+        let t = TransformBase64::custom(&format!("{}-.", ALPHABET_BASE), None)?;
+        assert_eq!(b"SGVsbG8sIHdvcmxkIQ".to_vec(), t.untransform(&b"Hello, world!".to_vec())?);
+
+        // Bad alphabets
+        assert!(TransformBase64::custom(ALPHABET_BASE, None).is_err());
+        assert!(TransformBase64::custom(&format!("{}-A", ALPHABET_BASE), None).is_err());
+        assert!(TransformBase64::custom(&format!("{}-.", ALPHABET_BASE), Some('A')).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_custom_permissive() -> SimpleResult<()> {
+        let t = TransformBase64::custom_permissive(&format!("{}-.", ALPHABET_BASE), Some('~'))?;
+        assert_eq!(false, t.is_two_way());
+
+        assert_eq!(b"Hello, world!".to_vec(), t.transform(&b"SGVsbG8s IHdv\ncmxk+/IQ~~".to_vec())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_infer() -> SimpleResult<()> {
+        // Standard data doesn't need a custom alphabet
+        assert!(TransformBase64::detect(&b"SGVsbG8=".to_vec()).iter().all(|t| {
+            match t {
+                Transformation::FromBase64(t) => t.alphabet().is_none(),
+                _ => false,
+            }
+        }));
+
+        // Unusual characters are tried in each position at the end
+        let detected = TransformBase64::detect(&b"-..-".to_vec());
+        assert_eq!(vec![
+            TransformBase64::custom(&format!("{}-.", ALPHABET_BASE), None)?,
+            TransformBase64::custom(&format!("{}.-", ALPHABET_BASE), None)?,
+        ], detected);
+
+        // Known alphabets are found if they decode
+        let inferred = TransformBase64::infer_alphabets(&b"G4JgP4wg65RjQalY6E==".to_vec());
+        assert!(inferred.contains(&TransformBase64::custom("./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz", Some('='))?));
+        assert!(!inferred.contains(&TransformBase64::standard()));
+
+        // Nothing works
+        assert_eq!(Vec::<Transformation>::new(), TransformBase64::detect(&b"!!!!!".to_vec()));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,379 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait, DETECT_SAMPLE_SIZE};
+
+/// The character set from ZeroMQ's Z85 spec.
+const Z85_ALPHABET: &[u8; 85] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+/// The character set from RFC 1924 (also used by git and Python's b85encode).
+const RFC1924_ALPHABET: &[u8; 85] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Which flavour of Base85.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub enum Base85Format {
+    /// Ascii85 (as used by btoa), with the characters `!` to `u`, and `z`
+    /// standing in for four NUL bytes.
+    Ascii85,
+
+    /// Adobe's Ascii85 (as used by PostScript and PDF), which is Ascii85
+    /// wrapped in `<~` and `~>`.
+    Ascii85Adobe,
+
+    /// ZeroMQ's Z85, which only encodes multiples of four bytes.
+    Z85,
+
+    /// The RFC 1924 character set.
+    RFC1924,
+}
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformBase85 {
+    format: Base85Format,
+}
+
+impl fmt::Display for TransformBase85 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformBase85 {
+    pub fn new(format: Base85Format) -> Transformation {
+        Transformation::FromBase85(TransformBase85 {
+            format,
+        })
+    }
+
+    pub fn ascii85() -> Transformation {
+        Self::new(Base85Format::Ascii85)
+    }
+
+    pub fn ascii85_adobe() -> Transformation {
+        Self::new(Base85Format::Ascii85Adobe)
+    }
+
+    pub fn z85() -> Transformation {
+        Self::new(Base85Format::Z85)
+    }
+
+    pub fn rfc1924() -> Transformation {
+        Self::new(Base85Format::RFC1924)
+    }
+
+    /// Convert a character to its value (0 - 84).
+    fn decode_char(self, c: u8) -> SimpleResult<u64> {
+        let value = match self.format {
+            Base85Format::Ascii85 | Base85Format::Ascii85Adobe => match c {
+                b'!'..=b'u' => Some(c - b'!'),
+                _ => None,
+            },
+            Base85Format::Z85     => Z85_ALPHABET.iter().position(|a| *a == c).map(|p| p as u8),
+            Base85Format::RFC1924 => RFC1924_ALPHABET.iter().position(|a| *a == c).map(|p| p as u8),
+        };
+
+        match value {
+            Some(v) => Ok(v as u64),
+            None => bail!("Invalid {:?} character: 0x{:02x}", self.format, c),
+        }
+    }
+
+    /// Convert a value (0 - 84) to its character.
+    fn encode_char(self, value: u32) -> u8 {
+        match self.format {
+            Base85Format::Ascii85 | Base85Format::Ascii85Adobe => b'!' + value as u8,
+            Base85Format::Z85     => Z85_ALPHABET[value as usize],
+            Base85Format::RFC1924 => RFC1924_ALPHABET[value as usize],
+        }
+    }
+
+    fn is_ascii85(self) -> bool {
+        self.format == Base85Format::Ascii85 || self.format == Base85Format::Ascii85Adobe
+    }
+
+    /// Remove the `<~` `~>` wrapper, if the format has one.
+    fn unwrap(self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        match self.format {
+            Base85Format::Ascii85Adobe => {
+                if !buffer.starts_with(b"<~") || !buffer.ends_with(b"~>") || buffer.len() < 4 {
+                    bail!("Adobe Ascii85 must start with <~ and end with ~>");
+                }
+
+                Ok(buffer[2..(buffer.len() - 2)].to_vec())
+            },
+            _ => Ok(buffer.to_vec()),
+        }
+    }
+
+    fn decode(self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        let buffer = self.unwrap(buffer)?;
+        let mut out: Vec<u8> = Vec::new();
+
+        // Groups of up to five characters - Ascii85's "z" is a group on its own
+        let mut group: Vec<u64> = Vec::new();
+        for c in buffer {
+            if c == b'z' && self.is_ascii85() {
+                if !group.is_empty() {
+                    bail!("Ascii85 'z' found in the middle of a group");
+                }
+
+                out.extend(&[0, 0, 0, 0]);
+                continue;
+            }
+
+            group.push(self.decode_char(c)?);
+
+            if group.len() == 5 {
+                out.extend(&Self::decode_group(&group)?);
+                group.clear();
+            }
+        }
+
+        // A partial group is padded with the highest value, and produces one
+        // fewer byte than it has characters
+        match group.len() {
+            0 => (),
+            1 => bail!("{:?} can't end with a single character", self.format),
+            n => {
+                if self.format == Base85Format::Z85 {
+                    bail!("Z85 length must be a multiple of 5");
+                }
+
+                group.resize(5, 84);
+                out.extend(&Self::decode_group(&group)?[0..(n - 1)]);
+            },
+        }
+
+        Ok(out)
+    }
+
+    fn decode_group(group: &[u64]) -> SimpleResult<[u8; 4]> {
+        let value = group.iter().fold(0u64, |acc, v| acc * 85 + v);
+
+        if value > u32::MAX as u64 {
+            bail!("Base85 group is out of range");
+        }
+
+        Ok((value as u32).to_be_bytes())
+    }
+
+    fn encode(self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        if self.format == Base85Format::Z85 && !buffer.len().is_multiple_of(4) {
+            bail!("Z85 can only encode a multiple of 4 bytes");
+        }
+
+        let mut out: Vec<u8> = Vec::new();
+
+        if self.format == Base85Format::Ascii85Adobe {
+            out.extend(b"<~");
+        }
+
+        for chunk in buffer.chunks(4) {
+            if chunk == [0, 0, 0, 0] && self.is_ascii85() {
+                out.push(b'z');
+                continue;
+            }
+
+            // Pad partial chunks with zeroes, then only use one more
+            // character than there are bytes
+            let mut padded = [0u8; 4];
+            padded[..chunk.len()].copy_from_slice(chunk);
+            let mut value = u32::from_be_bytes(padded);
+
+            let mut encoded = [0u8; 5];
+            for c in encoded.iter_mut().rev() {
+                *c = self.encode_char(value % 85);
+                value /= 85;
+            }
+
+            out.extend(&encoded[0..(chunk.len() + 1)]);
+        }
+
+        if self.format == Base85Format::Ascii85Adobe {
+            out.extend(b"~>");
+        }
+
+        Ok(out)
+    }
+
+    /// Does the buffer have a character that can't be Base64 (or hex)?
+    ///
+    /// Base85 alphabets cover almost every printable character, so almost any
+    /// string decodes; if it'd work as Base64, Base85 is very unlikely.
+    fn has_non_base64(buffer: &[u8]) -> bool {
+        buffer.iter().any(|b| !(b.is_ascii_alphanumeric() || b"+/=-_".contains(b)))
+    }
+
+    /// Are the buffer's symbols (anything that isn't a letter or digit) a
+    /// plausible mix for Base85?
+    ///
+    /// Encoded data uses its symbols about evenly, whereas text formats like
+    /// percent-encoding lean on one symbol (`%`) - so if any single symbol is
+    /// more than half of them, it's probably not Base85.
+    fn has_symbol_mix(buffer: &[u8]) -> bool {
+        let mut counts = [0usize; 256];
+        let mut total = 0;
+
+        for b in buffer.iter().filter(|b| !b.is_ascii_alphanumeric()) {
+            counts[*b as usize] += 1;
+            total += 1;
+        }
+
+        counts.iter().max().unwrap_or(&0) * 2 <= total
+    }
+}
+
+impl TransformerTrait for TransformBase85 {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let out = self.decode(buffer)?;
+
+        // Ensure it encodes to the same length - we can't handle length changes
+        // (this also catches Ascii85 zeroes that weren't written as 'z')
+        if self.encode(&out)?.len() != buffer.len() {
+            bail!("{:?} didn't decode correctly", self.format);
+        }
+
+        Ok(out)
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        self.encode(buffer)
+    }
+
+    fn is_two_way(&self) -> bool {
+        true
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        let mut out: Vec<_> = Vec::new();
+
+        // Adobe's wrapper is distinctive enough on its own
+        let t = Self::ascii85_adobe();
+        if t.can_transform(buffer) {
+            out.push(t);
+        }
+
+        let sample = &buffer[..buffer.len().min(DETECT_SAMPLE_SIZE)];
+        if Self::has_non_base64(buffer) && Self::has_symbol_mix(sample) {
+            for t in [Self::ascii85(), Self::z85(), Self::rfc1924()] {
+                if t.can_transform(buffer) {
+                    out.push(t);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_ascii85() -> SimpleResult<()> {
+        let t = TransformBase85::ascii85();
+        assert!(t.is_two_way());
+
+        // >>> base64.a85encode(b'Hello, world')
+        assert_eq!(b"Hello, world".to_vec(), t.transform(&b"87cURD_*#TDfTZ)".to_vec())?);
+        assert_eq!(b"87cURD_*#TDfTZ)".to_vec(), t.untransform(&b"Hello, world".to_vec())?);
+
+        // Zeroes become 'z', and partial groups work
+        assert_eq!(b"\x00\x00\x00\x00AB".to_vec(), t.transform(&b"z5sb".to_vec())?);
+        assert_eq!(b"z5sb".to_vec(), t.untransform(&b"\x00\x00\x00\x00AB".to_vec())?);
+        assert_eq!(b"A".to_vec(), t.transform(&b"5l".to_vec())?);
+
+        // Zeroes that aren't written as 'z' can't be reproduced
+        assert!(t.transform(&b"!!!!!".to_vec()).is_err());
+
+        // Errors
+        assert!(t.transform(&b"5".to_vec()).is_err());
+        assert!(t.transform(&b"5lz".to_vec()).is_err());
+        assert!(t.transform(&b"87cU~".to_vec()).is_err());
+        assert!(t.transform(&b"uuuuu".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ascii85_adobe() -> SimpleResult<()> {
+        let t = TransformBase85::ascii85_adobe();
+        assert!(t.is_two_way());
+
+        // >>> base64.a85encode(b'Hello, world', adobe=True)
+        assert_eq!(b"Hello, world".to_vec(), t.transform(&b"<~87cURD_*#TDfTZ)~>".to_vec())?);
+        assert_eq!(b"<~87cURD_*#TDfTZ)~>".to_vec(), t.untransform(&b"Hello, world".to_vec())?);
+
+        // The wrapper is required
+        assert!(t.transform(&b"87cURD_*#TDfTZ)".to_vec()).is_err());
+        assert!(t.transform(&b"<~87cURD_*#TDfTZ)".to_vec()).is_err());
+        assert!(t.transform(&b"<~>".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_z85() -> SimpleResult<()> {
+        let t = TransformBase85::z85();
+        assert!(t.is_two_way());
+
+        // The test vector from the spec
+        assert_eq!(b"\x86\x4f\xd2\x6f\xb5\x59\xf7\x5b".to_vec(), t.transform(&b"HelloWorld".to_vec())?);
+        assert_eq!(b"HelloWorld".to_vec(), t.untransform(&b"\x86\x4f\xd2\x6f\xb5\x59\xf7\x5b".to_vec())?);
+
+        // Only multiples of 4 bytes / 5 characters
+        assert!(t.transform(&b"Hello".to_vec()).is_ok());
+        assert!(t.transform(&b"Hell".to_vec()).is_err());
+        assert!(t.untransform(&b"ABC".to_vec()).is_err());
+
+        // Bad characters
+        assert!(t.transform(&b"Hello~orld".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rfc1924() -> SimpleResult<()> {
+        let t = TransformBase85::rfc1924();
+        assert!(t.is_two_way());
+
+        // >>> base64.b85encode(b'Hello, world')
+        assert_eq!(b"Hello, world".to_vec(), t.transform(&b"NM&qnZ!92pZ*pv8".to_vec())?);
+        assert_eq!(b"NM&qnZ!92pZ*pv8".to_vec(), t.untransform(&b"Hello, world".to_vec())?);
+
+        // No 'z' shortcut here
+        assert_eq!(b"\x00\x00\x00\x00AB".to_vec(), t.transform(&b"00000K|%".to_vec())?);
+        assert_eq!(b"A".to_vec(), t.transform(&b"K>".to_vec())?);
+
+        // Bad characters
+        assert!(t.transform(&b"NM&qn\"".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect() -> SimpleResult<()> {
+        let mut detected = TransformBase85::detect(&b"<~87cURD_*#TDfTZ)~>".to_vec());
+        detected.sort();
+        assert_eq!(vec![TransformBase85::ascii85_adobe(), TransformBase85::rfc1924()], detected);
+
+        let mut detected = TransformBase85::detect(&b"87cURD_*#TDfTZ)".to_vec());
+        detected.sort();
+        assert_eq!(vec![TransformBase85::ascii85(), TransformBase85::rfc1924()], detected);
+
+        // Anything that could be Base64 is left alone
+        assert_eq!(Vec::<Transformation>::new(), TransformBase85::detect(&b"AAAAAAAA".to_vec()));
+
+        // So is text that leans on one symbol, like percent-encoding
+        assert_eq!(Vec::<Transformation>::new(), TransformBase85::detect(&b"a+b%2Bc%21".to_vec()));
+        assert_eq!(Vec::<Transformation>::new(), TransformBase85::detect(&b"hello%20world%21%3F".to_vec()));
+
+        // Z85 is mostly letters and digits, but its symbols are still mixed
+        assert_eq!(vec![TransformBase85::z85()], TransformBase85::detect(&b"nm=QNz.92Pz/PV8".to_vec()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait};
+use crate::transformation::best_single_byte_candidate;
+
+/// Which operation to perform. Each of these undoes itself.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub enum BitwiseOperation {
+    /// Flip every bit (`0x12` -> `0xed`).
+    Not,
+
+    /// Swap the high and low four bits of each byte (`0x12` -> `0x21`).
+    NibbleSwap,
+
+    /// Reverse the order of the bits in each element, where the element is
+    /// 1, 2, 4, or 8 bytes (`0x12` -> `0x48`).
+    ReverseBits(usize),
+
+    /// Reverse the order of the bytes in each element, where the element is
+    /// 2, 4, or 8 bytes (`0x1234` -> `0x3412`). This converts between big-
+    /// and little-endian.
+    ByteSwap(usize),
+}
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformBitwise {
+    operation: BitwiseOperation,
+}
+
+impl fmt::Display for TransformBitwise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformBitwise {
+    pub fn new(operation: BitwiseOperation) -> SimpleResult<Transformation> {
+        Self::check_operation(operation)?;
+
+        Ok(Transformation::Bitwise(Self {
+            operation,
+        }))
+    }
+
+    /// Make sure the element width makes sense for the operation. This is
+    /// checked again before transforming, since a deserialized
+    /// transformation doesn't go through [`Self::new`].
+    fn check_operation(operation: BitwiseOperation) -> SimpleResult<()> {
+        match operation {
+            BitwiseOperation::ReverseBits(width) if ![1, 2, 4, 8].contains(&width) => {
+                bail!("Can't reverse the bits in a {}-byte element", width);
+            },
+            BitwiseOperation::ByteSwap(width) if ![2, 4, 8].contains(&width) => {
+                bail!("Can't swap the bytes in a {}-byte element", width);
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn not() -> Transformation {
+        Transformation::Bitwise(Self {
+            operation: BitwiseOperation::Not,
+        })
+    }
+
+    pub fn nibble_swap() -> Transformation {
+        Transformation::Bitwise(Self {
+            operation: BitwiseOperation::NibbleSwap,
+        })
+    }
+
+    pub fn reverse_bits(width: usize) -> SimpleResult<Transformation> {
+        Self::new(BitwiseOperation::ReverseBits(width))
+    }
+
+    pub fn byte_swap(width: usize) -> SimpleResult<Transformation> {
+        Self::new(BitwiseOperation::ByteSwap(width))
+    }
+
+    /// The size of each element, in bytes.
+    fn width(&self) -> usize {
+        match self.operation {
+            BitwiseOperation::Not | BitwiseOperation::NibbleSwap => 1,
+            BitwiseOperation::ReverseBits(width) | BitwiseOperation::ByteSwap(width) => width,
+        }
+    }
+}
+
+impl TransformerTrait for TransformBitwise {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        Self::check_operation(self.operation)?;
+
+        if !self.can_transform(buffer) {
+            bail!("Bitwise operation failed: the buffer isn't a multiple of the element size");
+        }
+
+        Ok(match self.operation {
+            BitwiseOperation::Not        => buffer.iter().map(|b| !b).collect(),
+            BitwiseOperation::NibbleSwap => buffer.iter().map(|b| b.rotate_left(4)).collect(),
+
+            // Reversing the bits in an element is the same as reversing the
+            // bytes, then the bits in each byte
+            BitwiseOperation::ReverseBits(width) => buffer.chunks(width).flat_map(|chunk| {
+                chunk.iter().rev().map(|b| b.reverse_bits()).collect::<Vec<u8>>()
+            }).collect(),
+            BitwiseOperation::ByteSwap(width) => buffer.chunks(width).flat_map(|chunk| {
+                chunk.iter().rev().copied().collect::<Vec<u8>>()
+            }).collect(),
+        })
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        // Every operation undoes itself
+        self.transform(buffer)
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        Self::check_operation(self.operation).is_ok() && buffer.len().is_multiple_of(self.width())
+    }
+
+    fn is_two_way(&self) -> bool {
+        true
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        // Swapping bytes doesn't change how printable anything is, so only
+        // the operations that change each byte are worth trying
+        let candidates = vec![
+            Self::not(),
+            Self::nibble_swap(),
+            Transformation::Bitwise(Self { operation: BitwiseOperation::ReverseBits(1) }),
+        ];
+
+        best_single_byte_candidate(buffer, candidates).into_iter().map(|(t, _)| t).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_not_and_nibble_swap() -> SimpleResult<()> {
+        let t = TransformBitwise::not();
+        assert!(t.is_two_way());
+        assert_eq!(vec![0xed, 0xff, 0x00], t.transform(&vec![0x12, 0x00, 0xff])?);
+        assert_eq!(vec![0x12, 0x00, 0xff], t.untransform(&vec![0xed, 0xff, 0x00])?);
+
+        let t = TransformBitwise::nibble_swap();
+        assert!(t.is_two_way());
+        assert_eq!(vec![0x21, 0x00, 0xba], t.transform(&vec![0x12, 0x00, 0xab])?);
+        assert_eq!(vec![0x12, 0x00, 0xab], t.untransform(&vec![0x21, 0x00, 0xba])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_bits() -> SimpleResult<()> {
+        let t = TransformBitwise::reverse_bits(1)?;
+        assert!(t.is_two_way());
+        assert_eq!(vec![0x48, 0x80, 0xf0], t.transform(&vec![0x12, 0x01, 0x0f])?);
+        assert_eq!(vec![0x12, 0x01, 0x0f], t.untransform(&vec![0x48, 0x80, 0xf0])?);
+
+        let t = TransformBitwise::reverse_bits(2)?;
+        assert_eq!(vec![0x80, 0x48], t.transform(&vec![0x12, 0x01])?);
+
+        let t = TransformBitwise::reverse_bits(8)?;
+        assert_eq!(vec![0x80, 0, 0, 0, 0, 0, 0, 0], t.transform(&vec![0, 0, 0, 0, 0, 0, 0, 1])?);
+        assert!(t.transform(&vec![0, 0, 0, 0]).is_err());
+
+        assert!(TransformBitwise::reverse_bits(0).is_err());
+        assert!(TransformBitwise::reverse_bits(3).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_byte_swap() -> SimpleResult<()> {
+        let t = TransformBitwise::byte_swap(2)?;
+        assert!(t.is_two_way());
+        assert_eq!(vec![0x34, 0x12, 0x78, 0x56], t.transform(&vec![0x12, 0x34, 0x56, 0x78])?);
+        assert_eq!(vec![0x12, 0x34, 0x56, 0x78], t.untransform(&vec![0x34, 0x12, 0x78, 0x56])?);
+
+        let t = TransformBitwise::byte_swap(4)?;
+        assert_eq!(vec![0x78, 0x56, 0x34, 0x12], t.transform(&vec![0x12, 0x34, 0x56, 0x78])?);
+
+        let t = TransformBitwise::byte_swap(8)?;
+        assert_eq!(vec![8, 7, 6, 5, 4, 3, 2, 1], t.transform(&vec![1, 2, 3, 4, 5, 6, 7, 8])?);
+        assert!(!t.can_transform(&vec![1, 2, 3, 4]));
+        assert!(t.transform(&vec![1, 2, 3, 4]).is_err());
+
+        assert!(TransformBitwise::byte_swap(1).is_err());
+        assert!(TransformBitwise::byte_swap(16).is_err());
+
+        // Bad widths that skipped new() are still caught
+        let t: Transformation = serde_json::from_str(r#"{"Bitwise":{"operation":{"ByteSwap":0}}}"#).unwrap();
+        assert!(!t.can_transform(&vec![1, 2, 3, 4]));
+        assert!(t.transform(&vec![1, 2, 3, 4]).is_err());
+        assert!(t.untransform(&vec![1, 2, 3, 4]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect() -> SimpleResult<()> {
+        let text = b"Here is some perfectly normal text, which has been obfuscated by swapping the nibbles of each byte.".to_vec();
+
+        let encoded = TransformBitwise::nibble_swap().untransform(&text)?;
+        assert_eq!(vec![TransformBitwise::nibble_swap()], TransformBitwise::detect(&encoded));
+
+        let encoded = TransformBitwise::not().untransform(&text)?;
+        assert_eq!(vec![TransformBitwise::not()], TransformBitwise::detect(&encoded));
+
+        let encoded = TransformBitwise::reverse_bits(1)?.untransform(&text)?;
+        assert_eq!(vec![TransformBitwise::reverse_bits(1)?], TransformBitwise::detect(&encoded));
+
+        // Text doesn't need to be changed
+        assert_eq!(Vec::<Transformation>::new(), TransformBitwise::detect(&text));
+
+        Ok(())
+    }
+}
@@ -1,12 +1,24 @@
 use aes::{Aes128, Aes192, Aes256};
+use aes_gcm::AesGcm;
+use aes_gcm::aead::{Aead, NewAead};
+use blowfish::Blowfish;
 use block_modes::block_padding::{NoPadding, Pkcs7, ZeroPadding};
-use block_modes::{BlockMode, Ecb, Cbc, Cfb};
-use des::Des;
+use block_modes::{BlockMode, Ecb, Cbc, Cfb, Ofb};
+use ccm::Ccm;
+use cipher::{BlockCipher, NewBlockCipher};
+use cipher::consts::{U7, U8, U9, U10, U11, U12, U13, U16};
+use cipher::generic_array::GenericArray;
+use des::{Des, TdesEde2, TdesEde3};
 use serde::{Serialize, Deserialize};
 use simple_error::{SimpleResult, bail};
 use std::fmt;
 
-use crate::transformation::{Transformation, TransformerTrait, KeyOrIV};
+use crate::transformation::{Transformation, TransformerTrait, KeyDerivation, CipherSources};
+use crate::transformation::{Camellia128, Camellia192, Camellia256, Rc2, Sm4, Twofish128, Twofish192, Twofish256};
+
+/// The size of the authentication tag that's appended to GCM and CCM
+/// ciphertext.
+const AEAD_TAG_SIZE: usize = 16;
 
 /// A macro to simplify decryption - lets us pass a class name as an argument
 /// (and greatly simplifies the code)
@@ -34,6 +46,80 @@ macro_rules! encrypt {
     };
 }
 
+/// Call one of the generic functions below with the cipher implementation
+/// that matches the settings (the key size picks between variants, like
+/// AES-128 and AES-256).
+macro_rules! with_cipher {
+    ($self:expr, $function:ident, $($arg:expr),*) => {
+        match ($self.cipher, $self.cipher_key().len()) {
+            (BlockCipherType::AES, 16)       => Self::$function::<Aes128>($self, $($arg),*),
+            (BlockCipherType::AES, 24)       => Self::$function::<Aes192>($self, $($arg),*),
+            (BlockCipherType::AES, 32)       => Self::$function::<Aes256>($self, $($arg),*),
+            (BlockCipherType::Twofish, 16)   => Self::$function::<Twofish128>($self, $($arg),*),
+            (BlockCipherType::Twofish, 24)   => Self::$function::<Twofish192>($self, $($arg),*),
+            (BlockCipherType::Twofish, 32)   => Self::$function::<Twofish256>($self, $($arg),*),
+            (BlockCipherType::Camellia, 16)  => Self::$function::<Camellia128>($self, $($arg),*),
+            (BlockCipherType::Camellia, 24)  => Self::$function::<Camellia192>($self, $($arg),*),
+            (BlockCipherType::Camellia, 32)  => Self::$function::<Camellia256>($self, $($arg),*),
+            (BlockCipherType::SM4, 16)       => Self::$function::<Sm4>($self, $($arg),*),
+            (_, _) => with_narrow_cipher!($self, $function, $($arg),*),
+        }
+    };
+}
+
+/// Like [`with_cipher!`], but only for ciphers with a 64-bit block.
+///
+/// These are separate because the AEAD modes and XTS only work with 128-bit
+/// blocks, so they can't be instantiated with these.
+macro_rules! with_narrow_cipher {
+    ($self:expr, $function:ident, $($arg:expr),*) => {
+        match ($self.cipher, $self.cipher_key().len()) {
+            (BlockCipherType::DES, 8)        => Self::$function::<Des>($self, $($arg),*),
+            (BlockCipherType::TripleDES, 16) => Self::$function::<TdesEde2>($self, $($arg),*),
+            (BlockCipherType::TripleDES, 24) => Self::$function::<TdesEde3>($self, $($arg),*),
+            (BlockCipherType::Blowfish, _)   => Self::$function::<Blowfish>($self, $($arg),*),
+            (BlockCipherType::RC2, _)        => Self::$function::<Rc2>($self, $($arg),*),
+            (_, _) => bail!("Invalid key size for {:?}", $self.cipher),
+        }
+    };
+}
+
+/// Like [`with_cipher!`], but only for ciphers with a 128-bit block.
+macro_rules! with_wide_cipher {
+    ($self:expr, $function:ident, $($arg:expr),*) => {
+        match ($self.cipher, $self.cipher_key().len()) {
+            (BlockCipherType::AES, 16)       => Self::$function::<Aes128>($self, $($arg),*),
+            (BlockCipherType::AES, 24)       => Self::$function::<Aes192>($self, $($arg),*),
+            (BlockCipherType::AES, 32)       => Self::$function::<Aes256>($self, $($arg),*),
+            (BlockCipherType::Twofish, 16)   => Self::$function::<Twofish128>($self, $($arg),*),
+            (BlockCipherType::Twofish, 24)   => Self::$function::<Twofish192>($self, $($arg),*),
+            (BlockCipherType::Twofish, 32)   => Self::$function::<Twofish256>($self, $($arg),*),
+            (BlockCipherType::Camellia, 16)  => Self::$function::<Camellia128>($self, $($arg),*),
+            (BlockCipherType::Camellia, 24)  => Self::$function::<Camellia192>($self, $($arg),*),
+            (BlockCipherType::Camellia, 32)  => Self::$function::<Camellia256>($self, $($arg),*),
+            (BlockCipherType::SM4, 16)       => Self::$function::<Sm4>($self, $($arg),*),
+            (_, _) => bail!("{:?} mode requires a cipher with a 128-bit block", $self.mode),
+        }
+    };
+}
+
+/// Call the CCM implementation for the nonce size (it has to be known at
+/// compile time).
+macro_rules! ccm {
+    ($cipher:ident, $key:expr, $nonce:expr, $function:ident, $buffer:expr) => {
+        match $nonce.len() {
+            7  => Ccm::<$cipher, U16, U7>::new(GenericArray::from_slice($key)).$function(GenericArray::from_slice($nonce), $buffer),
+            8  => Ccm::<$cipher, U16, U8>::new(GenericArray::from_slice($key)).$function(GenericArray::from_slice($nonce), $buffer),
+            9  => Ccm::<$cipher, U16, U9>::new(GenericArray::from_slice($key)).$function(GenericArray::from_slice($nonce), $buffer),
+            10 => Ccm::<$cipher, U16, U10>::new(GenericArray::from_slice($key)).$function(GenericArray::from_slice($nonce), $buffer),
+            11 => Ccm::<$cipher, U16, U11>::new(GenericArray::from_slice($key)).$function(GenericArray::from_slice($nonce), $buffer),
+            12 => Ccm::<$cipher, U16, U12>::new(GenericArray::from_slice($key)).$function(GenericArray::from_slice($nonce), $buffer),
+            13 => Ccm::<$cipher, U16, U13>::new(GenericArray::from_slice($key)).$function(GenericArray::from_slice($nonce), $buffer),
+            _  => bail!("Invalid nonce size for CCM (must be 7 - 13 bytes)"),
+        }
+    };
+}
+
 /// How should the ciphertext's padding be validated?
 ///
 /// If in doubt, use [`BlockCipherPadding::NoPadding`]. You'll see the padding in
@@ -70,6 +156,9 @@ pub enum BlockCipherPadding {
 ///
 /// The key length is determined at runtime by the length of key. So whether
 /// you need `AES128`, `AES192`, or `AES256` will be sorted out at runtime.
+///
+/// In [`BlockCipherMode::XTS`] mode, the key is twice as long as usual (it's
+/// two keys back to back).
 #[allow(non_camel_case_types)]
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
 pub enum BlockCipherType {
@@ -78,6 +167,26 @@ pub enum BlockCipherType {
 
     /// DES (64-bit keys, 64-bit IVs)
     DES,
+
+    /// Triple DES / 3DES in EDE mode (128-bit keys for two-key 3DES, or
+    /// 192-bit keys for three-key, 64-bit IVs)
+    TripleDES,
+
+    /// Blowfish (32 to 448-bit keys, 64-bit IVs)
+    Blowfish,
+
+    /// Twofish (128, 192, or 256-bit keys, 128-bit IVs)
+    Twofish,
+
+    /// Camellia (128, 192, or 256-bit keys, 128-bit IVs)
+    Camellia,
+
+    /// RC2 (8 to 1024-bit keys, 64-bit IVs); the effective key length is
+    /// the length of the key
+    RC2,
+
+    /// SM4 (128-bit keys, 128-bit IVs)
+    SM4,
 }
 
 /// Which mode of operation should we use?
@@ -103,26 +212,75 @@ pub enum BlockCipherMode {
     ///
     /// Each block of ciphertext is encrypted then XORed with the plaintext.
     CFB,
+
+    /// Output Feedback
+    ///
+    /// The IV is encrypted over and over, and the results are XORed with the
+    /// plaintext.
+    OFB,
+
+    /// Counter
+    ///
+    /// The IV is a big-endian counter that's encrypted and XORed with the
+    /// plaintext, then incremented for the next block. Works on any length,
+    /// and doesn't use padding.
+    CTR,
+
+    /// Galois/Counter Mode
+    ///
+    /// Authenticated counter mode; the IV is the nonce (96 or 128 bits), and
+    /// the 128-bit tag is at the end of the ciphertext. The tag is verified
+    /// when decrypting, and recalculated (and appended) when encrypting.
+    /// Requires a cipher with a 128-bit block, and doesn't use padding.
+    GCM,
+
+    /// Counter with CBC-MAC
+    ///
+    /// Authenticated counter mode; the IV is the nonce (7 - 13 bytes), and
+    /// the 128-bit tag is at the end of the ciphertext. Like GCM, the tag is
+    /// verified when decrypting and appended when encrypting. Requires a
+    /// cipher with a 128-bit block, and doesn't use padding.
+    CCM,
+
+    /// XEX-based tweaked-codebook mode
+    ///
+    /// Used for disk encryption. The data is split into sectors, each of
+    /// which is encrypted with a different tweak; the IV is the first
+    /// sector's number (a 128-bit little endian integer - 0 if it's not set),
+    /// and it's incremented for each sector. The key is two keys back to
+    /// back. Requires a cipher with a 128-bit block, and the data must be a
+    /// multiple of the block size (ciphertext stealing isn't supported).
+    XTS {
+        /// The size of each sector, in bytes (must be a multiple of 16).
+        sector_size: u64,
+    },
 }
 
 /// Configures a block cipher.
 ///
 /// This configures all the settings for a block cipher together, in a single
 /// serializable place.
-#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TransformBlockCipher {
     cipher: BlockCipherType,
     mode: BlockCipherMode,
     padding: BlockCipherPadding,
-    key: KeyOrIV,
-    iv: Option<KeyOrIV>,
+    key: Vec<u8>,
+    iv: Option<Vec<u8>>,
+
+    /// How the key and IV were derived, if they were.
+    derivation: Option<Box<KeyDerivation>>,
+
+    /// Where the key and IV came from, if they came from a
+    /// [`crate::transformation::CipherTemplate`].
+    sources: Option<Box<CipherSources>>,
 }
 
 impl fmt::Display for TransformBlockCipher {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.iv {
-            Some(iv) => write!(f, "TransformBlockCipher {{ cipher: {:?}, mode: {:?}, padding: {:?}, key: {}, IV: {} }}", self.cipher, self.mode, self.padding, self.key, iv),
-            None => write!(f, "TransformBlockCipher {{ cipher: {:?}, mode: {:?}, padding: {:?}, key: {}, IV: n/a }}", self.cipher, self.mode, self.padding, self.key),
+        match &self.iv {
+            Some(iv) => write!(f, "TransformBlockCipher {{ cipher: {:?}, mode: {:?}, padding: {:?}, key: {} ({} bits), IV: {} ({} bits) }}", self.cipher, self.mode, self.padding, hex::encode(&self.key), self.key.len() * 8, hex::encode(iv), iv.len() * 8),
+            None => write!(f, "TransformBlockCipher {{ cipher: {:?}, mode: {:?}, padding: {:?}, key: {} ({} bits), IV: n/a }}", self.cipher, self.mode, self.padding, hex::encode(&self.key), self.key.len() * 8),
         }
     }
 }
@@ -134,15 +292,6 @@ impl TransformBlockCipher {
     /// then they are "written in stone", so to speak - you can't change them
     /// without creating a new instance.
     pub fn new(cipher: BlockCipherType, mode: BlockCipherMode, padding: BlockCipherPadding, key: Vec<u8>, iv: Option<Vec<u8>>) -> SimpleResult<Transformation> {
-        // Validate and store the key
-        let key = KeyOrIV::new(key)?;
-
-        // Validate and store the IV (or a blank one)
-        let iv = match iv {
-            Some(iv) => Some(KeyOrIV::new(iv)?),
-            None => None,
-        };
-
         // Create the result so we can validate it
         let result = TransformBlockCipher {
             cipher: cipher,
@@ -150,6 +299,8 @@ impl TransformBlockCipher {
             padding: padding,
             key: key,
             iv: iv,
+            derivation: None,
+            sources: None,
         };
 
         // This validates the key length and iv and other characteristics
@@ -158,209 +309,461 @@ impl TransformBlockCipher {
         Ok(Transformation::FromBlockCipher(result))
     }
 
-    /// A helper function - ensure that the DES ciphertext length is sane.
-    fn des_check_length(length: usize) -> SimpleResult<()> {
-        if length % 8 != 0 {
-            bail!("DES length must be a multiple of 8 bytes / 64 bits");
-        }
+    /// Create a new instance of [`TransformBlockCipher`], with the key
+    /// derived from a passphrase.
+    ///
+    /// `key_length` is the length of the key, in bytes (the cipher determines
+    /// which lengths are valid). If `iv` is set, it's used as-is; otherwise,
+    /// the IV (or nonce) is derived along with the key, the same way OpenSSL
+    /// does it. The derivation is saved along with the key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use libh2gb::transformation::*;
+    ///
+    /// // Encrypted with:
+    /// // printf 'OpenSSL salted file' | openssl enc -aes-256-cbc -pbkdf2 -iter 1000 -md sha256 -pass pass:hunter2
+    /// let buffer = b"\x53\x61\x6c\x74\x65\x64\x5f\x5f\x2d\xbd\xeb\x48\x26\x6a\xe5\x82\x86\x2a\xae\x4c\x9b\x31\x30\x2e\x5f\x4b\x08\x4b\x8a\x67\x36\xfa\x8a\x2a\x68\xe5\x17\x49\x75\xf9\x37\x40\x42\x57\x26\x5b\x1c\x7a".to_vec();
+    ///
+    /// // The salt comes from the header
+    /// let derivation = KeyDerivation::from_openssl_header(&buffer, DigestType::SHA256, b"hunter2".to_vec(), Some(1000)).unwrap();
+    ///
+    /// let transformation = TransformBlockCipher::new_derived(
+    ///     BlockCipherType::AES,
+    ///     BlockCipherMode::CBC,
+    ///     BlockCipherPadding::Pkcs7,
+    ///     32,   // AES-256
+    ///     None, // Derive the IV too
+    ///     derivation,
+    /// ).unwrap();
+    ///
+    /// assert_eq!(b"OpenSSL salted file".to_vec(), transformation.transform(&buffer).unwrap());
+    /// ```
+    pub fn new_derived(cipher: BlockCipherType, mode: BlockCipherMode, padding: BlockCipherPadding, key_length: usize, iv: Option<Vec<u8>>, derivation: KeyDerivation) -> SimpleResult<Transformation> {
+        let (key, iv) = match iv {
+            Some(iv) => (derivation.derive(key_length, 0)?.0, Some(iv)),
+            None => derivation.derive(key_length, Self::derived_iv_length(cipher, mode))?,
+        };
 
-        Ok(())
+        let result = TransformBlockCipher {
+            cipher,
+            mode,
+            padding,
+            key,
+            iv,
+            derivation: Some(Box::new(derivation)),
+            sources: None,
+        };
+
+        result.validate_settings()?;
+
+        Ok(Transformation::FromBlockCipher(result))
+    }
+
+    /// Create a new instance of [`TransformBlockCipher`] from a resolved
+    /// [`crate::transformation::CipherTemplate`], keeping track of where the
+    /// key and IV came from.
+    pub(crate) fn new_with_sources(cipher: BlockCipherType, mode: BlockCipherMode, padding: BlockCipherPadding, key: Vec<u8>, iv: Option<Vec<u8>>, sources: CipherSources) -> SimpleResult<Transformation> {
+        let result = TransformBlockCipher {
+            cipher,
+            mode,
+            padding,
+            key,
+            iv,
+            derivation: None,
+            sources: Some(Box::new(sources)),
+        };
+
+        result.validate_settings()?;
+
+        Ok(Transformation::FromBlockCipher(result))
     }
 
-    /// A helper function - ensure that the AES ciphertext length is sane.
-    fn aes_check_length(length: usize) -> SimpleResult<()> {
-        if length % 16 != 0 {
-            bail!("AES length must be a multiple of 16 bytes / 128-bits");
+    /// Does the key or IV come from the given buffer?
+    pub fn references_buffer(&self, buffer: &str) -> bool {
+        self.sources.as_ref().is_some_and(|s| s.references_buffer(buffer))
+    }
+
+    /// How long of an IV to derive for a cipher and mode.
+    fn derived_iv_length(cipher: BlockCipherType, mode: BlockCipherMode) -> usize {
+        match mode {
+            BlockCipherMode::ECB => 0,
+
+            // 96 bits is the usual nonce size for both AEAD modes
+            BlockCipherMode::GCM => 12,
+            BlockCipherMode::CCM => 12,
+
+            _ => Self::cipher_block_size(cipher),
         }
+    }
 
-        Ok(())
+    /// The size of a single block for a cipher, in bytes.
+    fn cipher_block_size(cipher: BlockCipherType) -> usize {
+        match cipher {
+            BlockCipherType::AES       => 16,
+            BlockCipherType::DES       => 8,
+            BlockCipherType::TripleDES => 8,
+            BlockCipherType::Blowfish  => 8,
+            BlockCipherType::Twofish   => 16,
+            BlockCipherType::Camellia  => 16,
+            BlockCipherType::RC2       => 8,
+            BlockCipherType::SM4       => 16,
+        }
     }
 
-    /// Internal function to decrypt AES with any settings.
-    fn decrypt_aes(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        // A very quick sanity check
-        Self::aes_check_length(buffer.len())?;
+    /// The size of a single block, in bytes.
+    fn block_size(&self) -> usize {
+        Self::cipher_block_size(self.cipher)
+    }
 
-        // Get the iv, or a default blank one
-        let iv = match self.iv {
-            Some(iv) => iv.get128()?,
-            None     => [0; 16],
-        };
+    /// The bytes that come before the ciphertext - the key derivation's
+    /// header, and the key or IV if they're stored with the data.
+    fn header(&self) -> Vec<u8> {
+        let mut header = vec![];
 
-        Ok(match (self.key, self.mode, self.padding) {
-            (KeyOrIV::Bits128(k), BlockCipherMode::ECB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Ecb, Aes128, NoPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::ECB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Ecb, Aes192, NoPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::ECB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Ecb, Aes256, NoPadding),
+        if let Some(derivation) = &self.derivation {
+            header.extend(derivation.header().unwrap_or_default());
+        }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CBC, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Cbc, Aes128, NoPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CBC, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Cbc, Aes192, NoPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CBC, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Cbc, Aes256, NoPadding),
+        if let Some(sources) = &self.sources {
+            header.extend(sources.prefix(&self.key, self.iv.as_deref()));
+        }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CFB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Cfb, Aes128, NoPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CFB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Cfb, Aes192, NoPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CFB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, &k, &iv, Cfb, Aes256, NoPadding),
+        header
+    }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::ECB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Ecb, Aes128, Pkcs7),
-            (KeyOrIV::Bits192(k), BlockCipherMode::ECB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Ecb, Aes192, Pkcs7),
-            (KeyOrIV::Bits256(k), BlockCipherMode::ECB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Ecb, Aes256, Pkcs7),
+    /// Remove the header that comes before the ciphertext, if there is one.
+    fn strip_header(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        let header = self.header();
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CBC, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Cbc, Aes128, Pkcs7),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CBC, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Cbc, Aes192, Pkcs7),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CBC, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Cbc, Aes256, Pkcs7),
+        if !buffer.starts_with(&header) {
+            bail!("Buffer doesn't start with the expected header");
+        }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CFB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Cfb, Aes128, Pkcs7),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CFB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Cfb, Aes192, Pkcs7),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CFB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, &k, &iv, Cfb, Aes256, Pkcs7),
+        Ok(buffer[header.len()..].to_vec())
+    }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Ecb, Aes128, ZeroPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Ecb, Aes192, ZeroPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Ecb, Aes256, ZeroPadding),
+    /// The key that's used for the data (in XTS mode, this is the first half
+    /// of the key).
+    fn cipher_key(&self) -> &[u8] {
+        match self.mode {
+            BlockCipherMode::XTS { .. } => &self.key[..self.key.len() / 2],
+            _ => &self.key,
+        }
+    }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Cbc, Aes128, ZeroPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Cbc, Aes192, ZeroPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Cbc, Aes256, ZeroPadding),
+    /// The IV, or a blank one if it's not set.
+    fn iv_or_default(&self) -> Vec<u8> {
+        match &self.iv {
+            Some(iv) => iv.clone(),
+            None     => vec![0; self.block_size()],
+        }
+    }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Cfb, Aes128, ZeroPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Cfb, Aes192, ZeroPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Cfb, Aes256, ZeroPadding),
+    /// A helper function - ensure that the ciphertext length is sane.
+    fn check_length(&self, length: usize) -> SimpleResult<()> {
+        match self.mode {
+            BlockCipherMode::CTR => (),
+            BlockCipherMode::GCM | BlockCipherMode::CCM => {
+                if length < AEAD_TAG_SIZE {
+                    bail!("{:?} ciphertext must be at least {} bytes long (to hold the tag)", self.mode, AEAD_TAG_SIZE);
+                }
+            },
+            _ => {
+                if !length.is_multiple_of(self.block_size()) {
+                    bail!("{:?} length must be a multiple of {} bytes / {} bits", self.cipher, self.block_size(), self.block_size() * 8);
+                }
+            },
+        }
 
-            (_, _, _) => bail!("Invalid key size, mode, or padding"),
-        }.to_vec())
+        Ok(())
     }
 
-    /// Internal function to encrypt AES with any settings.
-    fn encrypt_aes(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        // Get the iv, or a default blank one
-        let iv = match self.iv {
-            Some(iv) => iv.get128()?,
-            None     => [0; 16],
-        };
+    /// Create an instance of the cipher with the given key.
+    fn get_cipher<C: NewBlockCipher>(key: &[u8]) -> SimpleResult<C> {
+        match C::new_varkey(key) {
+            Ok(c) => Ok(c),
+            Err(e) => bail!("Error setting up cipher: {}", e),
+        }
+    }
 
-        Ok(match (self.key, self.mode, self.padding) {
-            (KeyOrIV::Bits128(k), BlockCipherMode::ECB, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Ecb, Aes128, NoPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::ECB, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Ecb, Aes192, NoPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::ECB, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Ecb, Aes256, NoPadding),
+    /// Internal function to decrypt ECB, CBC, CFB, or OFB with any settings.
+    fn decrypt_block_mode<C: BlockCipher + NewBlockCipher>(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let key = self.cipher_key();
+        let iv = self.iv_or_default();
+
+        Ok(match (self.mode, self.padding) {
+            (BlockCipherMode::ECB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, key, &iv, Ecb, C, NoPadding),
+            (BlockCipherMode::CBC, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, key, &iv, Cbc, C, NoPadding),
+            (BlockCipherMode::CFB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, key, &iv, Cfb, C, NoPadding),
+            (BlockCipherMode::OFB, BlockCipherPadding::NoPadding)   => decrypt!(&buffer, key, &iv, Ofb, C, NoPadding),
+
+            (BlockCipherMode::ECB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, key, &iv, Ecb, C, Pkcs7),
+            (BlockCipherMode::CBC, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, key, &iv, Cbc, C, Pkcs7),
+            (BlockCipherMode::CFB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, key, &iv, Cfb, C, Pkcs7),
+            (BlockCipherMode::OFB, BlockCipherPadding::Pkcs7)       => decrypt!(&buffer, key, &iv, Ofb, C, Pkcs7),
+
+            (BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, key, &iv, Ecb, C, ZeroPadding),
+            (BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, key, &iv, Cbc, C, ZeroPadding),
+            (BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, key, &iv, Cfb, C, ZeroPadding),
+            (BlockCipherMode::OFB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, key, &iv, Ofb, C, ZeroPadding),
+
+            (_, _) => bail!("Invalid mode or padding"),
+        })
+    }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CBC, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Cbc, Aes128, NoPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CBC, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Cbc, Aes192, NoPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CBC, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Cbc, Aes256, NoPadding),
+    /// Internal function to encrypt ECB, CBC, CFB, or OFB with any settings.
+    fn encrypt_block_mode<C: BlockCipher + NewBlockCipher>(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let key = self.cipher_key();
+        let iv = self.iv_or_default();
+
+        Ok(match (self.mode, self.padding) {
+            (BlockCipherMode::ECB, BlockCipherPadding::NoPadding)   => encrypt!(&buffer, key, &iv, Ecb, C, NoPadding),
+            (BlockCipherMode::CBC, BlockCipherPadding::NoPadding)   => encrypt!(&buffer, key, &iv, Cbc, C, NoPadding),
+            (BlockCipherMode::CFB, BlockCipherPadding::NoPadding)   => encrypt!(&buffer, key, &iv, Cfb, C, NoPadding),
+            (BlockCipherMode::OFB, BlockCipherPadding::NoPadding)   => encrypt!(&buffer, key, &iv, Ofb, C, NoPadding),
+
+            (BlockCipherMode::ECB, BlockCipherPadding::Pkcs7)       => encrypt!(&buffer, key, &iv, Ecb, C, Pkcs7),
+            (BlockCipherMode::CBC, BlockCipherPadding::Pkcs7)       => encrypt!(&buffer, key, &iv, Cbc, C, Pkcs7),
+            (BlockCipherMode::CFB, BlockCipherPadding::Pkcs7)       => encrypt!(&buffer, key, &iv, Cfb, C, Pkcs7),
+            (BlockCipherMode::OFB, BlockCipherPadding::Pkcs7)       => encrypt!(&buffer, key, &iv, Ofb, C, Pkcs7),
+
+            (BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, key, &iv, Ecb, C, ZeroPadding),
+            (BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, key, &iv, Cbc, C, ZeroPadding),
+            (BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, key, &iv, Cfb, C, ZeroPadding),
+            (BlockCipherMode::OFB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, key, &iv, Ofb, C, ZeroPadding),
+
+            (_, _) => bail!("Invalid mode or padding"),
+        })
+    }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CFB, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Cfb, Aes128, NoPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CFB, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Cfb, Aes192, NoPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CFB, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Cfb, Aes256, NoPadding),
+    /// Internal function to encrypt or decrypt CTR mode (they're the same
+    /// operation).
+    fn crypt_ctr<C: BlockCipher + NewBlockCipher>(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        let cipher: C = Self::get_cipher(self.cipher_key())?;
+        let mut counter = self.iv_or_default();
+        let mut out = buffer.to_vec();
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::ECB, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Ecb, Aes128, Pkcs7),
-            (KeyOrIV::Bits192(k), BlockCipherMode::ECB, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Ecb, Aes192, Pkcs7),
-            (KeyOrIV::Bits256(k), BlockCipherMode::ECB, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Ecb, Aes256, Pkcs7),
+        for chunk in out.chunks_mut(self.block_size()) {
+            let mut keystream = GenericArray::clone_from_slice(&counter);
+            cipher.encrypt_block(&mut keystream);
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CBC, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Cbc, Aes128, Pkcs7),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CBC, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Cbc, Aes192, Pkcs7),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CBC, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Cbc, Aes256, Pkcs7),
+            for (b, k) in chunk.iter_mut().zip(keystream.iter()) {
+                *b ^= k;
+            }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CFB, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Cfb, Aes128, Pkcs7),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CFB, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Cfb, Aes192, Pkcs7),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CFB, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Cfb, Aes256, Pkcs7),
+            // Increment the counter as a big-endian number
+            for b in counter.iter_mut().rev() {
+                *b = b.wrapping_add(1);
+                if *b != 0 {
+                    break;
+                }
+            }
+        }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Ecb, Aes128, ZeroPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Ecb, Aes192, ZeroPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Ecb, Aes256, ZeroPadding),
+        Ok(out)
+    }
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Cbc, Aes128, ZeroPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Cbc, Aes192, ZeroPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Cbc, Aes256, ZeroPadding),
+    /// Internal function to decrypt GCM, and verify the tag.
+    fn decrypt_gcm<C: BlockCipher<BlockSize = U16> + NewBlockCipher>(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        let cipher: C = Self::get_cipher(self.cipher_key())?;
+        let nonce = self.iv_or_default();
 
-            (KeyOrIV::Bits128(k), BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Cfb, Aes128, ZeroPadding),
-            (KeyOrIV::Bits192(k), BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Cfb, Aes192, ZeroPadding),
-            (KeyOrIV::Bits256(k), BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Cfb, Aes256, ZeroPadding),
+        let result = match nonce.len() {
+            12 => AesGcm::<C, U12>::from(cipher).decrypt(GenericArray::from_slice(&nonce), buffer),
+            16 => AesGcm::<C, U16>::from(cipher).decrypt(GenericArray::from_slice(&nonce), buffer),
+            _  => bail!("Invalid nonce size for GCM (must be 96 or 128 bits)"),
+        };
 
-            (_, _, _) => bail!("Invalid key size, mode, or padding"),
-        }.to_vec())
+        match result {
+            Ok(d) => Ok(d),
+            Err(_) => bail!("Error decrypting buffer: GCM tag didn't match (wrong key, nonce, or corrupted data)"),
+        }
     }
 
-    /// Internal function to decrypt DES with any settings.
-    fn decrypt_des(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        Self::des_check_length(buffer.len())?;
+    /// Internal function to encrypt GCM; the tag is appended.
+    fn encrypt_gcm<C: BlockCipher<BlockSize = U16> + NewBlockCipher>(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        let cipher: C = Self::get_cipher(self.cipher_key())?;
+        let nonce = self.iv_or_default();
 
-        // Get the iv, or a default blank one
-        let iv = match self.iv {
-            Some(iv) => iv.get64()?,
-            None     => [0; 8],
+        let result = match nonce.len() {
+            12 => AesGcm::<C, U12>::from(cipher).encrypt(GenericArray::from_slice(&nonce), buffer),
+            16 => AesGcm::<C, U16>::from(cipher).encrypt(GenericArray::from_slice(&nonce), buffer),
+            _  => bail!("Invalid nonce size for GCM (must be 96 or 128 bits)"),
         };
 
-        Ok(match (self.key, self.mode, self.padding) {
-            (KeyOrIV::Bits64(k), BlockCipherMode::ECB, BlockCipherPadding::NoPadding) => decrypt!(&buffer, &k, &iv, Ecb, Des, NoPadding),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CBC, BlockCipherPadding::NoPadding) => decrypt!(&buffer, &k, &iv, Cbc, Des, NoPadding),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CFB, BlockCipherPadding::NoPadding) => decrypt!(&buffer, &k, &iv, Cfb, Des, NoPadding),
+        match result {
+            Ok(e) => Ok(e),
+            Err(_) => bail!("Error encrypting buffer with GCM"),
+        }
+    }
 
-            (KeyOrIV::Bits64(k), BlockCipherMode::ECB, BlockCipherPadding::Pkcs7) => decrypt!(&buffer, &k, &iv, Ecb, Des, Pkcs7),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CBC, BlockCipherPadding::Pkcs7) => decrypt!(&buffer, &k, &iv, Cbc, Des, Pkcs7),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CFB, BlockCipherPadding::Pkcs7) => decrypt!(&buffer, &k, &iv, Cfb, Des, Pkcs7),
+    /// Internal function to decrypt CCM, and verify the tag.
+    fn decrypt_ccm<C: BlockCipher<BlockSize = U16> + NewBlockCipher>(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        let nonce = self.iv_or_default();
 
-            (KeyOrIV::Bits64(k), BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Ecb, Des, ZeroPadding),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Cbc, Des, ZeroPadding),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => decrypt!(&buffer, &k, &iv, Cfb, Des, ZeroPadding),
+        match ccm!(C, self.cipher_key(), &nonce, decrypt, buffer) {
+            Ok(d) => Ok(d),
+            Err(_) => bail!("Error decrypting buffer: CCM tag didn't match (wrong key, nonce, or corrupted data)"),
+        }
+    }
 
-            (_, _, _) => bail!("Invalid key size, mode, or padding"),
-        }.to_vec())
+    /// Internal function to encrypt CCM; the tag is appended.
+    fn encrypt_ccm<C: BlockCipher<BlockSize = U16> + NewBlockCipher>(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        let nonce = self.iv_or_default();
+
+        match ccm!(C, self.cipher_key(), &nonce, encrypt, buffer) {
+            Ok(e) => Ok(e),
+            Err(_) => bail!("Error encrypting buffer with CCM"),
+        }
     }
 
-    /// Internal function to encrypt DES with any settings.
-    fn encrypt_des(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        // Get the iv, or a default blank one
-        let iv = match self.iv {
-            Some(iv) => iv.get64()?,
-            None     => [0; 8],
+    /// Internal function to encrypt or decrypt XTS.
+    fn crypt_xts<C: BlockCipher<BlockSize = U16> + NewBlockCipher>(&self, buffer: &[u8], decrypt: bool) -> SimpleResult<Vec<u8>> {
+        let sector_size = match self.mode {
+            BlockCipherMode::XTS { sector_size } => sector_size as usize,
+            _ => bail!("Not XTS mode"),
         };
 
-        Ok(match (self.key, self.mode, self.padding) {
-            (KeyOrIV::Bits64(k), BlockCipherMode::ECB, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Ecb, Des, NoPadding),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CBC, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Cbc, Des, NoPadding),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CFB, BlockCipherPadding::NoPadding) => encrypt!(&buffer, &k, &iv, Cfb, Des, NoPadding),
+        // The first half of the key is for the data, the second for the tweak
+        let data_cipher: C = Self::get_cipher(self.cipher_key())?;
+        let tweak_cipher: C = Self::get_cipher(&self.key[self.key.len() / 2..])?;
+
+        let mut first_sector = [0; 16];
+        first_sector.copy_from_slice(&self.iv_or_default());
+        let first_sector = u128::from_le_bytes(first_sector);
+
+        let mut out = buffer.to_vec();
+        for (i, sector) in out.chunks_mut(sector_size).enumerate() {
+            let mut tweak = GenericArray::clone_from_slice(&first_sector.wrapping_add(i as u128).to_le_bytes());
+            tweak_cipher.encrypt_block(&mut tweak);
+
+            for block in sector.chunks_mut(16) {
+                let mut b = GenericArray::clone_from_slice(block);
+                b.iter_mut().zip(tweak.iter()).for_each(|(b, t)| *b ^= t);
+                match decrypt {
+                    true  => data_cipher.decrypt_block(&mut b),
+                    false => data_cipher.encrypt_block(&mut b),
+                }
+                b.iter_mut().zip(tweak.iter()).for_each(|(b, t)| *b ^= t);
+                block.copy_from_slice(&b);
+
+                // Multiply the tweak by x in GF(2^128)
+                let mut t = [0; 16];
+                t.copy_from_slice(&tweak);
+                let t = u128::from_le_bytes(t);
+                let t = (t << 1) ^ ((t >> 127) * 0x87);
+                tweak = GenericArray::clone_from_slice(&t.to_le_bytes());
+            }
+        }
 
-            (KeyOrIV::Bits64(k), BlockCipherMode::ECB, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Ecb, Des, Pkcs7),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CBC, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Cbc, Des, Pkcs7),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CFB, BlockCipherPadding::Pkcs7) => encrypt!(&buffer, &k, &iv, Cfb, Des, Pkcs7),
+        Ok(out)
+    }
 
-            (KeyOrIV::Bits64(k), BlockCipherMode::ECB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Ecb, Des, ZeroPadding),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CBC, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Cbc, Des, ZeroPadding),
-            (KeyOrIV::Bits64(k), BlockCipherMode::CFB, BlockCipherPadding::ZeroPadding) => encrypt!(&buffer, &k, &iv, Cfb, Des, ZeroPadding),
+    fn decrypt_xts<C: BlockCipher<BlockSize = U16> + NewBlockCipher>(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        self.crypt_xts::<C>(buffer, true)
+    }
 
-            (_, _, _) => bail!("Invalid key size, mode, or padding"),
-        }.to_vec())
+    fn encrypt_xts<C: BlockCipher<BlockSize = U16> + NewBlockCipher>(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        self.crypt_xts::<C>(buffer, false)
     }
 
     /// Sanity check settings (key size, IV, etc).
-    fn validate_settings(self) -> SimpleResult<()> {
-        // Validate the iv for ECB mode
-        match (self.iv, self.mode) {
+    fn validate_settings(&self) -> SimpleResult<()> {
+        // Validate the padding - only the classic block modes use it
+        match (self.mode, self.padding) {
+            (BlockCipherMode::ECB, _) => (),
+            (BlockCipherMode::CBC, _) => (),
+            (BlockCipherMode::CFB, _) => (),
+            (BlockCipherMode::OFB, _) => (),
+            (_, BlockCipherPadding::NoPadding) => (),
+            (_, _) => bail!("{:?} mode doesn't use padding", self.mode),
+        };
+
+        // Validate the modes that only work with 128-bit blocks
+        match (self.mode, self.block_size()) {
+            (BlockCipherMode::GCM, 8) => bail!("GCM mode requires a cipher with a 128-bit block"),
+            (BlockCipherMode::CCM, 8) => bail!("CCM mode requires a cipher with a 128-bit block"),
+            (BlockCipherMode::XTS { .. }, 8) => bail!("XTS mode requires a cipher with a 128-bit block"),
+            (BlockCipherMode::XTS { sector_size }, _) => {
+                if sector_size == 0 || sector_size % 16 != 0 {
+                    bail!("XTS sector size must be a multiple of 16 bytes");
+                }
+            },
+            (_, _) => (),
+        };
+
+        // Validate the iv
+        match (&self.iv, self.mode) {
             // Don't allow an IV with ECB ever
             (Some(_), BlockCipherMode::ECB) => bail!("ECB is not compatible with IVs"),
 
+            // The AEAD modes need a nonce
+            (None, BlockCipherMode::GCM) => bail!("GCM requires a nonce (IV)"),
+            (None, BlockCipherMode::CCM) => bail!("CCM requires a nonce (IV)"),
+            (Some(iv), BlockCipherMode::GCM) => {
+                if iv.len() != 12 && iv.len() != 16 {
+                    bail!("Invalid nonce size for GCM (must be 96 or 128 bits)");
+                }
+            },
+            (Some(iv), BlockCipherMode::CCM) => {
+                if iv.len() < 7 || iv.len() > 13 {
+                    bail!("Invalid nonce size for CCM (must be 7 - 13 bytes)");
+                }
+            },
+
             // If the iv is set, make sure it's the correct length
             (Some(iv), _) => {
-                match (self.cipher, iv) {
-                    (BlockCipherType::AES, KeyOrIV::Bits128(_)) => (),
-                    (BlockCipherType::AES, _) => bail!("Invalid IV size for AES (must be 128 bits)"),
-
-                    (BlockCipherType::DES, KeyOrIV::Bits64(_)) => (),
-                    (BlockCipherType::DES, _) => bail!("Invalid IV size for DES (must be 64 bits)"),
+                if iv.len() != self.block_size() {
+                    bail!("Invalid IV size for {:?} (must be {} bits)", self.cipher, self.block_size() * 8);
                 }
             },
 
             // If no IV is set, we're probably okay
             (None, _) => (),
-
         };
 
+        // XTS uses two keys of the same size
+        if let BlockCipherMode::XTS { .. } = self.mode {
+            if !self.key.len().is_multiple_of(2) {
+                bail!("Invalid key size for XTS (must be two keys of the same size)");
+            }
+        }
+
         // Validate the key length
-        match (self.cipher, self.key) {
-            (BlockCipherType::AES, KeyOrIV::Bits128(_)) => (),
-            (BlockCipherType::AES, KeyOrIV::Bits192(_)) => (),
-            (BlockCipherType::AES, KeyOrIV::Bits256(_)) => (),
+        let key_length = self.cipher_key().len();
+        match (self.cipher, key_length) {
+            (BlockCipherType::AES, 16) => (),
+            (BlockCipherType::AES, 24) => (),
+            (BlockCipherType::AES, 32) => (),
             (BlockCipherType::AES, _) => bail!("Invalid key size for AES (must be 128, 192, or 256 bits)"),
 
-            (BlockCipherType::DES, KeyOrIV::Bits64(_)) => (),
+            (BlockCipherType::DES, 8) => (),
             (BlockCipherType::DES, _) => bail!("Invalid key size for DES (must be 64 bits)"),
+
+            (BlockCipherType::TripleDES, 16) => (),
+            (BlockCipherType::TripleDES, 24) => (),
+            (BlockCipherType::TripleDES, _) => bail!("Invalid key size for 3DES (must be 128 or 192 bits)"),
+
+            (BlockCipherType::Blowfish, 4..=56) => (),
+            (BlockCipherType::Blowfish, _) => bail!("Invalid key size for Blowfish (must be 32 - 448 bits)"),
+
+            (BlockCipherType::Twofish, 16) => (),
+            (BlockCipherType::Twofish, 24) => (),
+            (BlockCipherType::Twofish, 32) => (),
+            (BlockCipherType::Twofish, _) => bail!("Invalid key size for Twofish (must be 128, 192, or 256 bits)"),
+
+            (BlockCipherType::Camellia, 16) => (),
+            (BlockCipherType::Camellia, 24) => (),
+            (BlockCipherType::Camellia, 32) => (),
+            (BlockCipherType::Camellia, _) => bail!("Invalid key size for Camellia (must be 128, 192, or 256 bits)"),
+
+            (BlockCipherType::RC2, 1..=128) => (),
+            (BlockCipherType::RC2, _) => bail!("Invalid key size for RC2 (must be 8 - 1024 bits)"),
+
+            (BlockCipherType::SM4, 16) => (),
+            (BlockCipherType::SM4, _) => bail!("Invalid key size for SM4 (must be 128 bits)"),
         };
 
         Ok(())
@@ -372,20 +775,43 @@ impl TransformerTrait for TransformBlockCipher {
     fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
         self.validate_settings()?;
 
-        match self.cipher {
-            BlockCipherType::AES => self.decrypt_aes(buffer),
-            BlockCipherType::DES => self.decrypt_des(buffer),
+        let buffer = &self.strip_header(buffer)?;
+        self.check_length(buffer.len())?;
+
+        match self.mode {
+            BlockCipherMode::ECB         => with_cipher!(self, decrypt_block_mode, buffer),
+            BlockCipherMode::CBC         => with_cipher!(self, decrypt_block_mode, buffer),
+            BlockCipherMode::CFB         => with_cipher!(self, decrypt_block_mode, buffer),
+            BlockCipherMode::OFB         => with_cipher!(self, decrypt_block_mode, buffer),
+            BlockCipherMode::CTR         => with_cipher!(self, crypt_ctr, buffer),
+            BlockCipherMode::GCM         => with_wide_cipher!(self, decrypt_gcm, buffer),
+            BlockCipherMode::CCM         => with_wide_cipher!(self, decrypt_ccm, buffer),
+            BlockCipherMode::XTS { .. }  => with_wide_cipher!(self, decrypt_xts, buffer),
         }
     }
 
     /// transform() =~ encrypt
+    ///
+    /// In GCM and CCM modes, the (newly calculated) tag is appended to the
+    /// ciphertext. If the key derivation uses a header, it's prepended.
     fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
         self.validate_settings()?;
 
-        match self.cipher {
-            BlockCipherType::AES => self.encrypt_aes(buffer),
-            BlockCipherType::DES => self.encrypt_des(buffer),
-        }
+        let result = match self.mode {
+            BlockCipherMode::ECB         => with_cipher!(self, encrypt_block_mode, buffer),
+            BlockCipherMode::CBC         => with_cipher!(self, encrypt_block_mode, buffer),
+            BlockCipherMode::CFB         => with_cipher!(self, encrypt_block_mode, buffer),
+            BlockCipherMode::OFB         => with_cipher!(self, encrypt_block_mode, buffer),
+            BlockCipherMode::CTR         => with_cipher!(self, crypt_ctr, buffer),
+            BlockCipherMode::GCM         => with_wide_cipher!(self, encrypt_gcm, buffer),
+            BlockCipherMode::CCM         => with_wide_cipher!(self, encrypt_ccm, buffer),
+            BlockCipherMode::XTS { .. }  => {
+                self.check_length(buffer.len())?;
+                with_wide_cipher!(self, encrypt_xts, buffer)
+            },
+        }?;
+
+        Ok([self.header(), result].concat())
     }
 
     fn is_two_way(&self) -> bool {
@@ -434,18 +860,23 @@ impl TransformerTrait for TransformBlockCipher {
     }
 
     fn can_transform(&self, buffer: &Vec<u8>) -> bool {
-        // Check that the ciphertext is a multiple of the blocksize
-        match self.cipher {
-            BlockCipherType::AES => {
-                if buffer.len() % 16 != 0 {
-                    return false;
-                }
-            },
-            BlockCipherType::DES => {
-                if buffer.len() % 8 != 0 {
-                    return false;
-                }
-            },
+        // Make sure the header is there, if there's supposed to be one
+        let stripped = match self.strip_header(buffer) {
+            Ok(b) => b,
+            Err(_) => return false,
+        };
+
+        // Check that the ciphertext is a sane length (a multiple of the
+        // blocksize, for most modes)
+        if self.check_length(stripped.len()).is_err() {
+            return false;
+        }
+
+        // The AEAD modes can always tell, thanks to the tag
+        match self.mode {
+            BlockCipherMode::GCM => return self.transform(buffer).is_ok(),
+            BlockCipherMode::CCM => return self.transform(buffer).is_ok(),
+            _ => (),
         }
 
         // With many padding types, we just can't tell and have to assume it's
@@ -466,6 +897,8 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    use crate::transformation::DigestType;
+
     #[test]
     fn test_aes_ecb() -> SimpleResult<()> {
         let tests: Vec<(Vec<u8>, Vec<u8>, BlockCipherPadding, Vec<u8>)> = vec![
@@ -851,4 +1284,403 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_stream_modes() -> SimpleResult<()> {
+        let tests: Vec<(Vec<u8>, Option<Vec<u8>>, BlockCipherMode, Vec<u8>)> = vec![
+            (
+                b"AES-CTR works on any length!".to_vec(),                                     // Plaintext
+                Some(b"BBBBBBBBBBBBBBBB".to_vec()),                                           // IV
+                BlockCipherMode::CTR,
+                // Ciphertext
+                b"\x70\xa6\x69\x43\x11\x04\xc2\xba\x09\x3e\xfe\x8c\x1e\x0c\xf0\x17\
+                  \xb7\x3b\xb3\x19\x3a\xc3\x7d\x72\x51\xa3\xa2\xb0".to_vec(),
+            ),
+            (
+                b"AES-CTR works on any length!".to_vec(),                                     // Plaintext
+                None,                                                                         // IV
+                BlockCipherMode::CTR,
+                // Ciphertext
+                b"\xc0\x93\x9e\xee\xfe\x42\xa9\xad\x05\xd6\xc9\xe3\xf2\xab\x34\x87\
+                  \x35\x0a\xa1\x65\x40\x12\xc9\x56\xd7\xb2\x5b\x1d".to_vec(),
+            ),
+            (
+                b"Test for AES-OFB, two blocks!!!!".to_vec(),                                 // Plaintext
+                Some(b"BBBBBBBBBBBBBBBB".to_vec()),                                           // IV
+                BlockCipherMode::OFB,
+                // Ciphertext
+                b"\x65\x86\x49\x1a\x72\x36\xff\xe8\x5e\x10\xc9\xb4\x40\x63\xd9\x3b\
+                  \xef\x78\x28\x5c\xcc\x9d\x68\x2b\xcf\x63\xc1\x66\x4a\x8f\x34\xd0".to_vec(),
+            ),
+        ];
+
+        for (plaintext, iv, mode, ciphertext) in tests {
+            let transformation = TransformBlockCipher::new(
+                BlockCipherType::AES,
+                mode,
+                BlockCipherPadding::NoPadding,
+                b"AAAAAAAAAAAAAAAA".to_vec(),
+                iv,
+            )?;
+
+            let result = transformation.transform(&ciphertext)?;
+            assert_eq!(plaintext, result, "aes transform {}", std::str::from_utf8(&plaintext).unwrap());
+
+            let result = transformation.untransform(&result)?;
+            assert_eq!(ciphertext, result, "aes untransform {}", std::str::from_utf8(&plaintext).unwrap());
+        }
+
+        // CTR doesn't use padding
+        assert!(TransformBlockCipher::new(BlockCipherType::AES, BlockCipherMode::CTR, BlockCipherPadding::Pkcs7, b"AAAAAAAAAAAAAAAA".to_vec(), None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aead() -> SimpleResult<()> {
+        let tests: Vec<(Vec<u8>, BlockCipherType, Vec<u8>, Vec<u8>, BlockCipherMode, Vec<u8>)> = vec![
+            (
+                b"AES-GCM config blob".to_vec(),                                              // Plaintext
+                BlockCipherType::AES,
+                b"AAAAAAAAAAAAAAAA".to_vec(),                                                 // Key
+                b"CCCCCCCCCCCC".to_vec(),                                                     // Nonce
+                BlockCipherMode::GCM,
+                // Ciphertext + tag
+                b"\x40\x22\xb8\x19\xdb\x2c\x5f\x3f\x3a\xe8\x7b\xad\x91\x16\xc3\x51\
+                  \x0f\x8e\x89\x32\x17\x6d\xea\xd5\xd2\xce\xdd\x7d\x9b\xa3\xea\xf6\
+                  \xa7\x99\x25".to_vec(),
+            ),
+            (
+                b"AES-GCM config blob".to_vec(),                                              // Plaintext
+                BlockCipherType::AES,
+                b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(),                                 // Key
+                b"CCCCCCCCCCCC".to_vec(),                                                     // Nonce
+                BlockCipherMode::GCM,
+                // Ciphertext + tag
+                b"\x63\x8d\xad\x87\xc4\xe0\x52\x55\xed\xec\x41\x2d\x90\x34\x48\x60\
+                  \x69\x9a\xe6\x94\xb2\x92\x4d\x89\xde\xef\x94\xc4\x11\x2e\xd8\x57\
+                  \xac\xe6\xf8".to_vec(),
+            ),
+            (
+                b"16-byte nonce".to_vec(),                                                    // Plaintext
+                BlockCipherType::AES,
+                b"AAAAAAAAAAAAAAAA".to_vec(),                                                 // Key
+                b"CCCCCCCCCCCCCCCC".to_vec(),                                                 // Nonce
+                BlockCipherMode::GCM,
+                // Ciphertext + tag
+                b"\xa6\x17\xcc\x88\x1b\xd2\x74\x88\x76\xc4\x74\x80\x4a\x8a\x55\x80\
+                  \xea\x80\x58\x6c\xb8\xd4\xa4\x4c\x5a\xd6\x2c\x41\x23".to_vec(),
+            ),
+            (
+                b"SM4 in GCM mode".to_vec(),                                                  // Plaintext
+                BlockCipherType::SM4,
+                b"AAAAAAAAAAAAAAAA".to_vec(),                                                 // Key
+                b"CCCCCCCCCCCC".to_vec(),                                                     // Nonce
+                BlockCipherMode::GCM,
+                // Ciphertext + tag
+                b"\x93\x48\x50\x20\xbe\xa9\x2a\x5d\xbb\x25\xf3\x01\x7f\xaf\xc4\x56\
+                  \xc1\x22\x43\xa0\xaf\xef\x48\xdd\x21\xe3\xc9\xef\x99\xe1\xe7".to_vec(),
+            ),
+            (
+                b"AES-CCM message".to_vec(),                                                  // Plaintext
+                BlockCipherType::AES,
+                b"AAAAAAAAAAAAAAAA".to_vec(),                                                 // Key
+                b"CCCCCCCCCCCCC".to_vec(),                                                    // Nonce
+                BlockCipherMode::CCM,
+                // Ciphertext + tag
+                b"\x2b\x90\x93\xc2\x90\x88\xba\x23\x90\x74\x61\x22\x09\x1c\x3c\x80\
+                  \x71\xee\x66\xce\xc5\x2b\x4e\xd7\x51\x6d\x12\x38\xdd\x06\x60".to_vec(),
+            ),
+            (
+                b"AES-CCM message".to_vec(),                                                  // Plaintext
+                BlockCipherType::AES,
+                b"AAAAAAAAAAAAAAAA".to_vec(),                                                 // Key
+                b"CCCCCCC".to_vec(),                                                          // Nonce
+                BlockCipherMode::CCM,
+                // Ciphertext + tag
+                b"\xa8\x2d\x89\x59\x03\x52\x00\x15\x23\xe1\xbf\x75\xe3\x88\x83\xba\
+                  \x1f\x5c\x0a\xb1\x7e\x4a\x9f\x77\x0d\x01\xa4\x22\x68\x4f\x82".to_vec(),
+            ),
+        ];
+
+        for (plaintext, cipher, key, nonce, mode, ciphertext) in tests {
+            let transformation = TransformBlockCipher::new(
+                cipher,
+                mode,
+                BlockCipherPadding::NoPadding,
+                key,
+                Some(nonce),
+            )?;
+
+            let result = transformation.transform(&ciphertext)?;
+            assert_eq!(plaintext, result, "aead transform {}", std::str::from_utf8(&plaintext).unwrap());
+
+            // The tag is recalculated and included
+            let result = transformation.untransform(&result)?;
+            assert_eq!(ciphertext, result, "aead untransform {}", std::str::from_utf8(&plaintext).unwrap());
+
+            // Break the tag
+            let mut broken = ciphertext.clone();
+            let last = broken.len() - 1;
+            broken[last] ^= 1;
+            assert!(transformation.transform(&broken).is_err());
+            assert!(!transformation.can_transform(&broken));
+        }
+
+        // Nonces are required, and have to be a valid length
+        assert!(TransformBlockCipher::new(BlockCipherType::AES, BlockCipherMode::GCM, BlockCipherPadding::NoPadding, b"AAAAAAAAAAAAAAAA".to_vec(), None).is_err());
+        assert!(TransformBlockCipher::new(BlockCipherType::AES, BlockCipherMode::GCM, BlockCipherPadding::NoPadding, b"AAAAAAAAAAAAAAAA".to_vec(), Some(b"CCCCCCCC".to_vec())).is_err());
+        assert!(TransformBlockCipher::new(BlockCipherType::AES, BlockCipherMode::CCM, BlockCipherPadding::NoPadding, b"AAAAAAAAAAAAAAAA".to_vec(), Some(b"CCCCCCCCCCCCCCCC".to_vec())).is_err());
+
+        // 64-bit block ciphers don't work
+        assert!(TransformBlockCipher::new(BlockCipherType::DES, BlockCipherMode::GCM, BlockCipherPadding::NoPadding, b"AAAAAAAA".to_vec(), Some(b"CCCCCCCCCCCC".to_vec())).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_xts() -> SimpleResult<()> {
+        let plaintext = b"XTS encrypts disks, one sector at a time. This is sector 0!!!!!!\
+                          XTS encrypts disks, one sector at a time. This is sector 1!!!!!!".to_vec();
+        let ciphertext = b"\xa0\xd4\x7d\x27\x49\xe3\x49\x28\x9e\xb0\x44\x19\x5c\x99\x1b\x0c\
+                           \x5c\xe3\xef\xc7\x67\x9e\xdb\x82\xfa\x80\xa3\x9c\xef\x41\xa3\x0c\
+                           \xc2\x5d\xd1\x57\x06\xfb\x2e\x90\x29\x92\x7d\x01\x59\xf0\x99\x6e\
+                           \xf8\xb0\x2c\x98\x6d\x8c\xba\x49\xb8\x46\xda\xe0\x6c\x7d\x12\xd7\
+                           \x84\x4c\x51\x63\xd3\x69\x72\xb1\x30\x20\x0f\x51\x2f\x16\x63\x7e\
+                           \x99\x71\x4f\xfc\x61\xe9\x17\xb3\x25\x9c\xaa\x82\x4b\xec\x99\xb2\
+                           \x68\xed\xa3\xfc\xd9\xea\x89\xe8\x67\xd6\x52\x43\xfa\x51\x1c\xdf\
+                           \xc0\x65\x2b\x3f\xdb\xcf\xb4\x32\xb8\x33\x7f\xae\x16\xc0\x18\x39".to_vec();
+
+        // AES-128-XTS with 64-byte sectors, starting at sector 5
+        let transformation = TransformBlockCipher::new(
+            BlockCipherType::AES,
+            BlockCipherMode::XTS { sector_size: 64 },
+            BlockCipherPadding::NoPadding,
+            (0..32).collect(),
+            Some(b"\x05\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec()),
+        )?;
+
+        let result = transformation.transform(&ciphertext)?;
+        assert_eq!(plaintext, result);
+
+        let result = transformation.untransform(&result)?;
+        assert_eq!(ciphertext, result);
+
+        // Bad settings
+        assert!(TransformBlockCipher::new(BlockCipherType::AES, BlockCipherMode::XTS { sector_size: 64 }, BlockCipherPadding::NoPadding, (0..16).collect(), None).is_err());
+        assert!(TransformBlockCipher::new(BlockCipherType::AES, BlockCipherMode::XTS { sector_size: 10 }, BlockCipherPadding::NoPadding, (0..32).collect(), None).is_err());
+        assert!(TransformBlockCipher::new(BlockCipherType::Blowfish, BlockCipherMode::XTS { sector_size: 64 }, BlockCipherPadding::NoPadding, (0..32).collect(), None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_other_ciphers() -> SimpleResult<()> {
+        let tests: Vec<(Vec<u8>, BlockCipherType, Vec<u8>, Option<Vec<u8>>, BlockCipherMode, BlockCipherPadding, Vec<u8>)> = vec![
+            (
+                b"Triple DES!".to_vec(),                                                      // Plaintext
+                BlockCipherType::TripleDES,
+                b"AAAAAAAABBBBBBBBCCCCCCCC".to_vec(),                                         // Key
+                Some(b"BBBBBBBB".to_vec()),                                                   // IV
+                BlockCipherMode::CBC,
+                BlockCipherPadding::Pkcs7,
+                // Ciphertext
+                b"\x3f\xc5\x21\xb2\x4a\x5a\xe6\x4b\xf1\x42\x27\xa5\xca\x54\x1d\xdf".to_vec(),
+            ),
+            (
+                b"Two-key triple DES".to_vec(),                                               // Plaintext
+                BlockCipherType::TripleDES,
+                b"AAAAAAAABBBBBBBB".to_vec(),                                                 // Key
+                Some(b"BBBBBBBB".to_vec()),                                                   // IV
+                BlockCipherMode::CBC,
+                BlockCipherPadding::Pkcs7,
+                // Ciphertext
+                b"\x3b\x5d\x0a\x5f\xd2\xf8\xb0\x2f\x7a\x01\xbe\x24\x96\xc9\x1b\x66\
+                  \xa9\x70\x74\x7f\x88\x5b\xdc\x7e".to_vec(),
+            ),
+            (
+                b"3DES in CTR mode!".to_vec(),                                                // Plaintext
+                BlockCipherType::TripleDES,
+                b"AAAAAAAABBBBBBBBCCCCCCCC".to_vec(),                                         // Key
+                Some(b"BBBBBBBB".to_vec()),                                                   // IV
+                BlockCipherMode::CTR,
+                BlockCipherPadding::NoPadding,
+                // Ciphertext
+                b"\x2b\xba\x62\x5c\xa1\x69\x77\x3c\x8b\x6c\x5c\x2c\xdd\xf3\x2c\xf0\xe4".to_vec(),
+            ),
+            (
+                b"Blowfish save game".to_vec(),                                               // Plaintext
+                BlockCipherType::Blowfish,
+                b"savegame".to_vec(),                                                         // Key
+                Some(b"BBBBBBBB".to_vec()),                                                   // IV
+                BlockCipherMode::CBC,
+                BlockCipherPadding::Pkcs7,
+                // Ciphertext
+                b"\xd4\xd4\x67\x16\x3a\x82\x9a\x2d\xeb\x52\x5b\x81\xff\xd0\xac\x42\
+                  \xfc\x73\x6e\x50\xb6\x40\xff\xed".to_vec(),
+            ),
+            (
+                b"Blowfish save game".to_vec(),                                               // Plaintext
+                BlockCipherType::Blowfish,
+                b"a much longer blowfish key".to_vec(),                                       // Key
+                None,                                                                         // IV
+                BlockCipherMode::ECB,
+                BlockCipherPadding::Pkcs7,
+                // Ciphertext
+                b"\xaf\xf0\x4b\x57\x7c\xec\x39\xbe\x1b\x67\xe2\x10\x6c\xbb\x5c\xa8\
+                  \x47\xd2\x91\xea\x07\xbc\x2e\x73".to_vec(),
+            ),
+            (
+                b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec(), // Plaintext
+                BlockCipherType::Twofish,
+                b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec(), // Key
+                None,                                                                         // IV
+                BlockCipherMode::ECB,
+                BlockCipherPadding::NoPadding,
+                // Ciphertext (from the Twofish paper)
+                b"\x9f\x58\x9f\x5c\xf6\x12\x2c\x32\xb6\xbf\xec\x2f\x2a\xe8\xc3\x5a".to_vec(),
+            ),
+            (
+                b"Camellia in CBC mode".to_vec(),                                             // Plaintext
+                BlockCipherType::Camellia,
+                b"AAAAAAAAAAAAAAAA".to_vec(),                                                 // Key
+                Some(b"BBBBBBBBBBBBBBBB".to_vec()),                                           // IV
+                BlockCipherMode::CBC,
+                BlockCipherPadding::Pkcs7,
+                // Ciphertext
+                b"\x91\xef\xab\x62\xca\x18\x28\xba\x60\xa7\xfc\x88\xe9\x45\x61\xd5\
+                  \x23\x1a\xa9\x1f\x7b\xe1\xea\x5b\x51\x72\x03\x88\xc7\xd1\xcb\xcd".to_vec(),
+            ),
+            (
+                b"Camellia-256 ECB!".to_vec(),                                                // Plaintext
+                BlockCipherType::Camellia,
+                b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(),                                 // Key
+                None,                                                                         // IV
+                BlockCipherMode::ECB,
+                BlockCipherPadding::Pkcs7,
+                // Ciphertext
+                b"\x9c\x91\x87\x16\x10\x4d\xe6\xa3\xfb\x10\xff\x61\x14\x51\x69\x7b\
+                  \x49\xd3\xe4\x5a\x9c\x8c\xd1\x0a\x4b\xcb\x53\x41\xdd\x78\x22\x34".to_vec(),
+            ),
+            (
+                b"RC2 in CBC mode".to_vec(),                                                  // Plaintext
+                BlockCipherType::RC2,
+                b"AAAAAAAAAAAAAAAA".to_vec(),                                                 // Key
+                Some(b"BBBBBBBB".to_vec()),                                                   // IV
+                BlockCipherMode::CBC,
+                BlockCipherPadding::Pkcs7,
+                // Ciphertext
+                b"\x60\x6b\xa7\x4c\xf6\x45\x83\x71\x0a\xcd\x3d\x1e\x8f\x2c\xef\x82".to_vec(),
+            ),
+            (
+                b"SM4 in CBC mode!!".to_vec(),                                                // Plaintext
+                BlockCipherType::SM4,
+                b"AAAAAAAAAAAAAAAA".to_vec(),                                                 // Key
+                Some(b"BBBBBBBBBBBBBBBB".to_vec()),                                           // IV
+                BlockCipherMode::CBC,
+                BlockCipherPadding::Pkcs7,
+                // Ciphertext
+                b"\x92\xf6\xe3\xdf\xac\xad\x3f\xc5\xa6\xfa\xbb\xd1\x6b\x24\x1f\xe8\
+                  \x2b\xd4\x0f\xa5\x1b\x91\x06\xdf\x93\x4b\xc2\xc8\x51\xcb\x0c\x57".to_vec(),
+            ),
+        ];
+
+        for (plaintext, cipher, key, iv, mode, padding, ciphertext) in tests {
+            let transformation = TransformBlockCipher::new(
+                cipher,
+                mode,
+                padding,
+                key,
+                iv,
+            )?;
+
+            let result = transformation.transform(&ciphertext)?;
+            assert_eq!(plaintext, result, "{:?} transform", cipher);
+
+            let result = transformation.untransform(&result)?;
+            assert_eq!(ciphertext, result, "{:?} untransform", cipher);
+        }
+
+        // Bad key sizes
+        assert!(TransformBlockCipher::new(BlockCipherType::TripleDES, BlockCipherMode::ECB, BlockCipherPadding::NoPadding, b"AAAAAAAA".to_vec(), None).is_err());
+        assert!(TransformBlockCipher::new(BlockCipherType::Blowfish, BlockCipherMode::ECB, BlockCipherPadding::NoPadding, b"AAA".to_vec(), None).is_err());
+        assert!(TransformBlockCipher::new(BlockCipherType::SM4, BlockCipherMode::ECB, BlockCipherPadding::NoPadding, b"AAAAAAAA".to_vec(), None).is_err());
+
+        // Bad IV size
+        assert!(TransformBlockCipher::new(BlockCipherType::Twofish, BlockCipherMode::CBC, BlockCipherPadding::NoPadding, b"AAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBB".to_vec())).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_derived() -> SimpleResult<()> {
+        // printf 'OpenSSL salted file' | openssl enc -aes-256-cbc -md sha256 -pass pass:hunter2
+        let ciphertext = b"Salted__\x92\xdc\x62\x94\xdd\x5e\x81\xd4\
+                           \xf6\x06\x24\xf2\x4a\xf2\x42\x0d\xbe\x02\x1d\x5e\x36\x61\x6b\xcf\
+                           \x74\xd9\x40\xcd\xbc\x71\x4b\xae\x9b\x9f\xce\x00\x08\x89\xc0\x3b".to_vec();
+
+        let transformation = TransformBlockCipher::new_derived(
+            BlockCipherType::AES,
+            BlockCipherMode::CBC,
+            BlockCipherPadding::Pkcs7,
+            32,
+            None,
+            KeyDerivation::from_openssl_header(&ciphertext, DigestType::SHA256, b"hunter2".to_vec(), None)?,
+        )?;
+
+        let result = transformation.transform(&ciphertext)?;
+        assert_eq!(b"OpenSSL salted file".to_vec(), result);
+
+        // The header is put back
+        assert_eq!(ciphertext, transformation.untransform(&result)?);
+
+        // Without the header, it doesn't work
+        assert!(transformation.can_transform(&ciphertext));
+        assert!(!transformation.can_transform(&ciphertext[16..].to_vec()));
+        assert!(transformation.transform(&ciphertext[16..].to_vec()).is_err());
+
+        // The derivation survives being serialized
+        let serialized = ron::ser::to_string(&transformation).unwrap();
+        let deserialized: Transformation = ron::de::from_str(&serialized).unwrap();
+        assert_eq!(transformation, deserialized);
+        assert_eq!(b"OpenSSL salted file".to_vec(), deserialized.transform(&ciphertext)?);
+
+        // A key from MD5(passphrase) with an explicit IV
+        let transformation = TransformBlockCipher::new_derived(
+            BlockCipherType::AES,
+            BlockCipherMode::CBC,
+            BlockCipherPadding::Pkcs7,
+            16,
+            Some(vec![0; 16]),
+            KeyDerivation::Hash { hash: DigestType::MD5, passphrase: b"hunter2".to_vec() },
+        )?;
+
+        let ciphertext = b"\xac\x60\x8d\x3a\xb7\x48\x7c\x49\x82\xc2\xdd\x8a\x03\x5b\xc1\x14\
+                           \xe9\x94\xdc\x6d\xb4\x54\xe6\x79\x89\xce\x40\xf1\x15\x2d\xbe\x9c".to_vec();
+        let result = transformation.transform(&ciphertext)?;
+        assert_eq!(b"Hash of a passphrase".to_vec(), result);
+        assert_eq!(ciphertext, transformation.untransform(&result)?);
+
+        // MD5 can't make a 256-bit key
+        assert!(TransformBlockCipher::new_derived(
+            BlockCipherType::AES,
+            BlockCipherMode::CBC,
+            BlockCipherPadding::Pkcs7,
+            32,
+            None,
+            KeyDerivation::Hash { hash: DigestType::MD5, passphrase: b"hunter2".to_vec() },
+        ).is_err());
+
+        // Or an invalid key length for the cipher
+        assert!(TransformBlockCipher::new_derived(
+            BlockCipherType::AES,
+            BlockCipherMode::ECB,
+            BlockCipherPadding::Pkcs7,
+            12,
+            None,
+            KeyDerivation::PBKDF2 { hash: DigestType::SHA256, passphrase: b"hunter2".to_vec(), salt: vec![], iterations: 1 },
+        ).is_err());
+
+        Ok(())
+    }
 }
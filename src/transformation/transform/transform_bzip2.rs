@@ -0,0 +1,103 @@
+use bzip2::read::MultiBzDecoder;
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::io;
+
+use crate::transformation::{LimitedOutput, Transformation, TransformerTrait};
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformBzip2 {
+}
+
+impl fmt::Display for TransformBzip2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformBzip2 {
+    pub fn new() -> Transformation {
+        Transformation::FromBzip2(TransformBzip2 {})
+    }
+
+    /// Check for the "BZh" signature, followed by the block size ('1' - '9').
+    fn check_magic(buffer: &[u8]) -> bool {
+        buffer.len() > 3 && buffer.starts_with(b"BZh") && (b'1'..=b'9').contains(&buffer[3])
+    }
+
+    fn decompress(buffer: &[u8], mut out: LimitedOutput) -> SimpleResult<Vec<u8>> {
+        if !Self::check_magic(buffer) {
+            bail!("Not a bzip2 buffer (bad magic)");
+        }
+
+        match io::copy(&mut MultiBzDecoder::new(buffer), &mut out) {
+            Ok(_) => Ok(out.into_inner()),
+            Err(e) => bail!("Couldn't decompress bzip2: {}", e),
+        }
+    }
+}
+
+impl TransformerTrait for TransformBzip2 {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        Self::decompress(buffer, LimitedOutput::unlimited())
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("Bzip2 cannot be untransformed");
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        // Don't let a decompression bomb use up all the memory
+        Self::check_magic(buffer) && Self::decompress(buffer, LimitedOutput::for_detection(buffer.len())).is_ok()
+    }
+
+    fn is_two_way(&self) -> bool {
+        // Bzip2 can't reliably go backwards
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        match (TransformBzip2 {}).can_transform(buffer) {
+            true => vec![Self::new()],
+            false => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_bzip2() -> SimpleResult<()> {
+        let t = TransformBzip2::new();
+        assert_eq!(false, t.is_two_way());
+
+        // echo -ne 'ABCD' | bzip2
+        let result = t.transform(&b"\x42\x5a\x68\x39\x31\x41\x59\x26\x53\x59\x54\x30\x65\x9c\x00\x00\x00\x04\x00\x3c\x00\x20\x00\x21\x9a\x68\x33\x4d\x13\x3c\x5d\xc9\x14\xe1\x42\x41\x50\xc1\x96\x70".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // Bad block size
+        assert!(t.transform(&b"\x42\x5a\x68\x30\x31\x41\x59\x26\x53\x59\x54\x30\x65\x9c\x00\x00\x00\x04\x00\x3c\x00\x20\x00\x21\x9a\x68\x33\x4d\x13\x3c\x5d\xc9\x14\xe1\x42\x41\x50\xc1\x96\x70".to_vec()).is_err());
+
+        // Truncated
+        assert!(t.transform(&b"\x42\x5a\x68\x39\x31\x41\x59\x26\x53\x59\x54\x30\x65\x9c\x00\x00\x00\x04\x00\x3c".to_vec()).is_err());
+
+        // Not bzip2 at all
+        assert!(t.transform(&b"ABCD".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bzip2_limit() -> SimpleResult<()> {
+        let data = b"\x42\x5a\x68\x39\x31\x41\x59\x26\x53\x59\x54\x30\x65\x9c\x00\x00\x00\x04\x00\x3c\x00\x20\x00\x21\x9a\x68\x33\x4d\x13\x3c\x5d\xc9\x14\xe1\x42\x41\x50\xc1\x96\x70";
+
+        assert_eq!(b"ABCD".to_vec(), TransformBzip2::decompress(data, LimitedOutput::new(4))?);
+        assert!(TransformBzip2::decompress(data, LimitedOutput::new(3)).is_err());
+
+        Ok(())
+    }
+}
@@ -1,13 +1,26 @@
 use inflate;
+use flate2::Compression;
+use flate2::write::{DeflateEncoder, ZlibEncoder};
 use simple_error::{SimpleResult, bail};
 use serde::{Serialize, Deserialize};
 use std::fmt;
+use std::io::Write;
 
 use crate::transformation::{Transformation, TransformerTrait};
 
+/// The compression level used to re-compress data when no level is known.
+const DEFAULT_LEVEL: u32 = 6;
+
+/// The highest compression level that zlib supports.
+const MAX_LEVEL: u32 = 9;
+
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
 pub struct TransformDeflate {
     zlib_header: bool,
+
+    /// The compression level (0 - 9) that reproduces the original data, if
+    /// we know it.
+    level: Option<u32>,
 }
 
 impl fmt::Display for TransformDeflate {
@@ -20,9 +33,45 @@ impl TransformDeflate {
     pub fn new(zlib_header: bool) -> Transformation {
         Transformation::FromDeflated(Self {
             zlib_header: zlib_header,
+            level: None,
         })
     }
 
+    /// Create a deflate transformation that re-compresses at a known level.
+    ///
+    /// The level is the standard zlib level, from 0 (no compression) to 9
+    /// (best compression). When the level matches what the original data was
+    /// compressed with, untransforming reproduces the original bytes.
+    pub fn new_with_level(zlib_header: bool, level: u32) -> SimpleResult<Transformation> {
+        if level > MAX_LEVEL {
+            bail!("Invalid deflate compression level: {} (must be 0 - {})", level, MAX_LEVEL);
+        }
+
+        Ok(Transformation::FromDeflated(Self {
+            zlib_header,
+            level: Some(level),
+        }))
+    }
+
+    /// Create a deflate transformation that re-compresses at whichever level
+    /// reproduces `buffer` (the compressed data), so it's two-way.
+    ///
+    /// This has to try compressing at every level, so it can be slow on big
+    /// buffers; [`TransformerTrait::detect`] doesn't do it. Data compressed
+    /// by a different library (or with different settings) frequently won't
+    /// match any level, in which case this fails.
+    pub fn new_two_way(zlib_header: bool, buffer: &Vec<u8>) -> SimpleResult<Transformation> {
+        let t = Self {
+            zlib_header,
+            level: None,
+        };
+
+        match t.find_level(buffer) {
+            Some(level) => Self::new_with_level(zlib_header, level),
+            None => bail!("Couldn't find a deflate compression level that reproduces the data"),
+        }
+    }
+
     pub fn with_header() -> Transformation {
         Self::new(true)
     }
@@ -31,6 +80,14 @@ impl TransformDeflate {
         Self::new(false)
     }
 
+    pub fn with_header_level(level: u32) -> SimpleResult<Transformation> {
+        Self::new_with_level(true, level)
+    }
+
+    pub fn without_header_level(level: u32) -> SimpleResult<Transformation> {
+        Self::new_with_level(false, level)
+    }
+
     fn transform_deflated(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
         match inflate::inflate_bytes(buffer) {
             Ok(b) => Ok(b),
@@ -55,6 +112,60 @@ impl TransformDeflate {
         // The only reasonable way to check is by just doing it
         self.transform_deflated_zlib(buffer).is_ok()
     }
+
+    fn untransform_deflated(self, buffer: &[u8], level: u32) -> SimpleResult<Vec<u8>> {
+        let mut e = DeflateEncoder::new(Vec::new(), Compression::new(level));
+
+        if let Err(e) = e.write_all(buffer) {
+            bail!("Couldn't deflate: {}", e);
+        }
+
+        match e.finish() {
+            Ok(b) => Ok(b),
+            Err(e) => bail!("Couldn't deflate: {}", e),
+        }
+    }
+
+    fn untransform_deflated_zlib(self, buffer: &[u8], level: u32) -> SimpleResult<Vec<u8>> {
+        let mut e = ZlibEncoder::new(Vec::new(), Compression::new(level));
+
+        if let Err(e) = e.write_all(buffer) {
+            bail!("Couldn't deflate: {}", e);
+        }
+
+        match e.finish() {
+            Ok(b) => Ok(b),
+            Err(e) => bail!("Couldn't deflate: {}", e),
+        }
+    }
+
+    /// Compress the buffer at the given level, with or without the header.
+    fn compress(self, buffer: &[u8], level: u32) -> SimpleResult<Vec<u8>> {
+        match self.zlib_header {
+            true => self.untransform_deflated_zlib(buffer, level),
+            false => self.untransform_deflated(buffer, level),
+        }
+    }
+
+    /// Find the compression level that re-creates the original buffer, if
+    /// there is one.
+    ///
+    /// Data compressed by a different library (or with different settings)
+    /// frequently won't match any level, in which case this returns `None`.
+    fn find_level(self, buffer: &Vec<u8>) -> Option<u32> {
+        let decompressed = self.transform(buffer).ok()?;
+
+        // Try the default first, since it's by far the most common
+        let mut levels: Vec<u32> = vec![DEFAULT_LEVEL];
+        levels.extend((0..=MAX_LEVEL).filter(|l| *l != DEFAULT_LEVEL));
+
+        levels.into_iter().find(|level| {
+            match self.compress(&decompressed, *level) {
+                Ok(b) => b == *buffer,
+                Err(_) => false,
+            }
+        })
+    }
 }
 
 impl TransformerTrait for TransformDeflate {
@@ -65,8 +176,8 @@ impl TransformerTrait for TransformDeflate {
         }
     }
 
-    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        bail!("Deflate cannot be untransformed");
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        self.compress(buffer, self.level.unwrap_or(DEFAULT_LEVEL))
     }
 
     fn can_transform(&self, buffer: &Vec<u8>) -> bool {
@@ -77,21 +188,27 @@ impl TransformerTrait for TransformDeflate {
     }
 
     fn is_two_way(&self) -> bool {
-        // Deflate can't reliably go backwards
-        false
+        // We can always re-compress, but we can only promise the same length
+        // if we know which level the data was originally compressed with
+        self.level.is_some()
     }
 
     fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
         let mut out: Vec<_> = Vec::new();
 
-        let t = TransformDeflate::with_header();
-        if t.can_transform(buffer) {
-            out.push(t);
-        }
+        for zlib_header in [true, false].iter() {
+            let t = TransformDeflate {
+                zlib_header: *zlib_header,
+                level: None,
+            };
 
-        let t = TransformDeflate::without_header();
-        if t.can_transform(buffer) {
-            out.push(t);
+            if !t.can_transform(buffer) {
+                continue;
+            }
+
+            // Finding the level means compressing over and over, so leave
+            // that for new_two_way()
+            out.push(Transformation::FromDeflated(t));
         }
 
         out
@@ -162,4 +279,67 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_deflate_untransform() -> SimpleResult<()> {
+        let data = b"The quick brown fox jumps over the lazy dog, the quick brown fox jumps over the lazy dog".to_vec();
+
+        for level in 0..=MAX_LEVEL {
+            for t in [TransformDeflate::with_header_level(level)?, TransformDeflate::without_header_level(level)?].iter() {
+                assert!(t.is_two_way());
+
+                let compressed = t.untransform(&data)?;
+                assert_eq!(data, t.transform(&compressed)?);
+
+                // Re-compressing gives back the identical data
+                assert_eq!(compressed, t.untransform(&t.transform(&compressed)?)?);
+            }
+        }
+
+        // Without a level, we can still compress but it's not two-way
+        let t = TransformDeflate::with_header();
+        assert!(!t.is_two_way());
+        let compressed = t.untransform(&data)?;
+        assert_eq!(data, t.transform(&compressed)?);
+
+        // Known compressed data
+        assert_eq!(b"\x78\x9c\x73\x74\x72\x76\x01\x00\x02\x98\x01\x0b".to_vec(), TransformDeflate::with_header_level(6)?.untransform(&b"ABCD".to_vec())?);
+        assert_eq!(b"\x01\x04\x00\xfb\xff\x41\x42\x43\x44".to_vec(), TransformDeflate::without_header_level(0)?.untransform(&b"ABCD".to_vec())?);
+
+        // Bad level
+        assert!(TransformDeflate::with_header_level(10).is_err());
+        assert!(TransformDeflate::without_header_level(10).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deflate_detect_level() -> SimpleResult<()> {
+        let data = b"The quick brown fox jumps over the lazy dog, the quick brown fox jumps over the lazy dog".to_vec();
+
+        // Detecting doesn't look for the level
+        let compressed = TransformDeflate::with_header_level(9)?.untransform(&data)?;
+        assert_eq!(vec![TransformDeflate::with_header()], TransformDeflate::detect(&compressed));
+
+        // Stored (level 0) is easy to recognize
+        let compressed = TransformDeflate::with_header_level(0)?.untransform(&data)?;
+        assert_eq!(TransformDeflate::with_header_level(0)?, TransformDeflate::new_two_way(true, &compressed)?);
+
+        let compressed = TransformDeflate::without_header_level(0)?.untransform(&data)?;
+        assert_eq!(TransformDeflate::without_header_level(0)?, TransformDeflate::new_two_way(false, &compressed)?);
+
+        // Whatever level we find has to reproduce the data
+        let compressed = TransformDeflate::with_header_level(9)?.untransform(&data)?;
+        let t = TransformDeflate::new_two_way(true, &compressed)?;
+        assert!(t.is_two_way());
+        assert_eq!(compressed, t.untransform(&t.transform(&compressed)?)?);
+
+        // Not deflated, or compressed by something that doesn't match
+        assert!(TransformDeflate::new_two_way(true, &data).is_err());
+        let mut compressed = TransformDeflate::without_header_level(0)?.untransform(&data)?;
+        compressed[0] = 0x00;
+        assert!(TransformDeflate::new_two_way(false, &compressed).is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,189 @@
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::transformation::{LimitedOutput, Transformation, TransformerTrait};
+
+/// The first three bytes of any gzip file: the magic value plus the only
+/// compression method that's ever been defined (deflate).
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+
+/// The fields from the header of a gzip file.
+///
+/// Only the first member is considered if there are more than one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GzipHeader {
+    /// The original filename, if it was stored.
+    pub filename: Option<String>,
+
+    /// A free-form comment, if one was stored.
+    pub comment: Option<String>,
+
+    /// The raw "extra" field, if one was stored.
+    pub extra: Option<Vec<u8>>,
+
+    /// The modification time, as a unix timestamp (0 means not stored).
+    pub mtime: u32,
+
+    /// The operating system byte (0 = FAT, 3 = Unix, 11 = NTFS, 255 = unknown,
+    /// etc).
+    pub operating_system: u8,
+}
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformGzip {
+}
+
+impl fmt::Display for TransformGzip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformGzip {
+    pub fn new() -> Transformation {
+        Transformation::FromGzip(TransformGzip {})
+    }
+
+    /// Read the header fields from a gzip buffer, without decompressing the
+    /// whole thing.
+    pub fn header(buffer: &[u8]) -> SimpleResult<GzipHeader> {
+        if !buffer.starts_with(&GZIP_MAGIC) {
+            bail!("Not a gzip buffer (bad magic)");
+        }
+
+        // The decoder parses the header as soon as we start reading
+        let mut d = GzDecoder::new(buffer);
+        let mut discard = [0u8; 1];
+        if let Err(e) = d.read(&mut discard) {
+            bail!("Couldn't read gzip header: {}", e);
+        }
+
+        let header = match d.header() {
+            Some(h) => h,
+            None => bail!("Couldn't read gzip header"),
+        };
+
+        Ok(GzipHeader {
+            filename: header.filename().map(|f| String::from_utf8_lossy(f).to_string()),
+            comment: header.comment().map(|c| String::from_utf8_lossy(c).to_string()),
+            extra: header.extra().map(|e| e.to_vec()),
+            mtime: header.mtime(),
+            operating_system: header.operating_system(),
+        })
+    }
+
+    fn decompress(buffer: &[u8], mut out: LimitedOutput) -> SimpleResult<Vec<u8>> {
+        if !buffer.starts_with(&GZIP_MAGIC) {
+            bail!("Not a gzip buffer (bad magic)");
+        }
+
+        // Gzip files can legally have multiple members concatenated together
+        match io::copy(&mut MultiGzDecoder::new(buffer), &mut out) {
+            Ok(_) => Ok(out.into_inner()),
+            Err(e) => bail!("Couldn't decompress gzip: {}", e),
+        }
+    }
+}
+
+impl TransformerTrait for TransformGzip {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        Self::decompress(buffer, LimitedOutput::unlimited())
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("Gzip cannot be untransformed");
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        // Check the magic first, since that's cheap, and don't let a
+        // decompression bomb use up all the memory
+        buffer.starts_with(&GZIP_MAGIC) && Self::decompress(buffer, LimitedOutput::for_detection(buffer.len())).is_ok()
+    }
+
+    fn is_two_way(&self) -> bool {
+        // Gzip can't reliably go backwards
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        match (TransformGzip {}).can_transform(buffer) {
+            true => vec![Self::new()],
+            false => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_gzip() -> SimpleResult<()> {
+        let t = TransformGzip::new();
+        assert_eq!(false, t.is_two_way());
+
+        // echo -ne 'ABCD' | gzip -n | xxd -i
+        let result = t.transform(&b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // Two members concatenated together
+        let result = t.transform(&b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00\
+                                    \x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00".to_vec())?;
+        assert_eq!(b"ABCDABCD".to_vec(), result);
+
+        // Bad magic
+        assert!(t.transform(&b"\x1f\x8c\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00".to_vec()).is_err());
+
+        // Truncated
+        assert!(t.transform(&b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72".to_vec()).is_err());
+
+        // Bad checksum
+        assert!(t.transform(&b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdc\x04\x00\x00\x00".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzip_limit() -> SimpleResult<()> {
+        let data = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00";
+
+        assert_eq!(b"ABCD".to_vec(), TransformGzip::decompress(data, LimitedOutput::new(4))?);
+        assert!(TransformGzip::decompress(data, LimitedOutput::new(3)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzip_header() -> SimpleResult<()> {
+        // No optional fields
+        let header = TransformGzip::header(&b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00".to_vec())?;
+        assert_eq!(GzipHeader {
+            filename: None,
+            comment: None,
+            extra: None,
+            mtime: 0,
+            operating_system: 3,
+        }, header);
+
+        // A filename ("test.txt") and mtime (0x60000000)
+        let data = b"\x1f\x8b\x08\x08\x00\x00\x00\x60\x00\x03test.txt\x00\x73\x74\x72\x76\x01\x00\xa5\x20\x17\xdb\x04\x00\x00\x00".to_vec();
+        let header = TransformGzip::header(&data)?;
+        assert_eq!(GzipHeader {
+            filename: Some("test.txt".to_string()),
+            comment: None,
+            extra: None,
+            mtime: 0x60000000,
+            operating_system: 3,
+        }, header);
+        assert_eq!(b"ABCD".to_vec(), TransformGzip::new().transform(&data)?);
+
+        // Not gzip
+        assert!(TransformGzip::header(&b"ABCD".to_vec()).is_err());
+
+        Ok(())
+    }
+}
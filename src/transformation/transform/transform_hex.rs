@@ -1,16 +1,515 @@
 use simple_error::{SimpleResult, bail};
 use serde::{Serialize, Deserialize};
 use std::fmt;
+use std::ops::Range;
 
 use crate::transformation::{Transformation, TransformerTrait};
 
+/// How many bytes go on each line of a hex dump.
+const DUMP_WIDTH: usize = 16;
+
+/// How many times bigger than the hex dump the data can be, once the repeated
+/// lines (`*`) are expanded. This stops a small dump with a huge offset from
+/// using up all the memory.
+const DUMP_MAX_EXPANSION: usize = 4096;
+
+/// Splits a line of a hex dump into its offset and data.
+type DumpLineSplitter = fn(&str) -> SimpleResult<(&str, Option<&str>)>;
+
+/// How the hex digits are laid out.
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub enum HexFormat {
+    /// A contiguous string of hex digits - `deadbeef`.
+    Plain,
+
+    /// Bytes separated by whitespace - `de ad be ef`.
+    Spaced,
+
+    /// Bytes separated by colons - `de:ad:be:ef`.
+    Colons,
+
+    /// Escaped bytes, like in a C or Python string - `\xde\xad\xbe\xef`. The
+    /// string can be wrapped in quotes.
+    Escaped,
+
+    /// A C or Python array literal - `0xde, 0xad, 0xbe, 0xef`. The array can
+    /// be wrapped in `{ }` or `[ ]`, and anything before the `{` (such as a
+    /// variable declaration) is ignored.
+    CArray,
+
+    /// The output of `hexdump -C`, with offsets and an ASCII gutter. Repeated
+    /// lines (shown as `*`) are expanded.
+    Hexdump,
+
+    /// The output of `xxd`, with offsets and an ASCII gutter.
+    Xxd,
+}
+
+/// The parts of the hex text that aren't the data itself, so that encoding
+/// can reproduce them (see [`TransformHex::new_matching`]).
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+struct HexLayout {
+    /// Whether the hex digits are uppercase.
+    uppercase: bool,
+
+    /// Anything before the first byte, such as a declaration and `{`, or a
+    /// quote.
+    prefix: String,
+
+    /// What goes between bytes.
+    separator: String,
+
+    /// Anything after the last byte, such as a trailing comma and `};`.
+    suffix: String,
+
+    /// The offset of the first line of a hex dump.
+    start: usize,
+
+    /// Whether a hex dump has an ASCII gutter.
+    gutter: bool,
+
+    /// Whether a hex dump ends with a line that only has the final offset
+    /// (`xxd` never does).
+    final_offset: bool,
+}
+
+impl HexLayout {
+    /// The layout that the format uses when there's nothing to match.
+    fn default_for(format: HexFormat) -> Self {
+        let separator = match format {
+            HexFormat::Spaced => " ",
+            HexFormat::Colons => ":",
+            HexFormat::CArray => ", ",
+            _                 => "",
+        };
+
+        Self {
+            uppercase: false,
+            prefix: String::new(),
+            separator: separator.to_string(),
+            suffix: String::new(),
+            start: 0,
+            gutter: true,
+            final_offset: format == HexFormat::Hexdump,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TransformHex {
+    format: HexFormat,
+    layout: HexLayout,
 }
 
 impl TransformHex {
     pub fn new() -> Transformation {
-        Transformation::FromHex(TransformHex {})
+        Self::new_with_format(HexFormat::Plain)
+    }
+
+    pub fn new_with_format(format: HexFormat) -> Transformation {
+        Transformation::FromHex(TransformHex {
+            format,
+            layout: HexLayout::default_for(format),
+        })
+    }
+
+    /// Create a transformation that untransforms to the same layout as the
+    /// given hex text: the case of the digits, anything around the data (such
+    /// as a C declaration or quotes), the separator, and the starting offset,
+    /// ASCII gutter and final offset line of a hex dump.
+    ///
+    /// Anything that isn't recognized is left at the format's default.
+    pub fn new_matching(format: HexFormat, buffer: &[u8]) -> Transformation {
+        let layout = match std::str::from_utf8(buffer) {
+            Ok(s)  => Self::find_layout(format, s),
+            Err(_) => HexLayout::default_for(format),
+        };
+
+        Transformation::FromHex(TransformHex {
+            format,
+            layout,
+        })
+    }
+
+    pub fn spaced() -> Transformation {
+        Self::new_with_format(HexFormat::Spaced)
+    }
+
+    pub fn colons() -> Transformation {
+        Self::new_with_format(HexFormat::Colons)
+    }
+
+    pub fn escaped() -> Transformation {
+        Self::new_with_format(HexFormat::Escaped)
+    }
+
+    pub fn c_array() -> Transformation {
+        Self::new_with_format(HexFormat::CArray)
+    }
+
+    pub fn hexdump() -> Transformation {
+        Self::new_with_format(HexFormat::Hexdump)
+    }
+
+    pub fn xxd() -> Transformation {
+        Self::new_with_format(HexFormat::Xxd)
+    }
+
+    /// Decode a single byte, which must be exactly two hex digits.
+    fn decode_byte(s: &str) -> SimpleResult<u8> {
+        if s.len() != 2 {
+            bail!("Expected two hex digits: {:?}", s);
+        }
+
+        match u8::from_str_radix(s, 16) {
+            Ok(b) => Ok(b),
+            Err(e) => bail!("Couldn't decode hex {:?}: {}", s, e),
+        }
+    }
+
+    /// Decode a series of bytes, each of which is two hex digits.
+    fn decode_separated<'a>(s: impl Iterator<Item=&'a str>) -> SimpleResult<Vec<u8>> {
+        s.map(Self::decode_byte).collect()
+    }
+
+    fn decode_escaped(s: &str) -> SimpleResult<Vec<u8>> {
+        let s = s.trim();
+
+        // Remove the quotes, if they're there
+        let s = match (s.chars().next(), s.chars().last()) {
+            (Some('"'), Some('"')) | (Some('\''), Some('\'')) if s.len() >= 2 => &s[1..(s.len() - 1)],
+            _ => s,
+        };
+
+        if !s.starts_with("\\x") {
+            bail!("Escaped hex must start with \\x");
+        }
+
+        Self::decode_separated(s[2..].split("\\x"))
+    }
+
+    fn decode_c_array(s: &str) -> SimpleResult<Vec<u8>> {
+        let s = s.trim().trim_end_matches(';').trim_end();
+
+        // Find the start and end of the array, if they're there
+        let start = match s.find('{') {
+            Some(start) => Some(start),
+            None => s.find('['),
+        };
+
+        let s = match (start, s.chars().last()) {
+            (Some(start), Some('}')) | (Some(start), Some(']')) => &s[(start + 1)..(s.len() - 1)],
+            (None, _) => s,
+            _ => bail!("Array isn't closed"),
+        };
+
+        let mut values: Vec<&str> = s.split(',').map(|v| v.trim()).collect();
+
+        // Allow a trailing comma
+        if values.last() == Some(&"") {
+            values.pop();
+        }
+
+        values.into_iter().map(|v| {
+            let digits = match v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+                Some(d) => d,
+                None => bail!("Array values must start with 0x: {:?}", v),
+            };
+
+            if digits.is_empty() || digits.len() > 2 {
+                bail!("Array values must be one or two hex digits: {:?}", v);
+            }
+
+            match u8::from_str_radix(digits, 16) {
+                Ok(b) => Ok(b),
+                Err(e) => bail!("Couldn't decode hex {:?}: {}", v, e),
+            }
+        }).collect()
+    }
+
+    /// Split a line from `hexdump -C` into the offset and the data. The final
+    /// line has an offset but no data.
+    fn split_hexdump_line(line: &str) -> SimpleResult<(&str, Option<&str>)> {
+        let (offset, data) = match line.split_once(' ') {
+            Some((offset, data)) => (offset, data),
+            None => return Ok((line, None)),
+        };
+
+        // The ASCII gutter starts at the first '|' (it can contain more)
+        let data = match data.split_once('|') {
+            Some((data, _)) => data,
+            None => data,
+        };
+
+        Ok((offset, Some(data)))
+    }
+
+    /// Split a line from `xxd` into the offset and the data.
+    fn split_xxd_line(line: &str) -> SimpleResult<(&str, Option<&str>)> {
+        let (offset, data) = match line.split_once(": ") {
+            Some((offset, data)) => (offset, data),
+            None => bail!("xxd line is missing an offset"),
+        };
+
+        // The ASCII gutter comes after two spaces
+        let data = match data.split_once("  ") {
+            Some((data, _)) => data,
+            None => data,
+        };
+
+        Ok((offset, Some(data)))
+    }
+
+    /// Decode a hex dump, where each line has an offset and some data, and
+    /// `*` means that the previous line is repeated up to the next offset.
+    ///
+    /// The repeats can't make the data more than [`DUMP_MAX_EXPANSION`] times
+    /// the size of the dump.
+    fn decode_dump(s: &str, split_line: DumpLineSplitter) -> SimpleResult<Vec<u8>> {
+        let max_length = s.len().saturating_mul(DUMP_MAX_EXPANSION);
+        let mut out: Vec<u8> = Vec::new();
+        let mut start: Option<usize> = None;
+        let mut previous: Vec<u8> = Vec::new();
+        let mut repeating = false;
+        let mut finished = false;
+
+        for line in s.lines().map(|line| line.trim_end()).filter(|line| !line.is_empty()) {
+            if finished {
+                bail!("Hex dump continues after the final offset");
+            }
+
+            if line == "*" {
+                if previous.is_empty() {
+                    bail!("Hex dump has a repeat marker without a line to repeat");
+                }
+                repeating = true;
+                continue;
+            }
+
+            let (offset, data) = split_line(line)?;
+            let offset = match usize::from_str_radix(offset, 16) {
+                Ok(o) => o,
+                Err(e) => bail!("Couldn't parse hex dump offset {:?}: {}", offset, e),
+            };
+
+            // Offsets are relative to the first line
+            let start = *start.get_or_insert(offset);
+            let offset = match offset.checked_sub(start) {
+                Some(o) => o,
+                None => bail!("Hex dump offsets go backwards"),
+            };
+
+            if repeating {
+                let length = offset.saturating_sub(out.len());
+                if length % previous.len() != 0 {
+                    bail!("Hex dump offset 0x{:x} isn't a whole number of repeated lines away", offset + start);
+                }
+                if offset > max_length {
+                    bail!("Hex dump offset 0x{:x} is too far past the data for the size of the dump", offset + start);
+                }
+
+                out.reserve(length);
+                out.extend(previous.iter().cycle().take(length));
+                repeating = false;
+            }
+
+            if offset != out.len() {
+                bail!("Hex dump offset 0x{:x} doesn't match the data length 0x{:x}", offset + start, out.len() + start);
+            }
+
+            match data {
+                Some(data) => {
+                    let data: Vec<u8> = data.split_ascii_whitespace().map(|group| {
+                        // xxd groups two bytes together
+                        match hex::decode(group) {
+                            Ok(d) => Ok(d),
+                            Err(e) => bail!("Couldn't decode hex dump group {:?}: {}", group, e),
+                        }
+                    }).collect::<SimpleResult<Vec<Vec<u8>>>>()?.concat();
+
+                    // Only the hexdump's final line has no data
+                    if data.is_empty() {
+                        finished = true;
+                    }
+
+                    out.extend(&data);
+                    previous = data;
+                },
+                None => finished = true,
+            }
+        }
+
+        if repeating {
+            bail!("Hex dump ends with a repeat marker");
+        }
+
+        Ok(out)
+    }
+
+    /// Are the hex digits uppercase? Mixed case counts as lowercase.
+    fn is_uppercase<'a>(digits: impl Iterator<Item=&'a str>) -> bool {
+        let letters: Vec<char> = digits.flat_map(|d| d.chars()).filter(|c| matches!(*c, 'a'..='f' | 'A'..='F')).collect();
+
+        !letters.is_empty() && letters.iter().all(|c| c.is_ascii_uppercase())
+    }
+
+    /// Find where each byte is in the text, including its `\x` or `0x`
+    /// marker.
+    fn byte_spans(format: HexFormat, s: &str) -> Vec<Range<usize>> {
+        let bytes = s.as_bytes();
+        let mut spans = vec![];
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let digits_start = match format {
+                HexFormat::Escaped if bytes[i..].starts_with(b"\\x") => Some(i + 2),
+                HexFormat::CArray if bytes[i..].starts_with(b"0x") || bytes[i..].starts_with(b"0X") => Some(i + 2),
+                HexFormat::Escaped | HexFormat::CArray => None,
+                _ => Some(i),
+            };
+
+            let digits = digits_start.map_or(0, |start| bytes[start..].iter().take_while(|b| b.is_ascii_hexdigit()).count());
+
+            match digits {
+                0 => i += 1,
+                _ => {
+                    let end = digits_start.unwrap_or(i) + digits;
+                    spans.push(i..end);
+                    i = end;
+                },
+            }
+        }
+
+        spans
+    }
+
+    /// Work out the layout of some hex text (see [`Self::new_matching`]).
+    fn find_layout(format: HexFormat, s: &str) -> HexLayout {
+        let mut layout = HexLayout::default_for(format);
+
+        let split_line: DumpLineSplitter = match format {
+            HexFormat::Hexdump => Self::split_hexdump_line,
+            HexFormat::Xxd     => Self::split_xxd_line,
+            _ => {
+                let spans = Self::byte_spans(format, s);
+
+                if let (Some(first), Some(last)) = (spans.first(), spans.last()) {
+                    layout.uppercase = Self::is_uppercase(spans.iter().map(|span| &s[span.clone()]));
+                    layout.prefix = s[..first.start].to_string();
+                    layout.suffix = s[last.end..].to_string();
+
+                    if let Some(second) = spans.get(1) {
+                        layout.separator = s[first.end..second.start].to_string();
+                    }
+                }
+
+                return layout;
+            },
+        };
+
+        let lines: Vec<(&str, Option<&str>)> = s.lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty() && *line != "*")
+            .filter_map(|line| split_line(line).ok())
+            .collect();
+
+        if let Some((offset, _)) = lines.first() {
+            layout.start = usize::from_str_radix(offset, 16).unwrap_or(0);
+        }
+        layout.uppercase = Self::is_uppercase(lines.iter().filter_map(|(_, data)| *data));
+
+        // The splitters drop the gutter, so look for it in the original lines
+        let has_gutter = |line: &str| match format {
+            HexFormat::Hexdump => line.contains('|'),
+            _ => line.split_once(": ").is_some_and(|(_, data)| data.contains("  ")),
+        };
+        let data_lines: Vec<&str> = s.lines()
+            .map(|line| line.trim_end())
+            .filter(|line| split_line(line).is_ok_and(|(_, data)| data.is_some_and(|d| !d.trim().is_empty())))
+            .collect();
+        if !data_lines.is_empty() {
+            layout.gutter = data_lines.into_iter().any(has_gutter);
+        }
+
+        if format == HexFormat::Hexdump {
+            if let Some((_, data)) = lines.last() {
+                layout.final_offset = data.is_none_or(|d| d.trim().is_empty());
+            }
+        }
+
+        layout
+    }
+
+    /// Format an offset or byte in a hex dump, in the right case.
+    fn dump_hex(&self, s: String) -> String {
+        match self.layout.uppercase {
+            true  => s.to_uppercase(),
+            false => s,
+        }
+    }
+
+    /// Get the ASCII gutter for a line of a hex dump.
+    fn dump_ascii(line: &[u8]) -> String {
+        line.iter().map(|b| match b {
+            0x20..=0x7e => *b as char,
+            _ => '.',
+        }).collect()
+    }
+
+    fn encode_hexdump(&self, buffer: &[u8]) -> String {
+        let start = self.layout.start;
+        let mut out = String::new();
+        let mut previous: Option<&[u8]> = None;
+        let mut repeating = false;
+
+        for (i, line) in buffer.chunks(DUMP_WIDTH).enumerate() {
+            // Identical full lines are collapsed into a '*'
+            if line.len() == DUMP_WIDTH && previous == Some(line) {
+                if !repeating {
+                    out.push_str("*\n");
+                    repeating = true;
+                }
+                continue;
+            }
+            repeating = false;
+            previous = Some(line);
+
+            let mut hex = String::new();
+            for j in 0..DUMP_WIDTH {
+                match line.get(j) {
+                    Some(b) => hex.push_str(&format!("{:02x} ", b)),
+                    None => hex.push_str("   "),
+                }
+
+                if j == (DUMP_WIDTH / 2) - 1 {
+                    hex.push(' ');
+                }
+            }
+
+            let offset = self.dump_hex(format!("{:08x}", start + i * DUMP_WIDTH));
+            match self.layout.gutter {
+                true  => out.push_str(&format!("{}  {} |{}|\n", offset, self.dump_hex(hex), Self::dump_ascii(line))),
+                false => out.push_str(&format!("{}  {}\n", offset, self.dump_hex(hex).trim_end())),
+            }
+        }
+
+        if self.layout.final_offset && !buffer.is_empty() {
+            out.push_str(&format!("{}\n", self.dump_hex(format!("{:08x}", start + buffer.len()))));
+        }
+
+        out
+    }
+
+    fn encode_xxd(&self, buffer: &[u8]) -> String {
+        buffer.chunks(DUMP_WIDTH).enumerate().map(|(i, line)| {
+            let offset = self.dump_hex(format!("{:08x}", self.layout.start + i * DUMP_WIDTH));
+            let hex: Vec<String> = line.chunks(2).map(hex::encode).collect();
+
+            match self.layout.gutter {
+                true  => format!("{}: {:39}  {}\n", offset, self.dump_hex(hex.join(" ")), Self::dump_ascii(line)),
+                false => format!("{}: {}\n", offset, self.dump_hex(hex.join(" "))),
+            }
+        }).collect()
     }
 }
 
@@ -27,14 +526,39 @@ impl TransformerTrait for TransformHex {
             Err(e) => bail!("Couldn't convert the buffer into a string: {}", e),
         };
 
-        match hex::decode(s) {
-            Ok(s) => Ok(s),
-            Err(e) => bail!("Couldn't decode hex: {}", e),
+        match self.format {
+            HexFormat::Plain => match hex::decode(s) {
+                Ok(s) => Ok(s),
+                Err(e) => bail!("Couldn't decode hex: {}", e),
+            },
+            HexFormat::Spaced  => Self::decode_separated(s.split_ascii_whitespace()),
+            HexFormat::Colons  => Self::decode_separated(s.trim().split(':')),
+            HexFormat::Escaped => Self::decode_escaped(s),
+            HexFormat::CArray  => Self::decode_c_array(s),
+            HexFormat::Hexdump => Self::decode_dump(s, Self::split_hexdump_line),
+            HexFormat::Xxd     => Self::decode_dump(s, Self::split_xxd_line),
         }
     }
 
     fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        Ok(hex::encode(buffer).into_bytes())
+        let marker = match self.format {
+            HexFormat::Escaped => "\\x",
+            HexFormat::CArray  => "0x",
+            _                  => "",
+        };
+
+        let hex: Vec<String> = buffer.iter().map(|b| match self.layout.uppercase {
+            true  => format!("{}{:02X}", marker, b),
+            false => format!("{}{:02x}", marker, b),
+        }).collect();
+
+        let out = match self.format {
+            HexFormat::Hexdump => self.encode_hexdump(buffer),
+            HexFormat::Xxd     => self.encode_xxd(buffer),
+            _                  => format!("{}{}{}", self.layout.prefix, hex.join(&self.layout.separator), self.layout.suffix),
+        };
+
+        Ok(out.into_bytes())
     }
 
     fn is_two_way(&self) -> bool {
@@ -42,11 +566,39 @@ impl TransformerTrait for TransformHex {
     }
 
     fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
-        let s = Self::new();
-        match s.can_transform(buffer) {
-            true => vec![s],
-            false => vec![],
+        // Most of the formats would also accept plain hex (or nothing at
+        // all), so only try them when their separators are there
+        let mut formats = vec![HexFormat::Plain];
+
+        if buffer.contains(&b' ') {
+            formats.push(HexFormat::Spaced);
+        }
+
+        if buffer.contains(&b':') {
+            formats.push(HexFormat::Colons);
+        }
+
+        if buffer.windows(2).any(|w| w == b"\\x") {
+            formats.push(HexFormat::Escaped);
+        }
+
+        if buffer.windows(2).any(|w| w == b"0x" || w == b"0X") {
+            formats.push(HexFormat::CArray);
+        }
+
+        if buffer.windows(2).any(|w| w == b"  ") {
+            formats.push(HexFormat::Hexdump);
+        }
+
+        if buffer.windows(2).any(|w| w == b": ") {
+            formats.push(HexFormat::Xxd);
         }
+
+        // Keep the layout, so untransforming gives back the same text
+        formats.into_iter()
+            .map(|format| Self::new_matching(format, buffer))
+            .filter(|t| t.can_transform(buffer))
+            .collect()
     }
 }
 
@@ -81,4 +633,210 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_hex_separated() -> SimpleResult<()> {
+        let t = TransformHex::spaced();
+        assert!(t.is_two_way());
+        assert_eq!(vec![0xde, 0xad, 0xbe, 0xef], t.transform(&b"de ad BE EF".to_vec())?);
+        assert_eq!(vec![0xde, 0xad, 0xbe, 0xef], t.transform(&b"de ad\nbe  ef\n".to_vec())?);
+        assert_eq!(b"de ad be ef".to_vec(), t.untransform(&vec![0xde, 0xad, 0xbe, 0xef])?);
+        assert!(t.transform(&b"dead beef".to_vec()).is_err());
+        assert!(t.transform(&b"de a".to_vec()).is_err());
+
+        let t = TransformHex::colons();
+        assert!(t.is_two_way());
+        assert_eq!(vec![0xde, 0xad, 0xbe, 0xef], t.transform(&b"de:ad:BE:EF\n".to_vec())?);
+        assert_eq!(b"de:ad:be:ef".to_vec(), t.untransform(&vec![0xde, 0xad, 0xbe, 0xef])?);
+        assert!(t.transform(&b"de:ad:".to_vec()).is_err());
+        assert!(t.transform(&b"de ad".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_escaped() -> SimpleResult<()> {
+        let t = TransformHex::escaped();
+        assert!(t.is_two_way());
+
+        assert_eq!(vec![0xde, 0xad, 0xbe, 0xef], t.transform(&b"\\xde\\xad\\xBE\\xEF".to_vec())?);
+        assert_eq!(vec![0xde, 0xad], t.transform(&b"\"\\xde\\xad\"".to_vec())?);
+        assert_eq!(vec![0xde, 0xad], t.transform(&b"'\\xde\\xad'\n".to_vec())?);
+        assert_eq!(b"\\xde\\xad\\xbe\\xef".to_vec(), t.untransform(&vec![0xde, 0xad, 0xbe, 0xef])?);
+
+        assert!(t.transform(&b"dead".to_vec()).is_err());
+        assert!(t.transform(&b"\\xde\\xa".to_vec()).is_err());
+        assert!(t.transform(&b"\\xdeA".to_vec()).is_err());
+        assert!(t.transform(&b"\"\\xde".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_c_array() -> SimpleResult<()> {
+        let t = TransformHex::c_array();
+        assert!(t.is_two_way());
+
+        assert_eq!(vec![0xde, 0xad, 0xbe, 0xef], t.transform(&b"0xde, 0xad, 0xBE, 0XEF".to_vec())?);
+        assert_eq!(vec![0xde, 0x0a, 0x00], t.transform(&b"[0xde,0xa,0x0]".to_vec())?);
+        assert_eq!(vec![0xde, 0xad], t.transform(&b"unsigned char data[] = {\n  0xde, 0xad,\n};\n".to_vec())?);
+        assert_eq!(b"0xde, 0xad, 0xbe, 0xef".to_vec(), t.untransform(&vec![0xde, 0xad, 0xbe, 0xef])?);
+
+        assert!(t.transform(&b"de, ad".to_vec()).is_err());
+        assert!(t.transform(&b"0xde, 0x".to_vec()).is_err());
+        assert!(t.transform(&b"0xdead".to_vec()).is_err());
+        assert!(t.transform(&b"{0xde, 0xad".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_hexdump() -> SimpleResult<()> {
+        let t = TransformHex::hexdump();
+        assert!(t.is_two_way());
+
+        let mut data = b"Hello, world.\n".to_vec();
+        data.extend(vec![0; 48]);
+        data.extend(b"ab");
+
+        // $ hexdump -C
+        let dump = b"00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 2e 0a 00 00  |Hello, world....|\n\
+                     00000010  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n\
+                     *\n\
+                     00000030  00 00 00 00 00 00 00 00  00 00 00 00 00 00 61 62  |..............ab|\n\
+                     00000040\n".to_vec();
+        assert_eq!(data, t.transform(&dump)?);
+        assert_eq!(dump, t.untransform(&data)?);
+
+        // A short line, and ASCII that looks like hex or a separator
+        let dump = b"00000000  61 62 7c 20 66 66                                 |ab| ff|\n\
+                     00000006\n".to_vec();
+        assert_eq!(b"ab| ff".to_vec(), t.transform(&dump)?);
+        assert_eq!(dump, t.untransform(&b"ab| ff".to_vec())?);
+
+        // Offsets don't have to start at zero, and the last one is optional
+        assert_eq!(b"ab".to_vec(), t.transform(&b"00000100  61 62  |ab|".to_vec())?);
+
+        // Offsets that don't match
+        assert!(t.transform(&b"00000000  61 62  |ab|\n00000003\n".to_vec()).is_err());
+        assert!(t.transform(&b"00000000  61 62  |ab|\n00000000  61 62  |ab|\n".to_vec()).is_err());
+
+        // Bad repeats
+        assert!(t.transform(&b"*\n00000010\n".to_vec()).is_err());
+        assert!(t.transform(&b"00000000  61 62  |ab|\n*\n".to_vec()).is_err());
+        assert!(t.transform(&b"00000000  61 62 63  |abc|\n*\n00000008\n".to_vec()).is_err());
+
+        // A repeat that would expand a tiny dump into a huge buffer
+        assert!(t.transform(&b"00000000  41\n*\nffffffffffff\n".to_vec()).is_err());
+        assert!(t.transform(&b"00000000  41\n*\n00100000\n".to_vec()).is_err());
+
+        // Bad data
+        assert!(t.transform(&b"00000000  61 6  |a|\n".to_vec()).is_err());
+        assert!(t.transform(&b"0000000g  61 62  |ab|\n".to_vec()).is_err());
+        assert!(t.transform(&b"00000002\n00000000  61 62  |ab|\n".to_vec()).is_err());
+        assert!(t.transform(&b"00000000  +1 62  |ab|\n".to_vec()).is_err());
+
+        // Non-ASCII text that happens to have an even number of bytes
+        assert!(t.transform(&"00000000  a\u{e9}b".as_bytes().to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_xxd() -> SimpleResult<()> {
+        let t = TransformHex::xxd();
+        assert!(t.is_two_way());
+
+        let mut data = b"Hello, world.\n".to_vec();
+        data.extend(vec![0; 48]);
+        data.extend(b"ab");
+
+        // $ xxd
+        let dump = b"00000000: 4865 6c6c 6f2c 2077 6f72 6c64 2e0a 0000  Hello, world....\n\
+                     00000010: 0000 0000 0000 0000 0000 0000 0000 0000  ................\n\
+                     00000020: 0000 0000 0000 0000 0000 0000 0000 0000  ................\n\
+                     00000030: 0000 0000 0000 0000 0000 0000 0000 6162  ..............ab\n".to_vec();
+        assert_eq!(data, t.transform(&dump)?);
+        assert_eq!(dump, t.untransform(&data)?);
+
+        // A short line, with ASCII that looks like hex
+        let dump = b"00000000: 6162 2066 66                             ab ff\n".to_vec();
+        assert_eq!(b"ab ff".to_vec(), t.transform(&dump)?);
+        assert_eq!(dump, t.untransform(&b"ab ff".to_vec())?);
+
+        // Other group sizes, and CRLF line endings
+        assert_eq!(b"abcdef".to_vec(), t.transform(&b"00000000: 61 62  ab\r\n00000002: 63646566  cdef\r\n".to_vec())?);
+
+        // Offsets that don't match
+        assert!(t.transform(&b"00000000: 6162  ab\n00000003: 6364  cd\n".to_vec()).is_err());
+
+        // Bad data
+        assert!(t.transform(&b"6162  ab\n".to_vec()).is_err());
+        assert!(t.transform(&b"00000000: 616  a\n".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_layout() -> SimpleResult<()> {
+        // Untransforming a matching transformation gives back the same text
+        let tests: Vec<(HexFormat, &[u8])> = vec![
+            (HexFormat::Plain,   b"DEADBEEF"),
+            (HexFormat::Spaced,  b"de  ad  be  ef\n"),
+            (HexFormat::Colons,  b"DE:AD:BE:EF"),
+            (HexFormat::Escaped, b"\"\\xde\\xad\\xbe\\xef\""),
+            (HexFormat::Escaped, b"'\\xDE\\xAD\\xBE\\xEF'\n"),
+            (HexFormat::CArray,  b"unsigned char data[] = { 0xde, 0xad, 0xbe, 0xef, };\n"),
+            (HexFormat::CArray,  b"[0xDE,0xAD,0xBE,0xEF]"),
+            (HexFormat::Hexdump, b"00000100  de ad be ef                                       |....|\n00000104\n"),
+            (HexFormat::Hexdump, b"00000000  de ad be ef\n"),
+            (HexFormat::Hexdump, b"00000000  DE AD BE EF\n00000004\n"),
+            (HexFormat::Xxd,     b"000000F0: DEAD BEEF                                ....\n"),
+            (HexFormat::Xxd,     b"00000000: dead beef\n"),
+        ];
+
+        for (format, text) in tests {
+            let t = TransformHex::new_matching(format, text);
+            let data = t.transform(&text.to_vec())?;
+
+            assert_eq!(vec![0xde, 0xad, 0xbe, 0xef], data, "{:?}", format);
+            assert_eq!(text.to_vec(), t.untransform(&data)?, "{:?}", format);
+        }
+
+        // Anything that isn't recognized falls back to the defaults
+        assert_eq!(TransformHex::c_array(), TransformHex::new_matching(HexFormat::CArray, b"0xde"));
+        assert_eq!(TransformHex::hexdump(), TransformHex::new_matching(HexFormat::Hexdump, b"00000000  de ad be ef                                       |....|\n00000004\n"));
+        assert_eq!(TransformHex::xxd(), TransformHex::new_matching(HexFormat::Xxd, b"00000000: dead beef                                ....\n"));
+
+        // Dumps without a gutter stay that way
+        let t = TransformHex::new_matching(HexFormat::Xxd, b"00000000: 41\n");
+        assert_eq!(b"00000000: 41\n".to_vec(), t.untransform(&b"A".to_vec())?);
+        let t = TransformHex::new_matching(HexFormat::Hexdump, b"00000000  41\n");
+        assert_eq!(b"00000000  41\n".to_vec(), t.untransform(&b"A".to_vec())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_detect() -> SimpleResult<()> {
+        assert_eq!(vec![TransformHex::new()], TransformHex::detect(&b"deadbeef".to_vec()));
+        assert_eq!(vec![TransformHex::spaced()], TransformHex::detect(&b"de ad be ef".to_vec()));
+        assert_eq!(vec![TransformHex::colons()], TransformHex::detect(&b"de:ad:be:ef".to_vec()));
+        assert_eq!(vec![TransformHex::escaped()], TransformHex::detect(&b"\\xde\\xad".to_vec()));
+        assert_eq!(vec![TransformHex::c_array()], TransformHex::detect(&b"0xde, 0xad".to_vec()));
+        assert_eq!(vec![TransformHex::hexdump()], TransformHex::detect(&b"00000000  61 62  |ab|\n00000002\n".to_vec()));
+        assert_eq!(vec![TransformHex::xxd()], TransformHex::detect(&b"00000000: 6162  ab\n".to_vec()));
+
+        // The layout is kept
+        let text = b"{ 0xDE, 0xAD }".to_vec();
+        assert_eq!(vec![TransformHex::new_matching(HexFormat::CArray, &text)], TransformHex::detect(&text));
+        assert_eq!(text, TransformHex::detect(&text)[0].untransform(&vec![0xde, 0xad])?);
+
+        // Ordinary text mustn't break the hex dump formats
+        assert_eq!(Vec::<Transformation>::new(), TransformHex::detect(&"00000000  a\u{e9}b".as_bytes().to_vec()));
+        assert_eq!(Vec::<Transformation>::new(), TransformHex::detect(&"caf\u{e9}  r\u{e9}sum\u{e9}: na\u{ef}ve".as_bytes().to_vec()));
+        assert_eq!(Vec::<Transformation>::new(), TransformHex::detect(&b"00000000  41\n*\nffffffffffff\n".to_vec()));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,197 @@
+use lz4_flex::frame::FrameDecoder;
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::io::Read;
+
+use crate::transformation::{Transformation, TransformerTrait};
+
+/// The signature at the start of an LZ4 frame.
+const LZ4_FRAME_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+/// LZ4 can't possibly compress better than this (each byte of a match length
+/// can represent at most 255 bytes of output).
+const LZ4_MAX_RATIO: usize = 255;
+
+/// Which flavour of LZ4 the data is in.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub enum Lz4Format {
+    /// The LZ4 frame format (what the `lz4` commandline tool produces).
+    Frame,
+
+    /// A raw LZ4 block, prefixed with the 32-bit little endian uncompressed
+    /// size (as produced by, for example, Python's `lz4.block.compress()`).
+    BlockSizePrepended,
+
+    /// A raw LZ4 block with no header; since the block doesn't know its own
+    /// size, the uncompressed size needs to be provided.
+    Block(u64),
+}
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformLz4 {
+    format: Lz4Format,
+}
+
+impl fmt::Display for TransformLz4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformLz4 {
+    pub fn new(format: Lz4Format) -> Transformation {
+        Transformation::FromLz4(TransformLz4 {
+            format,
+        })
+    }
+
+    pub fn frame() -> Transformation {
+        Self::new(Lz4Format::Frame)
+    }
+
+    pub fn block_size_prepended() -> Transformation {
+        Self::new(Lz4Format::BlockSizePrepended)
+    }
+
+    pub fn block(uncompressed_size: u64) -> Transformation {
+        Self::new(Lz4Format::Block(uncompressed_size))
+    }
+
+    fn decompress_block(buffer: &[u8], uncompressed_size: usize) -> SimpleResult<Vec<u8>> {
+        // Don't let a bad size trick us into allocating a huge buffer
+        if uncompressed_size > buffer.len() * LZ4_MAX_RATIO {
+            bail!("Couldn't decompress LZ4 block: uncompressed size is impossibly large ({} bytes)", uncompressed_size);
+        }
+
+        let out = match lz4_flex::decompress(buffer, uncompressed_size) {
+            Ok(b) => b,
+            Err(e) => bail!("Couldn't decompress LZ4 block: {}", e),
+        };
+
+        // The library treats the size as a minimum, but we want it exact
+        if out.len() != uncompressed_size {
+            bail!("Couldn't decompress LZ4 block: expected {} bytes, got {}", uncompressed_size, out.len());
+        }
+
+        Ok(out)
+    }
+
+    fn check_magic(self, buffer: &[u8]) -> bool {
+        match self.format {
+            Lz4Format::Frame              => buffer.starts_with(&LZ4_FRAME_MAGIC),
+            Lz4Format::BlockSizePrepended => buffer.len() > 4,
+            Lz4Format::Block(_)           => true,
+        }
+    }
+}
+
+impl TransformerTrait for TransformLz4 {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        if !self.check_magic(buffer) {
+            bail!("Not a valid LZ4 buffer (bad header)");
+        }
+
+        match self.format {
+            Lz4Format::Frame => {
+                let mut out: Vec<u8> = Vec::new();
+                match FrameDecoder::new(&buffer[..]).read_to_end(&mut out) {
+                    Ok(_) => Ok(out),
+                    Err(e) => bail!("Couldn't decompress LZ4 frame: {}", e),
+                }
+            },
+            Lz4Format::BlockSizePrepended => {
+                let size = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+
+                Self::decompress_block(&buffer[4..], size as usize)
+            },
+            Lz4Format::Block(size) => {
+                Self::decompress_block(&buffer[..], size as usize)
+            },
+        }
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("LZ4 cannot be untransformed");
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        self.check_magic(buffer) && self.transform(buffer).is_ok()
+    }
+
+    fn is_two_way(&self) -> bool {
+        // LZ4 can't reliably go backwards
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        let mut out: Vec<_> = Vec::new();
+
+        // We can't detect headerless blocks, since we don't know the size
+        for format in [Lz4Format::Frame, Lz4Format::BlockSizePrepended].iter() {
+            let t = TransformLz4 {
+                format: *format,
+            };
+
+            if t.can_transform(buffer) {
+                out.push(Transformation::FromLz4(t));
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_lz4_frame() -> SimpleResult<()> {
+        let t = TransformLz4::frame();
+        assert_eq!(false, t.is_two_way());
+
+        // echo -ne 'ABCD' | lz4
+        let result = t.transform(&b"\x04\x22\x4d\x18\x64\x40\xa7\x04\x00\x00\x80\x41\x42\x43\x44\x00\x00\x00\x00\xa6\x0c\x96\xaa".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // echo -ne 'ABCDABCDABCDABCDABCDABCD' | lz4
+        let result = t.transform(&b"\x04\x22\x4d\x18\x64\x40\xa7\x0d\x00\x00\x00\x4b\x41\x42\x43\x44\x04\x00\x50\x44\x41\x42\x43\x44\x00\x00\x00\x00\xf2\x5a\x50\x4a".to_vec())?;
+        assert_eq!(b"ABCDABCDABCDABCDABCDABCD".to_vec(), result);
+
+        // Bad checksum
+        assert!(t.transform(&b"\x04\x22\x4d\x18\x64\x40\xa7\x04\x00\x00\x80\x41\x42\x43\x44\x00\x00\x00\x00\xa6\x0c\x96\xab".to_vec()).is_err());
+
+        // Not a frame
+        assert!(t.transform(&b"ABCD".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lz4_block() -> SimpleResult<()> {
+        // Just literals
+        let result = TransformLz4::block_size_prepended().transform(&b"\x04\x00\x00\x00\x40ABCD".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        let result = TransformLz4::block(4).transform(&b"\x40ABCD".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // A match
+        let result = TransformLz4::block_size_prepended().transform(&b"\x18\x00\x00\x00\x4b\x41\x42\x43\x44\x04\x00\x50\x44\x41\x42\x43\x44".to_vec())?;
+        assert_eq!(b"ABCDABCDABCDABCDABCDABCD".to_vec(), result);
+
+        let result = TransformLz4::block(24).transform(&b"\x4b\x41\x42\x43\x44\x04\x00\x50\x44\x41\x42\x43\x44".to_vec())?;
+        assert_eq!(b"ABCDABCDABCDABCDABCDABCD".to_vec(), result);
+
+        // Wrong size
+        assert!(TransformLz4::block(5).transform(&b"\x40ABCD".to_vec()).is_err());
+        assert!(TransformLz4::block_size_prepended().transform(&b"\x03\x00\x00\x00\x40ABCD".to_vec()).is_err());
+
+        // Impossibly large size
+        assert!(TransformLz4::block_size_prepended().transform(&b"\xff\xff\xff\xff\x40ABCD".to_vec()).is_err());
+
+        Ok(())
+    }
+}
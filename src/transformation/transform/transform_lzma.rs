@@ -0,0 +1,271 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{LimitedOutput, Transformation, TransformerTrait};
+
+/// The signature at the start of every .xz file.
+const XZ_MAGIC: [u8; 6] = [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+/// The size of an .xz stream header: the magic, two bytes of flags, and a
+/// CRC32.
+const XZ_HEADER_LENGTH: usize = 12;
+
+/// The size of a "legacy" .lzma header: a properties byte, a 32-bit
+/// dictionary size, and a 64-bit uncompressed size.
+const LZMA_HEADER_LENGTH: usize = 13;
+
+/// The properties byte encodes (pb * 5 + lp) * 9 + lc, with pb and lp <= 4
+/// and lc <= 8, so anything bigger than this is invalid.
+const LZMA_MAX_PROPERTIES: u8 = (4 * 5 + 4) * 9 + 8;
+
+/// Which container the LZMA data is in.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub enum LzmaFormat {
+    /// The "legacy" .lzma format (aka LZMA_Alone), with a 13-byte header.
+    Lzma,
+
+    /// The .xz container format.
+    Xz,
+}
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformLzma {
+    format: LzmaFormat,
+}
+
+impl fmt::Display for TransformLzma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformLzma {
+    pub fn new(format: LzmaFormat) -> Transformation {
+        Transformation::FromLzma(TransformLzma {
+            format,
+        })
+    }
+
+    pub fn lzma() -> Transformation {
+        Self::new(LzmaFormat::Lzma)
+    }
+
+    pub fn xz() -> Transformation {
+        Self::new(LzmaFormat::Xz)
+    }
+
+    fn check_magic(self, buffer: &[u8]) -> bool {
+        match self.format {
+            // There's no real magic, but the header has to be sane
+            LzmaFormat::Lzma => buffer.len() > LZMA_HEADER_LENGTH && buffer[0] <= LZMA_MAX_PROPERTIES,
+            LzmaFormat::Xz   => buffer.starts_with(&XZ_MAGIC),
+        }
+    }
+
+    /// Add up the sizes of the LZMA2 chunks in an .xz file, without
+    /// decompressing them.
+    ///
+    /// The decoder holds an entire block in memory before writing any of it,
+    /// so limiting the output isn't enough to stop a decompression bomb.
+    fn xz_uncompressed_size(buffer: &[u8]) -> SimpleResult<usize> {
+        let byte = |i: usize| -> SimpleResult<usize> {
+            match buffer.get(i) {
+                Some(b) => Ok(*b as usize),
+                None => bail!("Couldn't read xz structure: data is truncated"),
+            }
+        };
+        let u16_be = |i: usize| -> SimpleResult<usize> {
+            Ok((byte(i)? << 8) | byte(i + 1)?)
+        };
+
+        // The check type is in the low bits of the second flags byte; each
+        // group of three types has the same size
+        let check_length = match byte(XZ_MAGIC.len() + 1)? & 0x0f {
+            0 => 0,
+            check => 4 << ((check - 1) / 3),
+        };
+
+        let mut total: usize = 0;
+        let mut i = XZ_HEADER_LENGTH;
+        loop {
+            // A zero instead of a block header is the start of the index
+            let block_start = i;
+            let header_length = byte(i)?;
+            if header_length == 0 {
+                return Ok(total);
+            }
+            i += (header_length + 1) * 4;
+
+            loop {
+                let control = byte(i)?;
+                match control {
+                    // The end of the block
+                    0x00 => {
+                        i += 1;
+                        break;
+                    },
+
+                    // An uncompressed chunk
+                    0x01 | 0x02 => {
+                        let length = u16_be(i + 1)? + 1;
+                        total = total.saturating_add(length);
+                        i += 3 + length;
+                    },
+
+                    // An LZMA chunk, which has new properties if the top two
+                    // bits are set
+                    0x80..=0xff => {
+                        total = total.saturating_add((((control & 0x1f) << 16) | u16_be(i + 1)?) + 1);
+                        let header_length = if control >= 0xc0 { 6 } else { 5 };
+                        i += header_length + u16_be(i + 3)? + 1;
+                    },
+
+                    _ => bail!("Couldn't read xz structure: invalid LZMA2 chunk 0x{:02x}", control),
+                }
+            }
+
+            // The block is padded to a multiple of four bytes, then checked
+            i += (4 - (i - block_start) % 4) % 4 + check_length;
+        }
+    }
+
+    fn decompress(self, buffer: &[u8], limit: usize) -> SimpleResult<Vec<u8>> {
+        if !self.check_magic(buffer) {
+            bail!("Not a valid {:?} buffer (bad header)", self.format);
+        }
+
+        let mut input = buffer;
+        let mut out = LimitedOutput::new(limit);
+        let result = match self.format {
+            // The limit also stops the dictionary from growing too big
+            LzmaFormat::Lzma => lzma_rs::lzma_decompress_with_options(&mut input, &mut out, &lzma_rs::decompress::Options {
+                memlimit: Some(limit),
+                ..Default::default()
+            }),
+            LzmaFormat::Xz => {
+                if Self::xz_uncompressed_size(buffer)? > limit {
+                    bail!("Couldn't decompress {:?}: decompressed data is over the limit of {} bytes", self.format, limit);
+                }
+
+                lzma_rs::xz_decompress(&mut input, &mut out)
+            },
+        };
+
+        match result {
+            Ok(()) => Ok(out.into_inner()),
+            Err(e) => bail!("Couldn't decompress {:?}: {}", self.format, e),
+        }
+    }
+}
+
+impl TransformerTrait for TransformLzma {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        self.decompress(buffer, usize::MAX)
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("LZMA cannot be untransformed");
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        // Don't let a decompression bomb use up all the memory
+        self.check_magic(buffer) && self.decompress(buffer, LimitedOutput::detection_limit(buffer.len())).is_ok()
+    }
+
+    fn is_two_way(&self) -> bool {
+        // LZMA can't reliably go backwards
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        let mut out: Vec<_> = Vec::new();
+
+        for format in [LzmaFormat::Lzma, LzmaFormat::Xz].iter() {
+            let t = TransformLzma {
+                format: *format,
+            };
+
+            if t.can_transform(buffer) {
+                out.push(Transformation::FromLzma(t));
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_lzma() -> SimpleResult<()> {
+        let t = TransformLzma::lzma();
+        assert_eq!(false, t.is_two_way());
+
+        // echo -ne 'ABCD' | xz --format=lzma
+        let result = t.transform(&b"\x5d\x00\x00\x80\x00\xff\xff\xff\xff\xff\xff\xff\xff\x00\x20\x90\x84\x76\xc3\xf0\x11\xb2\xff\xfa\x1c\x80\x00".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // Invalid properties
+        assert!(t.transform(&b"\xe1\x00\x00\x80\x00\xff\xff\xff\xff\xff\xff\xff\xff\x00\x20\x90\x84\x76\xc3\xf0\x11\xb2\xff\xfa\x1c\x80\x00".to_vec()).is_err());
+
+        // Truncated
+        assert!(t.transform(&b"\x5d\x00\x00\x80\x00\xff\xff\xff\xff\xff\xff\xff\xff\x00\x20\x90".to_vec()).is_err());
+
+        // The xz format won't work
+        assert!(TransformLzma::xz().transform(&b"\x5d\x00\x00\x80\x00\xff\xff\xff\xff\xff\xff\xff\xff\x00\x20\x90\x84\x76\xc3\xf0\x11\xb2\xff\xfa\x1c\x80\x00".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_xz() -> SimpleResult<()> {
+        let t = TransformLzma::xz();
+        assert_eq!(false, t.is_two_way());
+
+        // echo -ne 'ABCD' | xz
+        let data = b"\xfd\x37\x7a\x58\x5a\x00\x00\x04\xe6\xd6\xb4\x46\x04\xc0\x08\x04\x21\x01\x16\x00\x00\x00\x00\x00\x00\x00\x00\x00\x4c\x41\xbc\x27\x01\x00\x03\x41\x42\x43\x44\x00\xcf\xd8\x9a\xb7\xd5\xb2\x84\x27\x00\x01\x24\x04\x94\x90\x03\xd6\x1f\xb6\xf3\x7d\x01\x00\x00\x00\x00\x04\x59\x5a".to_vec();
+        assert_eq!(b"ABCD".to_vec(), t.transform(&data)?);
+
+        // Only the xz format should be detected
+        assert_eq!(vec![TransformLzma::xz()], TransformLzma::detect(&data));
+
+        // Truncated
+        assert!(t.transform(&data[0..30].to_vec()).is_err());
+
+        // Not xz
+        assert!(t.transform(&b"ABCD".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lzma_limit() -> SimpleResult<()> {
+        let lzma = TransformLzma { format: LzmaFormat::Lzma };
+        let data = b"\x5d\x00\x00\x80\x00\xff\xff\xff\xff\xff\xff\xff\xff\x00\x20\x90\x84\x76\xc3\xf0\x11\xb2\xff\xfa\x1c\x80\x00";
+        assert_eq!(b"ABCD".to_vec(), lzma.decompress(data, 4)?);
+        assert!(lzma.decompress(data, 3).is_err());
+
+        let xz = TransformLzma { format: LzmaFormat::Xz };
+        let data = b"\xfd\x37\x7a\x58\x5a\x00\x00\x04\xe6\xd6\xb4\x46\x04\xc0\x08\x04\x21\x01\x16\x00\x00\x00\x00\x00\x00\x00\x00\x00\x4c\x41\xbc\x27\x01\x00\x03\x41\x42\x43\x44\x00\xcf\xd8\x9a\xb7\xd5\xb2\x84\x27\x00\x01\x24\x04\x94\x90\x03\xd6\x1f\xb6\xf3\x7d\x01\x00\x00\x00\x00\x04\x59\x5a";
+        assert_eq!(4, TransformLzma::xz_uncompressed_size(data)?);
+        assert_eq!(b"ABCD".to_vec(), xz.decompress(data, 4)?);
+        assert!(xz.decompress(data, 3).is_err());
+
+        // The same headers, then a block of LZMA2 chunks that each claim to
+        // be 2MiB; the sizes are checked before anything is decompressed
+        let mut bomb = data[..32].to_vec();
+        for _ in 0..16 {
+            bomb.extend(b"\x9f\xff\xff\x00\x00\x00");
+        }
+        bomb.extend([0; 13]);
+        assert_eq!(32 * 1024 * 1024, TransformLzma::xz_uncompressed_size(&bomb)?);
+        assert!(!xz.can_transform(&bomb));
+        assert_eq!(Vec::<Transformation>::new(), TransformLzma::detect(&bomb));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait};
+
+/// Decode URL percent-encoding (`%2F` and friends).
+///
+/// With `plus_is_space` set, '+' is decoded as a space, the way HTML forms
+/// (`application/x-www-form-urlencoded`) encode them.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformPercent {
+    plus_is_space: bool,
+}
+
+impl fmt::Display for TransformPercent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformPercent {
+    pub fn new(plus_is_space: bool) -> Transformation {
+        Transformation::FromPercent(TransformPercent {
+            plus_is_space,
+        })
+    }
+
+    pub fn url() -> Transformation {
+        Self::new(false)
+    }
+
+    pub fn form() -> Transformation {
+        Self::new(true)
+    }
+}
+
+impl TransformerTrait for TransformPercent {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let mut out: Vec<u8> = Vec::new();
+
+        let mut i = 0;
+        while i < buffer.len() {
+            match buffer[i] {
+                b'%' => {
+                    if i + 3 > buffer.len() {
+                        bail!("Incomplete percent escape");
+                    }
+
+                    match hex::decode(&buffer[(i + 1)..(i + 3)]) {
+                        Ok(b) => out.extend(b),
+                        Err(e) => bail!("Invalid percent escape: {}", e),
+                    }
+                    i += 3;
+                },
+                b'+' if self.plus_is_space => {
+                    out.push(b' ');
+                    i += 1;
+                },
+                b => {
+                    out.push(b);
+                    i += 1;
+                },
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("Percent-encoding cannot be untransformed");
+    }
+
+    fn is_two_way(&self) -> bool {
+        // There's no telling which characters were originally escaped
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        // Without an escape, it's the same as no transformation at all
+        if !buffer.contains(&b'%') {
+            return vec![];
+        }
+
+        let mut transformations = vec![Self::url()];
+
+        // They're only different if there's a '+'
+        if buffer.contains(&b'+') {
+            transformations.push(Self::form());
+        }
+
+        transformations.into_iter().filter(|t| t.can_transform(buffer)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_percent() -> SimpleResult<()> {
+        let t = TransformPercent::url();
+        assert!(!t.is_two_way());
+
+        // >>> urllib.parse.quote(b'Hello, world/?&=100%')
+        assert_eq!(b"Hello, world/?&=100%".to_vec(), t.transform(&b"Hello%2C%20world/%3F%26%3D100%25".to_vec())?);
+        assert_eq!(b"a+b+c".to_vec(), t.transform(&b"a+b%2Bc".to_vec())?);
+        assert_eq!(b"\xff\x00".to_vec(), t.transform(&b"%fF%00".to_vec())?);
+
+        // Errors
+        assert!(t.transform(&b"100%".to_vec()).is_err());
+        assert!(t.transform(&b"%4".to_vec()).is_err());
+        assert!(t.transform(&b"%zz".to_vec()).is_err());
+
+        assert!(t.untransform(&b"abc".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_percent_form() -> SimpleResult<()> {
+        let t = TransformPercent::form();
+        assert!(!t.is_two_way());
+
+        // >>> urllib.parse.quote_plus(b'a b+c')
+        assert_eq!(b"a b+c".to_vec(), t.transform(&b"a+b%2Bc".to_vec())?);
+
+        // Detect
+        let mut detected = TransformPercent::detect(&b"a+b%2Bc".to_vec());
+        detected.sort();
+        assert_eq!(vec![TransformPercent::url(), TransformPercent::form()], detected);
+        assert_eq!(vec![TransformPercent::url()], TransformPercent::detect(&b"a%20b".to_vec()));
+        assert_eq!(Vec::<Transformation>::new(), TransformPercent::detect(&b"a+b".to_vec()));
+
+        Ok(())
+    }
+}
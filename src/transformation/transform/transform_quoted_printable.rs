@@ -0,0 +1,134 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait};
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformQuotedPrintable {
+}
+
+impl TransformQuotedPrintable {
+    pub fn new() -> Transformation {
+        Transformation::FromQuotedPrintable(TransformQuotedPrintable {})
+    }
+}
+
+impl fmt::Display for TransformQuotedPrintable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformerTrait for TransformQuotedPrintable {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let mut out: Vec<u8> = Vec::new();
+
+        let lines: Vec<&[u8]> = buffer.split(|b| *b == b'\n').collect();
+        for (i, line) in lines.iter().enumerate() {
+            // Keep whichever line ending was used (the last line has none)
+            let (line, ending): (&[u8], &[u8]) = match (line.strip_suffix(b"\r"), i + 1 == lines.len()) {
+                (_, true)          => (line, b""),
+                (Some(line), false) => (line, b"\r\n"),
+                (None, false)       => (line, b"\n"),
+            };
+
+            // Trailing whitespace was added in transit, and isn't part of the
+            // data (RFC 2045, rule 3)
+            let line = match line.iter().rposition(|b| *b != b' ' && *b != b'\t') {
+                Some(last) => &line[..=last],
+                None => &line[0..0],
+            };
+
+            // An '=' at the end is a soft line break
+            let (line, ending) = match line.strip_suffix(b"=") {
+                Some(line) => (line, &b""[..]),
+                None => (line, ending),
+            };
+
+            let mut i = 0;
+            while i < line.len() {
+                match line[i] {
+                    b'=' => {
+                        if i + 3 > line.len() {
+                            bail!("Incomplete quoted-printable escape");
+                        }
+
+                        match hex::decode(&line[(i + 1)..(i + 3)]) {
+                            Ok(b) => out.extend(b),
+                            Err(e) => bail!("Invalid quoted-printable escape: {}", e),
+                        }
+                        i += 3;
+                    },
+                    b'\t' | b' '..=b'~' => {
+                        out.push(line[i]);
+                        i += 1;
+                    },
+                    b => bail!("Invalid quoted-printable character: 0x{:02x}", b),
+                }
+            }
+
+            out.extend(ending);
+        }
+
+        Ok(out)
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("Quoted-printable cannot be untransformed");
+    }
+
+    fn is_two_way(&self) -> bool {
+        // Encoders disagree on line lengths and which characters to escape, so
+        // we can't reliably get the same data back
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        // Without an escape, it's the same as no transformation at all
+        if !buffer.contains(&b'=') {
+            return vec![];
+        }
+
+        let s = Self::new();
+        match s.can_transform(buffer) {
+            true => vec![s],
+            false => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_quoted_printable() -> SimpleResult<()> {
+        let t = TransformQuotedPrintable::new();
+        assert!(!t.is_two_way());
+
+        // >>> quopri.encodestring(...)
+        assert_eq!(b"Caf\xc3\xa9 = caf\xc3\xa9".to_vec(), t.transform(&b"Caf=C3=A9 =3D caf=c3=a9".to_vec())?);
+
+        // Soft line breaks are removed, hard ones are kept as they are
+        assert_eq!(b"a long line\nnext line\r\nlast".to_vec(), t.transform(&b"a long=\n line\nnext=\r\n line\r\nlast".to_vec())?);
+
+        // Trailing whitespace is dropped, but escaped whitespace isn't
+        assert_eq!(b"abc\nabc \n".to_vec(), t.transform(&b"abc  \t\nabc=20\n".to_vec())?);
+
+        // Errors
+        assert!(t.transform(&b"AA==".to_vec()).is_err());
+        assert!(t.transform(&b"a=4".to_vec()).is_err());
+        assert!(t.transform(&b"a=4g".to_vec()).is_err());
+        assert!(t.transform(&b"\x00\x01".to_vec()).is_err());
+
+        assert!(t.untransform(&b"abc".to_vec()).is_err());
+
+        // Only detected when there's something to decode
+        assert_eq!(vec![TransformQuotedPrintable::new()], TransformQuotedPrintable::detect(&b"a=3Db".to_vec()));
+        assert_eq!(Vec::<Transformation>::new(), TransformQuotedPrintable::detect(&b"abc".to_vec()));
+
+        Ok(())
+    }
+}
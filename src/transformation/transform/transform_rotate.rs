@@ -0,0 +1,182 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::generic_number::Endian;
+use crate::transformation::{Transformation, TransformerTrait, XorSettings};
+use crate::transformation::{best_single_byte_candidate, read_element, write_element, element_mask};
+
+/// Rotate the bits in each element of the buffer.
+///
+/// The size of each element comes from the [`XorSettings`], and the value is
+/// how many bits to rotate by - so `XorSettings::SixteenBit(3)` rotates each
+/// 16-bit word by three bits.
+///
+/// Like [`crate::transformation::XorKeyMode`], this describes how the data
+/// was *encoded* - if the bits were rotated left, transforming the buffer
+/// rotates them right.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformRotate {
+    settings: XorSettings,
+    endian: Endian,
+    right: bool,
+}
+
+impl fmt::Display for TransformRotate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformRotate {
+    pub fn new(settings: XorSettings, endian: Endian, right: bool) -> SimpleResult<Transformation> {
+        if settings.value() >= (settings.width() * 8) as u64 {
+            bail!("Can't rotate a {}-bit element by {} bits", settings.width() * 8, settings.value());
+        }
+
+        Ok(Transformation::Rotate(Self {
+            settings,
+            endian,
+            right,
+        }))
+    }
+
+    /// The bits were rotated left.
+    pub fn left(settings: XorSettings, endian: Endian) -> SimpleResult<Transformation> {
+        Self::new(settings, endian, false)
+    }
+
+    /// The bits were rotated right.
+    pub fn right(settings: XorSettings, endian: Endian) -> SimpleResult<Transformation> {
+        Self::new(settings, endian, true)
+    }
+
+    /// Rotate each element in the given direction.
+    fn apply(&self, buffer: &[u8], right: bool) -> SimpleResult<Vec<u8>> {
+        let width = self.settings.width();
+        if !buffer.len().is_multiple_of(width) {
+            bail!("Rotate failed: the buffer isn't a multiple of the element size");
+        }
+
+        // A deserialized transformation doesn't go through new(), so the
+        // amount can be a full rotation or more
+        let bits = (width * 8) as u64;
+        let amount = self.settings.value() % bits;
+
+        // Rotating right is the same as rotating left by the rest of the way
+        let amount = match right {
+            false => amount,
+            true  => (bits - amount) % bits,
+        };
+
+        Ok(buffer.chunks(width).flat_map(|chunk| {
+            let value = read_element(chunk, self.endian);
+            let value = match amount {
+                0 => value,
+                _ => (value << amount) | (value >> (bits - amount)),
+            };
+
+            write_element(value & element_mask(width), width, self.endian)
+        }).collect())
+    }
+}
+
+impl TransformerTrait for TransformRotate {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        self.apply(buffer, !self.right)
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        self.apply(buffer, self.right)
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        buffer.len().is_multiple_of(self.settings.width())
+    }
+
+    fn is_two_way(&self) -> bool {
+        true
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        // Only try rotating bytes left - rotating right is the same as
+        // rotating left the rest of the way
+        let candidates = (1..8u8).filter_map(|amount| Self::left(XorSettings::EightBit(amount), Endian::Big).ok());
+
+        best_single_byte_candidate(buffer, candidates).into_iter().map(|(t, _)| t).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_rotate8() -> SimpleResult<()> {
+        let t = TransformRotate::left(XorSettings::EightBit(1), Endian::Big)?;
+        assert!(t.is_two_way());
+
+        // Rotated left when encoded, so transforming rotates right
+        assert_eq!(vec![0x80, 0x01, 0x91], t.transform(&vec![0x01, 0x02, 0x23])?);
+        assert_eq!(vec![0x01, 0x02, 0x23], t.untransform(&vec![0x80, 0x01, 0x91])?);
+
+        let t = TransformRotate::right(XorSettings::EightBit(4), Endian::Big)?;
+        assert_eq!(vec![0x10, 0x21, 0xba], t.transform(&vec![0x01, 0x12, 0xab])?);
+
+        // Zero does nothing
+        let t = TransformRotate::right(XorSettings::EightBit(0), Endian::Big)?;
+        assert_eq!(vec![0x01, 0x12, 0xab], t.transform(&vec![0x01, 0x12, 0xab])?);
+
+        // Can't rotate by the whole width or more
+        assert!(TransformRotate::left(XorSettings::EightBit(8), Endian::Big).is_err());
+        assert!(TransformRotate::left(XorSettings::SixtyFourBit(64), Endian::Big).is_err());
+
+        // ...but one that skipped new() wraps around instead of overflowing
+        let t: Transformation = serde_json::from_str(r#"{"Rotate":{"settings":{"EightBit":9},"endian":"Big","right":false}}"#).unwrap();
+        assert_eq!(vec![0x80, 0x01, 0x91], t.transform(&vec![0x01, 0x02, 0x23])?);
+        assert_eq!(vec![0x01, 0x02, 0x23], t.untransform(&vec![0x80, 0x01, 0x91])?);
+
+        let t: Transformation = serde_json::from_str(r#"{"Rotate":{"settings":{"EightBit":8},"endian":"Big","right":true}}"#).unwrap();
+        assert_eq!(vec![0x01, 0x02, 0x23], t.transform(&vec![0x01, 0x02, 0x23])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotate_words() -> SimpleResult<()> {
+        let t = TransformRotate::left(XorSettings::SixteenBit(4), Endian::Big)?;
+        assert_eq!(vec![0x41, 0x23, 0xda, 0xbc], t.transform(&vec![0x12, 0x34, 0xab, 0xcd])?);
+        assert_eq!(vec![0x12, 0x34, 0xab, 0xcd], t.untransform(&vec![0x41, 0x23, 0xda, 0xbc])?);
+
+        let t = TransformRotate::left(XorSettings::SixteenBit(4), Endian::Little)?;
+        assert_eq!(vec![0x41, 0x23], t.transform(&vec![0x12, 0x34])?);
+
+        let t = TransformRotate::right(XorSettings::ThirtyTwoBit(8), Endian::Big)?;
+        assert_eq!(vec![0x02, 0x03, 0x04, 0x01], t.transform(&vec![0x01, 0x02, 0x03, 0x04])?);
+
+        let t = TransformRotate::left(XorSettings::SixtyFourBit(1), Endian::Big)?;
+        assert_eq!(vec![0x80, 0, 0, 0, 0, 0, 0, 0], t.transform(&vec![0, 0, 0, 0, 0, 0, 0, 1])?);
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 1], t.untransform(&vec![0x80, 0, 0, 0, 0, 0, 0, 0])?);
+
+        // Lengths have to be a multiple of the width
+        assert!(!t.can_transform(&vec![0; 4]));
+        assert!(t.transform(&vec![0; 4]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect() -> SimpleResult<()> {
+        let text = b"Here is some perfectly normal text, which has been obfuscated by rotating the bits in each byte.".to_vec();
+        let t = TransformRotate::left(XorSettings::EightBit(3), Endian::Big)?;
+        let encoded = t.untransform(&text)?;
+
+        assert_eq!(vec![t], TransformRotate::detect(&encoded));
+
+        // Text doesn't need to be changed
+        assert_eq!(Vec::<Transformation>::new(), TransformRotate::detect(&text));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use snap::raw::{Decoder, decompress_len};
+use snap::read::FrameDecoder;
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::io::Read;
+
+use crate::transformation::{Transformation, TransformerTrait};
+
+/// The stream identifier chunk at the start of a framed Snappy stream.
+const SNAPPY_FRAME_MAGIC: [u8; 10] = [0xff, 0x06, 0x00, 0x00, 0x73, 0x4e, 0x61, 0x50, 0x70, 0x59];
+
+/// Snappy can't possibly compress better than this (the best case is a
+/// three-byte copy element that outputs 64 bytes).
+const SNAPPY_MAX_RATIO: usize = 32;
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformSnappy {
+    framed: bool,
+}
+
+impl fmt::Display for TransformSnappy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformSnappy {
+    pub fn new(framed: bool) -> Transformation {
+        Transformation::FromSnappy(TransformSnappy {
+            framed,
+        })
+    }
+
+    /// The raw Snappy format, which is just a length followed by the
+    /// compressed data.
+    pub fn raw() -> Transformation {
+        Self::new(false)
+    }
+
+    /// The Snappy framing format, which is what most tools write to disk.
+    pub fn framed() -> Transformation {
+        Self::new(true)
+    }
+
+    fn transform_raw(self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        // There's no magic, so the best we can do up front is make sure the
+        // length is plausible (which also stops us from allocating a huge
+        // buffer for garbage data)
+        match decompress_len(buffer) {
+            Ok(l) => {
+                if l > buffer.len() * SNAPPY_MAX_RATIO {
+                    bail!("Couldn't decompress Snappy: uncompressed size is impossibly large ({} bytes)", l);
+                }
+            },
+            Err(e) => bail!("Couldn't decompress Snappy: {}", e),
+        }
+
+        match Decoder::new().decompress_vec(buffer) {
+            Ok(b) => Ok(b),
+            Err(e) => bail!("Couldn't decompress Snappy: {}", e),
+        }
+    }
+
+    fn transform_framed(self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        if !buffer.starts_with(&SNAPPY_FRAME_MAGIC) {
+            bail!("Not a framed Snappy buffer (bad magic)");
+        }
+
+        let mut out: Vec<u8> = Vec::new();
+        match FrameDecoder::new(buffer).read_to_end(&mut out) {
+            Ok(_) => Ok(out),
+            Err(e) => bail!("Couldn't decompress Snappy: {}", e),
+        }
+    }
+}
+
+impl TransformerTrait for TransformSnappy {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        match self.framed {
+            true => self.transform_framed(buffer),
+            false => self.transform_raw(buffer),
+        }
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("Snappy cannot be untransformed");
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        match self.framed {
+            true => buffer.starts_with(&SNAPPY_FRAME_MAGIC) && self.transform_framed(buffer).is_ok(),
+            false => self.transform_raw(buffer).is_ok(),
+        }
+    }
+
+    fn is_two_way(&self) -> bool {
+        // Snappy can't reliably go backwards
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        let mut out: Vec<_> = Vec::new();
+
+        if (TransformSnappy { framed: true }).can_transform(buffer) {
+            out.push(Self::framed());
+        }
+
+        // Raw Snappy has no magic, and lots of short strings technically
+        // decode; only suggest it if it actually compresses something
+        match (TransformSnappy { framed: false }).transform(buffer) {
+            Ok(b) if b.len() > buffer.len() => out.push(Self::raw()),
+            _ => (),
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_snappy_raw() -> SimpleResult<()> {
+        let t = TransformSnappy::raw();
+        assert_eq!(false, t.is_two_way());
+
+        // Just a literal
+        let result = t.transform(&b"\x04\x0cABCD".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // A literal, then a copy with a two-byte offset
+        let result = t.transform(&b"\x18\x0cABCD\x4e\x04\x00".to_vec())?;
+        assert_eq!(b"ABCDABCDABCDABCDABCDABCD".to_vec(), result);
+
+        // Wrong length
+        assert!(t.transform(&b"\x05\x0cABCD".to_vec()).is_err());
+
+        // Impossibly long
+        assert!(t.transform(&b"\xff\xff\xff\xff\x0f\x0cABCD".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snappy_framed() -> SimpleResult<()> {
+        let t = TransformSnappy::framed();
+        assert_eq!(false, t.is_two_way());
+
+        let result = t.transform(&b"\xff\x06\x00\x00\x73\x4e\x61\x50\x70\x59\x00\x0a\x00\x00\x17\xe2\x68\xb3\x04\x0c\x41\x42\x43\x44".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // Bad checksum
+        assert!(t.transform(&b"\xff\x06\x00\x00\x73\x4e\x61\x50\x70\x59\x00\x0a\x00\x00\x17\xe2\x68\xb4\x04\x0c\x41\x42\x43\x44".to_vec()).is_err());
+
+        // Raw data isn't framed
+        assert!(t.transform(&b"\x04\x0cABCD".to_vec()).is_err());
+
+        Ok(())
+    }
+}
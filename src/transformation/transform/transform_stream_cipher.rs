@@ -1,16 +1,18 @@
 use simple_error::{SimpleResult, bail};
 use serde::{Serialize, Deserialize};
+use std::convert::TryFrom;
 use std::fmt;
 
-use cipher::{NewStreamCipher, SyncStreamCipher};
-use salsa20::Salsa20;
-use chacha20::ChaCha20Legacy;
+use cipher::{NewStreamCipher, SyncStreamCipher, SyncStreamCipherSeek};
+use salsa20::{Salsa20, XSalsa20};
+use chacha20::{ChaCha20Legacy, XChaCha20};
 
 // These aren't implemented in the standard cipher crate
 use nettle::cipher::Salsa20_128;
 use nettle::cipher::insecure_do_not_use::ArcFour;
 
-use crate::transformation::{Transformation, TransformerTrait, KeyOrIV};
+use crate::transformation::{Transformation, TransformerTrait, KeyOrIV, KeyDerivation, CipherSources};
+use crate::transformation::{Keystream, Hc128, Rabbit, Spritz, SKIP_CHUNK_SIZE};
 
 /// Which stream cipher should we use?
 #[allow(non_camel_case_types)]
@@ -22,8 +24,33 @@ pub enum StreamCipherType {
     /// ChaCha20 (20 rounds, 256-bit key, 64-bit IV)
     ChaCha,
 
-    /// Arc4 / RC4 (any size key (though we only support powers of 2), no IV
+    /// Arc4 / RC4 (any size key up to 256 bytes, no IV)
     Arc4,
+
+    /// XSalsa20 (256-bit key, 192-bit IV)
+    XSalsa20,
+
+    /// XChaCha20 (256-bit key, 192-bit IV)
+    XChaCha20,
+
+    /// RC4-drop\[n\] - Arc4, but the first n bytes of keystream are thrown
+    /// away (any size key up to 256 bytes, no IV)
+    Arc4Drop(usize),
+
+    /// HC-128 (128-bit key, 128-bit IV)
+    HC128,
+
+    /// Rabbit (128-bit key, optional 64-bit IV)
+    Rabbit,
+
+    /// Spritz (any size key, optional IV)
+    Spritz,
+
+    /// Not really a cipher - the "key" is the keystream itself, which is
+    /// XORed with the data (it can't be shorter than the data). Usually the
+    /// keystream is another buffer, which can be read with a
+    /// [`crate::transformation::CipherTemplate`].
+    Keystream,
 }
 
 /// Configures a stream cipher.
@@ -31,12 +58,23 @@ pub enum StreamCipherType {
 /// Configure all the settings for a stream cipher in a serializable place. Note
 /// that the settings must match standards or an error will be returned when
 /// creating (or transforming).
-#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+///
+/// The `offset` is where in the keystream the data starts, for data that
+/// starts mid-stream. It's in bytes, so for ciphers with a block counter, the
+/// counter is `offset / 64`.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TransformStreamCipher {
     cipher: StreamCipherType,
-    key: KeyOrIV,
+    key: Vec<u8>,
     iv: Option<KeyOrIV>,
     offset: u64,
+
+    /// How the key and IV were derived, if they were.
+    derivation: Option<Box<KeyDerivation>>,
+
+    /// Where the key and IV came from, if they came from a
+    /// [`crate::transformation::CipherTemplate`].
+    sources: Option<Box<CipherSources>>,
 }
 
 impl fmt::Display for TransformStreamCipher {
@@ -48,8 +86,13 @@ impl fmt::Display for TransformStreamCipher {
 impl TransformStreamCipher {
     /// Create a new instance of [`TransformStreamCipher`].
     pub fn new(cipher: StreamCipherType, key: Vec<u8>, iv: Option<Vec<u8>>) -> SimpleResult<Transformation> {
-        // Validate and store the key / iv
-        let key = KeyOrIV::new(key)?;
+        Self::new_with_offset(cipher, key, iv, 0)
+    }
+
+    /// Create a new instance of [`TransformStreamCipher`], where the data
+    /// starts `offset` bytes into the keystream.
+    pub fn new_with_offset(cipher: StreamCipherType, key: Vec<u8>, iv: Option<Vec<u8>>, offset: u64) -> SimpleResult<Transformation> {
+        // Validate and store the iv
         let iv = match iv {
             Some(iv) => Some(KeyOrIV::new(iv)?),
             None     => None,
@@ -60,7 +103,9 @@ impl TransformStreamCipher {
             cipher: cipher,
             key: key,
             iv: iv,
-            offset: 0,
+            offset,
+            derivation: None,
+            sources: None,
         };
 
         // This validates the key length and iv and other characteristics
@@ -69,58 +114,197 @@ impl TransformStreamCipher {
         Ok(Transformation::FromStreamCipher(result))
     }
 
+    /// Create a new instance of [`TransformStreamCipher`], with the key
+    /// derived from a passphrase.
+    ///
+    /// `key_length` is the length of the key, in bytes. If `iv` is set, it's
+    /// used as-is; otherwise, it's derived along with the key (if the cipher
+    /// uses one).
+    pub fn new_derived(cipher: StreamCipherType, key_length: usize, iv: Option<Vec<u8>>, derivation: KeyDerivation) -> SimpleResult<Transformation> {
+        let (key, iv) = match (iv, cipher) {
+            (Some(iv), _)                        => (derivation.derive(key_length, 0)?.0, Some(iv)),
+            (None, StreamCipherType::Salsa20)    => derivation.derive(key_length, 8)?,
+            (None, StreamCipherType::ChaCha)     => derivation.derive(key_length, 8)?,
+            (None, StreamCipherType::XSalsa20)   => derivation.derive(key_length, 24)?,
+            (None, StreamCipherType::XChaCha20)  => derivation.derive(key_length, 24)?,
+            (None, StreamCipherType::HC128)      => derivation.derive(key_length, 16)?,
+            (None, _)                            => derivation.derive(key_length, 0)?,
+        };
+
+        let result = TransformStreamCipher {
+            cipher,
+            key,
+            iv: match iv {
+                Some(iv) => Some(KeyOrIV::new(iv)?),
+                None     => None,
+            },
+            offset: 0,
+            derivation: Some(Box::new(derivation)),
+            sources: None,
+        };
+
+        result.validate_settings()?;
+
+        Ok(Transformation::FromStreamCipher(result))
+    }
+
+    /// Create a new instance of [`TransformStreamCipher`] from a resolved
+    /// [`crate::transformation::CipherTemplate`], keeping track of where the
+    /// key and IV came from.
+    pub(crate) fn new_with_sources(cipher: StreamCipherType, key: Vec<u8>, iv: Option<Vec<u8>>, offset: u64, sources: CipherSources) -> SimpleResult<Transformation> {
+        let result = TransformStreamCipher {
+            cipher,
+            key,
+            iv: match iv {
+                Some(iv) => Some(KeyOrIV::new(iv)?),
+                None     => None,
+            },
+            offset,
+            derivation: None,
+            sources: Some(Box::new(sources)),
+        };
+
+        result.validate_settings()?;
+
+        Ok(Transformation::FromStreamCipher(result))
+    }
+
+    /// Does the key or IV come from the given buffer?
+    pub fn references_buffer(&self, buffer: &str) -> bool {
+        self.sources.as_ref().is_some_and(|s| s.references_buffer(buffer))
+    }
+
+    /// The bytes that come before the ciphertext - the key derivation's
+    /// header, and the key or IV if they're stored with the data.
+    fn header(&self) -> Vec<u8> {
+        let mut header = vec![];
+
+        if let Some(derivation) = &self.derivation {
+            header.extend(derivation.header().unwrap_or_default());
+        }
+
+        if let Some(sources) = &self.sources {
+            header.extend(sources.prefix(&self.key, self.iv.map(KeyOrIV::to_vec).as_deref()));
+        }
+
+        header
+    }
+
+    /// Remove the header that comes before the ciphertext, if there is one.
+    fn strip_header(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        let header = self.header();
+
+        if !buffer.starts_with(&header) {
+            bail!("Buffer doesn't start with the expected header");
+        }
+
+        Ok(buffer[header.len()..].to_vec())
+    }
+
+    /// The offset, as a usize, for ciphers that have to generate (and throw
+    /// away) the keystream before it.
+    fn offset(&self) -> SimpleResult<usize> {
+        match usize::try_from(self.offset) {
+            Ok(o) => Ok(o),
+            Err(_) => bail!("Keystream offset is too large: {}", self.offset),
+        }
+    }
+
+    /// Apply the keystream from one of the standard ciphers, after seeking
+    /// to the offset.
+    fn apply_seekable<C: SyncStreamCipher + SyncStreamCipherSeek>(&self, mut cipher: C, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        if cipher.try_seek(self.offset).is_err() {
+            bail!("Keystream offset is too large: {}", self.offset);
+        }
+
+        // Clone the buffer to something mutable
+        let mut buffer = buffer.to_vec();
+
+        // Apply the keystream to decrypt it
+        if cipher.try_apply_keystream(&mut buffer).is_err() {
+            bail!("Ran out of keystream");
+        }
+
+        Ok(buffer)
+    }
+
+    /// Apply the keystream from one of the nettle ciphers, which can't seek,
+    /// `skip` bytes into the keystream.
+    ///
+    /// The keystream before that is thrown away a chunk at a time. Nettle's
+    /// Salsa20 throws away the rest of a block after each call, so the last
+    /// partial chunk is put in front of the data instead.
+    fn crypt_after_skip(skip: usize, buffer: &[u8], mut crypt: impl FnMut(&mut [u8], &[u8])) -> Vec<u8> {
+        let zeroes = [0; SKIP_CHUNK_SIZE];
+        let mut discard = [0; SKIP_CHUNK_SIZE];
+        let mut skip = skip;
+
+        while skip >= SKIP_CHUNK_SIZE {
+            crypt(&mut discard, &zeroes);
+            skip -= SKIP_CHUNK_SIZE;
+        }
+
+        let input = [&zeroes[..skip], buffer].concat();
+        let mut out = vec![0; input.len()];
+        crypt(&mut out, &input);
+
+        out.split_off(skip)
+    }
+
+    /// Apply the keystream from one of our own ciphers, after skipping to
+    /// the offset.
+    fn apply_keystream<K: Keystream>(&self, mut cipher: K, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        cipher.skip(self.offset()?);
+
+        let mut buffer = buffer.to_vec();
+        cipher.apply(&mut buffer);
+
+        Ok(buffer)
+    }
+
+    /// The IV as bytes, or an error if the cipher needs one.
+    fn iv_bytes(&self, name: &str) -> SimpleResult<Vec<u8>> {
+        match self.iv {
+            Some(iv) => Ok(iv.to_vec()),
+            None => bail!("{} requires an IV/Nonce", name),
+        }
+    }
+
     /// Internal function to decrypt
-    fn decrypt_salsa20(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+    fn decrypt_salsa20(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
         // Make sure the nonce is sane
         let nonce = match self.iv {
             Some(iv) => iv.get64()?,
             None => bail!("Salsa20 requires an IV/Nonce"),
         };
 
-        match self.key {
+        match self.key.len() {
             // The stream-cipher library pack doesn't seem to support
             // Salsa20-128, so we use the nettle library (which is simpler to
             // use, but seems to have some bugs)
-            KeyOrIV::Bits128(k) => {
-                // Create a buffer for the output
-                let mut encrypted = vec![0; buffer.len()];
-
-                let mut c = match Salsa20_128::with_key_and_nonce(&k, &nonce) {
+            16 => {
+                let mut c = match Salsa20_128::with_key_and_nonce(&self.key, &nonce) {
                     Ok(c) => c,
                     Err(e) => bail!("Salsa20_128 cipher failed: {}", e),
                 };
-                c.crypt(&mut encrypted, buffer);
 
-                Ok(encrypted)
+                // Nettle can't seek, so throw away the keystream up to the
+                // offset
+                Ok(Self::crypt_after_skip(self.offset()?, buffer, |dst, src| c.crypt(dst, src)))
             },
-            KeyOrIV::Bits256(k) => {
-                let key = salsa20::Key::from_slice(&k);
+            32 => {
+                let key = salsa20::Key::from_slice(&self.key);
 
                 let nonce = salsa20::Nonce::from_slice(&nonce);
 
-                let mut cipher = Salsa20::new(&key, &nonce);
-
-                // Clone the buffer to something mutable
-                let mut buffer = buffer.clone();
-
-                // Apply the keystream to decrypt it
-                cipher.apply_keystream(&mut buffer);
-
-                // And I guess that's it
-                Ok(buffer)
+                self.apply_seekable(Salsa20::new(key, nonce), buffer)
             },
             _ => bail!("Invalid key size for Salsa20"),
         }
     }
 
-    /// Internal function to encrypt
-    fn encrypt_salsa20(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        // Encrypting is literally identical to decrypting
-        self.decrypt_salsa20(buffer)
-    }
-
     /// Internal function to decrypt
-    fn decrypt_chacha(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+    fn decrypt_chacha(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
         // Get the nonce
         // Make sure the nonce is sane
         let nonce = match self.iv {
@@ -130,65 +314,113 @@ impl TransformStreamCipher {
         let nonce = chacha20::LegacyNonce::from_slice(&nonce);
 
         // Get the key
-        let key = self.key.get256()?;
-        let key = chacha20::Key::from_slice(&key);
+        let key = chacha20::Key::from_slice(&self.key);
 
-        let mut cipher = ChaCha20Legacy::new(&key, &nonce);
-
-        // Clone the buffer to something mutable
-        let mut buffer = buffer.clone();
+        self.apply_seekable(ChaCha20Legacy::new(key, nonce), buffer)
+    }
 
-        // Apply the keystream to decrypt it
-        cipher.apply_keystream(&mut buffer);
+    /// Internal function to decrypt
+    fn decrypt_arc4(&self, buffer: &[u8], drop: usize) -> SimpleResult<Vec<u8>> {
+        let mut c = ArcFour::with_key(&self.key);
 
-        // And I guess that's it
-        Ok(buffer)
-    }
+        // Throw away the dropped bytes and everything up to the offset
+        let skip = match drop.checked_add(self.offset()?) {
+            Some(skip) => skip,
+            None => bail!("Keystream offset is too large: {} (after dropping {} bytes)", self.offset, drop),
+        };
 
-    /// Internal function to encrypt
-    fn encrypt_chacha(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        // Encrypting is literally identical to decrypting
-        self.decrypt_chacha(buffer)
+        Ok(Self::crypt_after_skip(skip, buffer, |dst, src| c.crypt(dst, src)))
     }
 
-    /// Internal function to decrypt
-    fn decrypt_arc4(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        let mut c = match self.key {
-            KeyOrIV::Bits64(k)  => ArcFour::with_key(&k),
-            KeyOrIV::Bits128(k) => ArcFour::with_key(&k),
-            KeyOrIV::Bits192(k) => ArcFour::with_key(&k),
-            KeyOrIV::Bits256(k) => ArcFour::with_key(&k),
-        };
+    /// Decrypt or encrypt the buffer - for a stream cipher, they're
+    /// literally identical.
+    fn crypt(&self, buffer: &[u8]) -> SimpleResult<Vec<u8>> {
+        match self.cipher {
+            StreamCipherType::Salsa20        => self.decrypt_salsa20(buffer),
+            StreamCipherType::ChaCha         => self.decrypt_chacha(buffer),
+            StreamCipherType::Arc4           => self.decrypt_arc4(buffer, 0),
+            StreamCipherType::Arc4Drop(drop) => self.decrypt_arc4(buffer, drop),
+            StreamCipherType::XSalsa20 => {
+                let iv = self.iv_bytes("XSalsa20")?;
+                self.apply_seekable(XSalsa20::new(salsa20::Key::from_slice(&self.key), salsa20::XNonce::from_slice(&iv)), buffer)
+            },
+            StreamCipherType::XChaCha20 => {
+                let iv = self.iv_bytes("XChaCha20")?;
+                self.apply_seekable(XChaCha20::new(chacha20::Key::from_slice(&self.key), chacha20::XNonce::from_slice(&iv)), buffer)
+            },
+            StreamCipherType::HC128 => {
+                let key = KeyOrIV::new(self.key.clone())?.get128()?;
+                let iv = match self.iv {
+                    Some(iv) => iv.get128()?,
+                    None => bail!("HC-128 requires an IV/Nonce"),
+                };
 
-        // Create a buffer for the output
-        let mut encrypted = vec![0; buffer.len()];
+                self.apply_keystream(Hc128::new(&key, &iv), buffer)
+            },
+            StreamCipherType::Rabbit => {
+                let key = KeyOrIV::new(self.key.clone())?.get128()?;
+                let iv = match self.iv {
+                    Some(iv) => Some(iv.get64()?),
+                    None => None,
+                };
 
-        // Do the encryption
-        c.crypt(&mut encrypted, buffer);
+                self.apply_keystream(Rabbit::new(&key, iv.as_ref()), buffer)
+            },
+            StreamCipherType::Spritz => {
+                let iv = self.iv.map(KeyOrIV::to_vec);
 
-        Ok(encrypted)
+                self.apply_keystream(Spritz::new(&self.key, iv.as_deref()), buffer)
+            },
+            StreamCipherType::Keystream => {
+                let offset = self.offset()?;
+                match offset.checked_add(buffer.len()) {
+                    Some(end) if end <= self.key.len() => (),
+                    _ => bail!("Keystream is too short: need {} + {} bytes, but it's {} bytes", offset, buffer.len(), self.key.len()),
+                }
+
+                Ok(buffer.iter().zip(self.key[offset..].iter()).map(|(b, k)| b ^ k).collect())
+            },
+        }
     }
 
-    /// Internal function to encrypt
-    fn encrypt_arc4(self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
-        // Encrypting is literally identical to decrypting
-        self.decrypt_arc4(buffer)
-    }
     /// Sanity check settings (key size, IV, etc).
-    fn validate_settings(self) -> SimpleResult<()> {
-        match (self.cipher, self.iv, self.key) {
-
-            (StreamCipherType::Salsa20, Some(KeyOrIV::Bits64(_)), KeyOrIV::Bits128(_)) => (),
-            (StreamCipherType::Salsa20, Some(KeyOrIV::Bits64(_)), KeyOrIV::Bits256(_)) => (),
-            (StreamCipherType::Salsa20, _,                         _                 ) => bail!("Invalid stream cipher settings for Salsa20"),
-
-            (StreamCipherType::ChaCha,  Some(KeyOrIV::Bits64(_)), KeyOrIV::Bits256(_)) => (),
-            (StreamCipherType::ChaCha,  Some(KeyOrIV::Bits64(_)), _                  ) => bail!("Invalid key size for ChaCha"),
-            (StreamCipherType::ChaCha,  _,                        KeyOrIV::Bits256(_)) => bail!("Invalid iv size for ChaCha"),
-            (StreamCipherType::ChaCha,  _,                        _                  ) => bail!("Invalid key and iv sizes for ChaCha"),
-
-            (StreamCipherType::Arc4,    None,                     _                  ) => (),
-            (StreamCipherType::Arc4,    _,                        _                  ) => bail!("Arc4 does not support an IV"),
+    fn validate_settings(&self) -> SimpleResult<()> {
+        match (self.cipher, self.iv, self.key.len()) {
+
+            (StreamCipherType::Salsa20, Some(KeyOrIV::Bits64(_)), 16) => (),
+            (StreamCipherType::Salsa20, Some(KeyOrIV::Bits64(_)), 32) => (),
+            (StreamCipherType::Salsa20, _,                         _ ) => bail!("Invalid stream cipher settings for Salsa20"),
+
+            (StreamCipherType::ChaCha,  Some(KeyOrIV::Bits64(_)), 32) => (),
+            (StreamCipherType::ChaCha,  Some(KeyOrIV::Bits64(_)), _ ) => bail!("Invalid key size for ChaCha"),
+            (StreamCipherType::ChaCha,  _,                        32) => bail!("Invalid iv size for ChaCha"),
+            (StreamCipherType::ChaCha,  _,                        _ ) => bail!("Invalid key and iv sizes for ChaCha"),
+
+            (StreamCipherType::Arc4,        None, 1..=256) => (),
+            (StreamCipherType::Arc4,        None, _      ) => bail!("Invalid key size for Arc4"),
+            (StreamCipherType::Arc4,        _,    _      ) => bail!("Arc4 does not support an IV"),
+            (StreamCipherType::Arc4Drop(_), None, 1..=256) => (),
+            (StreamCipherType::Arc4Drop(_), None, _      ) => bail!("Invalid key size for Arc4"),
+            (StreamCipherType::Arc4Drop(_), _,    _      ) => bail!("Arc4 does not support an IV"),
+
+            (StreamCipherType::XSalsa20,  Some(KeyOrIV::Bits192(_)), 32) => (),
+            (StreamCipherType::XSalsa20,  _,                         _ ) => bail!("Invalid stream cipher settings for XSalsa20"),
+            (StreamCipherType::XChaCha20, Some(KeyOrIV::Bits192(_)), 32) => (),
+            (StreamCipherType::XChaCha20, _,                         _ ) => bail!("Invalid stream cipher settings for XChaCha20"),
+
+            (StreamCipherType::HC128,  Some(KeyOrIV::Bits128(_)), 16) => (),
+            (StreamCipherType::HC128,  _,                         _ ) => bail!("Invalid stream cipher settings for HC-128"),
+
+            (StreamCipherType::Rabbit, Some(KeyOrIV::Bits64(_)), 16) => (),
+            (StreamCipherType::Rabbit, None,                     16) => (),
+            (StreamCipherType::Rabbit, _,                        _ ) => bail!("Invalid stream cipher settings for Rabbit"),
+
+            (StreamCipherType::Spritz, _, 0) => bail!("Spritz requires a key"),
+            (StreamCipherType::Spritz, _, _) => (),
+
+            (StreamCipherType::Keystream, None, 0) => bail!("Keystream can't be empty"),
+            (StreamCipherType::Keystream, None, _) => (),
+            (StreamCipherType::Keystream, _,    _) => bail!("Keystream does not support an IV"),
         }
 
         Ok(())
@@ -200,22 +432,19 @@ impl TransformerTrait for TransformStreamCipher {
     fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
         self.validate_settings()?;
 
-        match self.cipher {
-            StreamCipherType::Salsa20 => self.decrypt_salsa20(buffer),
-            StreamCipherType::ChaCha  => self.decrypt_chacha(buffer),
-            StreamCipherType::Arc4    => self.decrypt_arc4(buffer),
-        }
+        // Remove the header, if there is one
+        let buffer = &self.strip_header(buffer)?;
+
+        self.crypt(buffer)
     }
 
     /// transform() =~ encrypt
     fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
         self.validate_settings()?;
 
-        match self.cipher {
-            StreamCipherType::Salsa20 => self.encrypt_salsa20(buffer),
-            StreamCipherType::ChaCha  => self.encrypt_chacha(buffer),
-            StreamCipherType::Arc4    => self.encrypt_arc4(buffer),
-        }
+        let result = self.crypt(buffer)?;
+
+        Ok([self.header(), result].concat())
     }
 
     fn is_two_way(&self) -> bool {
@@ -227,8 +456,9 @@ impl TransformerTrait for TransformStreamCipher {
     }
 
     // Unfortunately, we can never tell whether a stream cipher is valid
-    fn can_transform(&self, _buffer: &Vec<u8>) -> bool {
-        true
+    // (unless there's a header to check)
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        self.strip_header(buffer).is_ok()
     }
 }
 
@@ -237,6 +467,8 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    use crate::transformation::DigestType;
+
     #[test]
     fn test_salsa20() -> SimpleResult<()> {
         let tests: Vec<(Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>)> = vec![
@@ -349,4 +581,208 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_derived() -> SimpleResult<()> {
+        // ChaCha with the key and IV from PBKDF2
+        let transformation = TransformStreamCipher::new_derived(
+            StreamCipherType::ChaCha,
+            32,
+            None,
+            KeyDerivation::PBKDF2 { hash: DigestType::SHA256, passphrase: b"hunter2".to_vec(), salt: b"salty".to_vec(), iterations: 1000 },
+        )?;
+
+        let ciphertext = b"\x06\x81\xcd\x0f\x0a\xb2\x86\x79\xa3\x5a\x1f\x15\x27\x15\x1e\x44\xb1\x88\x8b\xfb\xb1\x9d\x6a\xcc\xf1".to_vec();
+        let result = transformation.transform(&ciphertext)?;
+        assert_eq!(b"ChaCha with a derived key".to_vec(), result);
+        assert_eq!(ciphertext, transformation.untransform(&result)?);
+
+        // RC4 from `openssl enc -rc4 -md sha256 -pass pass:hunter2`
+        let ciphertext = b"Salted__\xf2\xe6\x0f\x4f\xab\xd6\xf2\xd8\
+                           \x93\x95\x35\xb3\x85\xf7\x48\x17\xcb\x9c\xc7\xd4\x1c\x60\x37\xe7\
+                           \x0c\x19\xa2\x85\xe4\x3a\x90\x99\x37\x54\xdb\x4c\x3b\x72\x13".to_vec();
+        let transformation = TransformStreamCipher::new_derived(
+            StreamCipherType::Arc4,
+            16,
+            None,
+            KeyDerivation::from_openssl_header(&ciphertext, DigestType::SHA256, b"hunter2".to_vec(), None)?,
+        )?;
+
+        let result = transformation.transform(&ciphertext)?;
+        assert_eq!(b"Stream cipher with a passphrase".to_vec(), result);
+        assert_eq!(ciphertext, transformation.untransform(&result)?);
+
+        // The header has to be there
+        assert!(transformation.can_transform(&ciphertext));
+        assert!(!transformation.can_transform(&ciphertext[16..].to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extended_nonce() -> SimpleResult<()> {
+        // Generated with a Python implementation of HSalsa20 / HChaCha20 on
+        // top of Salsa20 / ChaCha20
+        let transformation = TransformStreamCipher::new(StreamCipherType::XSalsa20, b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBBBBBBBBBBBBBBBBBB".to_vec()))?;
+        let ciphertext = b"\x45\x38\x3e\xeb\x2e\x4e\x62\x09\x56\x15\xba\xaf\x29\x36\x2e\x51".to_vec();
+        assert_eq!(b"Testing XSalsa20".to_vec(), transformation.transform(&ciphertext)?);
+        assert_eq!(ciphertext, transformation.untransform(&b"Testing XSalsa20".to_vec())?);
+
+        let transformation = TransformStreamCipher::new(StreamCipherType::XChaCha20, b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBBBBBBBBBBBBBBBBBB".to_vec()))?;
+        let ciphertext = b"\x0e\x75\x2d\x10\xb4\x7f\x61\xcc\xb6\xdd\xe4\x6d\xc1\x44\x67\xe7\x4d".to_vec();
+        assert_eq!(b"Testing XChaCha20".to_vec(), transformation.transform(&ciphertext)?);
+        assert_eq!(ciphertext, transformation.untransform(&b"Testing XChaCha20".to_vec())?);
+
+        // Both need a 192-bit nonce and 256-bit key
+        assert!(TransformStreamCipher::new(StreamCipherType::XSalsa20, b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBB".to_vec())).is_err());
+        assert!(TransformStreamCipher::new(StreamCipherType::XChaCha20, b"AAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBBBBBBBBBBBBBBBBBB".to_vec())).is_err());
+        assert!(TransformStreamCipher::new(StreamCipherType::XChaCha20, b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_arc4_drop() -> SimpleResult<()> {
+        // The classic "Key" / "Plaintext" test vector, to show the key can be
+        // any length now
+        let transformation = TransformStreamCipher::new(StreamCipherType::Arc4, b"Key".to_vec(), None)?;
+        assert_eq!(b"Plaintext".to_vec(), transformation.transform(&b"\xbb\xf3\x16\xe8\xd9\x40\xaf\x0a\xd3".to_vec())?);
+
+        // RC4-drop[768]
+        let transformation = TransformStreamCipher::new(StreamCipherType::Arc4Drop(768), b"Key".to_vec(), None)?;
+        let ciphertext = b"\x81\x79\x55\x1f\x8c\x03\x22\x71\xdb\xd7\x63\x6a\xd0\x22\xdd\x11".to_vec();
+        assert_eq!(b"Testing RC4-drop".to_vec(), transformation.transform(&ciphertext)?);
+        assert_eq!(ciphertext, transformation.untransform(&b"Testing RC4-drop".to_vec())?);
+
+        // Dropping is the same as starting at an offset
+        let transformation = TransformStreamCipher::new_with_offset(StreamCipherType::Arc4, b"Key".to_vec(), None, 768)?;
+        assert_eq!(b"Testing RC4-drop".to_vec(), transformation.transform(&ciphertext)?);
+
+        assert!(TransformStreamCipher::new(StreamCipherType::Arc4Drop(768), vec![], None).is_err());
+        assert!(TransformStreamCipher::new(StreamCipherType::Arc4Drop(768), vec![0x41; 257], None).is_err());
+        assert!(TransformStreamCipher::new(StreamCipherType::Arc4Drop(768), b"Key".to_vec(), Some(b"BBBBBBBB".to_vec())).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hc128_rabbit_spritz() -> SimpleResult<()> {
+        // Decrypting zeroes gives the keystream, which matches the test
+        // vectors for each cipher
+        let transformation = TransformStreamCipher::new(StreamCipherType::HC128, vec![0; 16], Some(vec![0; 16]))?;
+        assert_eq!(b"\x82\x00\x15\x73\xa0\x03\xfd\x3b".to_vec(), transformation.transform(&vec![0; 8])?);
+        assert!(TransformStreamCipher::new(StreamCipherType::HC128, vec![0; 16], None).is_err());
+        assert!(TransformStreamCipher::new(StreamCipherType::HC128, vec![0; 32], Some(vec![0; 16])).is_err());
+
+        let transformation = TransformStreamCipher::new(StreamCipherType::Rabbit, vec![0; 16], None)?;
+        assert_eq!(b"\x02\xf7\x4a\x1c\x26\x45\x6b\xf5".to_vec(), transformation.transform(&vec![0; 8])?);
+        let transformation = TransformStreamCipher::new(StreamCipherType::Rabbit, vec![0; 16], Some(vec![0; 8]))?;
+        assert_eq!(b"\xed\xb7\x05\x67\x37\x5d\xcd\x7c".to_vec(), transformation.transform(&vec![0; 8])?);
+        assert!(TransformStreamCipher::new(StreamCipherType::Rabbit, vec![0; 16], Some(vec![0; 16])).is_err());
+
+        let transformation = TransformStreamCipher::new(StreamCipherType::Spritz, b"ABC".to_vec(), None)?;
+        assert_eq!(b"\x77\x9a\x8e\x01\xf9\xe9\xcb\xc0".to_vec(), transformation.transform(&vec![0; 8])?);
+        assert!(TransformStreamCipher::new(StreamCipherType::Spritz, vec![], None).is_err());
+
+        // Spritz with an IV is different, and everything goes both ways
+        let transformation = TransformStreamCipher::new(StreamCipherType::Spritz, b"ABC".to_vec(), Some(b"BBBBBBBB".to_vec()))?;
+        let ciphertext = transformation.untransform(&b"Testing Spritz".to_vec())?;
+        assert_ne!(b"\x77\x9a\x8e\x01\xf9\xe9\xcb\xc0".to_vec(), transformation.transform(&vec![0; 8])?);
+        assert_eq!(b"Testing Spritz".to_vec(), transformation.transform(&ciphertext)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_offset() -> SimpleResult<()> {
+        // Decrypting from the middle of the stream gives the same result as
+        // decrypting the whole thing
+        let tests: Vec<(StreamCipherType, Vec<u8>, Option<Vec<u8>>)> = vec![
+            (StreamCipherType::Salsa20,     b"AAAAAAAAAAAAAAAA".to_vec(),                 Some(b"BBBBBBBB".to_vec())),
+            (StreamCipherType::Salsa20,     b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBB".to_vec())),
+            (StreamCipherType::ChaCha,      b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBB".to_vec())),
+            (StreamCipherType::XSalsa20,    b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBBBBBBBBBBBBBBBBBB".to_vec())),
+            (StreamCipherType::XChaCha20,   b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBBBBBBBBBBBBBBBBBB".to_vec())),
+            (StreamCipherType::Arc4,        b"AAAAAAAA".to_vec(),                         None),
+            (StreamCipherType::Arc4Drop(3), b"AAAAAAAA".to_vec(),                         None),
+            (StreamCipherType::HC128,       b"AAAAAAAAAAAAAAAA".to_vec(),                 Some(b"BBBBBBBBBBBBBBBB".to_vec())),
+            (StreamCipherType::Rabbit,      b"AAAAAAAAAAAAAAAA".to_vec(),                 Some(b"BBBBBBBB".to_vec())),
+            (StreamCipherType::Spritz,      b"AAAA".to_vec(),                             None),
+            (StreamCipherType::Keystream,   (0..=255u8).collect(),                        None),
+        ];
+
+        let ciphertext: Vec<u8> = (0..200u8).collect();
+
+        for (cipher, key, iv) in tests {
+            let full = TransformStreamCipher::new(cipher, key.clone(), iv.clone())?.transform(&ciphertext)?;
+
+            // Try a few offsets, including ones that aren't on a block
+            // boundary
+            for offset in [1usize, 63, 64, 100, 130].iter() {
+                let transformation = TransformStreamCipher::new_with_offset(cipher, key.clone(), iv.clone(), *offset as u64)?;
+                let result = transformation.transform(&ciphertext[*offset..].to_vec())?;
+
+                assert_eq!(full[*offset..].to_vec(), result, "{:?} at offset {}", cipher, offset);
+                assert_eq!(ciphertext[*offset..].to_vec(), transformation.untransform(&result)?);
+            }
+        }
+
+        // A vector from the Python implementation, 100 bytes in
+        let transformation = TransformStreamCipher::new_with_offset(StreamCipherType::XChaCha20, b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBBBBBBBBBBBBBBBBBB".to_vec()), 100)?;
+        assert_eq!(b"Mid-stream".to_vec(), transformation.transform(&b"\x44\x5b\xcb\xa7\x4d\x4e\x42\x48\xaa\x99".to_vec())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_offset() -> SimpleResult<()> {
+        // The ciphers that can't seek skip the keystream in chunks, so try
+        // offsets on either side of a chunk boundary
+        let tests: Vec<(StreamCipherType, Vec<u8>, Option<Vec<u8>>)> = vec![
+            (StreamCipherType::Salsa20,     b"AAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBB".to_vec())),
+            (StreamCipherType::Arc4,        b"AAAAAAAA".to_vec(),         None),
+            (StreamCipherType::Arc4Drop(5), b"AAAAAAAA".to_vec(),         None),
+            (StreamCipherType::HC128,       b"AAAAAAAAAAAAAAAA".to_vec(), Some(b"BBBBBBBBBBBBBBBB".to_vec())),
+            (StreamCipherType::Spritz,      b"AAAA".to_vec(),             None),
+        ];
+
+        let ciphertext: Vec<u8> = (0..9000).map(|i| i as u8).collect();
+
+        for (cipher, key, iv) in tests {
+            let full = TransformStreamCipher::new(cipher, key.clone(), iv.clone())?.transform(&ciphertext)?;
+
+            for offset in [4095usize, 4096, 4097, 8192, 8200].iter() {
+                let transformation = TransformStreamCipher::new_with_offset(cipher, key.clone(), iv.clone(), *offset as u64)?;
+                assert_eq!(full[*offset..].to_vec(), transformation.transform(&ciphertext[*offset..].to_vec())?, "{:?} at offset {}", cipher, offset);
+            }
+        }
+
+        // Offsets that overflow
+        let transformation = TransformStreamCipher::new_with_offset(StreamCipherType::Arc4Drop(usize::MAX), b"AAAAAAAA".to_vec(), None, 1)?;
+        assert!(transformation.transform(&vec![0; 8]).is_err());
+
+        let transformation = TransformStreamCipher::new_with_offset(StreamCipherType::Keystream, b"\x01\x02\x03\x04\x05\x06".to_vec(), None, u64::MAX)?;
+        assert!(transformation.transform(&vec![0; 2]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keystream() -> SimpleResult<()> {
+        let transformation = TransformStreamCipher::new(StreamCipherType::Keystream, b"\x01\x02\x03\x04\x05\x06".to_vec(), None)?;
+        assert_eq!(b"\x40\x40\x40\x40".to_vec(), transformation.transform(&b"\x41\x42\x43\x44".to_vec())?);
+        assert_eq!(b"\x41\x42\x43\x44".to_vec(), transformation.untransform(&b"\x40\x40\x40\x40".to_vec())?);
+
+        // The keystream can't run out
+        assert!(transformation.transform(&vec![0; 7]).is_err());
+
+        let transformation = TransformStreamCipher::new_with_offset(StreamCipherType::Keystream, b"\x01\x02\x03\x04\x05\x06".to_vec(), None, 4)?;
+        assert_eq!(b"\x44\x44".to_vec(), transformation.transform(&b"\x41\x42".to_vec())?);
+        assert!(transformation.transform(&vec![0; 3]).is_err());
+
+        assert!(TransformStreamCipher::new(StreamCipherType::Keystream, vec![], None).is_err());
+        assert!(TransformStreamCipher::new(StreamCipherType::Keystream, vec![1, 2, 3], Some(b"BBBBBBBB".to_vec())).is_err());
+
+        Ok(())
+    }
 }
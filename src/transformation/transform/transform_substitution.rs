@@ -0,0 +1,211 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait, CipherSources};
+
+/// Replace each byte with the entry at that index in a 256-byte table (an
+/// S-box).
+///
+/// If every value appears in the table exactly once (ie, it's a
+/// permutation), the inverse table is calculated automatically so the
+/// transformation can be undone. Otherwise, it's one-way.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TransformSubstitution {
+    table: Vec<u8>,
+
+    /// Where the table came from, if it came from a
+    /// [`crate::transformation::CipherTemplate`].
+    sources: Option<Box<CipherSources>>,
+}
+
+impl fmt::Display for TransformSubstitution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformSubstitution {
+    pub fn new(table: Vec<u8>) -> SimpleResult<Transformation> {
+        Self::validate_table(&table)?;
+
+        Ok(Transformation::Substitution(Self {
+            table,
+            sources: None,
+        }))
+    }
+
+    /// Create a new instance of [`TransformSubstitution`] from a resolved
+    /// [`crate::transformation::CipherTemplate`], keeping track of where the
+    /// table came from.
+    pub(crate) fn new_with_sources(table: Vec<u8>, sources: CipherSources) -> SimpleResult<Transformation> {
+        Self::validate_table(&table)?;
+
+        Ok(Transformation::Substitution(Self {
+            table,
+            sources: Some(Box::new(sources)),
+        }))
+    }
+
+    fn validate_table(table: &[u8]) -> SimpleResult<()> {
+        if table.len() != 256 {
+            bail!("Substitution table must be 256 bytes long (is {})", table.len());
+        }
+
+        Ok(())
+    }
+
+    pub fn table(&self) -> &[u8] {
+        &self.table
+    }
+
+    /// Does every value appear in the table exactly once?
+    pub fn is_permutation(&self) -> bool {
+        self.inverse().is_some()
+    }
+
+    /// Calculate the table that undoes this one, if there is one.
+    pub fn inverse(&self) -> Option<Vec<u8>> {
+        let mut inverse: Vec<Option<u8>> = vec![None; 256];
+
+        for (i, b) in self.table.iter().enumerate() {
+            // Two inputs map to the same output, so there's no going back
+            if inverse[*b as usize].is_some() {
+                return None;
+            }
+
+            inverse[*b as usize] = Some(i as u8);
+        }
+
+        inverse.into_iter().collect()
+    }
+
+    /// Does the table come from the given buffer?
+    pub fn references_buffer(&self, buffer: &str) -> bool {
+        self.sources.as_ref().is_some_and(|s| s.references_buffer(buffer))
+    }
+
+    /// The bytes that come before the data - the table, if it's stored with
+    /// the data.
+    fn header(&self) -> Vec<u8> {
+        match &self.sources {
+            Some(sources) => sources.prefix(&self.table, None),
+            None => vec![],
+        }
+    }
+}
+
+impl TransformerTrait for TransformSubstitution {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        // A deserialized transformation doesn't go through new()
+        Self::validate_table(&self.table)?;
+
+        let header = self.header();
+
+        let buffer = match buffer.strip_prefix(&header[..]) {
+            Some(b) => b,
+            None => bail!("Buffer doesn't start with the substitution table"),
+        };
+
+        Ok(buffer.iter().map(|b| self.table[*b as usize]).collect())
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        Self::validate_table(&self.table)?;
+
+        let inverse = match self.inverse() {
+            Some(i) => i,
+            None => bail!("Substitution table isn't a permutation, so it can't be reversed"),
+        };
+
+        Ok([self.header(), buffer.iter().map(|b| inverse[*b as usize]).collect()].concat())
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        Self::validate_table(&self.table).is_ok() && buffer.starts_with(&self.header())
+    }
+
+    fn is_two_way(&self) -> bool {
+        self.is_permutation()
+    }
+
+    fn detect(_buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        // There's no way to guess the table
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    use crate::transformation::KeySource;
+
+    #[test]
+    fn test_substitution() -> SimpleResult<()> {
+        // Reverse the byte values
+        let table: Vec<u8> = (0..=255u8).rev().collect();
+        let t = TransformSubstitution::new(table)?;
+        assert!(t.is_two_way());
+
+        assert_eq!(vec![0xff, 0xfe, 0x00], t.transform(&vec![0x00, 0x01, 0xff])?);
+        assert_eq!(vec![0x00, 0x01, 0xff], t.untransform(&vec![0xff, 0xfe, 0x00])?);
+
+        // Something less symmetrical, where the inverse is different
+        let table: Vec<u8> = (0..=255u8).map(|b| b.wrapping_mul(3).wrapping_add(7)).collect();
+        let t = TransformSubstitution::new(table)?;
+        assert!(t.is_two_way());
+
+        assert_eq!(vec![0x07, 0x0a, 0x04], t.transform(&vec![0x00, 0x01, 0xff])?);
+        assert_eq!(vec![0x00, 0x01, 0xff], t.untransform(&vec![0x07, 0x0a, 0x04])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_substitution_one_way() -> SimpleResult<()> {
+        // Every byte becomes its top nibble, which can't be undone
+        let table: Vec<u8> = (0..=255u8).map(|b| b >> 4).collect();
+        let t = TransformSubstitution::new(table)?;
+        assert!(!t.is_two_way());
+
+        assert_eq!(vec![0x00, 0x01, 0x0f], t.transform(&vec![0x01, 0x12, 0xff])?);
+        assert!(t.untransform(&vec![0x00, 0x01, 0x0f]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_substitution_bad_table() -> SimpleResult<()> {
+        assert!(TransformSubstitution::new(vec![]).is_err());
+        assert!(TransformSubstitution::new(vec![0; 255]).is_err());
+        assert!(TransformSubstitution::new(vec![0; 257]).is_err());
+
+        // A short table that skipped new() is an error rather than a panic
+        let t: Transformation = serde_json::from_str(r#"{"Substitution":{"table":[1,2,3],"sources":null}}"#).unwrap();
+        assert!(!t.can_transform(&vec![0xff]));
+        assert!(t.transform(&vec![0xff]).is_err());
+        assert!(t.untransform(&vec![0xff]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_substitution_prefix() -> SimpleResult<()> {
+        let table: Vec<u8> = (0..=255u8).rev().collect();
+        let sources = CipherSources { key: KeySource::Prefix(256), iv: None };
+        let t = TransformSubstitution::new_with_sources(table.clone(), sources)?;
+
+        let data = [table.clone(), vec![0x00, 0x01, 0xff]].concat();
+        assert!(t.can_transform(&data));
+        assert_eq!(vec![0xff, 0xfe, 0x00], t.transform(&data)?);
+        assert_eq!(data, t.untransform(&vec![0xff, 0xfe, 0x00])?);
+
+        // The table has to be there
+        assert!(!t.can_transform(&vec![0x00, 0x01, 0xff]));
+        assert!(t.transform(&vec![0x00, 0x01, 0xff]).is_err());
+
+        Ok(())
+    }
+}
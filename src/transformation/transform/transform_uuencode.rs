@@ -0,0 +1,345 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait};
+
+/// The number of bytes on each line, in canonical uuencode.
+const DEFAULT_LINE_LENGTH: u8 = 45;
+
+/// The most bytes a line can have, since the length is a single character.
+const MAX_LINE_LENGTH: u8 = 63;
+
+/// The `begin` line (if there is one) and the lines of data, as returned by
+/// [`TransformUuencode::split_lines`].
+type UuencodeLines<'a> = (Option<&'a [u8]>, Vec<&'a [u8]>);
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TransformUuencode {
+    /// How many bytes go on each line when encoding.
+    line_length: u8,
+
+    /// The character that encodes zero - either '`' or (for older encoders)
+    /// ' '.
+    zero: char,
+
+    /// The `begin <mode> <filename>` line, if there is one. When there is,
+    /// encoding also adds the `end` line.
+    header: Option<String>,
+
+    /// Whether lines end with `\r\n`.
+    crlf: bool,
+}
+
+impl TransformUuencode {
+    /// Decode uuencoded data. Untransforming produces canonical uuencode:
+    /// 45-byte lines, '`' for zero, and no header.
+    pub fn new() -> Transformation {
+        Transformation::FromUuencode(TransformUuencode {
+            line_length: DEFAULT_LINE_LENGTH,
+            zero: '`',
+            header: None,
+            crlf: false,
+        })
+    }
+
+    /// Decode uuencoded data, and choose how untransforming lays it out.
+    ///
+    /// The `header` is the whole `begin` line (such as `begin 644 file.txt`).
+    pub fn new_with_layout(line_length: u8, zero: char, header: Option<&str>, crlf: bool) -> SimpleResult<Transformation> {
+        let t = TransformUuencode {
+            line_length,
+            zero,
+            header: header.map(|h| h.to_string()),
+            crlf,
+        };
+        t.check_layout()?;
+
+        Ok(Transformation::FromUuencode(t))
+    }
+
+    /// Decode uuencoded data, and untransform it to the same layout as the
+    /// given data (its line length, zero character, header, and line
+    /// endings).
+    ///
+    /// Anything that can't be worked out is left at the canonical default.
+    pub fn new_matching(buffer: &[u8]) -> Transformation {
+        let mut t = TransformUuencode {
+            line_length: DEFAULT_LINE_LENGTH,
+            zero: '`',
+            header: None,
+            crlf: buffer.windows(2).any(|w| w == b"\r\n"),
+        };
+
+        if let Ok((header, lines)) = Self::split_lines(buffer) {
+            t.header = header.map(|h| String::from_utf8_lossy(h).to_string());
+
+            let lengths: Vec<u8> = lines.iter().filter_map(|line| Self::decode_char(line[0]).ok()).collect();
+
+            // The first line is a full one, unless it's the only one with
+            // data (then the default works, if the line fits)
+            match lengths.as_slice() {
+                [first, second, ..] if *second > 0                   => t.line_length = *first,
+                [first, ..]         if *first > DEFAULT_LINE_LENGTH => t.line_length = *first,
+                _                                                    => (),
+            }
+
+            if lines.iter().any(|line| line.contains(&b' ')) && !lines.iter().any(|line| line.contains(&b'`')) {
+                t.zero = ' ';
+            }
+        }
+
+        Transformation::FromUuencode(t)
+    }
+
+    /// Make sure the layout can be encoded. This is checked again before
+    /// encoding, since a deserialized transformation doesn't go through
+    /// [`Self::new_with_layout`].
+    fn check_layout(&self) -> SimpleResult<()> {
+        if self.line_length == 0 || self.line_length > MAX_LINE_LENGTH {
+            bail!("Uuencode lines must be between 1 and {} bytes", MAX_LINE_LENGTH);
+        }
+
+        if self.zero != '`' && self.zero != ' ' {
+            bail!("Uuencode zero character must be '`' or ' '");
+        }
+
+        if let Some(header) = &self.header {
+            if !header.starts_with("begin ") || header.contains(['\r', '\n']) {
+                bail!("Uuencode header must be a single 'begin' line");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert a character into its six-bit value. Both ' ' and '`' are zero.
+    fn decode_char(c: u8) -> SimpleResult<u8> {
+        match c {
+            b' '..=b'`' => Ok((c - b' ') & 0x3f),
+            _ => bail!("Invalid uuencode character: 0x{:02x}", c),
+        }
+    }
+
+    /// Convert a six-bit value into a character.
+    fn encode_char(&self, value: u8) -> u8 {
+        match value {
+            0 => self.zero as u8,
+            v => v + b' ',
+        }
+    }
+
+    /// Decode a single line of data, which starts with its length.
+    fn decode_line(line: &[u8]) -> SimpleResult<Vec<u8>> {
+        let length = Self::decode_char(line[0])? as usize;
+
+        // Each group of 3 bytes is 4 characters
+        if line.len() - 1 != length.div_ceil(3) * 4 {
+            bail!("Uuencode line has the wrong number of characters for its length");
+        }
+
+        let mut out: Vec<u8> = Vec::new();
+        for chunk in line[1..].chunks(4) {
+            let c: Vec<u8> = chunk.iter().map(|c| Self::decode_char(*c)).collect::<SimpleResult<Vec<u8>>>()?;
+
+            out.push((c[0] << 2) | (c[1] >> 4));
+            out.push((c[1] << 4) | (c[2] >> 2));
+            out.push((c[2] << 6) | c[3]);
+        }
+        out.truncate(length);
+
+        Ok(out)
+    }
+
+    /// Encode a single line of data, starting with its length.
+    fn encode_line(&self, line: &[u8]) -> Vec<u8> {
+        let mut out = vec![self.encode_char(line.len() as u8)];
+
+        for chunk in line.chunks(3) {
+            let mut b = [0u8; 3];
+            b[..chunk.len()].copy_from_slice(chunk);
+
+            out.push(self.encode_char(b[0] >> 2));
+            out.push(self.encode_char(((b[0] & 0x03) << 4) | (b[1] >> 4)));
+            out.push(self.encode_char(((b[1] & 0x0f) << 2) | (b[2] >> 6)));
+            out.push(self.encode_char(b[2] & 0x3f));
+        }
+
+        out
+    }
+
+    /// Split the data into the `begin` line (if there is one) and the lines
+    /// of data, without the `end` line. The lines aren't empty.
+    fn split_lines(buffer: &[u8]) -> SimpleResult<UuencodeLines<'_>> {
+        let mut lines: Vec<&[u8]> = buffer.split(|b| *b == b'\n').map(|line| {
+            line.strip_suffix(b"\r").unwrap_or(line)
+        }).collect();
+
+        // A trailing newline leaves an empty line at the end
+        if lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+
+        // The "begin <mode> <filename>" header is optional, but if it's there
+        // then the "end" line has to be too
+        let header = match lines.first() {
+            Some(line) if line.starts_with(b"begin ") => Some(*line),
+            _                                         => None,
+        };
+
+        if header.is_some() {
+            if lines.last() != Some(&&b"end"[..]) {
+                bail!("Uuencode data has a 'begin' line but no 'end' line");
+            }
+
+            lines = lines[1..(lines.len() - 1)].to_vec();
+        }
+
+        if lines.iter().any(|line| line.is_empty()) {
+            bail!("Uuencode data can't have empty lines");
+        }
+
+        Ok((header, lines))
+    }
+}
+
+impl fmt::Display for TransformUuencode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformerTrait for TransformUuencode {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let (_, lines) = Self::split_lines(buffer)?;
+
+        let mut out: Vec<u8> = Vec::new();
+        let mut finished = false;
+        for line in lines {
+            if finished {
+                bail!("Uuencode data continues after the final line");
+            }
+
+            // A line with a length of zero is the end
+            let decoded = Self::decode_line(line)?;
+            if decoded.is_empty() {
+                finished = true;
+            }
+
+            out.extend(decoded);
+        }
+
+        Ok(out)
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        self.check_layout()?;
+
+        let newline: &[u8] = match self.crlf {
+            true  => b"\r\n",
+            false => b"\n",
+        };
+
+        let mut out: Vec<u8> = Vec::new();
+        if let Some(header) = &self.header {
+            out.extend(header.as_bytes());
+            out.extend(newline);
+        }
+
+        for line in buffer.chunks(self.line_length as usize) {
+            out.extend(self.encode_line(line));
+            out.extend(newline);
+        }
+
+        // A file ends with an empty line, then "end"
+        if self.header.is_some() {
+            out.extend(self.encode_line(&[]));
+            out.extend(newline);
+            out.extend(b"end");
+            out.extend(newline);
+        }
+
+        Ok(out)
+    }
+
+    fn is_two_way(&self) -> bool {
+        true
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        let s = Self::new_matching(buffer);
+        match s.can_transform(buffer) {
+            true => vec![s],
+            false => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_uuencode() -> SimpleResult<()> {
+        let t = TransformUuencode::new();
+        assert!(t.is_two_way());
+
+        // >>> binascii.b2a_uu(b'Hello, world')
+        assert_eq!(b"Hello, world".to_vec(), t.transform(&b",2&5L;&\\L('=O<FQD\n".to_vec())?);
+        assert_eq!(b"Hello, world".to_vec(), t.transform(&b",2&5L;&\\L('=O<FQD".to_vec())?);
+        assert_eq!(b",2&5L;&\\L('=O<FQD\n".to_vec(), t.untransform(&b"Hello, world".to_vec())?);
+
+        // >>> binascii.b2a_uu(b'\x00\x00A', backtick=True)
+        assert_eq!(b"#``!!\n".to_vec(), t.untransform(&b"\x00\x00A".to_vec())?);
+
+        // Multiple lines, with a header and CRLF line endings
+        let data = b"begin 644 fox.txt\r\n\
+                     M5&AE(\'%U:6-K(&)R;W=N(&9O>\"!J=6UP<R!O=F5R(\'1H92!L87IY(&1O9RX@\r\n\
+                     L5&AE(\'%U:6-K(&)R;W=N(&9O>\"!J=6UP<R!O=F5R(\'1H92!L87IY(&1O9RX`\r\n\
+                     `\r\n\
+                     end\r\n".to_vec();
+        let decoded = b"The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog.".to_vec();
+        assert_eq!(decoded, t.transform(&data)?);
+
+        // The canonical layout has no header
+        assert!(!t.untransform(&decoded)?.starts_with(b"begin"));
+
+        // Matching the layout gives back the same data
+        let t = TransformUuencode::new_matching(&data);
+        assert_eq!(TransformUuencode::new_with_layout(45, '`', Some("begin 644 fox.txt"), true)?, t);
+        assert_eq!(data, t.untransform(&decoded)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_uuencode_errors() -> SimpleResult<()> {
+        let t = TransformUuencode::new();
+
+        // Wrong length
+        assert!(t.transform(&b",2&5L;&\\L('=O<FQ".to_vec()).is_err());
+        assert!(t.transform(&b"AA".to_vec()).is_err());
+
+        // Bad characters
+        assert!(t.transform(&b",2&5L;&\\L('=O<FQd".to_vec()).is_err());
+
+        // Header without an end
+        assert!(t.transform(&b"begin 644 a.txt\n,2&5L;&\\L('=O<FQD\n`\n".to_vec()).is_err());
+
+        // Data after the end
+        assert!(t.transform(&b"`\n,2&5L;&\\L('=O<FQD\n".to_vec()).is_err());
+
+        // Empty lines
+        assert!(t.transform(&b",2&5L;&\\L('=O<FQD\n\n,2&5L;&\\L('=O<FQD\n".to_vec()).is_err());
+
+        // Layouts that can't be encoded
+        assert!(TransformUuencode::new_with_layout(0, '`', None, false).is_err());
+        assert!(TransformUuencode::new_with_layout(64, '`', None, false).is_err());
+        assert!(TransformUuencode::new_with_layout(45, 'x', None, false).is_err());
+        assert!(TransformUuencode::new_with_layout(45, '`', Some("hello"), false).is_err());
+        assert!(TransformUuencode::new_with_layout(45, '`', Some("begin 644 a\nb"), false).is_err());
+
+        Ok(())
+    }
+}
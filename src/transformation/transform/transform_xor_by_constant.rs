@@ -3,8 +3,15 @@ use serde::{Serialize, Deserialize};
 use std::fmt;
 
 use crate::transformation::{Transformation, TransformerTrait};
+use crate::transformation::{KNOWN_MAGIC, MAGIC_SCORE, content_score, plaintext_score, looks_like_text};
+use crate::transformation::{DETECT_MIN_LENGTH, DETECT_MIN_SAMPLES, DETECT_SAMPLE_SIZE, DETECT_MIN_CONFIDENCE, DETECT_MIN_IMPROVEMENT, DETECT_MAX_RESULTS};
 
 /// When performing an XorByConstant transformation, this represents the size
+/// and value of the constant.
+///
+/// It's also used by the other transformations that work on fixed-size
+/// elements, such as [`crate::transformation::TransformAddConstant`] and
+/// [`crate::transformation::TransformRotate`].
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
 pub enum XorSettings {
     /// One byte / 8 bits - eg, `0x12`
@@ -20,6 +27,40 @@ pub enum XorSettings {
     SixtyFourBit(u64),
 }
 
+impl XorSettings {
+    /// The size of the constant, in bytes.
+    pub fn width(self) -> usize {
+        match self {
+            XorSettings::EightBit(_)     => 1,
+            XorSettings::SixteenBit(_)   => 2,
+            XorSettings::ThirtyTwoBit(_) => 4,
+            XorSettings::SixtyFourBit(_) => 8,
+        }
+    }
+
+    /// The constant itself.
+    pub fn value(self) -> u64 {
+        match self {
+            XorSettings::EightBit(v)     => v as u64,
+            XorSettings::SixteenBit(v)   => v as u64,
+            XorSettings::ThirtyTwoBit(v) => v as u64,
+            XorSettings::SixtyFourBit(v) => v,
+        }
+    }
+
+    /// Build the settings from a big-endian key, which must be 1, 2, 4, or
+    /// 8 bytes long.
+    fn from_key(key: &[u8]) -> Option<Self> {
+        match key.len() {
+            1 => Some(XorSettings::EightBit(key[0])),
+            2 => Some(XorSettings::SixteenBit(u16::from_be_bytes([key[0], key[1]]))),
+            4 => Some(XorSettings::ThirtyTwoBit(u32::from_be_bytes([key[0], key[1], key[2], key[3]]))),
+            8 => Some(XorSettings::SixtyFourBit(u64::from_be_bytes([key[0], key[1], key[2], key[3], key[4], key[5], key[6], key[7]]))),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
 pub struct TransformXorByConstant {
     settings: XorSettings,
@@ -37,6 +78,195 @@ impl TransformXorByConstant {
             settings: settings,
         })
     }
+
+    /// Guess which XOR constants might have been used to encode a buffer.
+    ///
+    /// Every width in [`XorSettings`] is tried (as long as the buffer is a
+    /// multiple of that size). Candidate keys come from:
+    ///
+    /// * Frequency analysis - the most common byte at each key position is
+    ///   probably a NUL (in binary data) or a space (in text)
+    /// * The key byte at each position that makes the output most printable
+    /// * Known plaintext - runs of repeated bytes are probably encoded NULs,
+    ///   and the start of the buffer might be a file signature (MZ, PK, ELF,
+    ///   etc)
+    ///
+    /// Each candidate is scored on how much the result looks like real data
+    /// (see [`plaintext_score`]), and the results are sorted best-first with
+    /// a confidence between 0.0 and 1.0. A key of zero is never returned,
+    /// nor is a key that's just a shorter key repeated.
+    ///
+    /// Unlike [`Transformation::detect`], this returns every candidate, no
+    /// matter how unlikely.
+    pub fn find_keys(buffer: &[u8]) -> Vec<(Transformation, f64)> {
+        let mut out: Vec<(Transformation, f64)> = Vec::new();
+
+        for width in [1, 2, 4, 8].iter() {
+            let width = *width;
+
+            if !buffer.len().is_multiple_of(width) || buffer.len() / width < DETECT_MIN_SAMPLES {
+                continue;
+            }
+
+            let sample = &buffer[..buffer.len().min(DETECT_SAMPLE_SIZE)];
+
+            for key in Self::key_candidates(sample, width) {
+                // Zero does nothing, and repeated keys will be found at a
+                // smaller width
+                if key.iter().all(|b| *b == 0) || (width > 1 && key[..width / 2] == key[width / 2..]) {
+                    continue;
+                }
+
+                let settings = match XorSettings::from_key(&key) {
+                    Some(s) => s,
+                    None => continue,
+                };
+
+                let t = TransformXorByConstant { settings };
+                let confidence = match t.transform(&sample.to_vec()) {
+                    Ok(result) => Self::confidence(&result, width),
+                    Err(_) => continue,
+                };
+
+                let transformation = Transformation::XorByConstant(t);
+                match out.iter_mut().find(|(existing, _)| *existing == transformation) {
+                    Some(existing) => existing.1 = existing.1.max(confidence),
+                    None => out.push((transformation, confidence)),
+                }
+            }
+        }
+
+        // Best first; ties go to the smaller key
+        out.sort_by(|(a_t, a_c), (b_t, b_c)| b_c.partial_cmp(a_c).unwrap_or(std::cmp::Ordering::Equal).then(a_t.cmp(b_t)));
+
+        out
+    }
+
+    /// Score a decoded buffer.
+    ///
+    /// A file signature only counts if it's at least two bytes longer than
+    /// the key - otherwise, we could have picked the key to produce it.
+    fn confidence(result: &[u8], width: usize) -> f64 {
+        let verified_magic = KNOWN_MAGIC.iter().any(|(_, magic)| magic.len() >= width + 2 && result.starts_with(magic));
+
+        match verified_magic {
+            true => MAGIC_SCORE.max(content_score(result)),
+            false => content_score(result),
+        }
+    }
+
+    /// Generate possible keys of the given width (in bytes).
+    fn key_candidates(buffer: &[u8], width: usize) -> Vec<Vec<u8>> {
+        let mut out: Vec<Vec<u8>> = Vec::new();
+
+        // Split the buffer into the bytes that share each key byte
+        let columns: Vec<Vec<u8>> = (0..width).map(|i| {
+            buffer.iter().skip(i).step_by(width).copied().collect()
+        }).collect();
+
+        // Frequency analysis: the most common byte is probably a NUL or space
+        let most_common: Vec<u8> = columns.iter().map(|c| Self::most_common_byte(c)).collect();
+        out.push(most_common.clone());
+        out.push(most_common.iter().map(|b| b ^ b' ').collect());
+
+        // Pick whichever key byte makes each column look the best
+        out.push(columns.iter().map(|c| {
+            let mut best: (u8, f64) = (0, -1.0);
+
+            for k in 0..=255u8 {
+                let decoded: Vec<u8> = c.iter().map(|b| b ^ k).collect();
+                let score = content_score(&decoded);
+
+                if score > best.1 {
+                    best = (k, score);
+                }
+            }
+
+            best.0
+        }).collect());
+
+        // Known plaintext: a run of bytes that repeats every `width` bytes is
+        // probably a run of NULs
+        if let Some(key) = Self::repeated_run_key(buffer, width) {
+            out.push(key);
+        }
+
+        // Known plaintext: file signatures at the start of the buffer; if the
+        // signature is shorter than the key, fill in the rest with our
+        // frequency analysis guess
+        for (_, magic) in KNOWN_MAGIC.iter() {
+            if buffer.len() < magic.len() {
+                continue;
+            }
+
+            let mut key = most_common.clone();
+            let mut consistent = true;
+
+            for (i, m) in magic.iter().enumerate() {
+                let k = buffer[i] ^ m;
+
+                if i < width {
+                    key[i] = k;
+                } else if key[i % width] != k {
+                    consistent = false;
+                    break;
+                }
+            }
+
+            if consistent {
+                out.push(key);
+            }
+        }
+
+        out
+    }
+
+    /// Find the most common byte (the lowest value wins ties).
+    fn most_common_byte(buffer: &[u8]) -> u8 {
+        let mut counts = [0usize; 256];
+        for b in buffer {
+            counts[*b as usize] += 1;
+        }
+
+        let mut best: u8 = 0;
+        for b in 0..=255u8 {
+            if counts[b as usize] > counts[best as usize] {
+                best = b;
+            }
+        }
+
+        best
+    }
+
+    /// Find the longest run of bytes that repeats every `width` bytes, and
+    /// assume that it's a run of NULs.
+    fn repeated_run_key(buffer: &[u8], width: usize) -> Option<Vec<u8>> {
+        // (start, length)
+        let mut best: (usize, usize) = (0, 0);
+        let mut start: usize = 0;
+
+        for j in width..buffer.len() {
+            if buffer[j] != buffer[j - width] {
+                start = j - width + 1;
+            }
+
+            if j + 1 - start > best.1 {
+                best = (start, j + 1 - start);
+            }
+        }
+
+        // Require at least four repetitions of the key
+        if best.1 < width * 4 {
+            return None;
+        }
+
+        let mut key = vec![0; width];
+        for i in 0..width {
+            key[(best.0 + i) % width] = buffer[best.0 + i];
+        }
+
+        Some(key)
+    }
 }
 
 impl TransformerTrait for TransformXorByConstant {
@@ -128,8 +358,26 @@ impl TransformerTrait for TransformXorByConstant {
         true
     }
 
-    fn detect(_buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
-        vec![]
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        if buffer.len() < DETECT_MIN_LENGTH {
+            return vec![];
+        }
+
+        // XORing text only turns it into different text, so only look for
+        // keys when the buffer isn't text already
+        let sample = &buffer[..buffer.len().min(DETECT_SAMPLE_SIZE)];
+        if looks_like_text(sample) {
+            return vec![];
+        }
+
+        // Only suggest keys that make things look a lot better
+        let baseline = plaintext_score(sample);
+
+        Self::find_keys(buffer).into_iter()
+            .filter(|(_, confidence)| *confidence >= DETECT_MIN_CONFIDENCE && *confidence >= baseline + DETECT_MIN_IMPROVEMENT)
+            .take(DETECT_MAX_RESULTS)
+            .map(|(t, _)| t)
+            .collect()
     }
 }
 
@@ -310,4 +558,76 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_find_keys_text() -> SimpleResult<()> {
+        let text = b"Here is some perfectly normal text, which has been obfuscated with a single-byte XOR key so that nobody can read it.".to_vec();
+
+        let encoded = TransformXorByConstant::new(XorSettings::EightBit(0x5a)).transform(&text)?;
+        let keys = TransformXorByConstant::find_keys(&encoded);
+        assert_eq!(TransformXorByConstant::new(XorSettings::EightBit(0x5a)), keys[0].0);
+        assert!(keys[0].1 > 0.9);
+
+        // It should decode back to the text
+        assert_eq!(text, keys[0].0.transform(&encoded)?);
+
+        // Detect should suggest it first
+        assert_eq!(Some(&TransformXorByConstant::new(XorSettings::EightBit(0x5a))), TransformXorByConstant::detect(&encoded).first());
+
+        // A 16-bit key works too (the text is an even length)
+        let encoded = TransformXorByConstant::new(XorSettings::SixteenBit(0x1337)).transform(&text)?;
+        assert_eq!(TransformXorByConstant::new(XorSettings::SixteenBit(0x1337)), TransformXorByConstant::find_keys(&encoded)[0].0);
+
+        // The plain text shouldn't be XORed by anything
+        assert_eq!(0, TransformXorByConstant::detect(&text).len());
+
+        // Neither should text that isn't English, like a Base58 address
+        assert_eq!(0, TransformXorByConstant::detect(&b"1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa".to_vec()).len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_keys_binary() -> SimpleResult<()> {
+        // The start of a PE file
+        let mut pe = b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff\x00\x00\xb8\x00\x00\x00\x00\x00\x00\x00\x40\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        pe.extend(vec![0; 28]);
+        pe.extend(b"\x80\x00\x00\x00\x0e\x1f\xba\x0e\x00\xb4\x09\xcd\x21\xb8\x01\x4c\xcd\x21".to_vec());
+        pe.extend(b"This program cannot be run in DOS mode.\r\r\n$\x00\x00\x00\x00\x00\x00\x00".to_vec());
+        assert_eq!(128, pe.len());
+
+        for settings in [XorSettings::EightBit(0x41), XorSettings::SixteenBit(0xabcd), XorSettings::ThirtyTwoBit(0x11223344), XorSettings::SixtyFourBit(0x0102030405060708)].iter() {
+            let t = TransformXorByConstant::new(*settings);
+            let encoded = t.transform(&pe)?;
+
+            let keys = TransformXorByConstant::find_keys(&encoded);
+            assert_eq!(t, keys[0].0, "{:?}", settings);
+            assert_eq!(pe, keys[0].0.transform(&encoded)?);
+
+            assert_eq!(Some(&t), TransformXorByConstant::detect(&encoded).first());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_keys_nothing() -> SimpleResult<()> {
+        // Too short
+        assert_eq!(0, TransformXorByConstant::detect(&b"\x41\x41\x41\x41".to_vec()).len());
+
+        // Random-looking data shouldn't get any confident guesses
+        let random = b"\x8e\xf1\x93\x02\xc4\xaa\x17\xd9\xfe\x80\x5c\x31\x6b\x27\xe0\x4d\
+                       \x1c\xdc\x23\xdb\xc0\xc5\x5a\x68\xd6\xed\x4e\x5b\x1d\x36\xdf\xf5\
+                       \xfb\x35\x97\x36\x05\x1a\xf5\x90\x18\xe4\xdd\x95\x31\xcd\x6a\x27\
+                       \x68\x74\xd0\xd8\x48\x6b\x0b\xc8\x1f\x3c\xc4\xd1\x95\x14\xcc\x1a".to_vec();
+        assert_eq!(0, TransformXorByConstant::detect(&random).len());
+
+        // Keys are never zero, and never a repeated smaller key
+        let keys = TransformXorByConstant::find_keys(&random);
+        assert!(keys.len() > 0);
+        assert!(!keys.iter().any(|(t, _)| *t == TransformXorByConstant::new(XorSettings::EightBit(0))));
+        assert!(!keys.iter().any(|(t, _)| *t == TransformXorByConstant::new(XorSettings::SixteenBit(0x4141))));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,462 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait, TransformXorByConstant};
+use crate::transformation::{content_score, plaintext_score, index_of_coincidence, hamming_distance};
+use crate::transformation::{DETECT_MIN_LENGTH, DETECT_MIN_SAMPLES, DETECT_SAMPLE_SIZE, DETECT_MIN_CONFIDENCE, DETECT_MIN_IMPROVEMENT, DETECT_MAX_RESULTS};
+
+/// The longest key that `detect()` will look for.
+const DETECT_MAX_KEY_LENGTH: usize = 32;
+
+/// How many of the best key lengths (by each metric) to try.
+const DETECT_KEY_LENGTHS: usize = 3;
+
+/// How the key is combined with the data.
+///
+/// In every case, `i` is the offset into the buffer and the key repeats as
+/// many times as it needs to. These describe how the data was *encoded* -
+/// transforming the buffer reverses it.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub enum XorKeyMode {
+    /// `encoded[i] = plain[i] ^ key[i]`
+    Repeating,
+
+    /// The key increments by a fixed step after each byte:
+    /// `encoded[i] = plain[i] ^ (key[i] + (step * i))`
+    Incrementing(u8),
+
+    /// Each byte is also XORed with the previous plaintext byte:
+    /// `encoded[i] = plain[i] ^ key[i] ^ plain[i - 1]`
+    PreviousPlaintext,
+
+    /// Each byte is also XORed with the previous encoded byte (like CBC
+    /// mode): `encoded[i] = plain[i] ^ key[i] ^ encoded[i - 1]`
+    PreviousCiphertext,
+
+    /// The key is added rather than XORed: `encoded[i] = plain[i] + key[i]`
+    Add,
+
+    /// The key is subtracted rather than XORed:
+    /// `encoded[i] = plain[i] - key[i]`
+    Subtract,
+}
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TransformXorByKey {
+    key: Vec<u8>,
+    mode: XorKeyMode,
+}
+
+impl fmt::Display for TransformXorByKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformXorByKey {
+    pub fn new(key: Vec<u8>, mode: XorKeyMode) -> SimpleResult<Transformation> {
+        if key.is_empty() {
+            bail!("XOR key cannot be empty");
+        }
+
+        Ok(Transformation::XorByKey(Self {
+            key,
+            mode,
+        }))
+    }
+
+    pub fn repeating(key: Vec<u8>) -> SimpleResult<Transformation> {
+        Self::new(key, XorKeyMode::Repeating)
+    }
+
+    pub fn incrementing(key: Vec<u8>, step: u8) -> SimpleResult<Transformation> {
+        Self::new(key, XorKeyMode::Incrementing(step))
+    }
+
+    pub fn previous_plaintext(key: Vec<u8>) -> SimpleResult<Transformation> {
+        Self::new(key, XorKeyMode::PreviousPlaintext)
+    }
+
+    pub fn previous_ciphertext(key: Vec<u8>) -> SimpleResult<Transformation> {
+        Self::new(key, XorKeyMode::PreviousCiphertext)
+    }
+
+    pub fn add(key: Vec<u8>) -> SimpleResult<Transformation> {
+        Self::new(key, XorKeyMode::Add)
+    }
+
+    pub fn subtract(key: Vec<u8>) -> SimpleResult<Transformation> {
+        Self::new(key, XorKeyMode::Subtract)
+    }
+
+    /// Get the key byte for a particular offset.
+    fn key_at(&self, i: usize) -> u8 {
+        let k = self.key[i % self.key.len()];
+
+        match self.mode {
+            XorKeyMode::Incrementing(step) => k.wrapping_add(step.wrapping_mul(i as u8)),
+            _ => k,
+        }
+    }
+
+    /// Decode a single byte with a single key byte, for the modes that don't
+    /// depend on the previous byte.
+    fn decode_byte(mode: XorKeyMode, b: u8, k: u8) -> u8 {
+        match mode {
+            XorKeyMode::Add      => b.wrapping_sub(k),
+            XorKeyMode::Subtract => b.wrapping_add(k),
+            _                    => b ^ k,
+        }
+    }
+
+    /// Find the key byte that makes a column (all the bytes encoded with the
+    /// same key byte) look the most like real data.
+    fn best_key_byte(mode: XorKeyMode, column: &[u8]) -> u8 {
+        let mut best: (u8, f64) = (0, -1.0);
+
+        for k in 0..=255u8 {
+            let decoded: Vec<u8> = column.iter().map(|b| Self::decode_byte(mode, *b, k)).collect();
+            let score = content_score(&decoded);
+
+            if score > best.1 {
+                best = (k, score);
+            }
+        }
+
+        best.0
+    }
+
+    /// Shorten a key to its smallest repeating unit (so "ABCABC" becomes
+    /// "ABC").
+    fn shortest_period(key: Vec<u8>) -> Vec<u8> {
+        for period in 1..key.len() {
+            if key.len().is_multiple_of(period) && (period..key.len()).all(|i| key[i] == key[i - period]) {
+                return key[..period].to_vec();
+            }
+        }
+
+        key
+    }
+
+    /// Figure out which key lengths are most likely.
+    ///
+    /// When the data is split into columns by the right key length, each
+    /// column is encoded with a single key byte, so the columns keep the
+    /// index of coincidence of the original data (which, for anything other
+    /// than random data, is high). Likewise, blocks that are the length of the
+    /// key are all encoded the same way, so the Hamming distance between them
+    /// is the Hamming distance between the original data, which tends to be
+    /// low.
+    ///
+    /// Multiples of the correct length score well too, so the shortest length
+    /// that scores nearly as well as the best one is always included.
+    fn likely_key_lengths(buffer: &[u8]) -> Vec<usize> {
+        let max = DETECT_MAX_KEY_LENGTH.min(buffer.len() / DETECT_MIN_SAMPLES);
+        if max == 0 {
+            return vec![];
+        }
+
+        let mut by_ioc: Vec<(usize, f64)> = (1..=max).map(|length| {
+            let total: f64 = (0..length).map(|i| {
+                let column: Vec<u8> = buffer.iter().skip(i).step_by(length).copied().collect();
+                index_of_coincidence(&column)
+            }).sum();
+
+            (length, total / length as f64)
+        }).collect();
+
+        let mut by_hamming: Vec<(usize, f64)> = (1..=max).map(|length| {
+            let blocks: Vec<&[u8]> = buffer.chunks_exact(length).collect();
+            let total: u64 = blocks.windows(2).map(|pair| hamming_distance(pair[0], pair[1])).sum();
+
+            (length, total as f64 / ((blocks.len() - 1) * length) as f64)
+        }).collect();
+
+        let best_ioc = by_ioc.iter().map(|(_, ioc)| *ioc).fold(0.0, f64::max);
+        let shortest = by_ioc.iter().find(|(_, ioc)| *ioc >= best_ioc * 0.9).map(|(length, _)| *length);
+
+        by_ioc.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        by_hamming.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut out: Vec<usize> = Vec::new();
+        let candidates = shortest.into_iter()
+            .chain(by_ioc.iter().take(DETECT_KEY_LENGTHS).map(|(length, _)| *length))
+            .chain(by_hamming.iter().take(DETECT_KEY_LENGTHS).map(|(length, _)| *length));
+
+        for length in candidates {
+            if !out.contains(&length) {
+                out.push(length);
+            }
+        }
+
+        out
+    }
+
+    /// Guess the keys that might have been used to encode the buffer, with
+    /// the confidence (0.0 - 1.0) of each guess. The best guess is first.
+    ///
+    /// Only the [`XorKeyMode::Repeating`], [`XorKeyMode::Add`], and
+    /// [`XorKeyMode::PreviousCiphertext`] modes are guessed
+    /// ([`XorKeyMode::Subtract`] is the same as adding the negated key, so
+    /// those will show up as [`XorKeyMode::Add`]). Keys that
+    /// [`crate::transformation::TransformXorByConstant`] can handle on their
+    /// own aren't included.
+    pub fn find_keys(buffer: &[u8]) -> Vec<(Transformation, f64)> {
+        let mut out: Vec<(Transformation, f64)> = Vec::new();
+        let sample = &buffer[..buffer.len().min(DETECT_SAMPLE_SIZE)];
+
+        for mode in [XorKeyMode::Repeating, XorKeyMode::Add, XorKeyMode::PreviousCiphertext].iter() {
+            let mode = *mode;
+
+            // Chaining with the previous ciphertext is easy to undo first,
+            // and leaves behind a normal repeating key
+            let unchained: Vec<u8> = match mode {
+                XorKeyMode::PreviousCiphertext => {
+                    (0..sample.len()).map(|i| sample[i] ^ if i == 0 { 0 } else { sample[i - 1] }).collect()
+                },
+                _ => sample.to_vec(),
+            };
+
+            for length in Self::likely_key_lengths(&unchained) {
+                let key: Vec<u8> = (0..length).map(|i| {
+                    let column: Vec<u8> = unchained.iter().skip(i).step_by(length).copied().collect();
+                    Self::best_key_byte(mode, &column)
+                }).collect();
+                let key = Self::shortest_period(key);
+
+                // A key of all zeroes does nothing (except when chaining)
+                if mode != XorKeyMode::PreviousCiphertext && key.iter().all(|b| *b == 0) {
+                    continue;
+                }
+
+                // Don't duplicate what XorByConstant can already do
+                if mode == XorKeyMode::Repeating && [1, 2, 4, 8].contains(&key.len()) && buffer.len().is_multiple_of(key.len()) {
+                    continue;
+                }
+
+                let t = TransformXorByKey {
+                    key,
+                    mode,
+                };
+
+                let confidence = match t.transform(&sample.to_vec()) {
+                    Ok(result) => plaintext_score(&result),
+                    Err(_) => continue,
+                };
+
+                let transformation = Transformation::XorByKey(t);
+                match out.iter_mut().find(|(existing, _)| *existing == transformation) {
+                    Some(existing) => existing.1 = existing.1.max(confidence),
+                    None => out.push((transformation, confidence)),
+                }
+            }
+        }
+
+        // Best first; ties go to the smaller key
+        out.sort_by(|(a_t, a_c), (b_t, b_c)| b_c.partial_cmp(a_c).unwrap_or(std::cmp::Ordering::Equal).then(a_t.cmp(b_t)));
+
+        out
+    }
+}
+
+impl TransformerTrait for TransformXorByKey {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        if self.key.is_empty() {
+            bail!("XOR key cannot be empty");
+        }
+
+        let mut out: Vec<u8> = Vec::with_capacity(buffer.len());
+
+        for (i, b) in buffer.iter().enumerate() {
+            let k = self.key_at(i);
+
+            out.push(match self.mode {
+                XorKeyMode::PreviousPlaintext => {
+                    b ^ k ^ if i == 0 { 0 } else { out[i - 1] }
+                },
+                XorKeyMode::PreviousCiphertext => {
+                    b ^ k ^ if i == 0 { 0 } else { buffer[i - 1] }
+                },
+                _ => Self::decode_byte(self.mode, *b, k),
+            });
+        }
+
+        Ok(out)
+    }
+
+    fn untransform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        if self.key.is_empty() {
+            bail!("XOR key cannot be empty");
+        }
+
+        let mut out: Vec<u8> = Vec::with_capacity(buffer.len());
+
+        for (i, b) in buffer.iter().enumerate() {
+            let k = self.key_at(i);
+
+            out.push(match self.mode {
+                XorKeyMode::PreviousPlaintext => {
+                    b ^ k ^ if i == 0 { 0 } else { buffer[i - 1] }
+                },
+                XorKeyMode::PreviousCiphertext => {
+                    b ^ k ^ if i == 0 { 0 } else { out[i - 1] }
+                },
+                XorKeyMode::Add      => b.wrapping_add(k),
+                XorKeyMode::Subtract => b.wrapping_sub(k),
+                _                    => b ^ k,
+            });
+        }
+
+        Ok(out)
+    }
+
+    fn can_transform(&self, _buffer: &Vec<u8>) -> bool {
+        // Any length works
+        !self.key.is_empty()
+    }
+
+    fn is_two_way(&self) -> bool {
+        true
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        if buffer.len() < DETECT_MIN_LENGTH {
+            return vec![];
+        }
+
+        // Only suggest keys that make things look a lot better
+        let baseline = plaintext_score(&buffer[..buffer.len().min(DETECT_SAMPLE_SIZE)]);
+
+        // If a simple constant explains the data at least as well, that's
+        // almost certainly the real answer
+        let simplest = match TransformXorByConstant::find_keys(buffer).first() {
+            Some((_, confidence)) => *confidence,
+            None => 0.0,
+        };
+
+        Self::find_keys(buffer).into_iter()
+            .filter(|(_, confidence)| *confidence >= DETECT_MIN_CONFIDENCE && *confidence >= baseline + DETECT_MIN_IMPROVEMENT && *confidence > simplest)
+            .take(DETECT_MAX_RESULTS)
+            .map(|(t, _)| t)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TEXT: &[u8] = b"It was the best of times, it was the worst of times, it was the age of wisdom, \
+                          it was the age of foolishness, it was the epoch of belief, it was the epoch of \
+                          incredulity, it was the season of Light, it was the season of Darkness, it was \
+                          the spring of hope, it was the winter of despair.";
+
+    #[test]
+    fn test_repeating() -> SimpleResult<()> {
+        let t = TransformXorByKey::repeating(b"\x01\x02\x03".to_vec())?;
+        assert!(t.is_two_way());
+
+        assert_eq!(b"\x00\x00\x00\x03\x07\x05\x06".to_vec(), t.transform(&b"\x01\x02\x03\x02\x05\x06\x07".to_vec())?);
+        assert_eq!(b"\x01\x02\x03\x02\x05\x06\x07".to_vec(), t.untransform(&b"\x00\x00\x00\x03\x07\x05\x06".to_vec())?);
+
+        // Empty keys aren't allowed
+        assert!(TransformXorByKey::repeating(vec![]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_incrementing() -> SimpleResult<()> {
+        // Key bytes are 0x10, 0x11, 0x12, ...
+        let t = TransformXorByKey::incrementing(b"\x10".to_vec(), 1)?;
+        assert_eq!(b"\x00\x00\x00\x00".to_vec(), t.transform(&b"\x10\x11\x12\x13".to_vec())?);
+
+        // Key bytes are 0x10, 0x20, 0x12, 0x22, ...
+        let t = TransformXorByKey::incrementing(b"\x10\x20".to_vec(), 1)?;
+        assert_eq!(b"\x00\x00\x00\x00".to_vec(), t.transform(&b"\x10\x21\x12\x23".to_vec())?);
+
+        // It wraps around
+        let t = TransformXorByKey::incrementing(b"\xff".to_vec(), 0x80)?;
+        assert_eq!(b"\x00\x00\x00".to_vec(), t.transform(&b"\xff\x7f\xff".to_vec())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_previous() -> SimpleResult<()> {
+        // Plaintext: 01 02 03 04, key 0x10
+        let t = TransformXorByKey::previous_plaintext(b"\x10".to_vec())?;
+        assert_eq!(b"\x01\x02\x03\x04".to_vec(), t.transform(&b"\x11\x13\x11\x17".to_vec())?);
+        assert_eq!(b"\x11\x13\x11\x17".to_vec(), t.untransform(&b"\x01\x02\x03\x04".to_vec())?);
+
+        let t = TransformXorByKey::previous_ciphertext(b"\x10".to_vec())?;
+        assert_eq!(b"\x01\x02\x03\x04".to_vec(), t.transform(&b"\x11\x03\x10\x04".to_vec())?);
+        assert_eq!(b"\x11\x03\x10\x04".to_vec(), t.untransform(&b"\x01\x02\x03\x04".to_vec())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_subtract() -> SimpleResult<()> {
+        let t = TransformXorByKey::add(b"\x01\x80".to_vec())?;
+        assert_eq!(b"\x00\x80\xfe\x00".to_vec(), t.transform(&b"\x01\x00\xff\x80".to_vec())?);
+        assert_eq!(b"\x01\x00\xff\x80".to_vec(), t.untransform(&b"\x00\x80\xfe\x00".to_vec())?);
+
+        let t = TransformXorByKey::subtract(b"\x01\x80".to_vec())?;
+        assert_eq!(b"\x02\x80\x00\x00".to_vec(), t.transform(&b"\x01\x00\xff\x80".to_vec())?);
+        assert_eq!(b"\x01\x00\xff\x80".to_vec(), t.untransform(&b"\x02\x80\x00\x00".to_vec())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> SimpleResult<()> {
+        let modes = vec![
+            XorKeyMode::Repeating,
+            XorKeyMode::Incrementing(3),
+            XorKeyMode::PreviousPlaintext,
+            XorKeyMode::PreviousCiphertext,
+            XorKeyMode::Add,
+            XorKeyMode::Subtract,
+        ];
+
+        for mode in modes {
+            let t = TransformXorByKey::new(b"Secret".to_vec(), mode)?;
+            let encoded = t.untransform(&TEXT.to_vec())?;
+
+            assert_eq!(TEXT.len(), encoded.len());
+            assert_eq!(TEXT.to_vec(), t.transform(&encoded)?, "{:?}", mode);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect() -> SimpleResult<()> {
+        for mode in [XorKeyMode::Repeating, XorKeyMode::Add, XorKeyMode::PreviousCiphertext].iter() {
+            let t = TransformXorByKey::new(b"\x8aSECRET\xf0".to_vec(), *mode)?;
+            let encoded = t.untransform(&TEXT.to_vec())?;
+
+            let keys = TransformXorByKey::find_keys(&encoded);
+            assert_eq!(t, keys[0].0, "{:?}", mode);
+            assert!(keys[0].1 > 0.9);
+
+            assert_eq!(Some(&t), TransformXorByKey::detect(&encoded).first());
+        }
+
+        // Subtracting is found as adding the negated key
+        let encoded = TransformXorByKey::subtract(b"\x8aSECRET\xf0".to_vec())?.untransform(&TEXT.to_vec())?;
+        let negated: Vec<u8> = b"\x8aSECRET\xf0".iter().map(|b| b.wrapping_neg()).collect();
+        assert_eq!(Some(&TransformXorByKey::add(negated)?), TransformXorByKey::detect(&encoded).first());
+
+        // Plain text shouldn't be decoded
+        assert_eq!(0, TransformXorByKey::detect(&TEXT.to_vec()).len());
+
+        // Too short
+        assert_eq!(0, TransformXorByKey::detect(&b"\x01\x02\x03\x04\x05".to_vec()).len());
+
+        Ok(())
+    }
+}
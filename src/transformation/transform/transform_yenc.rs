@@ -0,0 +1,207 @@
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::transformation::{Transformation, TransformerTrait};
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformYenc {
+}
+
+impl TransformYenc {
+    pub fn new() -> Transformation {
+        Transformation::FromYenc(TransformYenc {})
+    }
+
+    /// Read a `key=value` parameter from a header line (like `=ybegin` or
+    /// `=yend`).
+    fn parameter<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+        line.split(' ').find_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+    }
+
+    /// Read a numeric parameter, with the given radix.
+    fn number(line: &str, key: &str, radix: u32) -> SimpleResult<Option<u64>> {
+        match Self::parameter(line, key) {
+            Some(value) => match u64::from_str_radix(value, radix) {
+                Ok(n) => Ok(Some(n)),
+                Err(e) => bail!("Couldn't parse yEnc parameter {}: {}", key, e),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+impl fmt::Display for TransformYenc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformerTrait for TransformYenc {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        let mut lines = buffer.split(|b| *b == b'\n').map(|line| {
+            line.strip_suffix(b"\r").unwrap_or(line)
+        });
+
+        // Without the header, anything at all would "decode"
+        let header = match lines.next().map(std::str::from_utf8) {
+            Some(Ok(line)) if line.starts_with("=ybegin ") => line.to_string(),
+            _ => bail!("yEnc data must start with a =ybegin line"),
+        };
+
+        let mut expected_length = match Self::number(&header, "size", 10)? {
+            Some(size) => size,
+            None => bail!("yEnc =ybegin line is missing the size"),
+        };
+
+        let multipart = Self::number(&header, "part", 10)?.is_some();
+
+        let mut out: Vec<u8> = Vec::new();
+        let mut trailer: Option<String> = None;
+        for line in lines {
+            if let Some(t) = &trailer {
+                if !line.is_empty() {
+                    bail!("yEnc data continues after the {} line", t);
+                }
+
+                continue;
+            }
+
+            if line.starts_with(b"=ypart ") && multipart && out.is_empty() {
+                let part = String::from_utf8_lossy(line);
+
+                // The range is inclusive, and starts at 1
+                expected_length = match (Self::number(&part, "begin", 10)?, Self::number(&part, "end", 10)?) {
+                    (Some(begin), Some(end)) if begin >= 1 && end >= begin => end - begin + 1,
+                    _ => bail!("yEnc =ypart line has a bad range"),
+                };
+
+                continue;
+            }
+
+            if line.starts_with(b"=yend") {
+                trailer = Some(String::from_utf8_lossy(line).to_string());
+                continue;
+            }
+
+            let mut bytes = line.iter();
+            while let Some(b) = bytes.next() {
+                match b {
+                    b'=' => match bytes.next() {
+                        Some(escaped) => out.push(escaped.wrapping_sub(64 + 42)),
+                        None => bail!("yEnc escape character at the end of a line"),
+                    },
+                    _ => out.push(b.wrapping_sub(42)),
+                }
+            }
+        }
+
+        let trailer = match trailer {
+            Some(t) => t,
+            None => bail!("yEnc data is missing the =yend line"),
+        };
+
+        if out.len() as u64 != expected_length {
+            bail!("yEnc data is the wrong length (expected {}, got {})", expected_length, out.len());
+        }
+
+        if let Some(size) = Self::number(&trailer, "size", 10)? {
+            if size != expected_length {
+                bail!("yEnc =yend size doesn't match");
+            }
+        }
+
+        // For multipart data, the whole-file crc32 covers data we don't have
+        let crc_key = match multipart {
+            true  => "pcrc32",
+            false => "crc32",
+        };
+
+        if let Some(crc) = Self::number(&trailer, crc_key, 16)? {
+            if crc != crc32fast::hash(&out) as u64 {
+                bail!("yEnc checksum doesn't match");
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("yEnc cannot be untransformed");
+    }
+
+    fn is_two_way(&self) -> bool {
+        // The header, line length, and which characters get escaped are all
+        // up to the encoder
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        let s = Self::new();
+        match s.can_transform(buffer) {
+            true => vec![s],
+            false => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_yenc() -> SimpleResult<()> {
+        let t = TransformYenc::new();
+        assert!(!t.is_two_way());
+
+        // A single part, with some characters that need escaping
+        let data = b"=ybegin line=128 size=18 name=hello world.bin\r\n\
+                     *+=}=@=J=Mr\x8f\x96\x96\x99VJ\xa1\x99\x9c\x96\x8e\r\n\
+                     =yend size=18 crc32=4d04d2f8\r\n".to_vec();
+        assert_eq!(b"\x00\x01\x13\xd6\xe0\xe3Hello, world".to_vec(), t.transform(&data)?);
+
+        // The data can be split over lines, and the checksum is optional
+        let data = b"=ybegin line=6 size=18 name=hello.bin\n\
+                     *+=}=@=J=M\n\
+                     r\x8f\x96\x96\x99V\n\
+                     J\xa1\x99\x9c\x96\x8e\n\
+                     =yend size=18\n".to_vec();
+        assert_eq!(b"\x00\x01\x13\xd6\xe0\xe3Hello, world".to_vec(), t.transform(&data)?);
+
+        // One part of a multipart file
+        let data = b"=ybegin part=2 line=128 size=100 name=hello.txt\n\
+                     =ypart begin=51 end=62\n\
+                     r\x8f\x96\x96\x99VJ\xa1\x99\x9c\x96\x8e\n\
+                     =yend size=12 part=2 pcrc32=e79aa9c2\n".to_vec();
+        assert_eq!(b"Hello, world".to_vec(), t.transform(&data)?);
+
+        assert!(t.untransform(&b"Hello, world".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yenc_errors() -> SimpleResult<()> {
+        let t = TransformYenc::new();
+
+        // No header or trailer
+        assert!(t.transform(&b"r\x8f\x96\x96\x99VJ\xa1\x99\x9c\x96\x8e".to_vec()).is_err());
+        assert!(t.transform(&b"=ybegin size=12 name=a\nr\x8f\x96\x96\x99VJ\xa1\x99\x9c\x96\x8e\n".to_vec()).is_err());
+
+        // Wrong size
+        assert!(t.transform(&b"=ybegin size=11 name=a\nr\x8f\x96\x96\x99VJ\xa1\x99\x9c\x96\x8e\n=yend\n".to_vec()).is_err());
+        assert!(t.transform(&b"=ybegin size=12 name=a\nr\x8f\x96\x96\x99VJ\xa1\x99\x9c\x96\x8e\n=yend size=13\n".to_vec()).is_err());
+
+        // Bad checksum
+        assert!(t.transform(&b"=ybegin size=12 name=a\nr\x8f\x96\x96\x99VJ\xa1\x99\x9c\x96\x8e\n=yend size=12 crc32=e79aa9c3\n".to_vec()).is_err());
+
+        // Escape at the end of a line
+        assert!(t.transform(&b"=ybegin size=1 name=a\n=\n=yend\n".to_vec()).is_err());
+
+        // Data after the trailer
+        assert!(t.transform(&b"=ybegin size=12 name=a\nr\x8f\x96\x96\x99VJ\xa1\x99\x9c\x96\x8e\n=yend\nabc\n".to_vec()).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use ruzstd::StreamingDecoder;
+use simple_error::{SimpleResult, bail};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::io;
+
+use crate::transformation::{LimitedOutput, Transformation, TransformerTrait};
+
+/// The signature at the start of a Zstandard frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Serialize, Deserialize)]
+pub struct TransformZstd {
+}
+
+impl fmt::Display for TransformZstd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TransformZstd {
+    pub fn new() -> Transformation {
+        Transformation::FromZstd(TransformZstd {})
+    }
+
+    fn decompress(buffer: &[u8], mut out: LimitedOutput) -> SimpleResult<Vec<u8>> {
+        if !buffer.starts_with(&ZSTD_MAGIC) {
+            bail!("Not a Zstandard buffer (bad magic)");
+        }
+
+        let mut source = buffer;
+        let mut decoder = match StreamingDecoder::new(&mut source) {
+            Ok(d) => d,
+            Err(e) => bail!("Couldn't decompress Zstandard: {}", e),
+        };
+
+        if let Err(e) = io::copy(&mut decoder, &mut out) {
+            bail!("Couldn't decompress Zstandard: {}", e);
+        }
+
+        Ok(out.into_inner())
+    }
+}
+
+impl TransformerTrait for TransformZstd {
+    fn transform(&self, buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        Self::decompress(buffer, LimitedOutput::unlimited())
+    }
+
+    fn untransform(&self, _buffer: &Vec<u8>) -> SimpleResult<Vec<u8>> {
+        bail!("Zstandard cannot be untransformed");
+    }
+
+    fn can_transform(&self, buffer: &Vec<u8>) -> bool {
+        // Don't let a decompression bomb use up all the memory
+        buffer.starts_with(&ZSTD_MAGIC) && Self::decompress(buffer, LimitedOutput::for_detection(buffer.len())).is_ok()
+    }
+
+    fn is_two_way(&self) -> bool {
+        // Zstandard can't reliably go backwards
+        false
+    }
+
+    fn detect(buffer: &Vec<u8>) -> Vec<Transformation> where Self: Sized {
+        match (TransformZstd {}).can_transform(buffer) {
+            true => vec![Self::new()],
+            false => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_zstd() -> SimpleResult<()> {
+        let t = TransformZstd::new();
+        assert_eq!(false, t.is_two_way());
+
+        // echo -ne 'ABCD' | zstd --no-check
+        let result = t.transform(&b"\x28\xb5\x2f\xfd\x20\x04\x21\x00\x00\x41\x42\x43\x44".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // echo -ne 'ABCD' | zstd
+        let result = t.transform(&b"\x28\xb5\x2f\xfd\x24\x04\x21\x00\x00\x41\x42\x43\x44\x6e\x9c\x71\x3f".to_vec())?;
+        assert_eq!(b"ABCD".to_vec(), result);
+
+        // Truncated
+        assert!(t.transform(&b"\x28\xb5\x2f\xfd\x24\x04\x21\x00\x00\x41".to_vec()).is_err());
+
+        // Not Zstandard
+        assert!(t.transform(&b"ABCD".to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zstd_limit() -> SimpleResult<()> {
+        let data = b"\x28\xb5\x2f\xfd\x24\x04\x21\x00\x00\x41\x42\x43\x44\x6e\x9c\x71\x3f";
+
+        assert_eq!(b"ABCD".to_vec(), TransformZstd::decompress(data, LimitedOutput::new(4))?);
+        assert!(TransformZstd::decompress(data, LimitedOutput::new(3)).is_err());
+
+        Ok(())
+    }
+}